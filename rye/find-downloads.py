@@ -6,6 +6,9 @@ from urllib.parse import unquote
 
 TOKEN = open("token.txt").read().strip()
 RELEASE_URL = "https://api.github.com/repos/indygreg/python-build-standalone/releases"
+RELEASE_CYCLE_URL = (
+    "https://raw.githubusercontent.com/python/devguide/main/include/release-cycle.json"
+)
 HEADERS = {
     "X-GitHub-Api-Version": "2022-11-28",
     "Authorization": "Bearer " + TOKEN,
@@ -105,6 +108,15 @@ def normalize_triple(triple):
     return "%s-%s" % (arch, platform)
 
 
+def read_end_of_life():
+    rv = []
+    for version, info in sess.get(RELEASE_CYCLE_URL).json().items():
+        major, minor = map(int, version.split("."))
+        year, month = map(int, info["end_of_life"].split("-")[:2])
+        rv.append((major, minor, year, month))
+    return sorted(rv)
+
+
 def read_sha256(url):
     resp = sess.get(url + ".sha256", headers=HEADERS)
     if not resp.ok:
@@ -247,3 +259,8 @@ for interpreter, py_ver, choices in sorted(
             % ((interpreter,) + py_ver + (arch, platform, url, sha256))
         )
 print("];")
+print()
+print("pub const CPYTHON_END_OF_LIFE: &[(u8, u8, u16, u8)] = &[")
+for major, minor, year, month in read_end_of_life():
+    print("    (%d, %d, %d, %d)," % (major, minor, year, month))
+print("];")
@@ -0,0 +1,164 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Error};
+
+use crate::bootstrap::ensure_self_venv;
+use crate::utils::{get_venv_python_bin, CommandOutput};
+
+/// Looks up a password in the keyring of the system.
+///
+/// This goes through the `keyring` package of the internal virtualenv, so
+/// the same backends as with pip and twine are used.
+pub fn keyring_get(service: &str, username: &str) -> Result<Option<String>, Error> {
+    let venv = ensure_self_venv(CommandOutput::Quiet)?;
+    let output = Command::new(get_venv_python_bin(&venv))
+        .arg("-mkeyring")
+        .arg("get")
+        .arg(service)
+        .arg(username)
+        .stderr(Stdio::null())
+        .output()
+        .context("unable to query the keyring")?;
+    let password = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(Some(password).filter(|x| output.status.success() && !x.is_empty()))
+}
+
+/// Stores a password in the keyring of the system.
+pub fn keyring_set(service: &str, username: &str, password: &str) -> Result<(), Error> {
+    let venv = ensure_self_venv(CommandOutput::Quiet)?;
+    let mut child = Command::new(get_venv_python_bin(&venv))
+        .arg("-mkeyring")
+        .arg("set")
+        .arg(service)
+        .arg(username)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .context("unable to store the password in the keyring")?;
+    writeln!(child.stdin.take().unwrap(), "{}", password)?;
+    if !child.wait()?.success() {
+        bail!("failed to store the password in the keyring");
+    }
+    Ok(())
+}
+
+/// Returns the path of the netrc file (`$NETRC` or `~/.netrc`).
+fn netrc_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("NETRC") {
+        return Some(PathBuf::from(path));
+    }
+    let home = simple_home_dir::home_dir()?;
+    if cfg!(windows) && !home.join(".netrc").is_file() {
+        Some(home.join("_netrc"))
+    } else {
+        Some(home.join(".netrc"))
+    }
+}
+
+/// Looks up the login and password for a host in the netrc file.
+pub fn netrc_lookup(host: &str) -> Result<Option<(Option<String>, String)>, Error> {
+    let path = match netrc_path() {
+        Some(path) if path.is_file() => path,
+        _ => return Ok(None),
+    };
+    let contents =
+        fs::read_to_string(&path).with_context(|| format!("unable to read {}", path.display()))?;
+    Ok(parse_netrc(&contents, host))
+}
+
+/// Finds the entry of a host (or the `default` entry) in a netrc file.
+fn parse_netrc(contents: &str, host: &str) -> Option<(Option<String>, String)> {
+    let mut tokens = Vec::new();
+    let mut in_macdef = false;
+    for line in contents.lines() {
+        // macro definitions run until the next empty line
+        if in_macdef {
+            in_macdef = !line.trim().is_empty();
+            continue;
+        }
+        for token in line.split_whitespace() {
+            if token == "macdef" {
+                in_macdef = true;
+                break;
+            }
+            tokens.push(token);
+        }
+    }
+
+    let mut rv = None;
+    let mut default = None;
+    let mut iter = tokens.into_iter();
+    let mut current: Option<(bool, Option<String>, Option<String>)> = None;
+    let mut finish = |entry: Option<(bool, Option<String>, Option<String>)>| {
+        if let Some((is_default, login, Some(password))) = entry {
+            match is_default {
+                true if default.is_none() => default = Some((login, password)),
+                false if rv.is_none() => rv = Some((login, password)),
+                _ => {}
+            }
+        }
+    };
+    while let Some(token) = iter.next() {
+        match token {
+            "machine" => {
+                finish(current.take());
+                if iter.next() == Some(host) {
+                    current = Some((false, None, None));
+                }
+            }
+            "default" => {
+                finish(current.take());
+                current = Some((true, None, None));
+            }
+            "login" => {
+                let value = iter.next().map(|x| x.to_string());
+                if let Some(ref mut entry) = current {
+                    entry.1 = value;
+                }
+            }
+            "password" => {
+                let value = iter.next().map(|x| x.to_string());
+                if let Some(ref mut entry) = current {
+                    entry.2 = value;
+                }
+            }
+            "account" => {
+                iter.next();
+            }
+            _ => {}
+        }
+    }
+    finish(current.take());
+    rv.or(default)
+}
+
+#[test]
+fn test_parse_netrc() {
+    let netrc = r#"
+machine pypi.internal.example.com
+  login ci
+  password secret
+
+macdef init
+  machine pypi.internal.example.com login wrong password wrong
+
+machine other.example.com login other password other2
+default login anonymous password guest
+"#;
+    assert_eq!(
+        parse_netrc(netrc, "pypi.internal.example.com"),
+        Some((Some("ci".into()), "secret".into()))
+    );
+    assert_eq!(
+        parse_netrc(netrc, "other.example.com"),
+        Some((Some("other".into()), "other2".into()))
+    );
+    assert_eq!(
+        parse_netrc(netrc, "unknown.example.com"),
+        Some((Some("anonymous".into()), "guest".into()))
+    );
+}
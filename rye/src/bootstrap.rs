@@ -14,14 +14,23 @@ use tempfile::NamedTempFile;
 
 use crate::config::Config;
 use crate::consts::VENV_BIN;
+use crate::logging;
+use crate::network::{
+    check_url, configure_curl, explain_curl_error, install_policy_hook, with_retries,
+    HttpStatusError,
+};
 use crate::platform::{
     get_app_dir, get_canonical_py_path, get_toolchain_python_bin, list_known_toolchains,
     symlinks_supported,
 };
+use crate::remote_cache::RemoteCache;
+use crate::sandbox::install_sandbox_hook;
 use crate::sources::{get_download_url, PythonVersion, PythonVersionRequest};
+use crate::transcript::run_status;
 use crate::utils::{
     check_checksum, set_proxy_variables, symlink_file, unpack_archive, CommandOutput,
 };
+use crate::warnings::{warn_with_output, Warning};
 
 /// this is the target version that we want to fetch
 pub const SELF_PYTHON_TARGET_VERSION: PythonVersionRequest = PythonVersionRequest {
@@ -32,7 +41,13 @@ pub const SELF_PYTHON_TARGET_VERSION: PythonVersionRequest = PythonVersionReques
     suffix: None,
 };
 
-const SELF_VERSION: u64 = 3;
+const SELF_VERSION: u64 = 5;
+
+/// The version of pip in the internal virtualenvs.
+///
+/// The sitecustomize hook of pip-compile patches internals of pip, so pip is
+/// pinned to the version that the hook was written against.
+pub const PIP_VERSION: &str = "23.1.2";
 
 const SELF_REQUIREMENTS: &str = r#"
 build==0.10.0
@@ -48,6 +63,7 @@ platformdirs==3.4.0
 pyproject_hooks==1.0.0
 requests==2.29.0
 tomli==2.0.1
+trove-classifiers==2024.7.2
 twine==4.0.2
 unearth==0.9.0
 urllib3==1.26.15
@@ -73,6 +89,8 @@ pub fn ensure_self_venv(output: CommandOutput) -> Result<PathBuf, Error> {
 
     if venv_dir.is_dir() {
         if is_up_to_date() {
+            install_policy_hook(&venv_dir)?;
+            install_sandbox_hook(&venv_dir)?;
             return Ok(venv_dir);
         } else {
             if output != CommandOutput::Quiet {
@@ -119,8 +137,7 @@ pub fn ensure_self_venv(output: CommandOutput) -> Result<PathBuf, Error> {
     venv_cmd.arg(&venv_dir);
     set_proxy_variables(&mut venv_cmd);
 
-    let status = venv_cmd
-        .status()
+    let status = run_status(&mut venv_cmd)
         .with_context(|| format!("unable to create self venv using {}", py_bin.display()))?;
     if !status.success() {
         bail!("failed to initialize virtualenv in {}", venv_dir.display());
@@ -130,6 +147,8 @@ pub fn ensure_self_venv(output: CommandOutput) -> Result<PathBuf, Error> {
 
     fs::write(venv_dir.join("tool-version.txt"), SELF_VERSION.to_string())?;
     FORCED_TO_UPDATE.store(true, atomic::Ordering::Relaxed);
+    install_policy_hook(&venv_dir)?;
+    install_sandbox_hook(&venv_dir)?;
 
     Ok(venv_dir)
 }
@@ -143,16 +162,14 @@ fn do_update(output: CommandOutput, venv_dir: &Path, app_dir: &Path) -> Result<(
     let mut pip_install_cmd = Command::new(venv_bin.join("pip"));
     pip_install_cmd.arg("install");
     pip_install_cmd.arg("--upgrade");
-    pip_install_cmd.arg("pip");
+    pip_install_cmd.arg(format!("pip=={}", PIP_VERSION));
     if output == CommandOutput::Verbose {
         pip_install_cmd.arg("--verbose");
     } else {
         pip_install_cmd.arg("--quiet");
         pip_install_cmd.env("PYTHONWARNINGS", "ignore");
     }
-    let status = pip_install_cmd
-        .status()
-        .context("unable to self-upgrade pip")?;
+    let status = run_status(&mut pip_install_cmd).context("unable to self-upgrade pip")?;
     if !status.success() {
         bail!("failed to initialize virtualenv (upgrade pip)");
     }
@@ -173,9 +190,7 @@ fn do_update(output: CommandOutput, venv_dir: &Path, app_dir: &Path) -> Result<(
         pip_install_cmd.env("PYTHONWARNINGS", "ignore");
     }
     set_proxy_variables(&mut pip_install_cmd);
-    let status = pip_install_cmd
-        .status()
-        .context("unable to install self-dependencies")?;
+    let status = run_status(&mut pip_install_cmd).context("unable to install self-dependencies")?;
     if !status.success() {
         bail!("failed to initialize virtualenv (install dependencies)");
     }
@@ -236,6 +251,11 @@ pub fn get_pip_runner(venv: &Path) -> Result<PathBuf, Error> {
 
 /// Returns the pip module for the self venv
 pub fn get_pip_module(venv: &Path) -> Result<PathBuf, Error> {
+    Ok(get_site_packages(venv)?.join("pip"))
+}
+
+/// Returns the site-packages folder of a venv
+pub fn get_site_packages(venv: &Path) -> Result<PathBuf, Error> {
     let mut rv = venv.to_path_buf();
     rv.push("lib");
     #[cfg(windows)]
@@ -271,10 +291,36 @@ pub fn get_pip_module(venv: &Path) -> Result<PathBuf, Error> {
             bail!("no site-packages in venv");
         }
     }
-    rv.push("pip");
     Ok(rv)
 }
 
+/// Installs a module into a venv that is imported on startup by a `.pth` file.
+///
+/// Without `source` the module is removed again, so that a venv does not keep
+/// a hook once the feature that needs it is turned off.
+pub fn update_site_hook(venv: &Path, module: &str, source: Option<&str>) -> Result<(), Error> {
+    let site_packages = get_site_packages(venv)?;
+    let hook = site_packages.join(format!("{}.py", module));
+    let pth = site_packages.join(format!("{}.pth", module));
+    match source {
+        Some(source) => {
+            if fs::read_to_string(&hook).ok().as_deref() != Some(source) || !pth.is_file() {
+                fs::write(&hook, source)?;
+                fs::write(&pth, format!("import {}\n", module))?;
+            }
+        }
+        None => {
+            for path in [&pth, &hook] {
+                if path.is_file() {
+                    fs::remove_file(path)
+                        .with_context(|| format!("failed to remove {}", path.display()))?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 /// we only support cpython 3.9 to 3.11
 pub fn is_self_compatible_toolchain(version: &PythonVersion) -> bool {
     version.kind == "cpython" && version.major == 3 && version.minor >= 9 && version.minor < 12
@@ -312,6 +358,13 @@ pub fn fetch(
         }
     }
 
+    if Config::current().offline() {
+        bail!(
+            "Python version {} is not installed and downloads are disabled in offline mode",
+            version
+        );
+    }
+
     let (version, url, sha256) = match get_download_url(version, OS, ARCH) {
         Some(result) => result,
         None => bail!("unknown version {}", version),
@@ -332,13 +385,34 @@ pub fn fetch(
     fs::create_dir_all(&target_dir)
         .with_context(|| format!("failed to create target folder {}", target_dir.display()))?;
 
+    let _phase = logging::phase("fetch", "fetching toolchain");
+    logging::debug(
+        "fetch",
+        format_args!("downloading {} from {}", version, url),
+    );
     if output == CommandOutput::Verbose {
         eprintln!("download url: {}", url);
     }
     if output != CommandOutput::Quiet {
         eprintln!("{} {}", style("Downloading").cyan(), version);
     }
-    let archive_buffer = download_url(url, output)?;
+    let remote_cache = RemoteCache::current()?;
+    let cache_key = format!("toolchains/{}", url.rsplit('/').next().unwrap_or(url));
+    let (archive_buffer, from_cache) = match remote_cache
+        .as_ref()
+        .and_then(|x| x.fetch_file(&cache_key, output))
+    {
+        Some(buffer) => (buffer, true),
+        None => (download_url(url, output)?, false),
+    };
+    logging::debug(
+        "fetch",
+        format_args!(
+            "got {} bytes{}",
+            archive_buffer.len(),
+            if from_cache { " from remote cache" } else { "" }
+        ),
+    );
 
     if let Some(sha256) = sha256 {
         if output != CommandOutput::Quiet {
@@ -350,9 +424,19 @@ pub fn fetch(
         eprintln!("Checksum check skipped (no hash available)");
     }
 
+    logging::debug(
+        "fetch",
+        format_args!("unpacking into {}", target_dir.display()),
+    );
     unpack_archive(&archive_buffer, &target_dir, 1)
         .with_context(|| format!("unpacking of downloaded tarball {} failed", &url))?;
 
+    if let (false, Some(remote_cache)) = (from_cache, remote_cache) {
+        if let Err(err) = remote_cache.store_file(&cache_key, &archive_buffer, output) {
+            warn_with_output(Warning::RemoteCache, err, output);
+        }
+    }
+
     if output != CommandOutput::Quiet {
         eprintln!("{} Downloaded {}", style("success:").green(), version);
     }
@@ -373,19 +457,56 @@ pub fn download_url_ignore_404(url: &str, output: CommandOutput) -> Result<Optio
         bail!("Refusing insecure download");
     }
 
+    check_url(url, "a download")?;
+    with_retries(output, || download_once(url, output))
+}
+
+fn download_once(url: &str, output: CommandOutput) -> Result<Option<Vec<u8>>, Error> {
     let config = Config::current();
     let mut archive_buffer = Vec::new();
     let mut handle = curl::easy::Easy::new();
-    handle.url(url)?;
+    let mut url = url.to_string();
+    handle.url(&url)?;
     handle.progress(true)?;
-    handle.follow_location(true)?;
 
-    // we only do https requests here, so we always set an https proxy
-    if let Some(proxy) = config.https_proxy_url() {
-        handle.proxy(&proxy)?;
+    // with a network policy redirects are followed by hand, so that the
+    // host they point to can be checked before it is contacted.
+    let restricted = config.network_allow().is_some();
+    handle.follow_location(!restricted)?;
+
+    configure_curl(&mut handle, &url)?;
+
+    for _ in 0..10 {
+        archive_buffer.clear();
+        perform_download(&mut handle, &mut archive_buffer, &url, output)?;
+        match handle.redirect_url()? {
+            Some(target) if restricted => {
+                logging::trace("fetch", format_args!("{} redirects to {}", url, target));
+                check_url(target, "a download")?;
+                url = target.to_string();
+                handle.url(&url)?;
+            }
+            _ => break,
+        }
+    }
+    let code = handle.response_code()?;
+    logging::debug("fetch", format_args!("GET {} returned {}", url, code));
+    if code == 404 {
+        Ok(None)
+    } else if !(200..300).contains(&code) {
+        Err(HttpStatusError { url, code }.into())
+    } else {
+        Ok(Some(archive_buffer))
     }
+}
 
-    let write_archive = &mut archive_buffer;
+fn perform_download(
+    handle: &mut curl::easy::Easy,
+    archive_buffer: &mut Vec<u8>,
+    url: &str,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let write_archive = archive_buffer;
     {
         let mut transfer = handle.transfer();
         let mut pb = None;
@@ -418,16 +539,9 @@ pub fn download_url_ignore_404(url: &str, output: CommandOutput) -> Result<Optio
         })?;
         transfer
             .perform()
-            .with_context(|| format!("download of {} failed", &url))?;
-    }
-    let code = handle.response_code()?;
-    if code == 404 {
-        Ok(None)
-    } else if !(200..300).contains(&code) {
-        bail!("Failed to download: {}", code)
-    } else {
-        Ok(Some(archive_buffer))
+            .map_err(|err| explain_curl_error(err, url))?;
     }
+    Ok(())
 }
 
 #[cfg(target_os = "linux")]
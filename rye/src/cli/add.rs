@@ -1,21 +1,36 @@
-use std::env;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str::FromStr;
+use std::{env, fs};
 
 use anyhow::{anyhow, bail, Context, Error};
-use clap::{Parser, ValueEnum};
+use clap::{ArgAction, Parser, ValueEnum};
 use console::style;
 use pep440_rs::{Operator, Version, VersionSpecifier, VersionSpecifiers};
 use pep508_rs::{Requirement, VersionOrUrl};
 use serde::Deserialize;
 use url::Url;
 
-use crate::bootstrap::ensure_self_venv;
+use crate::bootstrap::{ensure_self_venv, fetch};
+use crate::cli::search::{fetch_project_info, search_packages};
 use crate::config::Config;
 use crate::consts::VENV_BIN;
-use crate::pyproject::{BuildSystem, DependencyKind, ExpandedSources, PyProject};
-use crate::utils::{format_requirement, set_proxy_variables, CommandOutput};
+use crate::lock::{
+    locked_versions, update_single_project_lockfile, update_workspace_lockfile, LockFailed,
+    LockMode, LockOptions,
+};
+use crate::pyproject::{
+    normalize_package_name, BuildSystem, DependencyKind, ExpandedSources, PyProject,
+};
+use crate::utils::{
+    ensure_interactive, format_requirement, is_noninteractive, set_proxy_variables, CommandOutput,
+};
+use crate::warnings::{warn, warn_with_output, Warning};
+
+/// How often a conflict is checked again after the user resolved it.
+const MAX_CONFLICT_ROUNDS: usize = 5;
 
 const PACKAGE_FINDER_SCRIPT: &str = r#"
 import sys
@@ -44,9 +59,9 @@ print(json.dumps([x.as_json() for x in choices]))
 "#;
 
 #[derive(Deserialize, Debug)]
-struct Match {
-    name: String,
-    version: Option<String>,
+pub struct Match {
+    pub name: String,
+    pub version: Option<String>,
     link: Option<Link>,
 }
 
@@ -96,14 +111,18 @@ enum Pin {
     TildeEqual,
     #[value(alias = ">=", alias = "ge", alias = "gte")]
     GreaterThanEqual,
+    #[value(alias = "unbounded", alias = "*")]
+    None,
 }
 
-impl From<Pin> for Operator {
-    fn from(value: Pin) -> Self {
-        match value {
-            Pin::Equal => Operator::Equal,
-            Pin::TildeEqual => Operator::TildeEqual,
-            Pin::GreaterThanEqual => Operator::GreaterThanEqual,
+impl Pin {
+    /// Returns the operator for the version bound or `None` if unbounded.
+    fn operator(self) -> Option<Operator> {
+        match self {
+            Pin::Equal => Some(Operator::Equal),
+            Pin::TildeEqual => Some(Operator::TildeEqual),
+            Pin::GreaterThanEqual => Some(Operator::GreaterThanEqual),
+            Pin::None => None,
         }
     }
 }
@@ -183,7 +202,22 @@ impl ReqExtras {
 #[derive(Parser, Debug)]
 pub struct Args {
     /// The package to add as PEP 508 requirement string. e.g. 'flask==2.2.3'
+    ///
+    /// With `--interactive` these are search queries instead.
     requirements: Vec<String>,
+    /// Add the requirements from this requirements file.
+    #[arg(
+        short = 'r',
+        long = "requirements",
+        value_name = "PATH",
+        conflicts_with_all = ["git", "url", "path", "interactive"]
+    )]
+    requirements_file: Vec<PathBuf>,
+    /// Bound unversioned requirements to the versions pinned in this lockfile.
+    ///
+    /// This can be a Rye lockfile or the output of `pip freeze`.
+    #[arg(long, value_name = "LOCKFILE")]
+    pin_from: Option<PathBuf>,
     #[command(flatten)]
     req_extras: ReqExtras,
     /// Add this as dev dependency.
@@ -195,15 +229,29 @@ pub struct Args {
     /// Add this to an optional dependency group.
     #[arg(long, conflicts_with = "dev", conflicts_with = "excluded")]
     optional: Option<String>,
+    /// Add this to a dependency group (PEP 735).
+    #[arg(
+        long,
+        conflicts_with = "dev",
+        conflicts_with = "excluded",
+        conflicts_with = "optional"
+    )]
+    group: Option<String>,
     /// Include pre-releases when finding a package version.
     #[arg(long)]
     pre: bool,
     /// Overrides the pin operator
-    #[arg(long)]
+    #[arg(long, visible_alias = "bounds")]
     pin: Option<Pin>,
-    /// Enables verbose diagnostics.
-    #[arg(short, long)]
-    verbose: bool,
+    /// Search for the packages and pick the version and extras interactively.
+    #[arg(short, long, conflicts_with = "excluded")]
+    interactive: bool,
+    /// Do not check if the added packages conflict with the locked dependencies.
+    #[arg(long)]
+    no_conflict_check: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
     /// Turns off all output.
     #[arg(short, long, conflicts_with = "verbose")]
     quiet: bool,
@@ -217,6 +265,7 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     python_path.push("python");
 
     let mut pyproject_toml = PyProject::discover()?;
+    let original = fs::read_to_string(pyproject_toml.toml_path())?;
     let py_ver = match pyproject_toml.target_python_version() {
         Some(ver) => ver.format_simple(),
         None => "".to_string(),
@@ -227,21 +276,96 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         DependencyKind::Excluded
     } else if let Some(ref section) = cmd.optional {
         DependencyKind::Optional(section.into())
+    } else if let Some(ref group) = cmd.group {
+        DependencyKind::Group(group.into())
     } else {
         DependencyKind::Normal
     };
     let default_operator = match cmd.pin {
-        Some(pin) => Operator::from(pin),
+        Some(pin) => pin.operator(),
         None => Config::current().default_dependency_operator(),
     };
 
-    for str_requirement in cmd.requirements {
+    let mut requirements = if cmd.interactive {
+        ensure_interactive("rye add --interactive")?;
+        cmd.requirements
+            .iter()
+            .map(|query| pick_requirement(query, cmd.pre, default_operator.as_ref(), output))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        cmd.requirements
+    };
+    let mut seen = HashSet::new();
+    for path in &cmd.requirements_file {
+        read_requirements_file(path, &mut requirements, &mut seen)?;
+    }
+    let locked = match cmd.pin_from {
+        Some(ref path) => locked_versions(
+            &fs::read_to_string(path)
+                .with_context(|| format!("failed to read {}", path.display()))?,
+        ),
+        None => Default::default(),
+    };
+
+    for str_requirement in requirements {
         let mut requirement = Requirement::from_str(&str_requirement)?;
         cmd.req_extras.apply_to_requirement(&mut requirement)?;
 
+        // other members of the workspace are not looked up on the index.  They
+        // are installed from the workspace and the version bound is what gets
+        // published.
+        if let Some(member) = match pyproject_toml.workspace() {
+            Some(workspace) if !cmd.excluded => workspace.get_project(&requirement.name)?,
+            _ => None,
+        } {
+            if member.normalized_name()? == pyproject_toml.normalized_name()? {
+                bail!("a project cannot depend on itself");
+            }
+            requirement.name = member.name().unwrap_or(&requirement.name).to_string();
+            // members with a dynamic version are added without a bound
+            let member_version = member.static_version()?;
+            match (&default_operator, &requirement.version_or_url) {
+                (_, Some(VersionOrUrl::Url(_))) => {
+                    warn(
+                        Warning::MemberUrlDependency,
+                        format_args!(
+                            "{} is a member of the workspace and is installed from it \
+                             without a URL",
+                            requirement.name
+                        ),
+                    );
+                    requirement.version_or_url = match (&default_operator, member_version) {
+                        (Some(default_operator), Some(version)) => {
+                            Some(make_version_bound(default_operator, version)?)
+                        }
+                        _ => None,
+                    };
+                }
+                (Some(default_operator), None) => {
+                    if let Some(version) = member_version {
+                        requirement.version_or_url =
+                            Some(make_version_bound(default_operator, version)?);
+                    }
+                }
+                _ => {}
+            }
+            pyproject_toml.add_dependency(&requirement, &dep_kind)?;
+            added.push(requirement);
+            continue;
+        }
+
         // if we are excluding, we do not want a specific dependency version
         // stored, so we just skip the unearth step
         if !cmd.excluded {
+            if let (Some(default_operator), None, Some(Some(version))) = (
+                &default_operator,
+                &requirement.version_or_url,
+                locked.get(&normalize_package_name(&requirement.name)),
+            ) {
+                let version = Version::from_str(version)
+                    .map_err(|msg| anyhow!("invalid version: {}", msg))?;
+                requirement.version_or_url = Some(make_version_bound(default_operator, version)?);
+            }
             let matches = find_best_matches(
                 &pyproject_toml,
                 &python_path,
@@ -266,11 +390,13 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
                         )
                         .unwrap_or_default();
                         if let Some(pre) = all_pre_matches.into_iter().next() {
-                            eprintln!(
-                                "{}: {} ({}) was found considering pre-releases.  Pass --pre to allow use.",
-                                style("warning").red(),
-                                pre.name,
-                                pre.version.unwrap_or_default()
+                            warn(
+                                Warning::PreReleaseOnly,
+                                format_args!(
+                                    "{} ({}) was found considering pre-releases.  Pass --pre to allow use.",
+                                    pre.name,
+                                    pre.version.unwrap_or_default()
+                                ),
                             );
                         }
                         bail!(
@@ -307,28 +433,12 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
             }
 
             let m = matches.into_iter().next().unwrap();
-            if m.version.is_some() && requirement.version_or_url.is_none() {
-                let version = Version::from_str(m.version.as_ref().unwrap())
+            if let (Some(default_operator), Some(version), None) =
+                (&default_operator, &m.version, &requirement.version_or_url)
+            {
+                let version = Version::from_str(version)
                     .map_err(|msg| anyhow!("invalid version: {}", msg))?;
-                requirement.version_or_url = Some(VersionOrUrl::VersionSpecifier(
-                    VersionSpecifiers::from_iter(Some(
-                        VersionSpecifier::new(
-                            // local versions or versions with only one component cannot
-                            // use ~= but need to use ==.
-                            match default_operator {
-                                _ if version.is_local() => Operator::Equal,
-                                Operator::TildeEqual if version.release.len() < 2 => {
-                                    Operator::GreaterThanEqual
-                                }
-                                ref other => other.clone(),
-                            },
-                            Version::from_str(m.version.as_ref().unwrap())
-                                .map_err(|msg| anyhow!("invalid version: {}", msg))?,
-                            false,
-                        )
-                        .map_err(|msg| anyhow!("invalid version specifier: {}", msg))?,
-                    )),
-                ));
+                requirement.version_or_url = Some(make_version_bound(default_operator, version)?);
             }
             requirement.name = m.name;
         }
@@ -339,6 +449,37 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
 
     pyproject_toml.save()?;
 
+    // conflicts are only looked for if there is a lock to conflict with and
+    // someone is around to resolve them.
+    if !cmd.excluded
+        && !cmd.no_conflict_check
+        && !is_noninteractive()
+        && console::user_attended()
+        && pyproject_toml.venv_path().is_dir()
+        && pyproject_toml
+            .workspace_path()
+            .join("requirements-dev.lock")
+            .is_file()
+    {
+        let keep = resolve_conflicts(
+            &mut pyproject_toml,
+            &mut added,
+            &dep_kind,
+            &python_path,
+            &py_ver,
+            cmd.pre,
+            default_operator.as_ref(),
+            output,
+        )?;
+        if !keep {
+            fs::write(pyproject_toml.toml_path(), original)?;
+            if output != CommandOutput::Quiet {
+                eprintln!("Reverted the changes to pyproject.toml");
+            }
+            return Ok(());
+        }
+    }
+
     if output != CommandOutput::Quiet {
         for ref requirement in added {
             println!(
@@ -352,7 +493,377 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     Ok(())
 }
 
-fn find_best_matches(
+/// A cause of a resolution conflict as reported by pip.
+#[derive(Debug, PartialEq)]
+enum Conflict {
+    /// A requirement declared by the project.
+    Requested(Requirement),
+    /// A requirement of a candidate package.
+    DependsOn {
+        name: String,
+        version: String,
+        requirement: Requirement,
+    },
+}
+
+/// A way to resolve a conflict that is offered to the user.
+enum Resolution {
+    /// Drops the version bound of a declared dependency.
+    Relax(Requirement, DependencyKind<'static>),
+    /// Picks an older version of the added package with the given index.
+    Older(usize),
+    /// Overrides a dependency of other packages.
+    Override(Requirement),
+    /// Keeps the changes even though locking will fail.
+    Keep,
+    /// Reverts the changes to the pyproject.toml.
+    Undo,
+}
+
+/// Extracts the causes of a resolution conflict from the output of pip-compile.
+fn parse_conflicts(log: &str) -> Vec<Conflict> {
+    let mut rv = Vec::new();
+    let lines = log
+        .lines()
+        .map(|x| x.trim())
+        .skip_while(|x| *x != "The conflict is caused by:")
+        .skip(1);
+    for line in lines {
+        if line.is_empty() {
+            break;
+        }
+        if let Some(req) = line.strip_prefix("The user requested ") {
+            let req = req.strip_prefix("(constraint) ").unwrap_or(req);
+            if let Ok(req) = Requirement::from_str(req) {
+                rv.push(Conflict::Requested(req));
+            }
+        } else if let Some((package, req)) = line.split_once(" depends on ") {
+            // pip joins the version specifiers with "and"
+            let req = req.replace(" and ", ",");
+            if let (Some((name, version)), Ok(requirement)) =
+                (package.split_once(' '), Requirement::from_str(&req))
+            {
+                rv.push(Conflict::DependsOn {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                    requirement,
+                });
+            }
+        }
+    }
+    rv
+}
+
+/// Locks the project into a scratch lockfile to find conflicts.
+///
+/// The dev lockfile is the starting point so that pinned versions are kept
+/// where possible.
+fn check_lock(toml_path: &Path) -> Result<(), Error> {
+    let pyproject = PyProject::load(toml_path)?;
+    let py_ver = fetch(
+        &pyproject.venv_python_version()?.into(),
+        CommandOutput::Quiet,
+    )?;
+    let sources = ExpandedSources::from_sources(&pyproject.sources()?)?;
+    let scratch = tempfile::tempdir()?;
+    let lockfile = scratch.path().join("requirements-dev.lock");
+    fs::copy(
+        pyproject.workspace_path().join("requirements-dev.lock"),
+        &lockfile,
+    )?;
+    let lock_options = LockOptions {
+        all_features: true,
+        ..Default::default()
+    };
+    match pyproject.workspace() {
+        Some(workspace) => update_workspace_lockfile(
+            &py_ver,
+            workspace,
+            LockMode::Dev,
+            &lockfile,
+            CommandOutput::Quiet,
+            &sources,
+            &lock_options,
+        ),
+        None => update_single_project_lockfile(
+            &py_ver,
+            &pyproject,
+            LockMode::Dev,
+            &lockfile,
+            CommandOutput::Quiet,
+            &sources,
+            &lock_options,
+        ),
+    }
+}
+
+/// Finds the declared dependencies involved in a conflict that have a
+/// version bound which could be dropped.
+fn find_relaxable(
+    pyproject: &PyProject,
+    names: &HashSet<String>,
+    added: &[Requirement],
+) -> Vec<(Requirement, DependencyKind<'static>)> {
+    let mut kinds = vec![DependencyKind::Normal, DependencyKind::Dev];
+    let mut extras = pyproject.extras().into_iter().collect::<Vec<_>>();
+    extras.sort();
+    kinds.extend(
+        extras
+            .into_iter()
+            .map(|x| DependencyKind::Optional(Cow::Owned(x.to_string()))),
+    );
+
+    let mut rv = Vec::new();
+    for kind in kinds {
+        for dep in pyproject.iter_dependencies(kind.clone()) {
+            let req = match dep.expand(|name: &str| env::var(name).ok()) {
+                Ok(req) => req,
+                Err(_) => continue,
+            };
+            let name = normalize_package_name(&req.name);
+            if !names.contains(&name)
+                || added
+                    .iter()
+                    .any(|x| normalize_package_name(&x.name) == name)
+                || !matches!(req.version_or_url, Some(VersionOrUrl::VersionSpecifier(_)))
+            {
+                continue;
+            }
+            rv.push((req, kind.clone()));
+        }
+    }
+    rv
+}
+
+/// Checks if the added dependencies can be locked and lets the user resolve
+/// conflicts with the existing dependencies.
+///
+/// Returns `false` if the user decided to undo the changes.
+#[allow(clippy::too_many_arguments)]
+fn resolve_conflicts(
+    pyproject: &mut PyProject,
+    added: &mut [Requirement],
+    dep_kind: &DependencyKind,
+    python_path: &PathBuf,
+    py_ver: &str,
+    pre: bool,
+    default_operator: Option<&Operator>,
+    output: CommandOutput,
+) -> Result<bool, Error> {
+    for _ in 0..MAX_CONFLICT_ROUNDS {
+        let log = match check_lock(&pyproject.toml_path()) {
+            Ok(()) => return Ok(true),
+            Err(err) => match err.downcast::<LockFailed>() {
+                Ok(failed) => failed.log,
+                Err(err) => {
+                    warn_with_output(
+                        Warning::ConflictCheckFailed,
+                        format_args!("could not check for conflicts: {:#}", err),
+                        output,
+                    );
+                    return Ok(true);
+                }
+            },
+        };
+
+        let conflicts = parse_conflicts(&log);
+        warn(
+            Warning::DependencyConflict,
+            "the added dependencies conflict with the dependencies of the project",
+        );
+        if conflicts.is_empty() {
+            eprintln!("{}", log.trim_end());
+        }
+        let mut names = HashSet::new();
+        for conflict in &conflicts {
+            match conflict {
+                Conflict::Requested(req) => {
+                    eprintln!("  the project requires {}", style(req).yellow());
+                    names.insert(normalize_package_name(&req.name));
+                }
+                Conflict::DependsOn {
+                    name,
+                    version,
+                    requirement,
+                } => {
+                    eprintln!(
+                        "  {} {} requires {}",
+                        name,
+                        version,
+                        style(requirement).yellow()
+                    );
+                    names.insert(normalize_package_name(name));
+                    names.insert(normalize_package_name(&requirement.name));
+                }
+            }
+        }
+
+        let mut choices = Vec::new();
+        for (req, kind) in find_relaxable(pyproject, &names, added) {
+            let mut relaxed = req.clone();
+            relaxed.version_or_url = None;
+            choices.push((
+                format!(
+                    "Relax {} to {} ({} dependency)",
+                    format_requirement(&req),
+                    format_requirement(&relaxed),
+                    kind
+                ),
+                Resolution::Relax(relaxed, kind),
+            ));
+        }
+        for (idx, req) in added.iter().enumerate() {
+            if names.contains(&normalize_package_name(&req.name))
+                && !matches!(req.version_or_url, Some(VersionOrUrl::Url(_)))
+            {
+                choices.push((
+                    format!("Pick an older version of {}", req.name),
+                    Resolution::Older(idx),
+                ));
+            }
+        }
+        // overrides are a workspace wide setting
+        if pyproject.is_workspace_root() {
+            let mut seen = HashSet::new();
+            for conflict in &conflicts {
+                if let Conflict::Requested(req) = conflict {
+                    let name = normalize_package_name(&req.name);
+                    let depended_on = conflicts.iter().any(|x| {
+                        matches!(x, Conflict::DependsOn { requirement, .. }
+                            if normalize_package_name(&requirement.name) == name)
+                    });
+                    if depended_on && seen.insert(name) {
+                        choices.push((
+                            format!(
+                                "Override the dependencies on {} with {}",
+                                req.name,
+                                format_requirement(req)
+                            ),
+                            Resolution::Override(req.clone()),
+                        ));
+                    }
+                }
+            }
+        }
+        choices.push(("Keep the changes anyway".into(), Resolution::Keep));
+        choices.push(("Undo the changes".into(), Resolution::Undo));
+
+        let picked = dialoguer::Select::new()
+            .with_prompt("How do you want to resolve the conflict?")
+            .items(&choices.iter().map(|x| &x.0).collect::<Vec<_>>())
+            .default(0)
+            .interact()?;
+        match choices.swap_remove(picked).1 {
+            Resolution::Relax(req, kind) => pyproject.add_dependency(&req, &kind)?,
+            Resolution::Older(idx) => {
+                let mut bare = added[idx].clone();
+                bare.version_or_url = None;
+                let versions = find_best_matches(pyproject, python_path, Some(py_ver), &bare, pre)?
+                    .into_iter()
+                    .filter_map(|x| x.version)
+                    .take(20)
+                    .collect::<Vec<_>>();
+                if versions.is_empty() {
+                    bail!("did not find other versions of {}", bare.name);
+                }
+                let picked = dialoguer::Select::new()
+                    .with_prompt(format!("Select a version of {}", bare.name))
+                    .items(&versions)
+                    .default(versions.len().min(2) - 1)
+                    .interact()?;
+                let version = Version::from_str(&versions[picked])
+                    .map_err(|msg| anyhow!("invalid version: {}", msg))?;
+                added[idx].version_or_url = Some(make_version_bound(
+                    default_operator.unwrap_or(&Operator::Equal),
+                    version,
+                )?);
+                pyproject.add_dependency(&added[idx], dep_kind)?;
+            }
+            Resolution::Override(req) => pyproject.add_override(&req)?,
+            Resolution::Keep => return Ok(true),
+            Resolution::Undo => return Ok(false),
+        }
+        pyproject.save()?;
+    }
+
+    warn(
+        Warning::UnresolvedConflict,
+        "the conflict is not resolved, locking will fail",
+    );
+    Ok(true)
+}
+
+/// Creates the version bound that is written for a version of a dependency.
+fn make_version_bound(operator: &Operator, version: Version) -> Result<VersionOrUrl, Error> {
+    // local versions or versions with only one component cannot
+    // use ~= but need to use ==.
+    let operator = match operator {
+        _ if version.is_local() => Operator::Equal,
+        Operator::TildeEqual if version.release.len() < 2 => Operator::GreaterThanEqual,
+        other => other.clone(),
+    };
+    Ok(VersionOrUrl::VersionSpecifier(
+        VersionSpecifiers::from_iter(Some(
+            VersionSpecifier::new(operator, version, false)
+                .map_err(|msg| anyhow!("invalid version specifier: {}", msg))?,
+        )),
+    ))
+}
+
+/// Searches for a package and lets the user pick the version and extras.
+///
+/// Returns the picked requirement.  The version is only asked for if
+/// dependencies are added with a version bound.
+fn pick_requirement(
+    query: &str,
+    pre: bool,
+    operator: Option<&Operator>,
+    output: CommandOutput,
+) -> Result<String, Error> {
+    let results = search_packages(query, 10, false, output)?;
+    if results.is_empty() {
+        bail!("did not find any package matching '{}'", query);
+    }
+    let items = results
+        .iter()
+        .map(|x| format!("{} ({}) {}", x.name, x.version, style(&x.summary).dim()))
+        .collect::<Vec<_>>();
+    let picked = dialoguer::Select::new()
+        .with_prompt(format!("Select a package for '{}'", query))
+        .items(&items)
+        .default(0)
+        .interact()?;
+    let info = fetch_project_info(&results[picked].name)?
+        .ok_or_else(|| anyhow!("package '{}' disappeared", results[picked].name))?;
+
+    let mut requirement = Requirement::from_str(info.name())?;
+    let extras = info.extras();
+    if !extras.is_empty() {
+        let picked = dialoguer::MultiSelect::new()
+            .with_prompt("Select extras (space to toggle)")
+            .items(&extras)
+            .interact()?;
+        if !picked.is_empty() {
+            requirement.extras = Some(picked.into_iter().map(|x| extras[x].clone()).collect());
+        }
+    }
+    if let Some(operator) = operator {
+        let mut versions = info.versions(pre);
+        versions.truncate(20);
+        if !versions.is_empty() {
+            let picked = dialoguer::Select::new()
+                .with_prompt("Select a version")
+                .items(&versions)
+                .default(0)
+                .interact()?;
+            requirement.version_or_url =
+                Some(make_version_bound(operator, versions.swap_remove(picked))?);
+        }
+    }
+    Ok(requirement.to_string())
+}
+
+pub fn find_best_matches(
     pyproject: &PyProject,
     python_path: &PathBuf,
     py_ver: Option<&str>,
@@ -384,3 +895,148 @@ fn find_best_matches(
         );
     }
 }
+
+/// A logical line of a requirements file.
+#[derive(Debug, PartialEq)]
+enum RequirementsLine {
+    Requirement(String),
+    Include(String),
+    Unsupported(String),
+}
+
+/// Splits a requirements file into its logical lines.
+///
+/// Continuation lines are joined and comments are removed, as are the
+/// per-requirement options (such as `--hash`) that only matter to pip.
+fn parse_requirements_file(contents: &str) -> Vec<RequirementsLine> {
+    let mut rv = Vec::new();
+    let mut lines = contents.lines();
+    while let Some(line) = lines.next() {
+        let mut line = line.to_string();
+        while line.ends_with('\\') {
+            line.pop();
+            line.push_str(lines.next().unwrap_or(""));
+        }
+        // a comment starts with a `#` at the beginning or after whitespace
+        let end = line
+            .char_indices()
+            .find(|&(idx, c)| {
+                c == '#' && line[..idx].chars().last().map_or(true, char::is_whitespace)
+            })
+            .map_or(line.len(), |(idx, _)| idx);
+        let line = line[..end].trim();
+        if line.is_empty() {
+            continue;
+        }
+        rv.push(
+            if let Some(path) = line
+                .strip_prefix("--requirement")
+                .or_else(|| line.strip_prefix("-r"))
+            {
+                RequirementsLine::Include(path.trim_start_matches('=').trim().to_string())
+            } else if line.starts_with('-') {
+                RequirementsLine::Unsupported(line.to_string())
+            } else {
+                let requirement = line.find(" --").map_or(line, |idx| line[..idx].trim_end());
+                RequirementsLine::Requirement(requirement.to_string())
+            },
+        );
+    }
+    rv
+}
+
+/// Reads the requirements of a requirements file for `rye add -r`.
+///
+/// Included requirements files are followed.  Options, constraints and
+/// editables cannot be expressed in `pyproject.toml`, so they are skipped
+/// with a warning.
+fn read_requirements_file(
+    path: &Path,
+    requirements: &mut Vec<String>,
+    seen: &mut HashSet<PathBuf>,
+) -> Result<(), Error> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    if !seen.insert(path.canonicalize()?) {
+        return Ok(());
+    }
+    for line in parse_requirements_file(&contents) {
+        match line {
+            RequirementsLine::Requirement(req) if Requirement::from_str(&req).is_ok() => {
+                requirements.push(req);
+            }
+            RequirementsLine::Include(include) => {
+                let base = path.parent().unwrap_or(Path::new("."));
+                read_requirements_file(&base.join(include), requirements, seen)?;
+            }
+            RequirementsLine::Requirement(line) | RequirementsLine::Unsupported(line) => {
+                warn(
+                    Warning::RequirementsFileLine,
+                    format_args!("skipped '{}' from {}", line, path.display()),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_requirements_file() {
+    let contents = "\
+# a comment
+-r base.txt
+--index-url https://example.com/simple
+-e ./libs/foo
+flask[dotenv]>=2.0 ; python_version >= '3.8'  # web
+requests==2.31.0 \\
+    --hash=sha256:abc
+pkg @ https://example.com/pkg-1.0.tar.gz#sha256=def
+";
+    assert_eq!(
+        parse_requirements_file(contents),
+        vec![
+            RequirementsLine::Include("base.txt".into()),
+            RequirementsLine::Unsupported("--index-url https://example.com/simple".into()),
+            RequirementsLine::Unsupported("-e ./libs/foo".into()),
+            RequirementsLine::Requirement("flask[dotenv]>=2.0 ; python_version >= '3.8'".into()),
+            RequirementsLine::Requirement("requests==2.31.0".into()),
+            RequirementsLine::Requirement(
+                "pkg @ https://example.com/pkg-1.0.tar.gz#sha256=def".into()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_parse_conflicts() {
+    let log = "\
+Could not find a version that satisfies the requirement urllib3<2 (from botocore)
+
+The conflict is caused by:
+    The user requested urllib3>=2.2.1
+    The user requested (constraint) idna==3.4
+    botocore 1.29.0 depends on urllib3<1.27 and >=1.25.4
+
+To fix this you could try to:
+1. loosen the range of package versions you've specified
+";
+    let conflicts = parse_conflicts(log);
+    assert_eq!(conflicts.len(), 3);
+    assert_eq!(
+        conflicts[0],
+        Conflict::Requested(Requirement::from_str("urllib3>=2.2.1").unwrap())
+    );
+    assert_eq!(
+        conflicts[1],
+        Conflict::Requested(Requirement::from_str("idna==3.4").unwrap())
+    );
+    assert_eq!(
+        conflicts[2],
+        Conflict::DependsOn {
+            name: "botocore".into(),
+            version: "1.29.0".into(),
+            requirement: Requirement::from_str("urllib3<1.27,>=1.25.4").unwrap(),
+        }
+    );
+    assert!(parse_conflicts("ERROR: something else").is_empty());
+}
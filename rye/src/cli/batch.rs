@@ -0,0 +1,300 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::time::Instant;
+use std::{env, iter};
+
+use anyhow::Error;
+use clap::{ArgGroup, Parser};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::errors::Failure;
+use crate::transcript::redact;
+use crate::utils::{set_noninteractive, QuietExit};
+
+/// JSON-RPC error code for requests that are not valid JSON.
+const PARSE_ERROR: i32 = -32700;
+/// JSON-RPC error code for requests that are not valid requests.
+const INVALID_REQUEST: i32 = -32600;
+/// JSON-RPC error code for unknown methods.
+const METHOD_NOT_FOUND: i32 = -32601;
+/// JSON-RPC error code for invalid parameters (such as unknown arguments).
+const INVALID_PARAMS: i32 = -32602;
+/// JSON-RPC error code for commands that panicked.
+const INTERNAL_ERROR: i32 = -32603;
+/// Error code for commands that ran but failed.
+const COMMAND_FAILED: i32 = 1;
+
+/// Runs many commands in one process, driven by JSON-RPC requests.
+///
+/// Every line of the input is a JSON-RPC 2.0 request and for every request a
+/// response is written as a line to stdout, except for notifications (requests
+/// without an `id`).  The supported methods are `add`, `lock`, `sync` and
+/// `run`.
+#[derive(Parser, Debug)]
+#[command(group = ArgGroup::new("input").required(true))]
+pub struct Args {
+    /// Read the requests from stdin.
+    #[arg(long, group = "input")]
+    stdin: bool,
+    /// Read the requests from this file.
+    #[arg(group = "input")]
+    path: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Request {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Params,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct Params {
+    /// The command line arguments of the command.
+    #[serde(default)]
+    args: Vec<String>,
+    /// The directory to run the command in.
+    #[serde(default)]
+    cwd: Option<PathBuf>,
+}
+
+#[derive(Serialize, Debug)]
+struct Response {
+    jsonrpc: &'static str,
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<ResponseError>,
+}
+
+#[derive(Serialize, Debug)]
+struct ResponseError {
+    code: i32,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<Value>,
+}
+
+impl ResponseError {
+    fn new(code: i32, message: impl Into<String>) -> ResponseError {
+        ResponseError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    // nobody can answer prompts in between the requests
+    set_noninteractive();
+
+    let input: Box<dyn BufRead> = match cmd.path {
+        Some(ref path) if !cmd.stdin => Box::new(BufReader::new(File::open(path)?)),
+        _ => Box::new(io::stdin().lock()),
+    };
+    let mut results = results_output()?;
+    let base_dir = env::current_dir()?;
+
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Ok(value) => {
+                let id = value.get("id").cloned();
+                let is_notification = id.is_none() && value.is_object();
+                let rv = handle_request(value);
+                // the cwd of a request only applies to that request
+                env::set_current_dir(&base_dir)?;
+                if is_notification {
+                    continue;
+                }
+                let id = id.unwrap_or(Value::Null);
+                match rv {
+                    Ok(result) => Response {
+                        jsonrpc: "2.0",
+                        id,
+                        result: Some(result),
+                        error: None,
+                    },
+                    Err(error) => Response {
+                        jsonrpc: "2.0",
+                        id,
+                        result: None,
+                        error: Some(error),
+                    },
+                }
+            }
+            Err(err) => Response {
+                jsonrpc: "2.0",
+                id: Value::Null,
+                result: None,
+                error: Some(ResponseError::new(PARSE_ERROR, err.to_string())),
+            },
+        };
+        serde_json::to_writer(&mut results, &response)?;
+        results.write_all(b"\n")?;
+        results.flush()?;
+    }
+    Ok(())
+}
+
+/// Returns where the responses are written to.
+///
+/// On unix stdout is redirected to stderr so that the output of the commands
+/// and the processes they spawn does not end up between the responses.
+#[cfg(unix)]
+fn results_output() -> Result<Box<dyn Write>, Error> {
+    use nix::unistd::{dup, dup2};
+    use std::os::unix::io::FromRawFd;
+
+    io::stdout().flush()?;
+    let fd = dup(1)?;
+    dup2(2, 1)?;
+    // SAFETY: the file descriptor was just created and is not used elsewhere
+    Ok(Box::new(unsafe { File::from_raw_fd(fd) }))
+}
+
+#[cfg(windows)]
+fn results_output() -> Result<Box<dyn Write>, Error> {
+    Ok(Box::new(io::stdout()))
+}
+
+fn handle_request(value: Value) -> Result<Value, ResponseError> {
+    let request = serde_json::from_value::<Request>(value)
+        .map_err(|err| ResponseError::new(INVALID_REQUEST, err.to_string()))?;
+    if request.jsonrpc != "2.0" {
+        return Err(ResponseError::new(
+            INVALID_REQUEST,
+            "only JSON-RPC 2.0 is supported",
+        ));
+    }
+    if !matches!(request.method.as_str(), "add" | "lock" | "sync" | "run") {
+        return Err(ResponseError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method '{}'", request.method),
+        ));
+    }
+
+    if let Some(ref cwd) = request.params.cwd {
+        env::set_current_dir(cwd).map_err(|err| {
+            ResponseError::new(
+                INVALID_PARAMS,
+                format!("cannot change into {}: {}", cwd.display(), err),
+            )
+        })?;
+    }
+
+    let start = Instant::now();
+    let args = iter::once(request.method.clone())
+        .chain(request.params.args)
+        .collect::<Vec<_>>();
+    let rv = match request.method.as_str() {
+        "add" => run_in_process::<super::add::Args>(&args, super::add::execute),
+        "lock" => run_in_process::<super::lock::Args>(&args, super::lock::execute),
+        "sync" => run_in_process::<super::sync::Args>(&args, super::sync::execute),
+        "run" => run_in_subprocess(&args),
+        _ => unreachable!(),
+    };
+    let duration_ms = start.elapsed().as_millis() as u64;
+
+    match rv {
+        Ok(mut result) => {
+            result["duration_ms"] = json!(duration_ms);
+            Ok(result)
+        }
+        Err(mut err) => {
+            if let Some(Value::Object(ref mut data)) = err.data {
+                data.insert("duration_ms".into(), json!(duration_ms));
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Runs a command in this process.
+///
+/// A panic of the command is reported as an internal error, so that it does
+/// not take down the requests that follow.
+fn run_in_process<A: Parser>(
+    args: &[String],
+    execute: fn(A) -> Result<(), Error>,
+) -> Result<Value, ResponseError> {
+    let cmd = A::try_parse_from(args)
+        .map_err(|err| ResponseError::new(INVALID_PARAMS, err.to_string().trim()))?;
+    match panic::catch_unwind(AssertUnwindSafe(|| execute(cmd))) {
+        Ok(Ok(())) => Ok(json!({ "exit_code": 0 })),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(|x| x.as_str()))
+                .unwrap_or("unknown panic");
+            Err(ResponseError {
+                code: INTERNAL_ERROR,
+                message: redact(&format!("rye panicked: {}", message)),
+                data: Some(json!({ "exit_code": 101 })),
+            })
+        }
+        Ok(Err(err)) => {
+            let failure = Failure::of(&err);
+            let exit_code = match err.downcast_ref::<QuietExit>() {
+                Some(QuietExit(code)) => *code,
+                None => failure.exit_code(),
+            };
+            Err(ResponseError {
+                code: COMMAND_FAILED,
+                message: redact(&format!("{:#}", err)),
+                data: Some(json!({
+                    "exit_code": exit_code,
+                    "error_code": failure.code(),
+                    "hint": failure.hint(),
+                })),
+            })
+        }
+    }
+}
+
+/// Runs `rye run` in a subprocess and captures its output.
+///
+/// Commands are run by replacing the process, so this cannot happen in this
+/// process.
+fn run_in_subprocess(args: &[String]) -> Result<Value, ResponseError> {
+    let output = spawn_rye(args).map_err(|err| {
+        ResponseError::new(
+            COMMAND_FAILED,
+            redact(&format!("failed to run rye: {:#}", err)),
+        )
+    })?;
+    let exit_code = output.status.code().unwrap_or(1);
+    let data = json!({
+        "exit_code": exit_code,
+        "stdout": String::from_utf8_lossy(&output.stdout),
+        "stderr": String::from_utf8_lossy(&output.stderr),
+    });
+    if output.status.success() {
+        Ok(data)
+    } else {
+        Err(ResponseError {
+            code: COMMAND_FAILED,
+            message: format!("command failed with exit code {}", exit_code),
+            data: Some(data),
+        })
+    }
+}
+
+fn spawn_rye(args: &[String]) -> Result<Output, Error> {
+    Ok(Command::new(env::current_exe()?)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?)
+}
@@ -0,0 +1,299 @@
+use std::env::{self, join_paths, split_paths};
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error};
+use clap::{ArgAction, Parser};
+use console::style;
+use pep440_rs::Version;
+use pep508_rs::Requirement;
+
+use crate::bootstrap::ensure_self_venv;
+use crate::cli::add::find_best_matches;
+use crate::consts::VENV_BIN;
+use crate::lock::{group_lockfile_name, LockMode, LockOptions};
+use crate::pylock::pylock_filename;
+use crate::pyproject::PyProject;
+use crate::sync::{sync, SyncMode, SyncOptions};
+use crate::utils::{CommandOutput, QuietExit};
+use crate::warnings::{warn_with_output, Warning};
+
+/// Exit code of the test command that requests skipping a version.
+///
+/// This is the same convention that `git bisect run` uses.
+const SKIP_EXIT_CODE: i32 = 125;
+
+/// Finds the version of a dependency that introduced a failure.
+///
+/// The versions of the package between the good and the bad version are
+/// binary searched.  For every step the project is re-locked with the
+/// package constrained to the version under test and the command is run.
+/// An exit code of 0 marks the version as good, 125 skips it and any other
+/// exit code marks it as bad.  The lockfiles are restored afterwards.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The package to bisect.
+    #[arg(long)]
+    package: String,
+    /// A version of the package that is known to work.
+    #[arg(long)]
+    good: String,
+    /// A version of the package that is known to fail.
+    #[arg(long)]
+    bad: String,
+    /// Include pre-releases when finding versions to test.
+    #[arg(long)]
+    pre: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// The command to run for every version.
+    #[arg(last = true, required = true)]
+    cmd: Vec<OsString>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Good,
+    Bad,
+    Skip,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let good = Version::from_str(&cmd.good).map_err(|msg| anyhow!("invalid version: {}", msg))?;
+    let bad = Version::from_str(&cmd.bad).map_err(|msg| anyhow!("invalid version: {}", msg))?;
+    if good >= bad {
+        bail!("the good version needs to be older than the bad version");
+    }
+
+    let mut versions = find_versions(&pyproject, &cmd.package, &good, &bad, cmd.pre, output)?;
+    if output != CommandOutput::Quiet {
+        eprintln!(
+            "Bisecting {} versions of {} between {} and {}",
+            versions.len(),
+            style(&cmd.package).cyan(),
+            good,
+            bad
+        );
+    }
+
+    // the lockfiles are restored afterwards, the pyproject.toml is never
+    // touched as the version under test is passed as a constraint.
+    let workspace_path = pyproject.workspace_path();
+    let backup = [
+        workspace_path.join("requirements.lock"),
+        workspace_path.join("requirements-dev.lock"),
+        workspace_path.join(pylock_filename(LockMode::Production)),
+        workspace_path.join(pylock_filename(LockMode::Dev)),
+    ]
+    .into_iter()
+    .chain(
+        pyproject
+            .lock_groups()
+            .into_iter()
+            .filter_map(|group| group_lockfile_name(&group).ok())
+            .map(|filename| workspace_path.join(filename)),
+    )
+    .map(|path| {
+        let contents = fs::read(&path).ok();
+        (path, contents)
+    })
+    .collect::<Vec<_>>();
+
+    // conceptually the versions are [good, ..versions, bad] and the
+    // boundaries are never tested.
+    let mut lo = 0;
+    let mut hi = versions.len() + 1;
+    let result = (|| -> Result<(), Error> {
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            let version = &versions[mid - 1];
+            if output != CommandOutput::Quiet {
+                eprintln!(
+                    "Testing {} {} ({} versions left)",
+                    style(&cmd.package).cyan(),
+                    style(version).cyan(),
+                    hi - lo - 1
+                );
+            }
+            match test_version(&cmd, version, output)? {
+                Outcome::Good => lo = mid,
+                Outcome::Bad => hi = mid,
+                Outcome::Skip => {
+                    versions.remove(mid - 1);
+                    hi -= 1;
+                    continue;
+                }
+            }
+            if output != CommandOutput::Quiet {
+                eprintln!(
+                    "{} {} is {}",
+                    cmd.package,
+                    version,
+                    if lo == mid {
+                        style("good").green()
+                    } else {
+                        style("bad").red()
+                    }
+                );
+            }
+        }
+        Ok(())
+    })();
+
+    for (path, contents) in &backup {
+        match contents {
+            Some(contents) => fs::write(path, contents)
+                .with_context(|| format!("failed to restore {}", path.display()))?,
+            None => {
+                fs::remove_file(path).ok();
+            }
+        }
+    }
+    result?;
+
+    if output != CommandOutput::Quiet {
+        eprintln!("Restoring virtualenv");
+    }
+    sync(SyncOptions {
+        output: sync_output(output),
+        dev: true,
+        mode: SyncMode::Regular,
+        no_lock: true,
+        pyproject: cmd.pyproject.clone(),
+        ..SyncOptions::default()
+    })
+    .context("failed to restore virtualenv after bisecting")?;
+
+    let last_good = match lo {
+        0 => good.to_string(),
+        lo => versions[lo - 1].to_string(),
+    };
+    let first_bad = match hi {
+        hi if hi == versions.len() + 1 => bad.to_string(),
+        hi => versions[hi - 1].to_string(),
+    };
+    println!(
+        "First bad version: {} {} (last good version: {})",
+        style(&cmd.package).cyan(),
+        style(first_bad).red(),
+        style(last_good).green()
+    );
+    Ok(())
+}
+
+/// Finds all versions of the package between the good and bad version.
+fn find_versions(
+    pyproject: &PyProject,
+    package: &str,
+    good: &Version,
+    bad: &Version,
+    pre: bool,
+    output: CommandOutput,
+) -> Result<Vec<Version>, Error> {
+    let mut python_path = ensure_self_venv(output).context("error bootstrapping venv")?;
+    python_path.push(VENV_BIN);
+    python_path.push("python");
+    let py_ver = pyproject
+        .target_python_version()
+        .map(|x| x.format_simple())
+        .unwrap_or_default();
+    let requirement = Requirement::from_str(&format!("{}>{},<{}", package, good, bad))?;
+
+    let mut versions = find_best_matches(
+        pyproject,
+        &python_path,
+        Some(py_ver.as_str()),
+        &requirement,
+        pre,
+    )?
+    .into_iter()
+    .filter_map(|m| m.version)
+    .filter_map(|v| Version::from_str(&v).ok())
+    .filter(|v| v > good && v < bad)
+    .collect::<Vec<_>>();
+    versions.sort();
+    versions.dedup();
+    Ok(versions)
+}
+
+/// Locks and syncs with the package pinned to a version and runs the command.
+fn test_version(cmd: &Args, version: &Version, output: CommandOutput) -> Result<Outcome, Error> {
+    let pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let requirement = Requirement::from_str(&format!("{}=={}", cmd.package, version))?;
+
+    if let Err(err) = sync(SyncOptions {
+        output: sync_output(output),
+        dev: true,
+        mode: SyncMode::Regular,
+        lock_options: LockOptions {
+            update: vec![cmd.package.clone()],
+            constraints: vec![requirement.to_string()],
+            ..LockOptions::default()
+        },
+        pyproject: cmd.pyproject.clone(),
+        ..SyncOptions::default()
+    }) {
+        warn_with_output(
+            Warning::BisectSkipped,
+            format_args!(
+                "could not install {} {}, skipping: {:#}",
+                cmd.package, version, err
+            ),
+            output,
+        );
+        return Ok(Outcome::Skip);
+    }
+
+    let venv_bin = pyproject.venv_bin_path();
+    let mut test_cmd = Command::new(&cmd.cmd[0]);
+    test_cmd.args(&cmd.cmd[1..]);
+    test_cmd.env("VIRTUAL_ENV", &*pyproject.venv_path());
+    if let Some(path) = env::var_os("PATH") {
+        let mut paths = split_paths(&path).collect::<Vec<_>>();
+        paths.insert(0, venv_bin.into());
+        test_cmd.env("PATH", join_paths(paths)?);
+    } else {
+        test_cmd.env("PATH", &*venv_bin);
+    }
+    test_cmd.env_remove("PYTHONHOME");
+    let status = test_cmd.status().with_context(|| {
+        format!(
+            "unable to run test command {}",
+            cmd.cmd[0].to_string_lossy()
+        )
+    })?;
+
+    Ok(match status.code() {
+        Some(0) => Outcome::Good,
+        Some(SKIP_EXIT_CODE) => {
+            if output != CommandOutput::Quiet {
+                eprintln!("{} {} was skipped", cmd.package, version);
+            }
+            Outcome::Skip
+        }
+        Some(_) => Outcome::Bad,
+        // killed by a signal, most likely by the user
+        None => return Err(QuietExit(1).into()),
+    })
+}
+
+/// Locking and syncing is only shown in verbose mode as it is very noisy.
+fn sync_output(output: CommandOutput) -> CommandOutput {
+    if output == CommandOutput::Verbose {
+        CommandOutput::Verbose
+    } else {
+        CommandOutput::Quiet
+    }
+}
@@ -1,13 +1,18 @@
+use std::collections::HashSet;
 use std::fs;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 
-use anyhow::{bail, Error};
-use clap::Parser;
+use anyhow::{bail, Context, Error};
+use clap::{ArgAction, Parser};
 use console::style;
+use toml_edit::{value, Document};
 
 use crate::bootstrap::ensure_self_venv;
-use crate::pyproject::{normalize_package_name, PyProject};
+use crate::git_version::copy_checkout;
+use crate::hooks::Hooks;
+use crate::pyproject::{normalize_package_name, PyProject, VersionSource};
+use crate::reproducible::{normalize_archive, source_date_epoch};
 use crate::utils::{get_venv_python_bin, CommandOutput};
 
 /// Builds a package for distribution.
@@ -19,11 +24,11 @@ pub struct Args {
     /// Build a wheel
     #[arg(long)]
     wheel: bool,
-    /// Build all packages
+    /// Build all packages of the workspace
     #[arg(short, long)]
     all: bool,
     /// Build a specific package
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "all")]
     package: Vec<String>,
     /// An output directory (defaults to `workspace/dist`)
     #[arg(short, long)]
@@ -34,9 +39,9 @@ pub struct Args {
     /// Clean the output directory first
     #[arg(short, long)]
     clean: bool,
-    /// Enables verbose diagnostics.
-    #[arg(short, long)]
-    verbose: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
     /// Turns off all output.
     #[arg(short, long, conflicts_with = "verbose")]
     quiet: bool,
@@ -46,13 +51,15 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
     let venv = ensure_self_venv(output)?;
     let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let hooks = Hooks::load(&project)?;
+    hooks.run("pre-build", output)?;
 
     let out = match cmd.out {
         Some(path) => path,
         None => project.workspace_path().join("dist"),
     };
 
-    if cmd.clean {
+    if cmd.clean && out.is_dir() {
         for entry in fs::read_dir(&out)? {
             let path = entry?.path();
             if path.is_file() {
@@ -61,52 +68,79 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         }
     }
 
-    let mut projects = Vec::new();
-
-    if cmd.all {
-        match project.workspace() {
-            Some(workspace) => {
-                for project in workspace.iter_projects() {
-                    projects.push(project?);
+    // members are built after the members they depend on, so that a failing
+    // build stops before anything depending on it is built.
+    let projects = match project.workspace() {
+        Some(workspace) if cmd.all => workspace.projects_in_order(None)?,
+        Some(workspace) if !cmd.package.is_empty() => {
+            let names = cmd
+                .package
+                .iter()
+                .map(|x| normalize_package_name(x))
+                .collect::<HashSet<_>>();
+            let mut projects = Vec::new();
+            for project in workspace.projects_in_order(Some(&cmd.package))? {
+                if names.contains(&project.normalized_name()?) {
+                    projects.push(project);
                 }
             }
-            None => {
-                projects.push(project);
-            }
+            projects
         }
-    } else if cmd.package.is_empty() {
-        projects.push(project);
-    } else {
-        for package_name in cmd.package {
-            match project.workspace() {
-                Some(workspace) => {
-                    if let Some(project) = workspace.get_project(&package_name)? {
-                        projects.push(project);
-                    } else {
-                        bail!("unknown project '{}'", package_name);
-                    }
-                }
-                None => {
-                    if project.normalized_name()? != normalize_package_name(&package_name) {
-                        bail!("unknown project '{}'", package_name);
-                    }
+        _ => {
+            for package_name in &cmd.package {
+                if project.normalized_name()? != normalize_package_name(package_name) {
+                    bail!("unknown project '{}'", package_name);
                 }
             }
+            vec![project]
         }
-    }
+    };
 
-    for project in projects {
+    for mut project in projects {
         if output != CommandOutput::Quiet {
             eprintln!("building {}", style(project.normalized_name()?).cyan());
         }
 
+        // versions derived from git are written into the pyproject.toml of a
+        // copy of the project, so they end up in the built packages while the
+        // checkout is left alone.
+        let staging = match project.version_source()? {
+            VersionSource::Git => {
+                let version = project.version()?;
+                if output != CommandOutput::Quiet {
+                    eprintln!("using version {} from git", style(&version).cyan());
+                }
+                let staging = tempfile::tempdir()?;
+                copy_checkout(&project.root_path(), staging.path())?;
+                let toml_path = staging.path().join("pyproject.toml");
+                let mut doc = fs::read_to_string(&toml_path)
+                    .with_context(|| format!("failed to read {}", toml_path.display()))?
+                    .parse::<Document>()?;
+                doc["project"]["version"] = value(version.to_string());
+                fs::write(&toml_path, doc.to_string())?;
+                Some(staging)
+            }
+            VersionSource::Pyproject => None,
+        };
+
+        // the packages are built into a temporary folder first so they can
+        // be normalized before they are moved to the output folder.
+        fs::create_dir_all(&out)?;
+        let build_dir = tempfile::tempdir_in(&out)?;
+        let epoch = source_date_epoch(&project.root_path())?;
         let mut build_cmd = Command::new(get_venv_python_bin(&venv));
         build_cmd
             .arg("-mbuild")
             .env("NO_COLOR", "1")
+            .env("SOURCE_DATE_EPOCH", epoch.to_string())
+            .env("PYTHONHASHSEED", "0")
             .arg("--outdir")
-            .arg(&out)
-            .arg(&*project.root_path());
+            .arg(build_dir.path())
+            .arg(
+                &*staging
+                    .as_ref()
+                    .map_or(project.root_path(), |x| x.path().into()),
+            );
 
         if cmd.wheel {
             build_cmd.arg("--wheel");
@@ -120,11 +154,16 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
             build_cmd.stderr(Stdio::null());
         }
 
-        let status = build_cmd.status()?;
-        if !status.success() {
+        if !build_cmd.status()?.success() {
             bail!("failed to build dist");
         }
+
+        for entry in fs::read_dir(build_dir.path())? {
+            let path = entry?.path();
+            normalize_archive(&path, epoch)?;
+            fs::rename(&path, out.join(path.file_name().unwrap()))?;
+        }
     }
 
-    Ok(())
+    hooks.run("post-build", output)
 }
@@ -0,0 +1,306 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Error};
+use clap::{ArgAction, Parser};
+use console::style;
+use sha2::{Digest, Sha256};
+use walkdir::WalkDir;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::bootstrap::ensure_self_venv;
+use crate::consts::VENV_BIN;
+use crate::lock::{
+    authoritative_lockfile_path, make_project_root_fragment, read_locked_requirements, LockMode,
+};
+use crate::pyproject::{ExpandedSources, PyProject};
+use crate::sync::{sync, SyncOptions};
+use crate::transcript::run_status;
+use crate::utils::{get_venv_python_bin, set_proxy_variables, CommandOutput};
+
+/// Extracts the bundled packages on first run and calls the entry point.
+///
+/// Packages cannot be imported from the archive directly as extension
+/// modules have to be real files, so they are extracted into a cache folder
+/// keyed by the contents of the bundle.
+const BUNDLE_MAIN: &str = r#"
+import os
+import sys
+import shutil
+import zipfile
+import tempfile
+
+NAME = {name!r}
+ENTRY_POINT = {entry_point!r}
+BUNDLE_ID = {bundle_id!r}
+PYTHON = ({major}, {minor})
+
+
+def _extract():
+    if sys.version_info[:2] != PYTHON:
+        sys.exit("%s requires Python %d.%d" % (NAME, PYTHON[0], PYTHON[1]))
+    root = os.environ.get("RYE_BUNDLE_ROOT") or os.path.join(
+        os.path.expanduser("~"), ".cache", "rye-bundles"
+    )
+    target = os.path.join(root, "%s-%s" % (NAME, BUNDLE_ID))
+    if not os.path.isdir(target):
+        os.makedirs(root, exist_ok=True)
+        tmp = tempfile.mkdtemp(dir=root)
+        with zipfile.ZipFile(os.path.dirname(os.path.abspath(__file__))) as archive:
+            for member in archive.namelist():
+                if member.startswith("site-packages/"):
+                    archive.extract(member, tmp)
+        try:
+            os.rename(os.path.join(tmp, "site-packages"), target)
+        except OSError:
+            # another process extracted the bundle at the same time
+            pass
+        shutil.rmtree(tmp, ignore_errors=True)
+    return target
+
+
+sys.path.insert(0, _extract())
+module, _, attr = ENTRY_POINT.partition(":")
+obj = __import__(module, fromlist=["__name__"])
+for part in attr.split(".") if attr else ():
+    obj = getattr(obj, part)
+sys.exit(obj())
+"#;
+
+/// Bundles the project and its locked dependencies into a zipapp.
+///
+/// The result is a single executable `.pyz` file that runs an entry point of
+/// the project.  It only needs a Python interpreter of the same version as
+/// the virtualenv of the project on the same platform.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The script of `[project.scripts]` to run or a `module:function`.
+    ///
+    /// Defaults to the only script of the project.
+    #[arg(short, long)]
+    entry_point: Option<String>,
+    /// Write the bundle to this path (defaults to `dist/<name>-<version>.pyz`).
+    #[arg(short, long)]
+    out: Option<PathBuf>,
+    /// The interpreter in the shebang line of the bundle.
+    #[arg(long, default_value = "/usr/bin/env python3")]
+    python_shebang: String,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let self_venv = ensure_self_venv(output)?;
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let name = project.normalized_name()?;
+    let version = project
+        .static_version()?
+        .map_or("0".into(), |x| x.to_string());
+    let entry_point = pick_entry_point(&project, cmd.entry_point.as_deref())?;
+
+    let root = project.workspace_path();
+    let contents = match read_locked_requirements(&project, LockMode::Production)? {
+        Some(contents) => contents,
+        None => bail!(
+            "lockfile {} does not exist. Run `rye lock` first",
+            authoritative_lockfile_path(&project, LockMode::Production)?.display()
+        ),
+    };
+
+    // packages are installed for the interpreter of the virtualenv
+    sync(SyncOptions::python_only().pyproject(cmd.pyproject.clone()))
+        .context("failed to sync ahead of bundling")?;
+    let py_ver = project.venv_python_version()?;
+    let sources = ExpandedSources::from_sources(&project.sources()?)?;
+
+    // the editables are the project and the members of its workspace which
+    // are bundled as regular packages.
+    let (editables, locked): (Vec<&str>, Vec<&str>) =
+        contents.lines().partition(|x| x.starts_with("-e "));
+    let editables = editables
+        .iter()
+        .map(|x| x[3..].trim())
+        .map(|x| x.strip_prefix("file:").unwrap_or(x))
+        .collect::<Vec<_>>();
+    let staging = tempfile::tempdir()?;
+    let site = staging.path().join("site-packages");
+    let requirements = staging.path().join("requirements.txt");
+    fs::write(&requirements, locked.join("\n") + "\n")?;
+    if output != CommandOutput::Quiet {
+        eprintln!("installing locked packages into bundle");
+    }
+    install_into_folder(
+        &self_venv,
+        &project.venv_path(),
+        &root,
+        &sources,
+        &site,
+        &[OsStr::new("-r"), requirements.as_os_str()],
+        output,
+    )?;
+    if !editables.is_empty() {
+        install_into_folder(
+            &self_venv,
+            &project.venv_path(),
+            &root,
+            &sources,
+            &site,
+            &editables.iter().map(OsStr::new).collect::<Vec<_>>(),
+            output,
+        )?;
+    }
+
+    let out = match cmd.out {
+        Some(out) => out,
+        None => root.join("dist").join(format!("{}-{}.pyz", name, version)),
+    };
+    if let Some(parent) = out.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_bundle(
+        &out,
+        &site,
+        &cmd.python_shebang,
+        &name,
+        &entry_point,
+        (py_ver.major, py_ver.minor),
+    )
+    .with_context(|| format!("failed to write bundle {}", out.display()))?;
+    if output != CommandOutput::Quiet {
+        eprintln!(
+            "bundled {} ({}) into {}",
+            style(&name).cyan(),
+            entry_point,
+            style(out.display()).cyan()
+        );
+    }
+    Ok(())
+}
+
+/// Returns the entry point (`module:function`) that the bundle runs.
+fn pick_entry_point(project: &PyProject, requested: Option<&str>) -> Result<String, Error> {
+    let scripts = project.console_scripts();
+    match requested {
+        Some(requested) => match scripts.into_iter().find(|x| x.0 == requested) {
+            Some((_, target)) => Ok(target),
+            None if requested.contains(':') => Ok(requested.to_string()),
+            None => bail!("unknown script '{}' in [project.scripts]", requested),
+        },
+        None => match &scripts[..] {
+            [(_, target)] => Ok(target.clone()),
+            [] => bail!("project has no [project.scripts], pass --entry-point module:function"),
+            _ => bail!(
+                "project has multiple scripts, pick one with --entry-point: {}",
+                scripts
+                    .iter()
+                    .map(|x| x.0.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        },
+    }
+}
+
+/// Installs packages without their dependencies into a plain folder.
+///
+/// The packages are installed for the interpreter of the virtualenv.
+pub fn install_into_folder(
+    self_venv: &Path,
+    venv: &Path,
+    root: &Path,
+    sources: &ExpandedSources,
+    target: &Path,
+    args: &[&OsStr],
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let mut cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
+    cmd.env("PROJECT_ROOT", make_project_root_fragment(root))
+        .current_dir(root)
+        .arg("--python")
+        .arg(get_venv_python_bin(venv))
+        .arg("install")
+        .arg("--no-deps")
+        .arg("--no-compile")
+        .arg("--target")
+        .arg(target)
+        .args(args);
+    if output == CommandOutput::Verbose {
+        cmd.arg("--verbose");
+    } else {
+        cmd.arg("--quiet");
+        cmd.env("PYTHONWARNINGS", "ignore");
+    }
+    sources.add_as_pip_args(&mut cmd);
+    set_proxy_variables(&mut cmd);
+    let status = run_status(&mut cmd).context("unable to run pip")?;
+    if !status.success() {
+        bail!("failed to install packages into {}", target.display());
+    }
+    Ok(())
+}
+
+/// Writes the installed packages into an executable zipapp.
+fn write_bundle(
+    out: &Path,
+    site: &Path,
+    shebang: &str,
+    name: &str,
+    entry_point: &str,
+    python: (u8, u8),
+) -> Result<(), Error> {
+    let mut file = fs::File::create(out)?;
+    writeln!(file, "#!{}", shebang)?;
+    let mut writer = ZipWriter::new(file);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    // the bundle is keyed by its contents, so that a new version of the
+    // bundle is extracted again.
+    let mut hasher = Sha256::new();
+    let mut entries = WalkDir::new(site)
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by(|a, b| a.path().cmp(b.path()));
+    for entry in entries {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let rel = entry.path().strip_prefix(site)?;
+        let name = format!("site-packages/{}", rel.to_string_lossy().replace('\\', "/"));
+        let contents = fs::read(entry.path())?;
+        hasher.update(name.as_bytes());
+        hasher.update(&contents);
+        writer.start_file(name, options)?;
+        writer.write_all(&contents)?;
+    }
+    hasher.update(entry_point.as_bytes());
+    let bundle_id = hex::encode(hasher.finalize());
+
+    let main = BUNDLE_MAIN
+        .replace("{name!r}", &format!("{:?}", name))
+        .replace("{entry_point!r}", &format!("{:?}", entry_point))
+        .replace("{bundle_id!r}", &format!("{:?}", &bundle_id[..16]))
+        .replace("{major}", &python.0.to_string())
+        .replace("{minor}", &python.1.to_string());
+    writer.start_file("__main__.py", options)?;
+    writer.write_all(main.trim_start().as_bytes())?;
+    writer.finish()?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(out, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,376 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, Context, Error};
+use clap::{Parser, ValueEnum};
+use console::style;
+use indicatif::HumanBytes;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::cli::search::get_index_cache_dir;
+use crate::platform::get_app_dir;
+use crate::store::{get_store_dir, remove_object};
+use crate::wheel_cache::{get_build_cache_dir, get_wheel_cache_dir};
+
+/// Manages the caches of rye.
+#[derive(Parser, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    command: SubCommand,
+}
+
+/// Shows the size of the caches.
+#[derive(Parser, Debug)]
+pub struct InfoCommand {
+    /// Request parseable output format
+    #[arg(long)]
+    format: Option<Format>,
+}
+
+/// Removes everything from the caches.
+#[derive(Parser, Debug)]
+pub struct CleanCommand {
+    /// Only clean these caches.
+    #[arg(value_enum)]
+    category: Vec<Category>,
+}
+
+/// Removes cache entries that were not used recently or exceed a size budget.
+#[derive(Parser, Debug)]
+#[command(group = clap::ArgGroup::new("limit").required(true).multiple(true))]
+pub struct PruneCommand {
+    /// Only prune these caches.
+    #[arg(value_enum)]
+    category: Vec<Category>,
+    /// Remove entries that were not used in this many days.
+    #[arg(long, value_name = "DAYS", group = "limit")]
+    older_than: Option<u64>,
+    /// Remove the least recently used entries until the caches fit into this
+    /// size (for instance `500M` or `2G`).
+    #[arg(long, value_name = "SIZE", group = "limit")]
+    max_size: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    Info(InfoCommand),
+    Clean(CleanCommand),
+    Prune(PruneCommand),
+}
+
+#[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+enum Format {
+    Json,
+}
+
+#[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+enum Category {
+    /// Downloaded and built wheels.
+    Wheels,
+    /// Files shared between virtualenvs.
+    Store,
+    /// The package index of `rye search`.
+    Index,
+    /// The virtualenvs of the tools run with `rye x`.
+    Tools,
+    /// The wheels of locked packages for `rye sync --offline`.
+    Offline,
+    /// Wheels built from sdists.
+    Builds,
+}
+
+impl Category {
+    fn all() -> &'static [Category] {
+        &[
+            Category::Wheels,
+            Category::Store,
+            Category::Index,
+            Category::Tools,
+            Category::Offline,
+            Category::Builds,
+        ]
+    }
+
+    fn path(self) -> PathBuf {
+        match self {
+            Category::Wheels => get_app_dir().join("cache").join("pip"),
+            Category::Store => get_store_dir(),
+            Category::Index => get_index_cache_dir(),
+            Category::Tools => get_app_dir().join("cache").join("tools"),
+            Category::Offline => get_wheel_cache_dir(),
+            Category::Builds => get_build_cache_dir(),
+        }
+    }
+
+    /// The depth of the folders that are pruned as a whole.
+    ///
+    /// Every virtualenv of `rye x` and every entry of the build cache is
+    /// one unit, removing single files from them would break them.  The
+    /// other caches are pruned file by file.
+    fn unit_depth(self) -> Option<usize> {
+        match self {
+            Category::Tools => Some(1),
+            Category::Builds => Some(3),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct CacheInfo {
+    category: Category,
+    path: PathBuf,
+    size: u64,
+    files: u64,
+}
+
+/// A file (or a folder pruned as a whole) in a cache with the time it was
+/// last used.
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    match cmd.command {
+        SubCommand::Info(args) => info(args),
+        SubCommand::Clean(args) => clean(args),
+        SubCommand::Prune(args) => prune(args),
+    }
+}
+
+fn info(cmd: InfoCommand) -> Result<(), Error> {
+    let infos = Category::all()
+        .iter()
+        .map(|&category| {
+            let entries = list_entries(&category.path());
+            CacheInfo {
+                category,
+                path: category.path(),
+                size: entries.iter().map(|x| x.size).sum(),
+                files: entries.len() as u64,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(Format::Json) = cmd.format {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &infos)?;
+        println!();
+        return Ok(());
+    }
+    for info in &infos {
+        println!(
+            "{:<8} {:>12} {:>8} files  {}",
+            style(category_name(info.category)).cyan(),
+            HumanBytes(info.size).to_string(),
+            info.files,
+            style(info.path.display()).dim()
+        );
+    }
+    println!(
+        "{:<8} {:>12}",
+        "total",
+        HumanBytes(infos.iter().map(|x| x.size).sum()).to_string()
+    );
+    Ok(())
+}
+
+fn clean(cmd: CleanCommand) -> Result<(), Error> {
+    for category in selected(&cmd.category) {
+        let path = category.path();
+        let size = list_entries(&path).iter().map(|x| x.size).sum::<u64>();
+        if path.is_dir() {
+            fs::remove_dir_all(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        } else if path.is_file() {
+            fs::remove_file(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        } else {
+            continue;
+        }
+        eprintln!(
+            "Removed {} cache ({})",
+            category_name(category),
+            HumanBytes(size)
+        );
+    }
+    Ok(())
+}
+
+fn prune(cmd: PruneCommand) -> Result<(), Error> {
+    let max_size = cmd.max_size.as_deref().map(parse_size).transpose()?;
+    let mut entries = selected(&cmd.category)
+        .into_iter()
+        .flat_map(|category| list_units(&category.path(), category.unit_depth()))
+        .collect::<Vec<_>>();
+    // most recently used first
+    entries.sort_by(|a, b| b.last_used.cmp(&a.last_used));
+
+    let cutoff = cmd
+        .older_than
+        .map(|days| SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60));
+    let mut kept_size = 0;
+    let mut removed = 0;
+    let mut removed_size = 0;
+    for entry in entries {
+        let too_old = cutoff.map_or(false, |cutoff| entry.last_used < cutoff);
+        let too_big = max_size.map_or(false, |max_size| kept_size + entry.size > max_size);
+        if !too_old && !too_big {
+            kept_size += entry.size;
+            continue;
+        }
+        if entry.path.is_dir() {
+            fs::remove_dir_all(&entry.path)
+        } else {
+            remove_object(&entry.path)
+        }
+        .with_context(|| format!("failed to remove {}", entry.path.display()))?;
+        removed += 1;
+        removed_size += entry.size;
+    }
+    for category in selected(&cmd.category) {
+        remove_empty_dirs(&category.path());
+    }
+
+    eprintln!(
+        "Removed {} cache entries ({}), {} remain in the cache",
+        removed,
+        HumanBytes(removed_size),
+        HumanBytes(kept_size)
+    );
+    Ok(())
+}
+
+fn selected(categories: &[Category]) -> Vec<Category> {
+    if categories.is_empty() {
+        Category::all().to_vec()
+    } else {
+        categories.to_vec()
+    }
+}
+
+fn category_name(category: Category) -> &'static str {
+    match category {
+        Category::Wheels => "wheels",
+        Category::Store => "store",
+        Category::Index => "index",
+        Category::Tools => "tools",
+        Category::Offline => "offline",
+        Category::Builds => "builds",
+    }
+}
+
+/// Lists all files of a cache.
+///
+/// The last use is the later of the access and modification time, as not all
+/// filesystems record accesses.
+fn list_entries(path: &Path) -> Vec<CacheEntry> {
+    WalkDir::new(path)
+        .into_iter()
+        .filter_map(|x| x.ok())
+        .filter(|x| x.file_type().is_file())
+        .filter_map(|x| {
+            let metadata = x.metadata().ok()?;
+            let modified = metadata.modified().ok()?;
+            let accessed = metadata.accessed().unwrap_or(modified);
+            Some(CacheEntry {
+                path: x.into_path(),
+                size: metadata.len(),
+                last_used: modified.max(accessed),
+            })
+        })
+        .collect()
+}
+
+/// Lists the entries of a cache that are pruned one by one.
+///
+/// With a depth, the files are grouped by their folder at that depth.  Such
+/// a folder was last used when any of its files was.
+fn list_units(path: &Path, depth: Option<usize>) -> Vec<CacheEntry> {
+    let depth = match depth {
+        Some(depth) => depth,
+        None => return list_entries(path),
+    };
+    let mut units = BTreeMap::<PathBuf, CacheEntry>::new();
+    for entry in list_entries(path) {
+        let relative = match entry.path.strip_prefix(path) {
+            Ok(relative) => relative,
+            Err(_) => continue,
+        };
+        // files above the depth are units on their own
+        let unit = if relative.components().count() > depth {
+            path.join(relative.components().take(depth).collect::<PathBuf>())
+        } else {
+            entry.path.clone()
+        };
+        let rv = units.entry(unit.clone()).or_insert(CacheEntry {
+            path: unit,
+            size: 0,
+            last_used: entry.last_used,
+        });
+        rv.size += entry.size;
+        rv.last_used = rv.last_used.max(entry.last_used);
+    }
+    units.into_values().collect()
+}
+
+fn remove_empty_dirs(path: &Path) {
+    for entry in WalkDir::new(path)
+        .min_depth(1)
+        .contents_first(true)
+        .into_iter()
+        .filter_map(|x| x.ok())
+        .filter(|x| x.file_type().is_dir())
+    {
+        // fails for folders that are not empty
+        fs::remove_dir(entry.path()).ok();
+    }
+}
+
+/// Parses a size like `500M` or `2G` into bytes.
+fn parse_size(value: &str) -> Result<u64, Error> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => value.split_at(idx),
+        None => (value, ""),
+    };
+    let factor = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => bail!("invalid size '{}'", value),
+    };
+    let number = number
+        .parse::<u64>()
+        .with_context(|| format!("invalid size '{}'", value))?;
+    Ok(number * factor)
+}
+
+#[test]
+fn test_list_units() {
+    let dir = tempfile::tempdir().unwrap();
+    let venv = dir.path().join("black-23.1");
+    fs::create_dir_all(venv.join("lib")).unwrap();
+    fs::write(venv.join("rye-tool.json"), "{}").unwrap();
+    fs::write(venv.join("lib").join("black.py"), "pass").unwrap();
+    fs::write(dir.path().join("stray.txt"), "x").unwrap();
+
+    let mut units = list_units(dir.path(), Some(1));
+    units.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(
+        units.iter().map(|x| x.path.clone()).collect::<Vec<_>>(),
+        vec![venv, dir.path().join("stray.txt")]
+    );
+    assert_eq!(units[0].size, 6);
+    assert_eq!(list_units(dir.path(), None).len(), 3);
+}
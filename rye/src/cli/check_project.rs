@@ -0,0 +1,414 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Error};
+use clap::{Parser, ValueEnum};
+use console::style;
+use pep440_rs::Operator;
+use pep508_rs::{Requirement, VersionOrUrl};
+use serde::Serialize;
+use toml_edit::{Document, Item};
+
+use crate::pyproject::{normalize_package_name, PyProject};
+use crate::utils::QuietExit;
+
+/// The keys rye understands in `tool.rye`.
+pub(crate) const KNOWN_RYE_KEYS: &[&str] = &[
+    "managed",
+    "dev-dependencies",
+    "excluded-dependencies",
+    "constraint-dependencies",
+    "override-dependencies",
+    "sources",
+    "scripts",
+    "workspace",
+    "generate-hashes",
+    "lock-format",
+    "emit-pylock",
+    "lock-groups",
+    "env",
+    "env-file",
+    "hooks",
+    "publish",
+    "secrets-file",
+    "venv-location",
+    "envs",
+    "matrix",
+    "relocatable",
+    "coverage-fail-under",
+    "suppress-warnings",
+    "version-source",
+    "repositories",
+    "no-binary",
+    "only-binary",
+    "no-build-isolation",
+    "extra-build-dependencies",
+    "conda",
+];
+
+/// The fields of the project table that may be declared as dynamic.
+const DYNAMIC_FIELDS: &[&str] = &[
+    "version",
+    "description",
+    "readme",
+    "requires-python",
+    "license",
+    "authors",
+    "maintainers",
+    "keywords",
+    "classifiers",
+    "urls",
+    "scripts",
+    "gui-scripts",
+    "entry-points",
+    "dependencies",
+    "optional-dependencies",
+];
+
+/// Project fields that should be set so that the package is usable on an index.
+const RECOMMENDED_FIELDS: &[(&str, Severity)] = &[
+    ("readme", Severity::Warning),
+    ("license", Severity::Warning),
+    ("requires-python", Severity::Warning),
+    ("description", Severity::Info),
+];
+
+#[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+enum Format {
+    Json,
+}
+
+#[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Checks the pyproject.toml for problems.
+///
+/// This flags configuration rye never uses (for instance tables copied over
+/// from other tools), missing recommended metadata and questionable
+/// dependency specifiers.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Exit with an error if a finding of this severity or higher is reported.
+    #[arg(long, default_value = "error")]
+    fail_on: Severity,
+    /// Request parseable output format rather than lines.
+    #[arg(long)]
+    format: Option<Format>,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+}
+
+/// A single problem found in the pyproject.toml
+#[derive(Serialize, Debug)]
+struct Finding {
+    severity: Severity,
+    code: &'static str,
+    key: String,
+    message: String,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let path = project.toml_path();
+    let doc = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .parse::<Document>()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+
+    let mut findings = Vec::new();
+    check_foreign_config(&doc, &mut findings);
+    check_rye_keys(&doc, &mut findings);
+    check_dynamic(&doc, &mut findings);
+    check_recommended_fields(&doc, &mut findings);
+    check_dependencies(&doc, &mut findings);
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity));
+
+    if let Some(Format::Json) = cmd.format {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &findings)?;
+        println!();
+    } else if findings.is_empty() {
+        eprintln!("No problems found in {}", path.display());
+    } else {
+        for finding in &findings {
+            let severity = match finding.severity {
+                Severity::Info => style("info").cyan(),
+                Severity::Warning => style("warning").yellow(),
+                Severity::Error => style("error").red(),
+            };
+            println!(
+                "{}[{}]: {}: {}",
+                severity,
+                finding.code,
+                style(&finding.key).bold(),
+                finding.message
+            );
+        }
+    }
+
+    if findings.iter().any(|x| x.severity >= cmd.fail_on) {
+        return Err(QuietExit(1).into());
+    }
+    Ok(())
+}
+
+/// Flags tables of other tools that have no effect with rye.
+fn check_foreign_config(doc: &Document, findings: &mut Vec<Finding>) {
+    let tool = match doc.get("tool") {
+        Some(tool) => tool,
+        None => return,
+    };
+    let backend = doc
+        .get("build-system")
+        .and_then(|x| x.get("build-backend"))
+        .and_then(|x| x.as_str())
+        .unwrap_or("");
+
+    if tool.get("poetry").is_some() && !backend.starts_with("poetry.") {
+        findings.push(Finding {
+            severity: Severity::Warning,
+            code: "dead-config",
+            key: "tool.poetry".into(),
+            message: "poetry configuration is not used by rye or the build backend. \
+                      Declare dependencies in project.dependencies instead"
+                .into(),
+        });
+    }
+    for tool_name in ["pdm", "uv"] {
+        if tool
+            .get(tool_name)
+            .and_then(|x| x.get("dev-dependencies"))
+            .is_some()
+        {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                code: "dead-config",
+                key: format!("tool.{}.dev-dependencies", tool_name),
+                message: "not used by rye. Use tool.rye.dev-dependencies or \
+                          dependency-groups instead"
+                    .into(),
+            });
+        }
+    }
+    if tool.get("hatch").and_then(|x| x.get("envs")).is_some() {
+        findings.push(Finding {
+            severity: Severity::Info,
+            code: "dead-config",
+            key: "tool.hatch.envs".into(),
+            message: "hatch environments are not used by rye".into(),
+        });
+    }
+}
+
+/// Flags keys in `tool.rye` that rye does not know about.
+fn check_rye_keys(doc: &Document, findings: &mut Vec<Finding>) {
+    let rye = match doc
+        .get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.as_table_like())
+    {
+        Some(rye) => rye,
+        None => return,
+    };
+    for (key, _) in rye.iter() {
+        if !KNOWN_RYE_KEYS.contains(&key) {
+            let message = match KNOWN_RYE_KEYS
+                .iter()
+                .find(|x| x.replace('-', "_") == key.replace('-', "_"))
+            {
+                Some(known) => format!("unknown key, did you mean '{}'?", known),
+                None => "unknown key, it is ignored".into(),
+            };
+            findings.push(Finding {
+                severity: Severity::Warning,
+                code: "unknown-key",
+                key: format!("tool.rye.{}", key),
+                message,
+            });
+        }
+    }
+}
+
+/// Flags dynamic fields that are also set statically or do not exist.
+fn check_dynamic(doc: &Document, findings: &mut Vec<Finding>) {
+    let project = match doc.get("project") {
+        Some(project) => project,
+        None => return,
+    };
+    let dynamic = project
+        .get("dynamic")
+        .and_then(|x| x.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.as_str());
+    for field in dynamic {
+        if !DYNAMIC_FIELDS.contains(&field) {
+            findings.push(Finding {
+                severity: Severity::Error,
+                code: "stale-dynamic",
+                key: "project.dynamic".into(),
+                message: format!("'{}' cannot be declared as dynamic", field),
+            });
+        } else if project.get(field).is_some() {
+            findings.push(Finding {
+                severity: Severity::Error,
+                code: "stale-dynamic",
+                key: "project.dynamic".into(),
+                message: format!(
+                    "'{}' is declared as dynamic but also set in the project table",
+                    field
+                ),
+            });
+        }
+    }
+}
+
+/// Flags missing metadata that is recommended for published packages.
+fn check_recommended_fields(doc: &Document, findings: &mut Vec<Finding>) {
+    let project = match doc.get("project") {
+        Some(project) => project,
+        None => return,
+    };
+    let dynamic = project
+        .get("dynamic")
+        .and_then(|x| x.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.as_str())
+        .collect::<HashSet<_>>();
+    for &(field, severity) in RECOMMENDED_FIELDS {
+        if project.get(field).is_none() && !dynamic.contains(field) {
+            findings.push(Finding {
+                severity,
+                code: "missing-field",
+                key: format!("project.{}", field),
+                message: "recommended field is not set".into(),
+            });
+        }
+    }
+}
+
+/// Flags questionable dependency specifiers.
+///
+/// Exact pins and direct references only matter for the dependencies that
+/// end up in the package metadata.  Dev dependencies may use them freely.
+fn check_dependencies(doc: &Document, findings: &mut Vec<Finding>) {
+    let project = doc.get("project");
+    if let Some(deps) = project.and_then(|x| x.get("dependencies")) {
+        check_requirement_list("project.dependencies", deps, true, findings);
+    }
+    if let Some(optional) = project
+        .and_then(|x| x.get("optional-dependencies"))
+        .and_then(|x| x.as_table_like())
+    {
+        for (extra, deps) in optional.iter() {
+            check_requirement_list(
+                &format!("project.optional-dependencies.{}", extra),
+                deps,
+                true,
+                findings,
+            );
+        }
+    }
+    if let Some(rye) = doc.get("tool").and_then(|x| x.get("rye")) {
+        for key in [
+            "dev-dependencies",
+            "excluded-dependencies",
+            "constraint-dependencies",
+            "override-dependencies",
+        ] {
+            if let Some(deps) = rye.get(key) {
+                check_requirement_list(&format!("tool.rye.{}", key), deps, false, findings);
+            }
+        }
+    }
+    if let Some(groups) = doc.get("dependency-groups").and_then(|x| x.as_table_like()) {
+        for (group, deps) in groups.iter() {
+            check_requirement_list(
+                &format!("dependency-groups.{}", group),
+                deps,
+                false,
+                findings,
+            );
+        }
+    }
+}
+
+fn check_requirement_list(key: &str, deps: &Item, is_metadata: bool, findings: &mut Vec<Finding>) {
+    let mut seen = HashSet::new();
+    // dependency groups can contain tables to include other groups
+    for dep in deps
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.as_str())
+    {
+        let req = match dep.parse::<Requirement>() {
+            Ok(req) => req,
+            Err(err) => {
+                findings.push(Finding {
+                    severity: Severity::Error,
+                    code: "invalid-requirement",
+                    key: key.into(),
+                    message: format!("'{}' is not a valid requirement: {}", dep, err),
+                });
+                continue;
+            }
+        };
+        let marker = req.marker.as_ref().map(|x| x.to_string());
+        if !seen.insert((normalize_package_name(&req.name), marker)) {
+            findings.push(Finding {
+                severity: Severity::Warning,
+                code: "duplicate-requirement",
+                key: key.into(),
+                message: format!("'{}' is listed more than once", req.name),
+            });
+        }
+        if !is_metadata {
+            continue;
+        }
+        match req.version_or_url {
+            None => findings.push(Finding {
+                severity: Severity::Info,
+                code: "unbounded-requirement",
+                key: key.into(),
+                message: format!("'{}' has no version bound", req.name),
+            }),
+            Some(VersionOrUrl::VersionSpecifier(ref specs)) => {
+                if specs
+                    .iter()
+                    .any(|x| matches!(x.operator(), Operator::Equal | Operator::ExactEqual))
+                {
+                    findings.push(Finding {
+                        severity: Severity::Warning,
+                        code: "exact-pin",
+                        key: key.into(),
+                        message: format!(
+                            "'{}' is pinned to an exact version which makes the package \
+                             hard to install alongside others. Pins belong in the lockfile",
+                            dep
+                        ),
+                    });
+                }
+            }
+            Some(VersionOrUrl::Url(_)) => findings.push(Finding {
+                severity: Severity::Warning,
+                code: "direct-reference",
+                key: key.into(),
+                message: format!(
+                    "'{}' is a direct reference which package indexes like PyPI reject",
+                    req.name
+                ),
+            }),
+        }
+    }
+}
@@ -8,11 +8,15 @@ use clap::Parser;
 use clap::ValueEnum;
 use serde::Serialize;
 use toml_edit::value;
+use toml_edit::ArrayOfTables;
 use toml_edit::Item;
 use toml_edit::Table;
 use toml_edit::Value;
 
+use crate::auth::keyring_set;
 use crate::config::Config;
+use crate::pyproject::{SourceAuth, SourceRef};
+use crate::utils::ensure_interactive;
 
 #[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
 #[value(rename_all = "snake_case")]
@@ -28,7 +32,10 @@ enum Format {
 /// Each of the set operations takes a key=value pair. All of these can
 /// be supplied multiple times.
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct Args {
+    #[command(subcommand)]
+    command: Option<SubCommand>,
     /// Reads a config key
     #[arg(long)]
     get: Vec<String>,
@@ -52,7 +59,53 @@ pub struct Args {
     format: Option<Format>,
 }
 
+#[derive(Parser, Debug)]
+enum SubCommand {
+    /// Manages the default package sources.
+    #[command(subcommand)]
+    Sources(SourcesCommand),
+}
+
+#[derive(Parser, Debug)]
+enum SourcesCommand {
+    /// Adds a package source (or replaces the one with the same name).
+    Add(AddSourceArgs),
+    /// Removes a package source.
+    Remove {
+        /// The name of the source.
+        name: String,
+    },
+    /// Lists the package sources.
+    List,
+}
+
+#[derive(Parser, Debug)]
+struct AddSourceArgs {
+    /// The name of the source.
+    name: String,
+    /// The URL of the index (or of the links page with --find-links).
+    url: String,
+    /// The source is a links page rather than an index.
+    #[arg(long)]
+    find_links: bool,
+    /// The username to authenticate with.
+    #[arg(long)]
+    username: Option<String>,
+    /// Asks for the password and stores it in the keyring of the system.
+    #[arg(long, requires = "username", conflicts_with = "netrc")]
+    keyring: bool,
+    /// Reads the credentials from the netrc file.
+    #[arg(long)]
+    netrc: bool,
+    /// Do not verify the TLS certificate of the source.
+    #[arg(long)]
+    no_verify_ssl: bool,
+}
+
 pub fn execute(cmd: Args) -> Result<(), Error> {
+    if let Some(SubCommand::Sources(sources_cmd)) = cmd.command {
+        return execute_sources(sources_cmd);
+    }
     let mut config = Config::current();
     let doc = Arc::make_mut(&mut config).doc_mut();
 
@@ -177,6 +230,100 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     Ok(())
 }
 
+fn execute_sources(cmd: SourcesCommand) -> Result<(), Error> {
+    let mut config = Config::current();
+    let config = Arc::make_mut(&mut config);
+    match cmd {
+        SourcesCommand::Add(args) => {
+            let mut source = Table::new();
+            source.insert("name", value(&args.name));
+            source.insert("url", value(&args.url));
+            if args.find_links {
+                source.insert("type", value("find-links"));
+            }
+            if let Some(ref username) = args.username {
+                source.insert("username", value(username));
+            }
+            if args.no_verify_ssl {
+                source.insert("verify_ssl", value(false));
+            }
+            if args.netrc {
+                source.insert("auth", value("netrc"));
+            } else if args.keyring {
+                source.insert("auth", value("keyring"));
+            }
+
+            // validates the source and yields the URL the keyring is keyed by
+            let mut source_ref =
+                SourceRef::from_toml_table(&source, config.path().parent().unwrap())?;
+            source_ref.auth = None;
+            let mut url = source_ref.expand_url()?;
+            if let (true, Some(username)) = (args.keyring, &args.username) {
+                ensure_interactive("reading the password of the source")?;
+                let password = dialoguer::Password::new()
+                    .with_prompt(format!("Password for {} at {}", username, args.name))
+                    .report(false)
+                    .interact()?;
+                url.set_username("").ok();
+                keyring_set(url.as_str(), username, &password)?;
+            }
+
+            let doc = config.doc_mut();
+            let sources = doc
+                .entry("sources")
+                .or_insert(Item::ArrayOfTables(ArrayOfTables::new()))
+                .as_array_of_tables_mut()
+                .context("sources in the config is not an array of tables")?;
+            remove_source(sources, &args.name);
+            sources.push(source);
+            config.save()?;
+            eprintln!("Added source {}", args.name);
+        }
+        SourcesCommand::Remove { name } => {
+            let removed = match config
+                .doc_mut()
+                .get_mut("sources")
+                .and_then(|x| x.as_array_of_tables_mut())
+            {
+                Some(sources) => remove_source(sources, &name),
+                None => false,
+            };
+            if !removed {
+                bail!("unknown source '{}'", name);
+            }
+            config.save()?;
+            eprintln!("Removed source {}", name);
+        }
+        SourcesCommand::List => {
+            for source in config.sources()? {
+                let auth = match source.auth {
+                    Some(SourceAuth::Keyring) => " (keyring)",
+                    Some(SourceAuth::Netrc) => " (netrc)",
+                    None if source.password.is_some() => " (password)",
+                    None => "",
+                };
+                println!("{} {}{}", source.name, source.url, auth);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the sources with the given name, returns if any were removed.
+fn remove_source(sources: &mut ArrayOfTables, name: &str) -> bool {
+    let mut removed = false;
+    loop {
+        let idx = sources
+            .iter()
+            .position(|x| x.get("name").and_then(|x| x.as_str()) == Some(name));
+        match idx {
+            Some(idx) => sources.remove(idx),
+            None => return removed,
+        }
+        removed = true;
+    }
+}
+
 fn value_to_json(val: Option<&Value>) -> serde_json::Value {
     match val {
         Some(Value::String(s)) => serde_json::Value::String(s.value().into()),
@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+
+use anyhow::Error;
+use clap::Parser;
+use console::style;
+
+use crate::platform::list_known_toolchains;
+use crate::pyproject::PyProject;
+use crate::sources::{
+    current_year_month, get_support_status, get_supported_upgrade, PythonVersionRequest,
+    SupportStatus,
+};
+use crate::utils::QuietExit;
+
+/// Checks the Python versions in use for their end of life.
+///
+/// This looks at the Python version of the current project and at the
+/// registered toolchains.  For versions that reached their end of life, or
+/// reach it soon, the nearest supported version to upgrade to is suggested.
+/// The command fails if a version reached its end of life.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let now = current_year_month();
+    let mut problems = 0;
+    let mut end_of_life = false;
+
+    // outside of a project only the toolchains are checked
+    let pyproject = match cmd.pyproject {
+        Some(ref path) => Some(PyProject::load(path)?),
+        None => PyProject::discover().ok(),
+    };
+    if let Some(pyproject) = pyproject {
+        let py_ver = pyproject.venv_python_version()?;
+        let req = PythonVersionRequest::from(py_ver.clone());
+        let label = match pyproject.name() {
+            Some(name) => format!("project {}", name),
+            None => "project".to_string(),
+        };
+        let upgrade = get_supported_upgrade(&req, now)
+            .map(|x| format!(" Upgrade with `rye pin {}`.", x))
+            .unwrap_or_default();
+        match get_support_status(&req, now) {
+            SupportStatus::Supported => {
+                println!(
+                    "{}: {}: Python {} is supported",
+                    style("ok").green(),
+                    style(label).bold(),
+                    py_ver
+                );
+            }
+            status => {
+                end_of_life |= report(&label, &py_ver.to_string(), status, &upgrade);
+                problems += 1;
+            }
+        }
+    }
+
+    for (version, _) in list_known_toolchains()? {
+        let req = PythonVersionRequest::from(version.clone());
+        let status = get_support_status(&req, now);
+        if status == SupportStatus::Supported {
+            continue;
+        }
+        let advice = match get_supported_upgrade(&req, now) {
+            Some(upgrade) => format!(
+                " Fetch a supported one with `rye fetch {}` and remove it with \
+                 `rye toolchain remove {}`.",
+                upgrade, version
+            ),
+            None => format!(" Remove it with `rye toolchain remove {}`.", version),
+        };
+        end_of_life |= report("toolchain", &version.to_string(), status, &advice);
+        problems += 1;
+    }
+
+    if problems == 0 {
+        eprintln!("No problems found");
+    }
+    if end_of_life {
+        return Err(QuietExit(1).into());
+    }
+    Ok(())
+}
+
+/// Prints a Python version that is not supported (much longer).
+///
+/// Returns `true` if the version reached its end of life.
+fn report(label: &str, version: &str, status: SupportStatus, advice: &str) -> bool {
+    let (severity, message, end_of_life) = match status {
+        SupportStatus::EndOfLife(year, month) => (
+            style("error").red(),
+            format!("reached its end of life in {}-{:02}", year, month),
+            true,
+        ),
+        SupportStatus::EndingSoon(year, month) => (
+            style("warning").yellow(),
+            format!("reaches its end of life in {}-{:02}", year, month),
+            false,
+        ),
+        SupportStatus::Supported => return false,
+    };
+    println!(
+        "{}: {}: Python {} {}.{}",
+        severity,
+        style(label).bold(),
+        version,
+        message,
+        advice
+    );
+    end_of_life
+}
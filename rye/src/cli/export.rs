@@ -0,0 +1,545 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Error};
+use clap::{Parser, ValueEnum};
+use pep440_rs::Operator;
+use pep508_rs::{Requirement, VersionOrUrl};
+
+use crate::lock::{
+    available_lock_groups, group_lockfile_name, iter_lockfile_entries, lockfile_has_hashes,
+    merge_lockfiles, strip_line_continuation, LockMode,
+};
+use crate::pylock::{pylock_filename, pylock_to_requirements};
+use crate::pyproject::{normalize_package_name, CondaSettings, LockFormat, PyProject};
+use crate::sources::PythonVersion;
+
+/// Packages that are named differently on conda-forge than on PyPI.
+const CONDA_FORGE_NAMES: &[(&str, &str)] = &[
+    ("build", "python-build"),
+    ("duckdb", "python-duckdb"),
+    ("flatbuffers", "python-flatbuffers"),
+    ("graphviz", "python-graphviz"),
+    ("kaleido", "python-kaleido"),
+    ("msgpack", "msgpack-python"),
+    ("opencv-python", "opencv"),
+    ("opencv-python-headless", "opencv"),
+    ("psycopg2-binary", "psycopg2"),
+    ("ruamel-yaml", "ruamel.yaml"),
+    ("tables", "pytables"),
+    ("torch", "pytorch"),
+    ("typing-extensions", "typing_extensions"),
+];
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq)]
+#[value(rename_all = "snake_case")]
+enum Format {
+    /// A requirements file that can be installed with pip.
+    Requirements,
+    /// A conda `environment.yml` file.
+    Conda,
+    /// A multi-stage Dockerfile that installs the project.
+    Dockerfile,
+}
+
+/// The image a Dockerfile runs the project in.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq)]
+#[value(rename_all = "snake_case")]
+enum Base {
+    /// The slim Debian image of the Python version.
+    Slim,
+    /// The full Debian image of the Python version.
+    Full,
+    /// The distroless Python image (Python 3.11 only).
+    Distroless,
+}
+
+/// Exports the locked dependencies for other tools.
+///
+/// The export is made from the current lockfiles, so run `rye lock` first
+/// if the dependencies changed.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The format of the export.
+    #[arg(long, default_value = "requirements")]
+    format: Format,
+    /// Write to this file instead of stdout.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Include the dev dependencies.
+    #[arg(long)]
+    dev: bool,
+    /// Include the locked dependencies of this group.
+    #[arg(long, value_name = "GROUP")]
+    group: Vec<String>,
+    /// Include the hashes of the packages.
+    #[arg(long)]
+    hashes: bool,
+    /// Leave out all comments.
+    #[arg(long)]
+    no_annotate: bool,
+    /// The image the Dockerfile runs the project in.
+    #[arg(long, default_value = "slim")]
+    base: Base,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let root = pyproject.workspace_path();
+    let lock_mode = if cmd.dev {
+        LockMode::Dev
+    } else {
+        LockMode::Production
+    };
+
+    let (filename, mut contents) = match pyproject.lock_format()? {
+        LockFormat::Requirements => {
+            let filename = match lock_mode {
+                LockMode::Production => "requirements.lock",
+                LockMode::Dev => "requirements-dev.lock",
+            };
+            (filename, read_lockfile(&root.join(filename))?)
+        }
+        LockFormat::Pylock => {
+            let filename = pylock_filename(lock_mode);
+            let path = root.join(filename);
+            check_locked(&path)?;
+            (filename, pylock_to_requirements(&path, &root)?)
+        }
+    };
+    let mut lockfiles = vec![filename.to_string()];
+
+    for group in &cmd.group {
+        let filename = group_lockfile_name(group)?;
+        let path = root.join(&filename);
+        if !path.is_file() {
+            if available_lock_groups(&pyproject)?.contains(&normalize_package_name(group)) {
+                bail!(
+                    "group '{}' is not locked. Run `rye lock --group {}` first",
+                    group,
+                    group
+                );
+            }
+            bail!("unknown group '{}'", group);
+        }
+        contents = merge_lockfiles(&contents, &read_lockfile(&path)?);
+        lockfiles.push(filename);
+    }
+
+    match cmd.format {
+        Format::Conda if cmd.hashes => bail!("conda environments cannot record hashes"),
+        Format::Dockerfile if cmd.hashes => {
+            bail!("a Dockerfile installs the lockfiles as they are, --hashes does not apply")
+        }
+        _ => {}
+    }
+    if cmd.hashes && !lockfile_has_hashes(&contents) {
+        bail!("the lockfiles have no hashes.  Set tool.rye.generate-hashes to create them");
+    }
+
+    let rv = match cmd.format {
+        Format::Requirements => {
+            render_requirements(&contents, &lockfiles, cmd.hashes, !cmd.no_annotate)
+        }
+        Format::Conda => {
+            let py_ver = pyproject.venv_python_version()?;
+            let name = match pyproject.name() {
+                Some(name) => name.to_string(),
+                None => root
+                    .file_name()
+                    .map_or_else(|| "rye".into(), |x| x.to_string_lossy().into_owned()),
+            };
+            render_conda(
+                &contents,
+                &lockfiles,
+                &name,
+                &format!("{}.{}", py_ver.major, py_ver.minor),
+                &pyproject.conda_settings(),
+                !cmd.no_annotate,
+            )
+        }
+        Format::Dockerfile => {
+            if pyproject.lock_format()? == LockFormat::Pylock {
+                bail!("a Dockerfile can only be exported for requirements lockfiles");
+            }
+            render_dockerfile(
+                &contents,
+                &lockfiles,
+                &pyproject.venv_python_version()?,
+                cmd.base,
+                pyproject.console_scripts().into_iter().next(),
+                !cmd.no_annotate,
+            )?
+        }
+    };
+    match cmd.output {
+        Some(path) => {
+            fs::write(&path, rv).with_context(|| format!("failed to write {}", path.display()))?
+        }
+        None => print!("{}", rv),
+    }
+    Ok(())
+}
+
+fn check_locked(path: &Path) -> Result<(), Error> {
+    if !path.is_file() {
+        bail!(
+            "lockfile {} does not exist. Run `rye lock` first",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+fn read_lockfile(path: &Path) -> Result<String, Error> {
+    check_locked(path)?;
+    fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Renders the contents of a lockfile as requirements file.
+///
+/// The header of the lockfile is replaced by one that names the lockfiles.
+/// Editables cannot be installed in hash-checking mode, so they are left out
+/// when hashes are exported.
+fn render_requirements(
+    contents: &str,
+    lockfiles: &[String],
+    hashes: bool,
+    annotate: bool,
+) -> String {
+    let mut rv = String::new();
+    if annotate {
+        rv.push_str(&format!(
+            "# exported from {} by `rye export`\n",
+            lockfiles.join(", ")
+        ));
+    }
+    for entry in iter_lockfile_entries(contents) {
+        let line = strip_line_continuation(entry[0]);
+        if line.is_empty() {
+            continue;
+        } else if line.starts_with('#') {
+            if annotate && line.starts_with("# excluded ") {
+                rv.push_str(line);
+                rv.push('\n');
+            }
+        } else if hashes && line.starts_with("-e ") {
+            if annotate {
+                rv.push_str(&format!("# omitted editable {}\n", &line[3..]));
+            }
+        } else if hashes {
+            for line in entry {
+                rv.push_str(line);
+                rv.push('\n');
+            }
+        } else {
+            rv.push_str(line);
+            rv.push('\n');
+        }
+    }
+    rv
+}
+
+/// Renders the contents of a lockfile as conda environment.
+///
+/// Packages pinned to a version from an index are installed with conda under
+/// their conda-forge name.  Everything else (direct references, editables,
+/// packages with markers and packages configured to come from PyPI) is
+/// installed by pip, as conda cannot express it.
+fn render_conda(
+    contents: &str,
+    lockfiles: &[String],
+    name: &str,
+    python: &str,
+    settings: &CondaSettings,
+    annotate: bool,
+) -> String {
+    let mut conda = vec![format!("python={}", python)];
+    let mut pip = Vec::new();
+    let mut comments = Vec::new();
+    for entry in iter_lockfile_entries(contents) {
+        let line = strip_line_continuation(entry[0]);
+        if line.is_empty() {
+            continue;
+        } else if line.starts_with('#') {
+            if line.starts_with("# excluded ") {
+                comments.push(line);
+            }
+            continue;
+        }
+        match line.parse::<Requirement>() {
+            Ok(req) => {
+                let name = normalize_package_name(&req.name);
+                let version = match req.version_or_url {
+                    Some(VersionOrUrl::VersionSpecifier(ref specs)) if specs.len() == 1 => specs
+                        .iter()
+                        .find(|x| *x.operator() == Operator::Equal)
+                        .map(|x| x.version().to_string()),
+                    _ => None,
+                };
+                match version {
+                    Some(version) if req.marker.is_none() && !settings.pip.contains(&name) => {
+                        let conda_name = settings.names.get(&name).cloned().unwrap_or_else(|| {
+                            CONDA_FORGE_NAMES
+                                .iter()
+                                .find(|x| x.0 == name)
+                                .map_or(name, |x| x.1.to_string())
+                        });
+                        conda.push(format!("{}=={}", conda_name, version));
+                    }
+                    _ => pip.push(line),
+                }
+            }
+            // options and editables
+            Err(_) => pip.push(line),
+        }
+    }
+
+    let mut rv = String::new();
+    if annotate {
+        rv.push_str(&format!(
+            "# exported from {} by `rye export`\n",
+            lockfiles.join(", ")
+        ));
+    }
+    rv.push_str(&format!("name: {}\n", name));
+    rv.push_str("channels:\n");
+    for channel in &settings.channels {
+        rv.push_str(&format!("  - {}\n", channel));
+    }
+    rv.push_str("dependencies:\n");
+    for dep in &conda {
+        rv.push_str(&format!("  - {}\n", dep));
+    }
+    if !pip.is_empty() {
+        rv.push_str("  - pip\n");
+        rv.push_str("  - pip:\n");
+        for line in &pip {
+            rv.push_str(&format!("      - '{}'\n", line.replace('\'', "''")));
+        }
+    }
+    if annotate {
+        for comment in comments {
+            rv.push_str(comment);
+            rv.push('\n');
+        }
+    }
+    rv
+}
+
+/// Renders a multi-stage Dockerfile that installs the project.
+///
+/// The builder stage installs the lockfiles without the editables into a
+/// virtualenv (or a plain folder for distroless images, which do not come
+/// with the same interpreter) and then the workspace members themselves as
+/// regular packages.  Only the installed packages are copied into the final
+/// image.
+fn render_dockerfile(
+    contents: &str,
+    lockfiles: &[String],
+    py_ver: &PythonVersion,
+    base: Base,
+    script: Option<(String, String)>,
+    annotate: bool,
+) -> Result<String, Error> {
+    if py_ver.kind != "cpython" {
+        bail!(
+            "a Dockerfile can only be exported for CPython, not {}",
+            py_ver.kind
+        );
+    }
+    let version = format!("{}.{}.{}", py_ver.major, py_ver.minor, py_ver.patch);
+    let distroless = base == Base::Distroless;
+    if distroless && (py_ver.major, py_ver.minor) != (3, 11) {
+        bail!(
+            "distroless images come with Python 3.11, the project uses {}",
+            version
+        );
+    }
+    let mut members = contents
+        .lines()
+        .filter_map(|x| x.strip_prefix("-e "))
+        .map(|x| x.trim())
+        .map(|x| x.strip_prefix("file:").unwrap_or(x))
+        .map(|x| match x {
+            "." => x.to_string(),
+            _ if x.starts_with('/') || x.starts_with("./") => x.to_string(),
+            _ => format!("./{}", x),
+        })
+        .collect::<Vec<_>>();
+    members.dedup();
+    let (target, install) = if distroless {
+        (
+            "/app/packages",
+            "pip install --no-deps --target /app/packages",
+        )
+    } else {
+        ("/app/.venv", "pip install --no-deps")
+    };
+
+    let mut rv = String::new();
+    if annotate {
+        rv.push_str(&format!(
+            "# exported from {} by `rye export`\n",
+            lockfiles.join(", ")
+        ));
+    }
+    rv.push_str(&format!("FROM python:{} AS builder\n", version));
+    rv.push_str("ENV PIP_NO_CACHE_DIR=1 PIP_DISABLE_PIP_VERSION_CHECK=1 PROJECT_ROOT=/app\n");
+    rv.push_str("WORKDIR /app\n");
+    if !distroless {
+        rv.push_str("RUN python -m venv /app/.venv\n");
+        rv.push_str("ENV PATH=\"/app/.venv/bin:$PATH\"\n");
+    }
+    if annotate {
+        rv.push_str("# the dependencies are installed ahead of the sources to cache them\n");
+    }
+    rv.push_str(&format!("COPY {} ./\n", lockfiles.join(" ")));
+    rv.push_str(&format!(
+        "RUN cat {} | sed '/^-e /d' > /tmp/requirements.txt \\\n    && {} -r /tmp/requirements.txt\n",
+        lockfiles.join(" "),
+        install
+    ));
+    rv.push_str("COPY . .\n");
+    if !members.is_empty() {
+        rv.push_str(&format!("RUN {} {}\n", install, members.join(" ")));
+    }
+
+    rv.push('\n');
+    rv.push_str(&match base {
+        Base::Slim => format!("FROM python:{}-slim\n", version),
+        Base::Full => format!("FROM python:{}\n", version),
+        Base::Distroless => "FROM gcr.io/distroless/python3-debian12\n".to_string(),
+    });
+    rv.push_str("WORKDIR /app\n");
+    rv.push_str(&format!("COPY --from=builder {} {}\n", target, target));
+    if distroless {
+        rv.push_str("ENV PYTHONPATH=/app/packages\n");
+    } else {
+        rv.push_str("ENV PATH=\"/app/.venv/bin:$PATH\"\n");
+    }
+    match script {
+        // the scripts of packages installed into a folder cannot be run
+        // directly, so the entry point is called by the interpreter.
+        Some((_, target)) if distroless => {
+            let (module, attr) = target.split_once(':').unwrap_or((target.as_str(), "main"));
+            rv.push_str(&format!(
+                "CMD [\"-c\", \"import sys, {}; sys.exit({}.{}())\"]\n",
+                module.trim(),
+                module.trim(),
+                attr.trim()
+            ));
+        }
+        Some((name, _)) => rv.push_str(&format!("CMD [\"{}\"]\n", name)),
+        None if distroless => {}
+        None => rv.push_str("CMD [\"python\"]\n"),
+    }
+    Ok(rv)
+}
+
+#[test]
+fn test_render_requirements() {
+    let lockfile = "\
+# generated by rye
+# use `rye lock` or `rye sync` to update this lockfile
+
+-e file:.
+# excluded colorama==0.4.6
+flask==3.0.0 \\
+    --hash=sha256:abc
+werkzeug==3.0.1 \\
+    --hash=sha256:def
+";
+    let lockfiles = vec!["requirements.lock".to_string()];
+    assert_eq!(
+        render_requirements(lockfile, &lockfiles, false, true),
+        "# exported from requirements.lock by `rye export`\n\
+         -e file:.\n\
+         # excluded colorama==0.4.6\n\
+         flask==3.0.0\n\
+         werkzeug==3.0.1\n"
+    );
+    assert_eq!(
+        render_requirements(lockfile, &lockfiles, true, false),
+        "flask==3.0.0 \\\n    --hash=sha256:abc\nwerkzeug==3.0.1 \\\n    --hash=sha256:def\n"
+    );
+}
+
+#[test]
+fn test_render_conda() {
+    let lockfile = "\
+-e file:.
+numpy==1.26.4
+pywin32==306 ; sys_platform == 'win32'
+torch==2.2.0
+urllib3==2.2.0
+";
+    let settings = CondaSettings {
+        channels: vec!["conda-forge".into()],
+        names: [("urllib3".to_string(), "urllib3-custom".to_string())].into(),
+        pip: vec![],
+    };
+    assert_eq!(
+        render_conda(lockfile, &[], "demo", "3.12", &settings, false),
+        "name: demo
+channels:
+  - conda-forge
+dependencies:
+  - python=3.12
+  - numpy==1.26.4
+  - pytorch==2.2.0
+  - urllib3-custom==2.2.0
+  - pip
+  - pip:
+      - '-e file:.'
+      - 'pywin32==306 ; sys_platform == ''win32'''
+"
+    );
+}
+
+#[test]
+fn test_render_dockerfile() {
+    let lockfile = "-e file:.\n-e file:libs/core\nflask==3.0.0\n";
+    let lockfiles = vec!["requirements.lock".to_string()];
+    let py_ver = PythonVersion {
+        kind: "cpython".into(),
+        major: 3,
+        minor: 12,
+        patch: 3,
+        suffix: None,
+    };
+    assert_eq!(
+        render_dockerfile(
+            lockfile,
+            &lockfiles,
+            &py_ver,
+            Base::Slim,
+            Some(("demo".into(), "demo.cli:main".into())),
+            false,
+        )
+        .unwrap(),
+        r#"FROM python:3.12.3 AS builder
+ENV PIP_NO_CACHE_DIR=1 PIP_DISABLE_PIP_VERSION_CHECK=1 PROJECT_ROOT=/app
+WORKDIR /app
+RUN python -m venv /app/.venv
+ENV PATH="/app/.venv/bin:$PATH"
+COPY requirements.lock ./
+RUN cat requirements.lock | sed '/^-e /d' > /tmp/requirements.txt \
+    && pip install --no-deps -r /tmp/requirements.txt
+COPY . .
+RUN pip install --no-deps . ./libs/core
+
+FROM python:3.12.3-slim
+WORKDIR /app
+COPY --from=builder /app/.venv /app/.venv
+ENV PATH="/app/.venv/bin:$PATH"
+CMD ["demo"]
+"#
+    );
+    assert!(
+        render_dockerfile(lockfile, &lockfiles, &py_ver, Base::Distroless, None, false).is_err()
+    );
+}
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str::FromStr;
 use std::{env, fs};
@@ -8,15 +8,20 @@ use clap::Parser;
 use console::style;
 use license::License;
 use minijinja::{context, Environment};
-use pep440_rs::VersionSpecifier;
+use pep440_rs::VersionSpecifiers;
+use toml_edit::Document;
 
 use crate::config::Config;
 use crate::platform::{
     get_default_author, get_latest_cpython_version, get_python_version_request_from_pyenv_pin,
 };
+use crate::project_import::{import_project, ImportSource};
+use crate::project_template::{builtin_templates, merge_toml, ProjectTemplate};
 use crate::pyproject::BuildSystem;
+use crate::script::SCRIPT_TEMPLATE;
 use crate::sources::PythonVersionRequest;
-use crate::utils::is_inside_git_work_tree;
+use crate::utils::{is_inside_git_work_tree, CommandOutput};
+use crate::warnings::{warn, Warning};
 
 /// Creates a new python project.
 #[derive(Parser, Debug)]
@@ -45,9 +50,32 @@ pub struct Args {
     /// The name of the package.
     #[arg(long)]
     name: Option<String>,
-    /// Set "Private :: Do Not Upload" classifier, used for private projects
+    /// Set "Private :: Do Not Upload" classifier, used for private projects.
+    ///
+    /// This also restricts `rye publish` to the repository configured in
+    /// `default.private-repository` or disables publishing altogether.
     #[arg(long)]
     private: bool,
+    /// Import the project from Poetry, Pipenv or setuptools.
+    ///
+    /// This converts the metadata and dependencies of a Poetry pyproject.toml,
+    /// a Pipfile (or Pipfile.lock) or a setup.py/setup.cfg.
+    #[arg(long = "import")]
+    import: bool,
+    /// Create the project from a template.
+    ///
+    /// This is the name of a built-in template, a git URL (optionally with
+    /// `#<branch-or-tag>`) or a folder.
+    #[arg(long, conflicts_with = "import")]
+    template: Option<String>,
+    /// List the built-in templates.
+    #[arg(long)]
+    list_templates: bool,
+    /// Create a single-file script with inline metadata (PEP 723) instead.
+    ///
+    /// The path is the file to create.  Run it with `rye run --script`.
+    #[arg(long, conflicts_with_all = ["import", "template"])]
+    script: bool,
 }
 
 /// The pyproject.toml template
@@ -91,6 +119,13 @@ build-backend = "pdm.backend"
 
 [tool.rye]
 managed = true
+{%- if private %}
+{%- if publish_repository %}
+publish = [{{ publish_repository }}]
+{%- else %}
+publish = false
+{%- endif %}
+{%- endif %}
 
 {%- if build_system == "hatchling" %}
 
@@ -144,16 +179,47 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     let license_file = dir.join("LICENSE.txt");
     let python_version_file = dir.join(".python-version");
 
-    if toml.is_file() {
+    if cmd.list_templates {
+        for (name, description) in builtin_templates() {
+            println!("{} ({})", style(name).cyan(), description);
+        }
+        return Ok(());
+    }
+    if cmd.script {
+        return init_script(&dir, cmd.min_py.as_deref());
+    }
+
+    let imported = if cmd.import {
+        let imported = import_project(&dir, CommandOutput::Normal)?;
+        for msg in &imported.warnings {
+            warn(Warning::ImportIncomplete, msg);
+        }
+        Some(imported)
+    } else {
+        None
+    };
+
+    // a poetry pyproject.toml is replaced by the import
+    if toml.is_file() && imported.as_ref().map(|x| x.source) != Some(ImportSource::Poetry) {
         bail!("pyproject.toml already exists");
     }
 
+    let template = cmd
+        .template
+        .as_deref()
+        .map(ProjectTemplate::load)
+        .transpose()?;
+
     // fail silently if it already exists or cannot be created.
     fs::create_dir_all(&dir).ok();
 
     // Write pyproject.toml
-    let mut requires_python = match cmd.min_py {
-        Some(py) => format!(">= {}", py),
+    let mut requires_python = match cmd
+        .min_py
+        .map(|py| format!(">= {}", py))
+        .or_else(|| imported.as_ref()?.requires_python.clone())
+    {
+        Some(requires_python) => requires_python,
         None => get_python_version_request_from_pyenv_pin(&dir)
             .map(|x| format!(">= {}.{}", x.major, x.minor.unwrap_or_default()))
             .unwrap_or_else(|| cfg.default_requires_python()),
@@ -167,29 +233,56 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         },
     };
     if !cmd.no_pin
-        && !VersionSpecifier::from_str(&requires_python)
+        && !VersionSpecifiers::from_str(&requires_python)
             .map_err(|msg| anyhow!("invalid version specifier: {}", msg))?
             .contains(&py.clone().into())
     {
-        eprintln!(
-            "{} conflicted python version with project's requires-python, will auto fix it.",
-            style("warning:").red()
+        warn(
+            Warning::RequiresPythonConflict,
+            "conflicted python version with project's requires-python, will auto fix it.",
         );
         requires_python = format!(">= {}.{}", py.major, py.minor.unwrap_or_default());
     }
 
     // In some cases there might not be a file name (eg: docker root)
-    let name = slug::slugify(cmd.name.unwrap_or_else(|| {
-        dir.file_name()
-            .map(|x| x.to_string_lossy().into_owned())
-            .unwrap_or_else(|| "unknown".into())
-    }));
-    let version = "0.1.0";
+    let name = slug::slugify(
+        cmd.name
+            .or_else(|| imported.as_ref()?.name.clone())
+            .unwrap_or_else(|| {
+                dir.file_name()
+                    .map(|x| x.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "unknown".into())
+            }),
+    );
+    let version = imported
+        .as_ref()
+        .and_then(|x| x.version.clone())
+        .unwrap_or_else(|| "0.1.0".into());
     let author = get_default_author();
+    // an imported project keeps its own license
     let license = match cmd.license {
         Some(license) => Some(license),
+        None if imported.is_some() => None,
         None => cfg.default_license(),
     };
+
+    // the files of the template take precedence over the generated ones
+    let template_pyproject = match template {
+        Some(ref template) => template.render(
+            &dir,
+            &context! {
+                name,
+                module_name => name.replace('-', "_"),
+                version,
+                author_name => author.as_ref().map(|x| &x.0),
+                author_email => author.as_ref().map(|x| &x.1),
+                requires_python,
+                python_version => py.to_string(),
+            },
+        )?,
+        None => None,
+    };
+
     if license.is_some() && !license_file.is_file() {
         let license_obj: &dyn License = license
             .clone()
@@ -216,7 +309,7 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     // create a readme if one is missing
     let with_readme = if readme.is_file() {
         true
-    } else if !cmd.no_readme {
+    } else if !cmd.no_readme && imported.is_none() {
         let rv = env.render_named_str(
             "README.txt",
             README_TEMPLATE,
@@ -237,6 +330,11 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     };
 
     let private = cmd.private;
+    let publish_repository = if private {
+        cfg.default_private_repository()
+    } else {
+        None
+    };
 
     let rv = env.render_named_str(
         "pyproject.json",
@@ -250,12 +348,27 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
             with_readme,
             build_system,
             private,
+            publish_repository,
         },
     )?;
+    let rv = if imported.is_some() || template_pyproject.is_some() {
+        let mut doc = rv
+            .parse::<Document>()
+            .context("failed to parse generated pyproject.toml")?;
+        if let Some(ref imported) = imported {
+            imported.apply(&mut doc);
+        }
+        if let Some(ref template_doc) = template_pyproject {
+            merge_toml(doc.as_table_mut(), template_doc.as_table());
+        }
+        doc.to_string()
+    } else {
+        rv
+    };
     fs::write(&toml, rv).context("failed to write pyproject.toml")?;
 
     let src_dir = dir.join("src");
-    if !src_dir.is_dir() {
+    if !src_dir.is_dir() && imported.is_none() {
         let project_dir = src_dir.join(name.replace('-', "_"));
         fs::create_dir_all(&project_dir).ok();
         let rv = env.render_named_str("__init__.py", INIT_PY_TEMPLATE, context! { name })?;
@@ -282,6 +395,9 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         }
     }
 
+    if let Some(ref imported) = imported {
+        eprintln!("Imported project from {}", imported.source.describe());
+    }
     eprintln!(
         "{} Initialized project in {}",
         style("success:").green(),
@@ -291,3 +407,34 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Creates a single-file script with inline metadata.
+fn init_script(path: &Path, min_py: Option<&str>) -> Result<(), Error> {
+    if path.is_dir() {
+        bail!("pass the path of the script to create");
+    }
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+    let requires_python = match min_py {
+        Some(py) => format!(">= {}", py),
+        None => Config::current().default_requires_python(),
+    };
+    let name = path
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "script".into());
+    let rv = Environment::new().render_str(SCRIPT_TEMPLATE, context! { name, requires_python })?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    fs::write(path, rv).with_context(|| format!("failed to write {}", path.display()))?;
+
+    eprintln!(
+        "{} Initialized script in {}",
+        style("success:").green(),
+        path.display()
+    );
+    eprintln!("  Run `rye run --script {}` to run it", path.display());
+    Ok(())
+}
@@ -1,11 +1,11 @@
 use std::path::Path;
 
 use anyhow::{Context, Error};
-use clap::Parser;
+use clap::{ArgAction, Parser};
 use pep508_rs::Requirement;
 
 use crate::cli::add::ReqExtras;
-use crate::installer::{install, resolve_local_requirement};
+use crate::installer::{get_tool_info, install, resolve_local_requirement};
 use crate::sources::PythonVersionRequest;
 use crate::utils::CommandOutput;
 
@@ -23,14 +23,21 @@ pub struct Args {
     #[arg(long)]
     extra_requirement: Vec<String>,
     /// Optionally the Python version to use.
+    ///
+    /// The version is recorded and reused when the tool is reinstalled.
     #[arg(short, long)]
     python: Option<String>,
+    /// Run the tool with the Python version of the project it's invoked in.
+    ///
+    /// Outside of a project the tool runs on the Python it was installed with.
+    #[arg(long)]
+    match_project_python: bool,
     /// Force install the package even if it's already there.
     #[arg(short, long)]
     force: bool,
-    /// Enables verbose diagnostics.
-    #[arg(short, long)]
-    verbose: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
     /// Turns off all output.
     #[arg(short, long, conflicts_with = "verbose")]
     quiet: bool,
@@ -51,23 +58,23 @@ pub fn execute(mut cmd: Args) -> Result<(), Error> {
         extra_requirements.push(handle_requirement(&req, output, false)?);
     }
 
-    let py_ver: PythonVersionRequest = match cmd.python {
-        Some(ref py) => py.parse()?,
-        None => PythonVersionRequest {
-            kind: None,
-            major: 3,
-            minor: None,
-            patch: None,
-            suffix: None,
-        },
+    // if no python version is provided, we stick to the one that the tool
+    // was previously installed with.
+    let py_ver: Option<PythonVersionRequest> = match cmd.python {
+        Some(ref py) => Some(py.parse()?),
+        None => get_tool_info(&requirement.name)
+            .and_then(|info| info.python)
+            .map(|py| py.parse())
+            .transpose()?,
     };
 
     install(
         requirement,
-        &py_ver,
+        py_ver.as_ref(),
         cmd.force,
         &cmd.include_dep,
         &extra_requirements,
+        cmd.match_project_python,
         output,
     )?;
     Ok(())
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Error};
+use clap::{Parser, ValueEnum};
+use console::style;
+use serde::{Deserialize, Serialize};
+
+use crate::pyproject::PyProject;
+use crate::utils::{get_venv_python_bin, QuietExit};
+
+const LICENSE_SCRIPT: &str = r#"
+import sys
+import json
+
+if sys.version_info >= (3, 8):
+    from importlib.metadata import distributions
+else:
+    from importlib_metadata import distributions
+
+result = []
+for dist in distributions():
+    meta = dist.metadata
+    license = meta.get("License-Expression") or meta.get("License") or None
+    if license is not None:
+        license = license.strip().splitlines()[0] if license.strip() else None
+    result.append({
+        "name": meta["Name"],
+        "version": meta["Version"],
+        "license": license,
+        "classifiers": [
+            c.split(" :: ")[-1]
+            for c in meta.get_all("Classifier") or ()
+            if c.startswith("License ::")
+        ],
+    })
+
+print(json.dumps(result))
+"#;
+
+/// Licenses that are not more specific than this are ignored in favor of classifiers.
+const UNSPECIFIC_LICENSES: &[&str] = &["UNKNOWN", "OSI Approved", "Other/Proprietary License"];
+
+#[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+enum Format {
+    Json,
+}
+
+/// Prints a summary of the licenses of the installed packages.
+///
+/// The license information is read from the metadata of the packages that
+/// are installed into the virtualenv of the project, so make sure to run
+/// `rye sync` first.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Fail if a package uses a license of this family (eg: `GPL`).
+    ///
+    /// License families are matched case-insensitively against the words of
+    /// the license names of the packages, so `GPL` matches `GPL-3.0-only` and
+    /// `GPLv3` but not `LGPL-2.1`.  Can be supplied multiple times.
+    #[arg(long)]
+    fail_on: Vec<String>,
+    /// Request parseable output format rather than lines.
+    #[arg(long)]
+    format: Option<Format>,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PackageMetadata {
+    name: String,
+    version: String,
+    license: Option<String>,
+    classifiers: Vec<String>,
+}
+
+/// Output structure for licenses --format=json
+#[derive(Serialize, Debug)]
+struct PackageLicense {
+    name: String,
+    version: String,
+    license: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rejected_by: Option<String>,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let python = get_venv_python_bin(&project.venv_path());
+    if !python.is_file() {
+        bail!("project is not synced.  Run `rye sync` first");
+    }
+
+    let out = Command::new(&python)
+        .arg("-c")
+        .arg(LICENSE_SCRIPT)
+        .env("PYTHONWARNINGS", "ignore")
+        .stdout(Stdio::piped())
+        .output()
+        .context("unable to read package metadata from virtualenv")?;
+    if !out.status.success() {
+        bail!(
+            "failed to read package metadata\n{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    }
+    let packages: Vec<PackageMetadata> =
+        serde_json::from_slice(&out.stdout).context("could not parse package metadata")?;
+
+    let mut licenses = packages
+        .into_iter()
+        .map(|pkg| {
+            let license = pick_license(&pkg);
+            let rejected_by = cmd
+                .fail_on
+                .iter()
+                .find(|family| matches_license_family(&license, family))
+                .cloned();
+            PackageLicense {
+                name: pkg.name,
+                version: pkg.version,
+                license,
+                rejected_by,
+            }
+        })
+        .collect::<Vec<_>>();
+    licenses.sort_by_cached_key(|x| x.name.to_ascii_lowercase());
+
+    if let Some(Format::Json) = cmd.format {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &licenses)?;
+        println!();
+    } else {
+        let mut by_license = BTreeMap::<&str, Vec<&PackageLicense>>::new();
+        for pkg in &licenses {
+            by_license.entry(&pkg.license).or_default().push(pkg);
+        }
+        for (license, packages) in by_license {
+            println!("{} ({})", style(license).yellow(), packages.len());
+            for pkg in packages {
+                if pkg.rejected_by.is_some() {
+                    println!("  {} {}", style(&pkg.name).red(), style(&pkg.version).dim());
+                } else {
+                    println!(
+                        "  {} {}",
+                        style(&pkg.name).cyan(),
+                        style(&pkg.version).dim()
+                    );
+                }
+            }
+        }
+    }
+
+    let rejected = licenses
+        .iter()
+        .filter(|x| x.rejected_by.is_some())
+        .collect::<Vec<_>>();
+    if !rejected.is_empty() {
+        eprintln!();
+        for pkg in &rejected {
+            eprintln!(
+                "{}: {} ({}) uses disallowed license {} ({})",
+                style("error").red(),
+                pkg.name,
+                pkg.version,
+                style(&pkg.license).yellow(),
+                pkg.rejected_by.as_deref().unwrap_or_default(),
+            );
+        }
+        return Err(QuietExit(1).into());
+    }
+
+    Ok(())
+}
+
+/// Picks the most descriptive license from the package metadata.
+fn pick_license(pkg: &PackageMetadata) -> String {
+    let classifiers = pkg
+        .classifiers
+        .iter()
+        .filter(|x| !UNSPECIFIC_LICENSES.contains(&x.as_str()))
+        .map(|x| x.as_str())
+        .collect::<Vec<_>>();
+    match pkg.license.as_deref() {
+        Some(license) if !UNSPECIFIC_LICENSES.contains(&license) => license.to_string(),
+        _ if !classifiers.is_empty() => classifiers.join(" OR "),
+        _ => "UNKNOWN".into(),
+    }
+}
+
+/// Checks if a license belongs to a given license family.
+///
+/// Both are split into words at non-alphanumeric characters and the words of
+/// the family have to show up in the license in a row.  The last one may be
+/// followed by a version (`GPLv3`), but a family does not match a word it is
+/// only a part of, so `GPL` does not match `LGPL` or `AGPL`.
+fn matches_license_family(license: &str, family: &str) -> bool {
+    let family = license_words(family);
+    let (last, head) = match family.split_last() {
+        Some(split) => split,
+        None => return false,
+    };
+    license_words(license)
+        .windows(family.len())
+        .any(|words| words[..head.len()] == *head && matches_license_word(&words[head.len()], last))
+}
+
+fn license_words(value: &str) -> Vec<String> {
+    value
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_ascii_lowercase())
+        .collect()
+}
+
+fn matches_license_word(word: &str, family: &str) -> bool {
+    match word.strip_prefix(family) {
+        Some("") => true,
+        Some(rest) => {
+            let version = rest.strip_prefix('v').unwrap_or(rest);
+            !version.is_empty() && version.bytes().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+#[test]
+fn test_matches_license_family() {
+    assert!(matches_license_family("GPL-3.0-only", "GPL"));
+    assert!(matches_license_family("GPL-3.0-or-later", "gpl-3.0"));
+    assert!(matches_license_family(
+        "GNU General Public License v3 (GPLv3)",
+        "GPL"
+    ));
+    assert!(!matches_license_family("LGPL-2.1-or-later", "GPL"));
+    assert!(!matches_license_family("AGPL-3.0-only", "GPL"));
+    assert!(!matches_license_family(
+        "GNU Lesser General Public License v3 (LGPLv3)",
+        "GPL"
+    ));
+    assert!(matches_license_family("LGPL-2.1-or-later", "LGPL"));
+    assert!(!matches_license_family("GPL-2.0-only", "GPL-3.0"));
+    assert!(matches_license_family("MIT OR Apache-2.0", "MIT"));
+    assert!(matches_license_family("MIT OR Apache-2.0", "Apache"));
+    assert!(matches_license_family("MIT OR Apache-2.0", "apache-2.0"));
+    assert!(!matches_license_family("MIT OR Apache-2.0", "GPL"));
+    assert!(!matches_license_family("MIT-0", "MIT-CMU"));
+    assert!(!matches_license_family("MIT", ""));
+}
@@ -0,0 +1,141 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{bail, Context, Error};
+use clap::{Parser, ValueEnum};
+use console::style;
+use serde::{Deserialize, Serialize};
+
+use crate::bootstrap::ensure_self_venv;
+use crate::consts::VENV_BIN;
+use crate::lock::locked_package_names;
+use crate::pyproject::{normalize_package_name, ExpandedSources, PyProject};
+use crate::utils::{get_venv_python_bin, set_proxy_variables, CommandOutput};
+
+#[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+enum Format {
+    Json,
+}
+
+/// Lists the packages installed in the virtualenv of the project.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Request parseable output format rather than lines.
+    #[arg(long)]
+    format: Option<Format>,
+    /// Only list packages with newer releases on the sources of the project.
+    #[arg(long)]
+    outdated: bool,
+    /// Only list packages that are not in any lockfile of the workspace.
+    #[arg(long)]
+    not_in_lock: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+}
+
+/// A package as reported by `pip list`.
+#[derive(Deserialize, Debug)]
+struct PipPackage {
+    name: String,
+    version: String,
+    editable_project_location: Option<String>,
+    latest_version: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct Package {
+    name: String,
+    version: String,
+    editable: bool,
+    locked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latest: Option<String>,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let venv = project.venv_path();
+    if !get_venv_python_bin(&venv).is_file() {
+        bail!("project is not synced.  Run `rye sync` first");
+    }
+    let self_venv = ensure_self_venv(CommandOutput::Normal)?;
+
+    let mut pip_list = Command::new(self_venv.join(VENV_BIN).join("pip"));
+    pip_list
+        .arg("--python")
+        .arg(get_venv_python_bin(&venv))
+        .arg("list")
+        .arg("--format=json")
+        .env("PYTHONWARNINGS", "ignore");
+    if cmd.outdated {
+        pip_list.arg("--outdated");
+        ExpandedSources::from_sources(&project.sources()?)?.add_as_pip_args(&mut pip_list);
+        set_proxy_variables(&mut pip_list);
+    }
+    let out = pip_list
+        .output()
+        .context("unable to list installed packages")?;
+    if !out.status.success() {
+        bail!(
+            "failed to list installed packages\n{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    }
+    let installed: Vec<PipPackage> = serde_json::from_slice(&out.stdout)
+        .context("could not parse list of installed packages")?;
+
+    // the workspace is installed from all of its lockfiles (production, dev
+    // and the groups), editables are the members of the workspace.
+    let mut locked = HashSet::new();
+    for entry in fs::read_dir(project.workspace_path())? {
+        let path = entry?.path();
+        let is_lockfile = path
+            .file_name()
+            .and_then(|x| x.to_str())
+            .map_or(false, |x| {
+                x.starts_with("requirements") && x.ends_with(".lock")
+            });
+        if is_lockfile {
+            locked.extend(locked_package_names(&fs::read_to_string(&path)?));
+        }
+    }
+
+    let packages = installed
+        .into_iter()
+        .map(|pkg| {
+            let editable = pkg.editable_project_location.is_some();
+            Package {
+                locked: editable || locked.contains(&normalize_package_name(&pkg.name)),
+                name: pkg.name,
+                version: pkg.version,
+                editable,
+                latest: pkg.latest_version,
+            }
+        })
+        .filter(|pkg| !cmd.not_in_lock || !pkg.locked)
+        .collect::<Vec<_>>();
+
+    if let Some(Format::Json) = cmd.format {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &packages)?;
+        println!();
+        return Ok(());
+    }
+    for pkg in packages {
+        print!("{} {}", style(&pkg.name).cyan(), pkg.version);
+        if let Some(ref latest) = pkg.latest {
+            print!(" -> {}", style(latest).green());
+        }
+        if pkg.editable {
+            print!(" {}", style("(editable)").dim());
+        } else if !pkg.locked {
+            print!(" {}", style("(not in lockfile)").yellow());
+        }
+        println!();
+    }
+    Ok(())
+}
@@ -1,18 +1,20 @@
 use std::path::PathBuf;
 
 use anyhow::Error;
-use clap::Parser;
+use clap::{ArgAction, Parser};
 
-use crate::lock::LockOptions;
+use crate::hooks::Hooks;
+use crate::lock::{parse_exclude_newer, LockOptions, LockPlatform};
+use crate::pyproject::PyProject;
 use crate::sync::{sync, SyncMode, SyncOptions};
 use crate::utils::CommandOutput;
 
 /// Updates the lockfiles without installing dependencies.
 #[derive(Parser, Debug)]
 pub struct Args {
-    /// Enables verbose diagnostics.
-    #[arg(short, long)]
-    verbose: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
     /// Turns off all output.
     #[arg(short, long, conflicts_with = "verbose")]
     quiet: bool,
@@ -31,6 +33,36 @@ pub struct Args {
     /// Enables all features.
     #[arg(long)]
     all_features: bool,
+    /// Also write a lockfile for this dependency group or optional dependency.
+    #[arg(long, value_name = "GROUP")]
+    group: Vec<String>,
+    /// Write a lockfile for every dependency group and optional dependency.
+    #[arg(long)]
+    all_groups: bool,
+    /// Record hashes of all packages in the lockfile.
+    #[arg(long)]
+    generate_hashes: bool,
+    /// Resolve the lockfiles for every supported platform at once.
+    #[arg(long, conflicts_with = "platform")]
+    universal: bool,
+    /// Resolve the lockfiles for this platform (eg: linux-x86_64, macos-arm64).
+    #[arg(long, value_name = "PLATFORM")]
+    platform: Vec<String>,
+    /// Resolve the lockfiles for this Python version (eg: 3.11).
+    #[arg(long, value_name = "VERSION")]
+    python_version: Option<String>,
+    /// Always build this package from source (`:all:` for all packages).
+    #[arg(long, value_name = "PACKAGE")]
+    no_binary: Vec<String>,
+    /// Never build this package from source (`:all:` for all packages).
+    #[arg(long, value_name = "PACKAGE")]
+    only_binary: Vec<String>,
+    /// Ignore packages uploaded after this date or timestamp.
+    #[arg(long, value_name = "DATE")]
+    exclude_newer: Option<String>,
+    /// Report the decisions of the resolver while locking.
+    #[arg(long, conflicts_with = "quiet")]
+    verbose_resolution: bool,
     /// Use this pyproject.toml file
     #[arg(long, value_name = "PYPROJECT_TOML")]
     pyproject: Option<PathBuf>,
@@ -38,18 +70,42 @@ pub struct Args {
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let hooks = Hooks::load(&PyProject::load_or_discover(cmd.pyproject.as_deref())?)?;
+    hooks.run("pre-lock", output)?;
     sync(SyncOptions {
         output,
         mode: SyncMode::LockOnly,
         lock_options: LockOptions {
             update: cmd.update,
+            constraints: Vec::new(),
             update_all: cmd.update_all,
             pre: cmd.pre,
             features: cmd.features,
             all_features: cmd.all_features,
+            generate_hashes: cmd.generate_hashes,
+            platforms: if cmd.universal {
+                LockPlatform::all_names()
+            } else {
+                cmd.platform
+            },
+            python_version: cmd.python_version,
+            target: None,
+            record_files: None,
+            exclude_newer: cmd
+                .exclude_newer
+                .as_deref()
+                .map(parse_exclude_newer)
+                .transpose()?,
+            verbose_resolution: cmd.verbose_resolution,
+            no_binary: cmd.no_binary,
+            only_binary: cmd.only_binary,
+            build_settings: Default::default(),
         },
+        lock_groups: cmd.group,
+        all_groups: cmd.all_groups,
         pyproject: cmd.pyproject,
+        env: None,
         ..SyncOptions::default()
     })?;
-    Ok(())
+    hooks.run("post-lock", output)
 }
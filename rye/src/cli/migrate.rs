@@ -0,0 +1,354 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Error};
+use clap::{Parser, ValueEnum};
+use console::style;
+use serde::Serialize;
+use toml_edit::{value, ArrayOfTables, Document, TableLike};
+use url::Url;
+
+use crate::cli::check_project::KNOWN_RYE_KEYS;
+use crate::config::Config;
+use crate::pyproject::PyProject;
+use crate::utils::QuietExit;
+
+/// Keys of the config that were renamed: ID, table, old key, new key.
+const RENAMED_CONFIG_KEYS: &[(&str, &str, &str, &str)] = &[
+    (
+        "RYEM001",
+        "default",
+        "dependency_operator",
+        "dependency-operator",
+    ),
+    (
+        "RYEM002",
+        "behavior",
+        "force_rye_managed",
+        "force-rye-managed",
+    ),
+];
+
+#[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+enum Format {
+    Json,
+}
+
+/// Migrates configuration and projects after an update of rye.
+#[derive(Parser, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    command: SubCommand,
+}
+
+/// Finds deprecated configuration and migrates it.
+///
+/// This checks the rye config and the pyproject.toml of the current project.
+/// Every finding carries a stable ID.  Without `--fix` the command fails if
+/// something needs migrating, so it can be used as a check in scripts.
+#[derive(Parser, Debug)]
+pub struct SelfArgs {
+    /// Rewrite the configuration where possible.
+    #[arg(long)]
+    fix: bool,
+    /// Request parseable output format rather than lines.
+    #[arg(long)]
+    format: Option<Format>,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    #[command(name = "self")]
+    SelfMigrate(SelfArgs),
+}
+
+/// A single deprecation.
+#[derive(Serialize, Debug)]
+struct Finding {
+    id: &'static str,
+    path: PathBuf,
+    key: String,
+    message: String,
+    fixable: bool,
+    fixed: bool,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    match cmd.command {
+        SubCommand::SelfMigrate(cmd) => migrate_self(cmd),
+    }
+}
+
+fn migrate_self(cmd: SelfArgs) -> Result<(), Error> {
+    let mut findings = Vec::new();
+
+    let mut config = Config::current();
+    let config_path = config.path().to_path_buf();
+    let changed = {
+        let doc = Arc::make_mut(&mut config).doc_mut();
+        let mut changed = migrate_config(doc, &config_path, cmd.fix, &mut findings);
+        changed |= migrate_sources(
+            doc.get_mut("sources")
+                .and_then(|x| x.as_array_of_tables_mut()),
+            "sources",
+            config_path.parent().unwrap_or(Path::new(".")),
+            &config_path,
+            cmd.fix,
+            &mut findings,
+        );
+        changed
+    };
+    if changed {
+        config.save()?;
+    }
+
+    // outside of a project only the config is migrated
+    let pyproject = match cmd.pyproject {
+        Some(ref path) => Some(PyProject::load(path)?),
+        None => PyProject::discover().ok(),
+    };
+    if let Some(pyproject) = pyproject {
+        let path = pyproject.toml_path();
+        let mut doc = read_toml(&path)?;
+        let mut changed = migrate_pyproject(&mut doc, &path, cmd.fix, &mut findings);
+        changed |= migrate_sources(
+            doc.get_mut("tool")
+                .and_then(|x| x.get_mut("rye"))
+                .and_then(|x| x.get_mut("sources"))
+                .and_then(|x| x.as_array_of_tables_mut()),
+            "tool.rye.sources",
+            &pyproject.root_path(),
+            &path,
+            cmd.fix,
+            &mut findings,
+        );
+        if changed {
+            fs::write(&path, doc.to_string())
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+    }
+
+    if let Some(Format::Json) = cmd.format {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &findings)?;
+        println!();
+    } else if findings.is_empty() {
+        eprintln!("Nothing to migrate");
+    } else {
+        for finding in &findings {
+            let status = if finding.fixed {
+                style("fixed").green()
+            } else if finding.fixable {
+                style("fixable").yellow()
+            } else {
+                style("manual").red()
+            };
+            println!(
+                "{}[{}]: {}: {}: {}",
+                status,
+                finding.id,
+                finding.path.display(),
+                style(&finding.key).bold(),
+                finding.message
+            );
+        }
+        if !cmd.fix && findings.iter().any(|x| x.fixable) {
+            eprintln!("Run `rye migrate self --fix` to apply the fixes");
+        }
+    }
+
+    if findings.iter().any(|x| !x.fixed) {
+        return Err(QuietExit(1).into());
+    }
+    Ok(())
+}
+
+fn read_toml(path: &Path) -> Result<Document, Error> {
+    fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .parse::<Document>()
+        .with_context(|| format!("failed to parse {}", path.display()))
+}
+
+/// Migrates keys of the config that were renamed.
+///
+/// The old keys are still honored, but only if the new ones are not set.
+fn migrate_config(doc: &mut Document, path: &Path, fix: bool, findings: &mut Vec<Finding>) -> bool {
+    let mut changed = false;
+    for &(id, table_name, old, new) in RENAMED_CONFIG_KEYS {
+        if let Some(table) = doc.get_mut(table_name).and_then(|x| x.as_table_like_mut()) {
+            changed |= rename_key(
+                table,
+                id,
+                &format!("{}.", table_name),
+                old,
+                new,
+                "was renamed",
+                path,
+                fix,
+                findings,
+            );
+        }
+    }
+    changed
+}
+
+/// Migrates keys of `tool.rye` that were spelled with underscores.
+///
+/// Unlike in the config, these keys were never honored in the
+/// pyproject.toml, so renaming them turns them on.
+fn migrate_pyproject(
+    doc: &mut Document,
+    path: &Path,
+    fix: bool,
+    findings: &mut Vec<Finding>,
+) -> bool {
+    let table = match doc
+        .get_mut("tool")
+        .and_then(|x| x.get_mut("rye"))
+        .and_then(|x| x.as_table_like_mut())
+    {
+        Some(table) => table,
+        None => return false,
+    };
+    let keys = table
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| key.contains('_'))
+        .filter_map(|key| {
+            let new = key.replace('_', "-");
+            KNOWN_RYE_KEYS
+                .iter()
+                .find(|x| **x == new)
+                .map(|new| (key, *new))
+        })
+        .collect::<Vec<_>>();
+    let mut changed = false;
+    for (old, new) in keys {
+        changed |= rename_key(
+            table,
+            "RYEM003",
+            "tool.rye.",
+            &old,
+            new,
+            "is ignored as it's spelled with underscores",
+            path,
+            fix,
+            findings,
+        );
+    }
+    changed
+}
+
+/// Migrates sources that refer to a local folder with a `file://` URL.
+///
+/// Folders with wheels are configured with `path` now, which is resolved
+/// against the folder of the file the source is configured in.
+fn migrate_sources(
+    sources: Option<&mut ArrayOfTables>,
+    prefix: &str,
+    base: &Path,
+    path: &Path,
+    fix: bool,
+    findings: &mut Vec<Finding>,
+) -> bool {
+    let mut changed = false;
+    for source in sources.into_iter().flat_map(|x| x.iter_mut()) {
+        if source.get("type").and_then(|x| x.as_str()) != Some("find-links")
+            || source.contains_key("path")
+        {
+            continue;
+        }
+        let folder = match source
+            .get("url")
+            .and_then(|x| x.as_str())
+            .and_then(|x| Url::parse(x).ok())
+            .filter(|x| x.scheme() == "file")
+            .and_then(|x| x.to_file_path().ok())
+        {
+            Some(folder) => folder,
+            None => continue,
+        };
+        let new_path = folder
+            .strip_prefix(base)
+            .unwrap_or(&folder)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let name = source
+            .get("name")
+            .and_then(|x| x.as_str())
+            .unwrap_or("?")
+            .to_string();
+        if fix {
+            source.remove("url");
+            source.insert("path", value(new_path.as_str()));
+            changed = true;
+        }
+        findings.push(Finding {
+            id: "RYEM004",
+            path: path.to_path_buf(),
+            key: format!("{}[{}].url", prefix, name),
+            message: format!(
+                "refers to a local folder, use path = \"{}\" instead",
+                new_path
+            ),
+            fixable: true,
+            fixed: fix,
+        });
+    }
+    changed
+}
+
+#[allow(clippy::too_many_arguments)]
+fn rename_key(
+    table: &mut dyn TableLike,
+    id: &'static str,
+    prefix: &str,
+    old: &str,
+    new: &str,
+    reason: &str,
+    path: &Path,
+    fix: bool,
+    findings: &mut Vec<Finding>,
+) -> bool {
+    if table.get(old).is_none() {
+        return false;
+    }
+    // if both keys are set, it's up to the user which value to keep.
+    if table.get(new).is_some() {
+        findings.push(Finding {
+            id,
+            path: path.to_path_buf(),
+            key: format!("{}{}", prefix, old),
+            message: format!(
+                "is ignored as {}{} is also set. Remove one of them",
+                prefix, new
+            ),
+            fixable: false,
+            fixed: false,
+        });
+        return false;
+    }
+    let fixed = fix
+        && match table.remove(old) {
+            Some(item) => {
+                table.insert(new, item);
+                true
+            }
+            None => false,
+        };
+    findings.push(Finding {
+        id,
+        path: path.to_path_buf(),
+        key: format!("{}{}", prefix, old),
+        message: format!("{}, use {}{} instead", reason, prefix, new),
+        fixable: true,
+        fixed,
+    });
+    fixed
+}
@@ -1,34 +1,57 @@
 use std::env;
+use std::path::PathBuf;
 
 use anyhow::Error;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 mod add;
+mod batch;
+mod bisect;
 mod build;
+mod bundle;
+mod cache;
+mod check_project;
 mod config;
+mod doctor;
+mod export;
 mod fetch;
 mod init;
 mod install;
+mod licenses;
+mod list;
 mod lock;
 mod make_req;
+mod migrate;
 mod pin;
 mod publish;
 mod remove;
 mod run;
 mod rye;
+mod search;
 mod shell;
 mod shim;
 mod show;
 mod sync;
+mod test;
 mod toolchain;
 mod tools;
+#[cfg(feature = "tui")]
+mod ui;
 mod uninstall;
+mod vendor;
 mod version;
+mod workspace;
+mod x;
 
 use git_testament::git_testament;
 
 use crate::bootstrap::SELF_PYTHON_TARGET_VERSION;
+use crate::errors::{set_json_errors, DeniedWarnings};
+use crate::logging::Level;
 use crate::platform::symlinks_supported;
+use crate::pyproject::PyProject;
+use crate::utils::{is_noninteractive, set_noninteractive};
+use crate::warnings::{denied_warnings_emitted, deny_warnings, suppress_warnings};
 
 git_testament!(TESTAMENT);
 
@@ -41,37 +64,82 @@ struct Args {
     /// Print the version
     #[arg(long)]
     version: bool,
+    /// Never prompt and fail instead (also enabled by RYE_NONINTERACTIVE=1).
+    #[arg(long, global = true)]
+    ci: bool,
+    /// Fail the command if it emits warnings that are not suppressed.
+    #[arg(long, global = true)]
+    deny_warnings: bool,
+    /// How failures are reported (json is also enabled by RYE_OUTPUT=json).
+    #[arg(long, global = true, value_name = "FORMAT")]
+    error_format: Option<ErrorFormat>,
+    /// Log what rye does at this level (also enabled by RYE_LOG=<level>).
+    #[arg(long, global = true, value_name = "LEVEL")]
+    log_level: Option<Level>,
+    /// Write the log to this file instead of stderr (also RYE_LOG_FILE).
+    #[arg(long, global = true, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq)]
+#[value(rename_all = "snake_case")]
+enum ErrorFormat {
+    Text,
+    Json,
 }
 
 #[derive(Parser, Debug)]
 enum Command {
     Add(add::Args),
+    Batch(batch::Args),
+    Bisect(bisect::Args),
     Build(build::Args),
+    Bundle(bundle::Args),
+    Cache(cache::Args),
+    CheckProject(check_project::Args),
     Config(config::Args),
+    Doctor(doctor::Args),
+    Export(export::Args),
     Fetch(fetch::Args),
     Init(init::Args),
     Install(install::Args),
+    Licenses(licenses::Args),
+    List(list::Args),
     Lock(lock::Args),
     MakeReq(make_req::Args),
+    Migrate(migrate::Args),
     Pin(pin::Args),
     Publish(publish::Args),
     Remove(remove::Args),
     Run(run::Args),
+    Search(search::Args),
     Shell(shell::Args),
     Show(show::Args),
     Sync(sync::Args),
+    Test(test::Args),
     Toolchain(toolchain::Args),
     Tools(tools::Args),
+    #[cfg(feature = "tui")]
+    Ui(ui::Args),
     #[command(name = "self")]
     Rye(rye::Args),
     Uninstall(uninstall::Args),
+    Vendor(vendor::Args),
     Version(version::Args),
+    Workspace(workspace::Args),
+    X(x::Args),
 }
 
 pub fn execute() -> Result<(), Error> {
     // common initialization
     crate::platform::init()?;
     crate::config::load()?;
+    if is_noninteractive() {
+        set_noninteractive();
+    }
+
+    // shims do not parse arguments, so they only log from the environment
+    crate::logging::init(None, None)?;
 
     let args = env::args_os().collect::<Vec<_>>();
 
@@ -84,6 +152,24 @@ pub fn execute() -> Result<(), Error> {
     }
 
     let args = Args::parse();
+    if args.log_level.is_some() || args.log_file.is_some() {
+        crate::logging::init(args.log_level, args.log_file.as_deref())?;
+    }
+    if args.ci {
+        set_noninteractive();
+    }
+    if args.deny_warnings {
+        deny_warnings();
+    }
+    match args.error_format {
+        Some(ErrorFormat::Json) => set_json_errors(),
+        Some(ErrorFormat::Text) => env::remove_var("RYE_OUTPUT"),
+        None => {}
+    }
+    // a project that cannot be loaded is reported by the command itself
+    if let Ok(pyproject) = PyProject::discover() {
+        suppress_warnings(pyproject.suppressed_warnings());
+    }
     let cmd = if args.version {
         return print_version();
     } else if let Some(cmd) = args.command {
@@ -92,28 +178,49 @@ pub fn execute() -> Result<(), Error> {
         unreachable!()
     };
 
-    match cmd {
+    let rv = match cmd {
         Command::Add(cmd) => add::execute(cmd),
+        Command::Batch(cmd) => batch::execute(cmd),
+        Command::Bisect(cmd) => bisect::execute(cmd),
         Command::Build(cmd) => build::execute(cmd),
+        Command::Bundle(cmd) => bundle::execute(cmd),
+        Command::Cache(cmd) => cache::execute(cmd),
+        Command::CheckProject(cmd) => check_project::execute(cmd),
         Command::Config(cmd) => config::execute(cmd),
+        Command::Doctor(cmd) => doctor::execute(cmd),
+        Command::Export(cmd) => export::execute(cmd),
         Command::Fetch(cmd) => fetch::execute(cmd),
         Command::Init(cmd) => init::execute(cmd),
         Command::Install(cmd) => install::execute(cmd),
+        Command::Licenses(cmd) => licenses::execute(cmd),
+        Command::List(cmd) => list::execute(cmd),
         Command::Lock(cmd) => lock::execute(cmd),
         Command::MakeReq(cmd) => make_req::execute(cmd),
+        Command::Migrate(cmd) => migrate::execute(cmd),
         Command::Pin(cmd) => pin::execute(cmd),
         Command::Publish(cmd) => publish::execute(cmd),
         Command::Remove(cmd) => remove::execute(cmd),
         Command::Run(cmd) => run::execute(cmd),
+        Command::Search(cmd) => search::execute(cmd),
         Command::Shell(cmd) => shell::execute(cmd),
         Command::Show(cmd) => show::execute(cmd),
         Command::Sync(cmd) => sync::execute(cmd),
+        Command::Test(cmd) => test::execute(cmd),
         Command::Toolchain(cmd) => toolchain::execute(cmd),
         Command::Tools(cmd) => tools::execute(cmd),
+        #[cfg(feature = "tui")]
+        Command::Ui(cmd) => ui::execute(cmd),
         Command::Rye(cmd) => rye::execute(cmd),
         Command::Uninstall(cmd) => uninstall::execute(cmd),
+        Command::Vendor(cmd) => vendor::execute(cmd),
         Command::Version(cmd) => version::execute(cmd),
+        Command::Workspace(cmd) => workspace::execute(cmd),
+        Command::X(cmd) => x::execute(cmd),
+    };
+    if rv.is_ok() && denied_warnings_emitted() {
+        return Err(DeniedWarnings.into());
     }
+    rv
 }
 
 fn print_version() -> Result<(), Error> {
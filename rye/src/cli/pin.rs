@@ -10,6 +10,8 @@ use crate::platform::get_pinnable_version;
 use crate::pyproject::DiscoveryUnsuccessful;
 use crate::pyproject::PyProject;
 use crate::sources::PythonVersionRequest;
+use crate::sync::check_python_end_of_life;
+use crate::utils::CommandOutput;
 
 /// Pins a Python version to this project.
 ///
@@ -39,6 +41,7 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         .with_context(|| format!("'{}' is not a valid version", cmd.version))?;
     let to_write = get_pinnable_version(&req, cmd.relaxed)
         .ok_or_else(|| anyhow!("unsupported/unknown version for this platform"))?;
+    check_python_end_of_life(&to_write.parse()?, CommandOutput::Normal)?;
 
     let pyproject = match PyProject::load_or_discover(cmd.pyproject.as_deref()) {
         Ok(proj) => Some(proj),
@@ -1,29 +1,93 @@
+use std::fs;
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::str::FromStr;
 
 use age::{
     secrecy::{ExposeSecret, Secret},
     Decryptor, Encryptor,
 };
 use anyhow::{bail, Context, Error};
-use clap::Parser;
-use toml_edit::{Item, Table};
+use clap::{ArgAction, Parser};
+use console::style;
+use globset::GlobBuilder;
+use pep508_rs::Requirement;
+use toml_edit::{Document, Item, Table};
 use url::Url;
 
+use crate::auth::{keyring_get, keyring_set};
 use crate::bootstrap::ensure_self_venv;
+use crate::config::Config;
+use crate::hooks::Hooks;
+use crate::installer::ensure_ephemeral_tool;
+use crate::network::check_url;
 use crate::platform::{get_credentials, write_credentials};
-use crate::pyproject::PyProject;
-use crate::utils::{get_venv_python_bin, CommandOutput};
+use crate::pyproject::{PyProject, RepositoryRef};
+use crate::transcript::run_status;
+use crate::trusted_publishing::mint_token;
+use crate::utils::{ensure_interactive, get_venv_python_bin, CommandOutput};
+
+/// Validates the metadata of built packages beyond what `twine check` covers.
+///
+/// Errors (unknown or deprecated classifiers) are printed as `error: ...`
+/// and fail the check, incomplete metadata is only reported.
+const METADATA_CHECK_SCRIPT: &str = r#"
+import glob
+import sys
+from trove_classifiers import classifiers, deprecated_classifiers
+from twine.package import PackageFile
+
+failed = False
+for pattern in sys.argv[1:]:
+    for filename in sorted(glob.glob(pattern)) or [pattern]:
+        if not filename.endswith((".whl", ".tar.gz", ".zip")):
+            continue
+        meta = PackageFile.from_filename(filename, None).metadata_dictionary()
+        def report(kind, msg):
+            print(f"{filename}: {kind}: {msg}")
+        for classifier in meta.get("classifiers") or ():
+            if classifier in deprecated_classifiers:
+                replacements = deprecated_classifiers[classifier]
+                report("error", f"deprecated classifier '{classifier}'" + (
+                    f", use {', '.join(repr(x) for x in replacements)}" if replacements else ""
+                ))
+                failed = True
+            elif classifier not in classifiers and not classifier.startswith("Private ::"):
+                report("error", f"unknown classifier '{classifier}'")
+                failed = True
+        if not meta.get("summary"):
+            report("warning", "missing summary (project.description)")
+        if not meta.get("license") and not meta.get("license_expression") and not any(
+            x.startswith("License ::") for x in meta.get("classifiers") or ()
+        ):
+            report("warning", "missing license")
+        if not meta.get("requires_python"):
+            report("warning", "missing requires-python")
+        if not meta.get("project_urls") and not meta.get("home_page"):
+            report("warning", "missing project URLs (project.urls)")
+        if not any(meta.get(x) for x in ("author", "author_email", "maintainer", "maintainer_email")):
+            report("warning", "missing authors or maintainers")
+
+sys.exit(1 if failed else 0)
+"#;
+
+/// The packages that create and upload attestations.
+const ATTESTATIONS_REQUIREMENT: &str = "pypi-attestations>=0.0.13";
+const ATTESTATIONS_TWINE_REQUIREMENT: &str = "twine>=6.0";
 
 /// Publish packages to a package repository.
 #[derive(Parser, Debug)]
 pub struct Args {
     /// The distribution files to upload to the repository (defaults to <workspace-root>/dist/*).
     dist: Option<Vec<PathBuf>>,
-    /// The repository to publish to.
-    #[arg(short, long, default_value = "pypi")]
-    repository: String,
+    /// The repository to publish to (defaults to pypi).
+    ///
+    /// Can be supplied multiple times to publish to several repositories.
+    /// Projects that restrict publishing with `tool.rye.publish` default to
+    /// the repositories listed there.
+    #[arg(short, long)]
+    repository: Vec<String>,
     /// The repository url to publish to.
     #[arg(long)]
     repository_url: Option<Url>,
@@ -37,22 +101,56 @@ pub struct Args {
     #[arg(long)]
     sign: bool,
     /// GPG identity used to sign files.
-    #[arg(short, long)]
+    #[arg(short, long, requires = "sign")]
     identity: Option<String>,
+    /// Sign the files with sigstore and upload the attestations (PEP 740).
+    ///
+    /// Outside of CI this opens a browser to authenticate with sigstore.
+    #[arg(long, alias = "sigstore")]
+    attest: bool,
+    /// Continue uploading files if one already exists.
+    ///
+    /// This can also be enabled per repository with `skip-existing` in the
+    /// credentials file.
+    #[arg(long)]
+    skip_existing: bool,
     /// Path to alternate CA bundle.
     #[arg(long)]
     cert: Option<PathBuf>,
     /// Skip prompts.
     #[arg(short, long)]
     yes: bool,
-    /// Enables verbose diagnostics.
-    #[arg(short, long)]
-    verbose: bool,
+    /// Only check the distribution files without uploading them.
+    #[arg(long, conflicts_with = "no_check")]
+    check: bool,
+    /// Upload without checking the distribution files first.
+    #[arg(long)]
+    no_check: bool,
+    /// Do not use trusted publishing in CI even if it is available.
+    #[arg(long)]
+    no_trusted_publishing: bool,
+    /// Use trusted publishing with indexes other than PyPI and TestPyPI.
+    ///
+    /// This hands the OIDC token of the CI job to the index.
+    #[arg(long, conflicts_with = "no_trusted_publishing")]
+    trusted_publishing: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
     /// Turns off all output.
     #[arg(short, long, conflicts_with = "verbose")]
     quiet: bool,
 }
 
+/// A repository with the resolved credentials to upload to.
+struct UploadTarget {
+    repository: String,
+    repository_url: Url,
+    username: String,
+    token: Secret<String>,
+    skip_existing: bool,
+}
+
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
     let venv = ensure_self_venv(output)?;
@@ -60,55 +158,421 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
 
     // Get the files to publish.
     let files = match cmd.dist {
-        Some(paths) => paths,
+        Some(ref paths) => paths.clone(),
         None => vec![project.workspace_path().join("dist").join("*")],
     };
 
-    // a. Get token from arguments and offer encryption, then store in credentials file.
-    // b. Get token from ~/.rye/credentials keyed by provided repository and provide decryption option.
-    // c. Otherwise prompt for token and provide encryption option, storing the result in credentials.
-    let repository = &cmd.repository;
+    if !cmd.no_check {
+        check_files(&venv, &files, output)?;
+        if cmd.check {
+            return Ok(());
+        }
+    }
+
+    let repositories =
+        publish_repositories(&project, &cmd.repository, cmd.repository_url.as_ref())?;
+    let hooks = Hooks::load(&project)?;
+
+    if repositories.len() > 1
+        && (cmd.repository_url.is_some() || cmd.username.is_some() || cmd.token.is_some())
+    {
+        bail!("--repository-url, --username and --token can only be used with a single repository");
+    }
+
+    // credentials for all repositories are resolved ahead of time so that
+    // a missing token does not abort the publish half way through.
     let mut credentials = get_credentials()?;
+    let configured_repositories = project.repositories()?;
+    let mut targets = Vec::new();
+    for repository in &repositories {
+        if targets
+            .iter()
+            .any(|x: &UploadTarget| &x.repository == repository)
+        {
+            continue;
+        }
+        let configured = configured_repositories
+            .iter()
+            .find(|x| &x.name == repository);
+        let target = resolve_target(repository, configured, &cmd, &mut credentials)?;
+        if project.is_private() && is_pypi_url(&target.repository_url) {
+            bail!(
+                "project is marked as private (Private :: Do Not Upload) and cannot be published to pypi ({})",
+                target.repository_url
+            );
+        }
+        targets.push(target);
+    }
+    write_credentials(&credentials)?;
+
+    hooks.run("pre-publish", output)?;
+
+    // attestations are created once and uploaded to every repository that
+    // accepts them.  This needs tools newer than the ones of rye itself.
+    let (upload_venv, files) = if cmd.attest {
+        let files = expand_dist_files(&files)?;
+        let venv = ensure_attestation_tools(output)?;
+        create_attestations(&venv, &files, output)?;
+        (venv, files)
+    } else {
+        (venv, files)
+    };
+
+    let mut results = Vec::new();
+    for target in &targets {
+        if output != CommandOutput::Quiet && targets.len() > 1 {
+            eprintln!(
+                "Publishing to {} ({})",
+                style(&target.repository).cyan(),
+                target.repository_url
+            );
+        }
+        let mut publish_cmd = Command::new(get_venv_python_bin(&upload_venv));
+        publish_cmd
+            .arg("-mtwine")
+            .arg("--no-color")
+            .arg("upload")
+            .args(&files)
+            .arg("--username")
+            .arg(&target.username)
+            .arg("--password")
+            .arg(target.token.expose_secret())
+            .arg("--repository-url")
+            .arg(target.repository_url.to_string());
+        if cmd.attest {
+            if supports_attestations(&target.repository_url) {
+                publish_cmd.arg("--attestations");
+            } else if output != CommandOutput::Quiet {
+                eprintln!(
+                    "{} does not accept attestations, they are kept next to the files",
+                    target.repository
+                );
+            }
+        }
+        if cmd.sign {
+            publish_cmd.arg("--sign");
+        }
+        if let Some(ref identity) = cmd.identity {
+            publish_cmd.arg("--identity").arg(identity);
+        }
+        if let Some(ref cert) = cmd.cert {
+            publish_cmd.arg("--cert").arg(cert);
+        }
+        if target.skip_existing {
+            publish_cmd.arg("--skip-existing");
+        }
+
+        if output == CommandOutput::Quiet {
+            publish_cmd.stdout(Stdio::null());
+            publish_cmd.stderr(Stdio::null());
+        }
+
+        let status = publish_cmd.status()?;
+        results.push((&target.repository, status.success()));
+    }
+
+    if targets.len() > 1 && output != CommandOutput::Quiet {
+        eprintln!();
+        for (repository, success) in &results {
+            if *success {
+                eprintln!("{} {}", style("published").green(), repository);
+            } else {
+                eprintln!("{} {}", style("failed").red(), repository);
+            }
+        }
+    }
+
+    let failed = results
+        .iter()
+        .filter(|(_, success)| !success)
+        .map(|(repository, _)| repository.as_str())
+        .collect::<Vec<_>>();
+    if !failed.is_empty() {
+        bail!("failed to publish files to {}", failed.join(", "));
+    }
+
+    hooks.run("post-publish", output)
+}
+
+/// Checks that the distribution files render on the package index and have
+/// complete metadata.
+///
+/// The long description is checked with `twine check --strict`, the
+/// classifiers and remaining metadata with [`METADATA_CHECK_SCRIPT`].
+fn check_files(venv: &Path, files: &[PathBuf], output: CommandOutput) -> Result<(), Error> {
+    if output != CommandOutput::Quiet {
+        eprintln!("Checking distribution files");
+    }
+    let mut twine_cmd = Command::new(get_venv_python_bin(venv));
+    twine_cmd
+        .arg("-mtwine")
+        .arg("--no-color")
+        .arg("check")
+        .arg("--strict")
+        .args(files);
+    let mut metadata_cmd = Command::new(get_venv_python_bin(venv));
+    metadata_cmd
+        .arg("-c")
+        .arg(METADATA_CHECK_SCRIPT)
+        .args(files);
+    if output == CommandOutput::Quiet {
+        twine_cmd.stdout(Stdio::null());
+        metadata_cmd.stdout(Stdio::null());
+    }
+
+    // both checks run so that all problems are reported at once
+    let twine_ok = twine_cmd.status()?.success();
+    let metadata_ok = metadata_cmd.status()?.success();
+    if !twine_ok || !metadata_ok {
+        bail!("distribution files failed the checks (pass --no-check to upload anyway)");
+    }
+    Ok(())
+}
+
+/// Expands the distribution files to publish.
+///
+/// Patterns like the default `dist/*` are expanded here, and only sdists
+/// and wheels are kept, so that attestations are not signed in turn.
+fn expand_dist_files(files: &[PathBuf]) -> Result<Vec<PathBuf>, Error> {
+    let mut rv = Vec::new();
+    for path in files {
+        let name = path.file_name().and_then(|x| x.to_str()).unwrap_or("");
+        if !name.contains('*') {
+            rv.push(path.clone());
+            continue;
+        }
+        let matcher = GlobBuilder::new(name).build()?.compile_matcher();
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let mut matches = Vec::new();
+        for entry in
+            fs::read_dir(dir).with_context(|| format!("unable to read {}", dir.display()))?
+        {
+            let entry_path = entry?.path();
+            if entry_path
+                .file_name()
+                .map_or(false, |x| matcher.is_match(x))
+            {
+                matches.push(entry_path);
+            }
+        }
+        matches.sort();
+        rv.extend(matches);
+    }
+    rv.retain(|x| {
+        let name = x.to_string_lossy();
+        name.ends_with(".whl") || name.ends_with(".tar.gz")
+    });
+    if rv.is_empty() {
+        bail!("no distribution files to publish");
+    }
+    Ok(rv)
+}
+
+/// Returns a virtualenv with the tools to create and upload attestations.
+fn ensure_attestation_tools(output: CommandOutput) -> Result<PathBuf, Error> {
+    ensure_ephemeral_tool(
+        &Requirement::from_str(ATTESTATIONS_REQUIREMENT)?,
+        &[Requirement::from_str(ATTESTATIONS_TWINE_REQUIREMENT)?],
+        None,
+        false,
+        output,
+    )
+}
+
+/// Signs the files with sigstore.
+///
+/// This writes a `<file>.publish.attestation` next to every file.  In CI
+/// the identity of the job is used, otherwise sigstore asks to log in.
+fn create_attestations(venv: &Path, files: &[PathBuf], output: CommandOutput) -> Result<(), Error> {
+    if output != CommandOutput::Quiet {
+        eprintln!("Creating attestations");
+    }
+    let mut sign_cmd = Command::new(get_venv_python_bin(venv));
+    sign_cmd.arg("-mpypi_attestations").arg("sign").args(files);
+    if output == CommandOutput::Quiet {
+        sign_cmd.stdout(Stdio::null());
+    }
+    if !run_status(&mut sign_cmd)?.success() {
+        bail!("failed to create attestations");
+    }
+    Ok(())
+}
+
+/// Can attestations be uploaded to the repository?
+///
+/// Only PyPI accepts them so far.
+fn supports_attestations(repository_url: &Url) -> bool {
+    matches!(
+        repository_url.host_str(),
+        Some("upload.pypi.org") | Some("test.pypi.org")
+    )
+}
+
+/// Returns the repositories to publish to.
+///
+/// This enforces `tool.rye.publish` and refuses to upload projects with the
+/// `Private :: Do Not Upload` classifier to PyPI.  As the URL of a repository
+/// can point anywhere, the resolved URLs are checked again in [`execute`].
+fn publish_repositories(
+    project: &PyProject,
+    requested: &[String],
+    repository_url: Option<&Url>,
+) -> Result<Vec<String>, Error> {
+    let allowed = project.publish_repositories();
+    let repositories = match allowed {
+        _ if !requested.is_empty() => requested.to_vec(),
+        Some(ref allowed) if !allowed.is_empty() => allowed.clone(),
+        _ => vec!["pypi".to_string()],
+    };
+    if let Some(allowed) = allowed {
+        if allowed.is_empty() {
+            bail!("publishing is disabled for this project (tool.rye.publish = false)");
+        }
+        if repository_url.is_some() {
+            bail!(
+                "--repository-url cannot be used when tool.rye.publish restricts the repositories"
+            );
+        }
+        if let Some(repository) = repositories.iter().find(|x| !allowed.contains(x)) {
+            bail!(
+                "publishing to '{}' is not allowed for this project. Allowed repositories: {}",
+                repository,
+                allowed.join(", ")
+            );
+        }
+    }
+    if project.is_private() && repositories.iter().any(|x| x == "pypi") {
+        bail!("project is marked as private (Private :: Do Not Upload) and cannot be published to pypi");
+    }
+    Ok(repositories)
+}
+
+/// Checks if a URL points to PyPI (or TestPyPI).
+fn is_pypi_url(url: &Url) -> bool {
+    url.host_str().map_or(false, |host| {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        host == "pypi.org" || host.ends_with(".pypi.org")
+    })
+}
+
+/// Resolves the repository url and credentials of a repository.
+///
+/// a. Get token from arguments and offer encryption, then store in credentials file.
+/// b. Get token from ~/.rye/credentials keyed by provided repository and provide decryption option.
+/// c. Otherwise prompt for token and provide encryption option, storing the result in credentials.
+fn resolve_target(
+    repository: &str,
+    configured: Option<&RepositoryRef>,
+    cmd: &Args,
+    credentials: &mut Document,
+) -> Result<UploadTarget, Error> {
     credentials
         .entry(repository)
         .or_insert(Item::Table(Table::new()));
 
-    let repository_url = match cmd.repository_url {
-        Some(url) => url,
-        None => {
+    let repository_url = match (&cmd.repository_url, configured) {
+        (Some(url), _) => url.clone(),
+        (None, Some(configured)) => Url::parse(&configured.url)
+            .with_context(|| format!("invalid url of repository '{}'", configured.name))?,
+        (None, None) => {
             let default_repository_url = Url::parse("https://upload.pypi.org/legacy/")?;
-            credentials
+            match credentials
                 .get(repository)
                 .and_then(|table| table.get("repository-url"))
-                .map(|url| match Url::parse(&escape_string(url.to_string())) {
-                    Ok(url) => url,
-                    Err(_) => default_repository_url.clone(),
-                })
-                .unwrap_or(default_repository_url)
+            {
+                Some(url) => {
+                    Url::parse(&escape_string(url.to_string())).unwrap_or(default_repository_url)
+                }
+                None if repository == "pypi" => default_repository_url,
+                None => bail!(
+                    "unknown repository '{}'. Add it to `repositories` in the config or \
+                     `tool.rye.repositories`, or pass --repository-url",
+                    repository
+                ),
+            }
         }
     };
 
     // If -r is pypi but the url isn't pypi then bail
+    check_url(
+        repository_url.as_str(),
+        &format!("publishing to '{}'", repository),
+    )?;
     if repository == "pypi" && repository_url.domain() != Some("upload.pypi.org") {
         bail!("invalid pypi url {} (use -h for help)", repository_url);
     }
 
-    let username = match cmd.username {
-        Some(username) => username,
-        None => credentials
+    let skip_existing = cmd.skip_existing
+        || configured.map_or(false, |x| x.skip_existing)
+        || credentials
             .get(repository)
-            .and_then(|table| table.get("username"))
-            .map(|username| username.to_string())
-            .map(escape_string)
-            .unwrap_or("__token__".to_string()),
+            .and_then(|table| table.get("skip-existing"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false);
+
+    // in CI a short-lived token from trusted publishing takes precedence
+    // over stored credentials.  It is never written to the credentials file.
+    if cmd.token.is_none() && !cmd.no_trusted_publishing {
+        let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+        if let Some(token) = mint_token(&repository_url, cmd.trusted_publishing, output)? {
+            if output != CommandOutput::Quiet {
+                eprintln!("Using trusted publishing for {}", repository);
+            }
+            return Ok(UploadTarget {
+                repository: repository.to_string(),
+                repository_url,
+                username: "__token__".to_string(),
+                token,
+                skip_existing,
+            });
+        }
+    }
+
+    let username = match cmd.username {
+        Some(ref username) => username.clone(),
+        None => match configured.and_then(|x| x.username.as_ref()) {
+            Some(username) => username.clone(),
+            None => credentials
+                .get(repository)
+                .and_then(|table| table.get("username"))
+                .map(|username| username.to_string())
+                .map(escape_string)
+                .unwrap_or("__token__".to_string()),
+        },
     };
 
-    let token = if let Some(token) = cmd.token {
-        let secret = Secret::new(token);
+    // with the keyring the token is stored under the repository url, the
+    // same way twine looks it up.
+    let use_keyring = Config::current().use_keyring();
+    let token = if use_keyring {
+        let service = repository_url.to_string();
+        let stored = match cmd.token {
+            Some(_) => None,
+            None => keyring_get(&service, &username)?,
+        };
+        match stored {
+            Some(token) => Secret::new(token),
+            None => {
+                let token = match cmd.token {
+                    Some(ref token) => token.clone(),
+                    None if cmd.yes => String::new(),
+                    None => {
+                        eprintln!("No access token found for {} in the keyring", repository);
+                        prompt_for_token()?
+                    }
+                };
+                if token.is_empty() {
+                    bail!("an access token is required")
+                }
+                keyring_set(&service, &username, &token)?;
+                Secret::new(token)
+            }
+        }
+    } else if let Some(ref token) = cmd.token {
+        let secret = Secret::new(token.clone());
         let maybe_encrypted = maybe_encrypt(&secret, cmd.yes)?;
         let maybe_encoded = maybe_encode(&secret, &maybe_encrypted);
         credentials[repository]["token"] = Item::Value(maybe_encoded.expose_secret().into());
-        write_credentials(&credentials)?;
 
         secret
     } else if let Some(token) = credentials
@@ -121,7 +585,10 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
 
         maybe_decrypt(&secret, cmd.yes)?
     } else {
-        eprintln!("No access token found, generate one at: https://pypi.org/manage/account/token/");
+        eprintln!(
+            "No access token found for {}, generate one at: https://pypi.org/manage/account/token/",
+            repository
+        );
         let token = if !cmd.yes {
             prompt_for_token()?
         } else {
@@ -140,44 +607,18 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
 
     credentials[repository]["repository-url"] = Item::Value(repository_url.to_string().into());
     credentials[repository]["username"] = Item::Value(username.clone().into());
-    write_credentials(&credentials)?;
-
-    let mut publish_cmd = Command::new(get_venv_python_bin(&venv));
-    publish_cmd
-        .arg("-mtwine")
-        .arg("--no-color")
-        .arg("upload")
-        .args(files)
-        .arg("--username")
-        .arg(username)
-        .arg("--password")
-        .arg(token.expose_secret())
-        .arg("--repository-url")
-        .arg(repository_url.to_string());
-    if cmd.sign {
-        publish_cmd.arg("--sign");
-    }
-    if let Some(identity) = cmd.identity {
-        publish_cmd.arg("--identity").arg(identity);
-    }
-    if let Some(cert) = cmd.cert {
-        publish_cmd.arg("--cert").arg(cert);
-    }
 
-    if output == CommandOutput::Quiet {
-        publish_cmd.stdout(Stdio::null());
-        publish_cmd.stderr(Stdio::null());
-    }
-
-    let status = publish_cmd.status()?;
-    if !status.success() {
-        bail!("failed to publish files");
-    }
-
-    Ok(())
+    Ok(UploadTarget {
+        repository: repository.to_string(),
+        repository_url,
+        username,
+        token,
+        skip_existing,
+    })
 }
 
 fn prompt_for_token() -> Result<String, Error> {
+    ensure_interactive("reading the access token")?;
     eprint!("Access token: ");
     let token = get_trimmed_user_input().context("failed to read provided token")?;
 
@@ -186,6 +627,7 @@ fn prompt_for_token() -> Result<String, Error> {
 
 fn maybe_encrypt(secret: &Secret<String>, yes: bool) -> Result<Secret<Vec<u8>>, Error> {
     let phrase = if !yes {
+        ensure_interactive("reading the token passphrase")?;
         dialoguer::Password::new()
             .with_prompt("Enter a passphrase (optional)")
             .allow_empty_password(true)
@@ -214,6 +656,7 @@ fn maybe_encrypt(secret: &Secret<String>, yes: bool) -> Result<Secret<Vec<u8>>,
 
 fn maybe_decrypt(secret: &Secret<String>, yes: bool) -> Result<Secret<String>, Error> {
     let phrase = if !yes {
+        ensure_interactive("reading the token passphrase")?;
         dialoguer::Password::new()
             .with_prompt("Enter a passphrase (optional)")
             .allow_empty_password(true)
@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use anyhow::Error;
-use clap::Parser;
+use clap::{ArgAction, Parser};
 use pep508_rs::Requirement;
 
 use crate::pyproject::{DependencyKind, PyProject};
@@ -18,9 +18,12 @@ pub struct Args {
     /// Remove this from an optional dependency group.
     #[arg(long, conflicts_with = "dev")]
     optional: Option<String>,
-    /// Enables verbose diagnostics.
-    #[arg(short, long)]
-    verbose: bool,
+    /// Remove this from a dependency group (PEP 735).
+    #[arg(long, conflicts_with = "dev", conflicts_with = "optional")]
+    group: Option<String>,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
     /// Turns off all output.
     #[arg(short, long, conflicts_with = "verbose")]
     quiet: bool,
@@ -39,6 +42,8 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
                 DependencyKind::Dev
             } else if let Some(ref section) = cmd.optional {
                 DependencyKind::Optional(section.into())
+            } else if let Some(ref group) = cmd.group {
+                DependencyKind::Group(group.into())
             } else {
                 DependencyKind::Normal
             },
@@ -1,15 +1,30 @@
+use std::collections::HashMap;
 use std::env::{self, join_paths, split_paths};
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus};
+use std::thread;
 
 use anyhow::{bail, Context, Error};
 use clap::Parser;
 use console::style;
+use pep508_rs::Requirement;
+use tempfile::TempDir;
 
-use crate::pyproject::{PyProject, Script};
-use crate::sync::{sync, SyncOptions};
-use crate::utils::{exec_spawn, success_status};
+use crate::bootstrap::ensure_self_venv;
+use crate::consts::VENV_BIN;
+use crate::lock::locked_versions;
+use crate::pyproject::{ExpandedSources, PyProject, Script};
+use crate::script::ScriptMetadata;
+use crate::secrets::load_secrets;
+use crate::sources::PythonVersionRequest;
+use crate::sync::{refresh_editables, sync, SyncOptions};
+use crate::transcript::run_status;
+use crate::utils::{
+    exec_spawn, find_similar, get_venv_python_bin, parse_env_file, set_proxy_variables,
+    success_status, CommandOutput, QuietExit,
+};
 
 /// Runs a command installed into this package.
 #[derive(Parser, Debug)]
@@ -21,6 +36,52 @@ pub struct Args {
     /// The command to run
     #[command(subcommand)]
     cmd: Option<Cmd>,
+    /// Run in a named environment from `tool.rye.envs`.
+    #[arg(long, value_name = "NAME")]
+    env: Option<String>,
+    /// Run the scripts a script depends on in parallel where possible.
+    #[arg(long)]
+    parallel: bool,
+    /// Run the command in this directory (relative to the project).
+    ///
+    /// Scripts that declare a `cwd` keep running in their own directory.
+    #[arg(long, value_name = "PATH")]
+    cwd: Option<PathBuf>,
+    /// Run the command in every member of the workspace.
+    ///
+    /// Members run in the order of their dependencies, each in its own
+    /// directory.  If the command is a script, only the members that define
+    /// it run it.
+    #[arg(long, conflicts_with = "cwd")]
+    all: bool,
+    /// Run the command in this member of the workspace (can be repeated).
+    ///
+    /// The members it depends on run it first.
+    #[arg(short, long, value_name = "MEMBER", conflicts_with_all = ["cwd", "all"])]
+    package: Vec<String>,
+    /// Run with an additional package that is only installed for this run.
+    ///
+    /// Can be given multiple times.  Neither the pyproject.toml nor the
+    /// lockfiles are modified.
+    #[arg(long = "with", value_name = "REQUIREMENT")]
+    with: Vec<String>,
+    /// Do not load the env file (`tool.rye.env-file`).
+    #[arg(long)]
+    no_env_file: bool,
+    /// Run a single-file script with inline metadata (PEP 723).
+    ///
+    /// The first argument is the path to the script.  Its dependencies are
+    /// installed into a cached virtualenv, so no project is needed.
+    #[arg(long)]
+    script: bool,
+    /// The Python version to run the script with.
+    ///
+    /// Defaults to a version matching the `requires-python` of the script.
+    #[arg(long, requires = "script")]
+    python: Option<String>,
+    /// Reinstall the dependencies of the script.
+    #[arg(long, requires = "script")]
+    refresh: bool,
     /// Use this pyproject.toml file
     #[arg(long, value_name = "PYPROJECT_TOML")]
     pyproject: Option<PathBuf>,
@@ -33,11 +94,23 @@ enum Cmd {
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
-    let pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    if cmd.script {
+        return run_single_file_script(cmd);
+    }
+
+    let mut pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    if let Some(ref env) = cmd.env {
+        pyproject = pyproject.with_env(env)?;
+    }
 
     // make sure we have the minimal virtualenv.
-    sync(SyncOptions::python_only().pyproject(cmd.pyproject))
-        .context("failed to sync ahead of run")?;
+    sync(
+        SyncOptions::python_only()
+            .pyproject(cmd.pyproject)
+            .env(cmd.env),
+    )
+    .context("failed to sync ahead of run")?;
+    refresh_editables(&pyproject, CommandOutput::Normal)?;
 
     if cmd.list || cmd.cmd.is_none() {
         return list_scripts(&pyproject);
@@ -47,25 +120,363 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         None => unreachable!(),
     };
 
-    invoke_script(&pyproject, args, true)?;
-    unreachable!();
+    let overlay = if cmd.with.is_empty() {
+        None
+    } else {
+        Some(
+            install_overlay(&pyproject, &cmd.with)
+                .context("failed to install the packages passed with --with")?,
+        )
+    };
+    let overlay_path = overlay.as_ref().map(|x| x.path());
+
+    // secrets are exported into the environment of rye itself so that all
+    // invoked commands (including chained ones) inherit them.
+    for (key, value) in load_secrets(&pyproject)? {
+        if env::var_os(&key).is_none() {
+            env::set_var(key, value);
+        }
+    }
+    if !cmd.no_env_file {
+        for (key, value) in load_env_file(&pyproject)? {
+            if env::var_os(&key).is_none() {
+                env::set_var(key, value);
+            }
+        }
+    }
+    // unlike the files, the variables of the project override the
+    // environment as they can extend it (`PATH = "${PATH}:..."`).
+    for (key, value) in pyproject.env_vars()? {
+        env::set_var(key, value);
+    }
+
+    // like the secrets, the working directory is applied to rye itself so
+    // that all invoked commands inherit it.
+    if let Some(ref cwd) = cmd.cwd {
+        let path = resolve_cwd(&pyproject, cwd)?;
+        env::set_current_dir(&path)
+            .with_context(|| format!("failed to change into {}", path.display()))?;
+    }
+
+    if let Some(members) = select_members(&pyproject, cmd.all, &cmd.package)? {
+        let rv = run_in_members(&members, args, overlay_path);
+        drop(overlay);
+        return rv;
+    }
+
+    run_dependencies(
+        &pyproject,
+        &args[0].to_string_lossy(),
+        cmd.parallel,
+        overlay_path,
+    )?;
+    if overlay.is_none() {
+        invoke_script(&pyproject, args, true, None)?;
+        unreachable!();
+    }
+
+    // the overlay is removed once the command is done, so rye has to stay
+    // around instead of being replaced by the command.
+    let status = invoke_script(&pyproject, args, false, overlay_path)?;
+    drop(overlay);
+    if !status.success() {
+        return Err(QuietExit(status.code().unwrap_or(1)).into());
+    }
+    Ok(())
+}
+
+/// Runs a single-file script in a virtualenv with its dependencies.
+fn run_single_file_script(cmd: Args) -> Result<(), Error> {
+    let mut args = match cmd.cmd {
+        Some(Cmd::External(args)) => args,
+        None => bail!("no script given"),
+    };
+    let script = PathBuf::from(args.remove(0));
+    let source = fs::read_to_string(&script)
+        .with_context(|| format!("failed to read script {}", script.display()))?;
+    let meta = ScriptMetadata::parse(&source)
+        .with_context(|| format!("invalid inline metadata in {}", script.display()))?;
+    let py_ver = match cmd.python {
+        Some(ref python) => python.parse::<PythonVersionRequest>()?,
+        None => meta.python_version()?,
+    };
+    let extra_requirements = cmd
+        .with
+        .iter()
+        .map(|x| {
+            x.parse::<Requirement>()
+                .with_context(|| format!("invalid requirement '{}'", x))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let venv = meta.ensure_venv(
+        &py_ver,
+        &extra_requirements,
+        cmd.refresh,
+        CommandOutput::Normal,
+    )?;
+
+    let mut script_cmd = Command::new(get_venv_python_bin(&venv));
+    script_cmd
+        .arg(&script)
+        .args(args)
+        .env("VIRTUAL_ENV", &venv)
+        .env_remove("PYTHONHOME");
+    match exec_spawn(&mut script_cmd)? {}
+}
+
+/// Installs extra packages into a temporary folder for `--with`.
+///
+/// The locked versions of the project are used as constraints so that the
+/// packages in the folder, which take precedence over the virtualenv, do not
+/// replace the ones the project depends on with other versions.
+fn install_overlay(pyproject: &PyProject, requirements: &[String]) -> Result<TempDir, Error> {
+    let self_venv = ensure_self_venv(CommandOutput::Normal)?;
+    let overlay = tempfile::tempdir()?;
+    let mut constraints = String::new();
+    for name in ["requirements-dev.lock", "requirements.lock"] {
+        let lockfile = pyproject.workspace_path().join(name);
+        if let Ok(contents) = fs::read_to_string(lockfile) {
+            for (name, version) in locked_versions(&contents) {
+                if let Some(version) = version {
+                    constraints.push_str(&format!("{}=={}\n", name, version));
+                }
+            }
+            break;
+        }
+    }
+    let constraints_path = overlay.path().join("constraints.txt");
+    fs::write(&constraints_path, constraints)?;
+
+    eprintln!("Installing {}", style(requirements.join(", ")).cyan());
+    let mut pip_cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
+    pip_cmd
+        .arg("--python")
+        .arg(get_venv_python_bin(&pyproject.venv_path()))
+        .arg("install")
+        .arg("--quiet")
+        .arg("--disable-pip-version-check")
+        .arg("--no-warn-script-location")
+        .arg("--target")
+        .arg(overlay.path().join("packages"))
+        .arg("-c")
+        .arg(&constraints_path)
+        .args(requirements)
+        .current_dir(pyproject.root_path());
+    ExpandedSources::from_sources(&pyproject.sources()?)?.add_as_pip_args(&mut pip_cmd);
+    set_proxy_variables(&mut pip_cmd);
+    let status = run_status(&mut pip_cmd).context("unable to run pip")?;
+    if !status.success() {
+        bail!("failed to install {}", requirements.join(", "));
+    }
+    Ok(overlay)
+}
+
+/// Returns the workspace members selected with `--all` or `--package`.
+fn select_members(
+    pyproject: &PyProject,
+    all: bool,
+    packages: &[String],
+) -> Result<Option<Vec<PyProject>>, Error> {
+    if !all && packages.is_empty() {
+        return Ok(None);
+    }
+    let workspace = match pyproject.workspace() {
+        Some(workspace) => workspace,
+        None => bail!("--all and --package can only be used in a workspace"),
+    };
+    workspace
+        .projects_in_order(if all { None } else { Some(packages) })
+        .map(Some)
+}
+
+/// Runs a command in the root of every given member, one after another.
+///
+/// If the command is a script of some members, the others are skipped.
+fn run_in_members(
+    members: &[PyProject],
+    args: Vec<OsString>,
+    overlay: Option<&Path>,
+) -> Result<(), Error> {
+    let name = args[0].to_string_lossy().into_owned();
+    let is_script = members.iter().any(|x| x.get_script_cmd(&name).is_some());
+    for member in members {
+        if is_script && member.get_script_cmd(&name).is_none() {
+            continue;
+        }
+        let member_name = member.normalized_name()?;
+        eprintln!("Running in {}", style(&member_name).cyan());
+        env::set_current_dir(member.root_path())
+            .with_context(|| format!("failed to change into {}", member.root_path().display()))?;
+        run_dependencies(member, &name, false, overlay)?;
+        let status = invoke_script(member, args.clone(), false, overlay)?;
+        if !status.success() {
+            bail!("'{}' failed in {} with {}", name, member_name, status);
+        }
+    }
+    Ok(())
+}
+
+/// Runs the scripts a script depends on (`depends`).
+///
+/// Every script runs once, after all of its own dependencies.  In parallel
+/// mode the scripts of a stage run at the same time.
+fn run_dependencies(
+    pyproject: &PyProject,
+    name: &str,
+    parallel: bool,
+    overlay: Option<&Path>,
+) -> Result<(), Error> {
+    let stages = plan_dependencies(name, &mut |name| pyproject.get_script_depends(name))?;
+    for script in stages.iter().flatten() {
+        if pyproject.get_script_cmd(script).is_none() {
+            bail!(
+                "unknown script '{}' in the dependencies of '{}'",
+                script,
+                name
+            );
+        }
+    }
+
+    for stage in stages {
+        let results = if parallel {
+            thread::scope(|scope| {
+                let handles = stage
+                    .iter()
+                    .map(|script| {
+                        scope.spawn(move || {
+                            invoke_script(pyproject, vec![script.into()], false, overlay)
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                handles
+                    .into_iter()
+                    .map(|x| x.join().expect("script thread panicked"))
+                    .collect::<Vec<_>>()
+            })
+        } else {
+            stage
+                .iter()
+                .map(|script| invoke_script(pyproject, vec![script.into()], false, overlay))
+                .collect()
+        };
+        for (script, result) in stage.iter().zip(results) {
+            let status = result?;
+            if !status.success() {
+                bail!("script '{}' failed with {}", script, status);
+            }
+        }
+    }
+    Ok(())
 }
 
+/// Orders the dependencies of a script into stages.
+///
+/// The scripts of a stage only depend on scripts of earlier stages.  The
+/// script itself is not included.
+fn plan_dependencies(
+    name: &str,
+    depends: &mut dyn FnMut(&str) -> Vec<String>,
+) -> Result<Vec<Vec<String>>, Error> {
+    fn visit(
+        name: &str,
+        depends: &mut dyn FnMut(&str) -> Vec<String>,
+        stages: &mut HashMap<String, usize>,
+        path: &mut Vec<String>,
+    ) -> Result<usize, Error> {
+        if let Some(stage) = stages.get(name) {
+            return Ok(*stage);
+        }
+        if let Some(pos) = path.iter().position(|x| x == name) {
+            bail!(
+                "dependency cycle between scripts: {} -> {}",
+                path[pos..].join(" -> "),
+                name
+            );
+        }
+        path.push(name.to_string());
+        let mut stage = 0;
+        for dep in depends(name) {
+            stage = stage.max(visit(&dep, depends, stages, path)? + 1);
+        }
+        path.pop();
+        stages.insert(name.to_string(), stage);
+        Ok(stage)
+    }
+
+    let mut stages = HashMap::new();
+    let last = visit(name, depends, &mut stages, &mut Vec::new())?;
+    let mut rv = vec![Vec::new(); last];
+    for (script, stage) in stages {
+        if stage < last {
+            rv[stage].push(script);
+        }
+    }
+    for stage in &mut rv {
+        stage.sort();
+    }
+    Ok(rv)
+}
+
+/// Runs a script or command of the virtualenv.
+///
+/// The packages in the overlay (from `--with`) take precedence over the ones
+/// of the virtualenv.
 fn invoke_script(
     pyproject: &PyProject,
     mut args: Vec<OsString>,
     exec: bool,
+    overlay: Option<&Path>,
 ) -> Result<ExitStatus, Error> {
     let venv_bin = pyproject.venv_bin_path();
+    let overlay_bin = overlay.map(|x| x.join("packages").join(VENV_BIN));
+    let find_overlay_bin = |name: &OsString| {
+        overlay_bin
+            .as_ref()
+            .and_then(|bin| which::which_in(name, Some(bin), bin).ok())
+    };
     let mut env_overrides = None;
+    let mut cwd = None;
+
+    let name = args[0].to_string_lossy().into_owned();
+    let interpolate = |value: &str| {
+        pyproject
+            .interpolate(value)
+            .with_context(|| format!("invalid value in script '{}'", name))
+    };
+    let interpolate_path = |path: PathBuf| interpolate(&path.to_string_lossy()).map(PathBuf::from);
 
-    match pyproject.get_script_cmd(&args[0].to_string_lossy()) {
-        Some(Script::Cmd(script_args, env_vars)) => {
+    match pyproject.get_script_cmd(&name) {
+        Some(Script::Cmd(script_args, script_env)) => {
             if script_args.is_empty() {
                 bail!("script has no arguments");
             }
-            env_overrides = Some(env_vars);
-            let script_target = venv_bin.join(&script_args[0]);
+            let script_args = script_args
+                .iter()
+                .map(|x| interpolate(x))
+                .collect::<Result<Vec<_>, _>>()?;
+            let root = pyproject.root_path();
+            let mut vars = Vec::new();
+            if let Some(env_file) = script_env.env_file {
+                let path = root.join(interpolate_path(env_file)?);
+                let contents = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read env file {}", path.display()))?;
+                vars = parse_env_file(&contents)
+                    .with_context(|| format!("failed to parse env file {}", path.display()))?;
+            }
+            // variables from the env key win over the ones from the file
+            for (key, value) in script_env.vars {
+                vars.push((key, interpolate(&value)?));
+            }
+            env_overrides = Some(vars);
+            cwd = script_env
+                .cwd
+                .map(interpolate_path)
+                .transpose()?
+                .map(|x| resolve_cwd(pyproject, &x))
+                .transpose()
+                .with_context(|| format!("invalid cwd of script '{}'", name))?;
+            let script_target = find_overlay_bin(&OsString::from(&script_args[0]))
+                .unwrap_or_else(|| venv_bin.join(&script_args[0]));
             if script_target.is_file() {
                 args = Some(script_target.as_os_str().to_owned())
                     .into_iter()
@@ -88,8 +499,11 @@ fn invoke_script(
                 bail!("extra arguments to chained commands are not allowed");
             }
             for args in commands {
-                let status =
-                    invoke_script(pyproject, args.into_iter().map(Into::into).collect(), false)?;
+                let args = args
+                    .iter()
+                    .map(|x| interpolate(x).map(OsString::from))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let status = invoke_script(pyproject, args, false, overlay)?;
                 if !status.success() {
                     if !exec {
                         return Ok(status);
@@ -103,8 +517,19 @@ fn invoke_script(
             }
             return Ok(success_status());
         }
+        None if find_overlay_bin(&args[0]).is_some() => {
+            args[0] = find_overlay_bin(&args[0]).unwrap().into();
+        }
         None => {
-            bail!("invalid or unknown script '{}'", args[0].to_string_lossy());
+            let scripts = pyproject.list_scripts();
+            match find_similar(&name, scripts.iter().map(|x| x.as_str())) {
+                Some(similar) => bail!(
+                    "invalid or unknown script '{}'; did you mean '{}'?",
+                    name,
+                    similar
+                ),
+                None => bail!("invalid or unknown script '{}'", name),
+            }
         }
     }
 
@@ -114,13 +539,32 @@ fn invoke_script(
     if let Some(path) = env::var_os("PATH") {
         let mut paths = split_paths(&path).collect::<Vec<_>>();
         paths.insert(0, venv_bin.into());
+        paths.splice(0..0, overlay_bin.clone());
         let new_path = join_paths(paths)?;
         cmd.env("PATH", new_path);
     } else {
-        cmd.env("PATH", &*venv_bin);
+        cmd.env(
+            "PATH",
+            join_paths(
+                overlay_bin
+                    .iter()
+                    .map(|x| x.as_path())
+                    .chain(Some(&*venv_bin)),
+            )?,
+        );
+    }
+    if let Some(overlay) = overlay {
+        let mut paths = vec![overlay.join("packages")];
+        if let Some(path) = env::var_os("PYTHONPATH") {
+            paths.extend(split_paths(&path));
+        }
+        cmd.env("PYTHONPATH", join_paths(paths)?);
     }
     if let Some(env_overrides) = env_overrides {
-        cmd.envs(env_overrides.iter());
+        cmd.envs(env_overrides);
+    }
+    if let Some(cwd) = cwd {
+        cmd.current_dir(cwd);
     }
     cmd.env_remove("PYTHONHOME");
 
@@ -131,6 +575,41 @@ fn invoke_script(
     }
 }
 
+/// Resolves a working directory relative to the project.
+///
+/// The directory has to exist and may not leave the project, or the
+/// workspace it is a member of.
+fn resolve_cwd(pyproject: &PyProject, cwd: &Path) -> Result<PathBuf, Error> {
+    let path = pyproject.root_path().join(cwd);
+    let resolved = match fs::canonicalize(&path) {
+        Ok(resolved) if resolved.is_dir() => resolved,
+        _ => bail!("working directory {} does not exist", path.display()),
+    };
+    let workspace = fs::canonicalize(pyproject.workspace_path())?;
+    if !resolved.starts_with(&workspace) {
+        bail!(
+            "working directory {} is outside of {}",
+            path.display(),
+            workspace.display()
+        );
+    }
+    Ok(resolved)
+}
+
+/// Loads the variables of the env file (`tool.rye.env-file`).
+///
+/// The file is optional, so a missing file is not an error.
+fn load_env_file(pyproject: &PyProject) -> Result<Vec<(String, String)>, Error> {
+    let path = match pyproject.env_file() {
+        Some(path) if path.is_file() => path,
+        _ => return Ok(Vec::new()),
+    };
+    let contents = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read env file {}", path.display()))?;
+    parse_env_file(&contents)
+        .with_context(|| format!("failed to parse env file {}", path.display()))
+}
+
 fn list_scripts(pyproject: &PyProject) -> Result<(), Error> {
     let mut scripts: Vec<_> = pyproject
         .list_scripts()
@@ -150,3 +629,29 @@ fn list_scripts(pyproject: &PyProject) -> Result<(), Error> {
     }
     Ok(())
 }
+
+#[test]
+fn test_plan_dependencies() {
+    let graph = |name: &str| -> Vec<String> {
+        match name {
+            "build" => vec!["assets".into(), "codegen".into()],
+            "assets" => vec!["codegen".into()],
+            "loop" => vec!["loop2".into()],
+            "loop2" => vec!["loop".into()],
+            _ => vec![],
+        }
+    };
+    assert_eq!(
+        plan_dependencies("build", &mut |x| graph(x)).unwrap(),
+        vec![vec!["codegen".to_string()], vec!["assets".to_string()]]
+    );
+    assert!(plan_dependencies("codegen", &mut |x| graph(x))
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        plan_dependencies("loop", &mut |x| graph(x))
+            .unwrap_err()
+            .to_string(),
+        "dependency cycle between scripts: loop -> loop2 -> loop"
+    );
+}
@@ -20,7 +20,7 @@ use crate::bootstrap::{
 };
 use crate::cli::toolchain::register_toolchain;
 use crate::platform::{get_app_dir, symlinks_supported};
-use crate::utils::{check_checksum, CommandOutput, QuietExit};
+use crate::utils::{check_checksum, ensure_interactive, CommandOutput, QuietExit};
 
 #[cfg(windows)]
 const DEFAULT_HOME: &str = "%USERPROFILE%\\.rye";
@@ -264,12 +264,14 @@ fn remove_dir_all_if_exists(path: &Path) -> Result<(), Error> {
 }
 
 fn uninstall(args: UninstallCommand) -> Result<(), Error> {
-    if !args.yes
-        && !dialoguer::Confirm::new()
+    if !args.yes {
+        ensure_interactive("uninstalling rye")?;
+        if !dialoguer::Confirm::new()
             .with_prompt("Do you want to uninstall rye?")
             .interact()?
-    {
-        return Ok(());
+        {
+            return Ok(());
+        }
     }
 
     let app_dir = get_app_dir();
@@ -376,13 +378,15 @@ fn perform_install(mode: InstallMode, toolchain_path: Option<&Path>) -> Result<(
     }
 
     eprintln!();
-    if !matches!(mode, InstallMode::NoPrompts)
-        && !dialoguer::Confirm::new()
+    if !matches!(mode, InstallMode::NoPrompts) {
+        ensure_interactive("installing rye")?;
+        if !dialoguer::Confirm::new()
             .with_prompt("Continue?")
             .interact()?
-    {
-        eprintln!("Installation cancelled!");
-        return Err(QuietExit(1).into());
+        {
+            eprintln!("Installation cancelled!");
+            return Err(QuietExit(1).into());
+        }
     }
 
     // place executable in rye home folder
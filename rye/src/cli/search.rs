@@ -0,0 +1,345 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+use std::{fs, thread};
+
+use anyhow::{anyhow, bail, Context, Error};
+use clap::{ArgAction, Parser};
+use console::style;
+use once_cell::sync::Lazy;
+use pep440_rs::Version;
+use regex::Regex;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::bootstrap::{download_url, download_url_ignore_404};
+use crate::config::Config;
+use crate::platform::get_app_dir;
+use crate::pyproject::{normalize_package_name, ExpandedSources, PyProject};
+use crate::utils::CommandOutput;
+
+/// How long the list of all package names is cached.
+const NAMES_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Names that are less similar than this are not considered a match.
+const MIN_NAME_SCORE: f64 = 0.85;
+
+static SIMPLE_INDEX_LINK_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r">([^<]+)</a>").unwrap());
+
+/// Searches for packages on the package index.
+///
+/// Package names are fuzzy matched against the query and the summaries of
+/// the closest matches are considered for the final ranking.  The primary
+/// index of the project (or of the config) is searched, it needs to provide
+/// the JSON API of PyPI.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The name or keywords to search for.
+    #[arg(required = true)]
+    query: Vec<String>,
+    /// The maximum number of packages to show.
+    #[arg(short, long, default_value_t = 10)]
+    limit: usize,
+    /// Refresh the cached list of package names.
+    #[arg(long)]
+    refresh: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// A package found by [`search_packages`].
+#[derive(Debug)]
+pub struct SearchResult {
+    pub name: String,
+    pub version: String,
+    pub summary: String,
+}
+
+/// The subset of the PyPI JSON API response rye cares about.
+#[derive(Deserialize, Debug)]
+pub struct ProjectInfo {
+    info: ProjectMetadata,
+    #[serde(default)]
+    releases: HashMap<String, Vec<ReleaseFile>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ProjectMetadata {
+    name: String,
+    version: String,
+    summary: Option<String>,
+    provides_extra: Option<Vec<String>>,
+    requires_dist: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ReleaseFile {
+    #[serde(default)]
+    yanked: bool,
+}
+
+impl ProjectInfo {
+    /// Returns the canonical name of the package.
+    pub fn name(&self) -> &str {
+        &self.info.name
+    }
+
+    /// Returns the extras of the latest release.
+    ///
+    /// Older uploads do not declare `provides_extra`, for those the extras
+    /// are picked up from the markers of the dependencies.
+    pub fn extras(&self) -> Vec<String> {
+        static EXTRA_MARKER_RE: Lazy<Regex> =
+            Lazy::new(|| Regex::new(r#"extra\s*==\s*["']([^"']+)["']"#).unwrap());
+        let mut rv = match self.info.provides_extra {
+            Some(ref extras) if !extras.is_empty() => extras.clone(),
+            _ => self
+                .info
+                .requires_dist
+                .iter()
+                .flatten()
+                .filter_map(|x| EXTRA_MARKER_RE.captures(x))
+                .map(|x| x[1].to_string())
+                .collect(),
+        };
+        rv.sort();
+        rv.dedup();
+        rv
+    }
+
+    /// Returns all installable versions, newest first.
+    pub fn versions(&self, pre: bool) -> Vec<Version> {
+        let mut rv = self
+            .releases
+            .iter()
+            .filter(|(_, files)| files.iter().any(|x| !x.yanked))
+            .filter_map(|(version, _)| Version::from_str(version).ok())
+            .filter(|version| pre || !version.any_prerelease())
+            .collect::<Vec<_>>();
+        rv.sort_by(|a, b| b.cmp(a));
+        rv
+    }
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let results = search_packages(&cmd.query.join(" "), cmd.limit, cmd.refresh, output)?;
+    if results.is_empty() && output != CommandOutput::Quiet {
+        eprintln!("No matching packages found");
+    }
+    for result in results {
+        println!("{} ({})", style(&result.name).cyan(), result.version);
+        if !result.summary.is_empty() {
+            println!("  {}", style(&result.summary).dim());
+        }
+    }
+    Ok(())
+}
+
+/// The index that packages are searched on.
+struct PackageIndex {
+    /// The URL of the simple API.
+    simple_url: Url,
+    /// The URL of the JSON API.
+    json_url: Url,
+}
+
+impl PackageIndex {
+    /// Returns the primary index of the project or the config.
+    fn primary() -> Result<PackageIndex, Error> {
+        let sources = match PyProject::discover() {
+            Ok(project) => project.sources()?,
+            Err(_) => Config::current().sources()?,
+        };
+        let simple_url = ExpandedSources::from_sources(&sources)?
+            .index_urls
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("no package index is configured"))?;
+
+        // indexes that implement the JSON API of PyPI serve it next to the
+        // simple API (https://pypi.org/simple/ and https://pypi.org/pypi/).
+        let mut json_url = simple_url.clone();
+        let path = simple_url.path().trim_end_matches('/');
+        match path.strip_suffix("/simple") {
+            Some(prefix) => json_url.set_path(&format!("{}/pypi/", prefix)),
+            None => bail!(
+                "package index {} does not provide the JSON API of PyPI",
+                public_url(&simple_url)
+            ),
+        }
+        Ok(PackageIndex {
+            simple_url,
+            json_url,
+        })
+    }
+
+    /// Returns the path the package names of the index are cached at.
+    fn cache_path(&self) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(public_url(&self.simple_url).as_bytes());
+        let key = hex::encode(hasher.finalize());
+        get_index_cache_dir().join(format!("{}.txt", &key[..16]))
+    }
+
+    /// Fetches the metadata of a package from the JSON API.
+    fn fetch_project_info(&self, name: &str) -> Result<Option<ProjectInfo>, Error> {
+        let url = self
+            .json_url
+            .join(&format!("{}/json", normalize_package_name(name)))?;
+        match download_url_ignore_404(url.as_str(), CommandOutput::Quiet)? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data).with_context(|| {
+                format!("failed to parse package metadata of {}", name)
+            })?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Returns the URL without credentials.
+fn public_url(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_username("").ok();
+    url.set_password(None).ok();
+    url.to_string()
+}
+
+/// Returns the folder the package names of the indexes are cached in.
+pub fn get_index_cache_dir() -> PathBuf {
+    get_app_dir().join("cache").join("index")
+}
+
+/// Searches the package index for packages matching a query.
+pub fn search_packages(
+    query: &str,
+    limit: usize,
+    refresh: bool,
+    output: CommandOutput,
+) -> Result<Vec<SearchResult>, Error> {
+    let words = query
+        .split_whitespace()
+        .map(|x| x.to_lowercase())
+        .collect::<Vec<_>>();
+    let needle = normalize_package_name(&words.join("-"));
+    if needle.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let index = PackageIndex::primary()?;
+    let names = load_package_names(&index, refresh, output)?;
+    let mut candidates = names
+        .lines()
+        .filter_map(|name| {
+            let score = score_name(&needle, &normalize_package_name(name));
+            (score >= MIN_NAME_SCORE).then_some((score, name))
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+    candidates.truncate(limit * 2);
+
+    // the metadata of the candidates is fetched in parallel
+    let infos = thread::scope(|scope| {
+        let handles = candidates
+            .iter()
+            .map(|(_, name)| scope.spawn(|| index.fetch_project_info(name)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|x| x.join().unwrap())
+            .collect::<Result<Vec<_>, Error>>()
+    })?;
+
+    let mut rv = Vec::new();
+    for ((score, _), info) in candidates.into_iter().zip(infos) {
+        let info = match info {
+            Some(info) => info,
+            None => continue,
+        };
+        let summary = info.info.summary.unwrap_or_default();
+        let summary_lower = summary.to_lowercase();
+        let summary_score = words
+            .iter()
+            .filter(|word| summary_lower.contains(word.as_str()))
+            .count() as f64
+            / words.len() as f64;
+        rv.push((
+            score + summary_score * 0.5,
+            SearchResult {
+                name: info.info.name,
+                version: info.info.version,
+                summary: summary.trim().to_string(),
+            },
+        ));
+    }
+    rv.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+    Ok(rv.into_iter().take(limit).map(|x| x.1).collect())
+}
+
+/// Fetches the metadata of a package from the primary index.
+pub fn fetch_project_info(name: &str) -> Result<Option<ProjectInfo>, Error> {
+    PackageIndex::primary()?.fetch_project_info(name)
+}
+
+/// Scores how well a normalized package name matches the query.
+///
+/// Exact matches rank above prefix matches which rank above substring
+/// matches.  Everything else is compared by similarity.
+fn score_name(needle: &str, name: &str) -> f64 {
+    let length_penalty = || (name.len() - needle.len()) as f64 / name.len() as f64 * 0.5;
+    if name == needle {
+        2.0
+    } else if name.starts_with(needle) {
+        1.5 - length_penalty()
+    } else if name.contains(needle) {
+        1.2 - length_penalty()
+    } else {
+        strsim::jaro_winkler(needle, name)
+    }
+}
+
+/// Returns the newline separated names of all packages on the index.
+///
+/// The list is large, so it is cached for a day.
+fn load_package_names(
+    index: &PackageIndex,
+    refresh: bool,
+    output: CommandOutput,
+) -> Result<String, Error> {
+    let cache_path = index.cache_path();
+    let is_fresh = fs::metadata(&cache_path)
+        .and_then(|x| x.modified())
+        .ok()
+        .and_then(|x| SystemTime::now().duration_since(x).ok())
+        .map_or(false, |age| age < NAMES_CACHE_TTL);
+    if !refresh && is_fresh {
+        if let Ok(names) = fs::read_to_string(&cache_path) {
+            return Ok(names);
+        }
+    }
+
+    if output != CommandOutput::Quiet {
+        eprintln!(
+            "Updating package index from {}",
+            public_url(&index.simple_url)
+        );
+    }
+    let listing = download_url(index.simple_url.as_str(), output)?;
+    let listing = String::from_utf8_lossy(&listing);
+    let mut names = String::new();
+    for m in SIMPLE_INDEX_LINK_RE.captures_iter(&listing) {
+        names.push_str(m[1].trim());
+        names.push('\n');
+    }
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&cache_path, &names).context("failed to cache package index")?;
+    Ok(names)
+}
@@ -1,16 +1,24 @@
-use std::env;
-use std::path::PathBuf;
+use std::env::{self, join_paths, split_paths};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use anyhow::{bail, Context, Error};
 use clap::Parser;
 use console::style;
+use tempfile::TempDir;
 
+use crate::consts::VENV_BIN;
 use crate::pyproject::PyProject;
 use crate::sync::{sync, SyncOptions};
 use crate::utils::QuietExit;
 
 /// Spawns a shell with the virtualenv activated.
+///
+/// bash, zsh, fish and PowerShell get the name of the project added to their
+/// prompt.  Leaving the shell (or calling `deactivate`) returns to the
+/// environment from before.
 #[derive(Parser, Debug)]
 pub struct Args {
     /// Do not show banner
@@ -24,6 +32,34 @@ pub struct Args {
     pyproject: Option<PathBuf>,
 }
 
+/// The shells that can be activated with a prompt.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ShellKind {
+    Bash,
+    Zsh,
+    Fish,
+    PowerShell,
+    Other,
+}
+
+impl ShellKind {
+    fn detect(shell: &Path) -> ShellKind {
+        match shell
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "bash" => ShellKind::Bash,
+            "zsh" => ShellKind::Zsh,
+            "fish" => ShellKind::Fish,
+            "pwsh" | "powershell" => ShellKind::PowerShell,
+            _ => ShellKind::Other,
+        }
+    }
+}
+
 pub fn execute(cmd: Args) -> Result<(), Error> {
     if !cmd.allow_nested && env::var("__RYE_SHELL").ok().as_deref() == Some("1") {
         bail!("cannot invoke recursive rye shell");
@@ -34,22 +70,27 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         .context("failed to sync ahead of shell")?;
 
     let venv_path = pyproject.venv_path();
-    let venv_bin = venv_path.join("bin");
+    let venv_bin = venv_path.join(VENV_BIN);
+    let prompt = pyproject.name().unwrap_or("venv").to_string();
 
-    let mut shell = Command::new(env::var("SHELL")?);
-    shell.arg("-l").env("VIRTUAL_ENV", &*venv_path);
+    let shell_path = find_shell()?;
+    let kind = ShellKind::detect(&shell_path);
+    let mut shell = Command::new(&shell_path);
+    shell.env("VIRTUAL_ENV", &*venv_path);
+    shell.env("VIRTUAL_ENV_PROMPT", &prompt);
 
+    let mut paths = vec![venv_bin.clone()];
     if let Some(path) = env::var_os("PATH") {
-        let mut new_path = venv_bin.as_os_str().to_owned();
-        new_path.push(":");
-        new_path.push(path);
-        shell.env("PATH", new_path);
-    } else {
-        shell.env("PATH", &*venv_bin);
+        paths.extend(split_paths(&path));
     }
+    shell.env("PATH", join_paths(paths)?);
     shell.env_remove("PYTHONHOME");
     shell.env("__RYE_SHELL", "1");
 
+    // the rc files for bash and zsh need to stay around until the shell is
+    // gone, which is why the folder is held on to here.
+    let _rc_dir = configure_shell(&mut shell, kind, &venv_bin, &prompt)?;
+
     if !cmd.no_banner {
         eprintln!(
             "Spawning virtualenv shell from {}",
@@ -66,3 +107,123 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         Ok(())
     }
 }
+
+/// Returns the shell of the user.
+fn find_shell() -> Result<PathBuf, Error> {
+    if let Some(shell) = env::var_os("SHELL").filter(|x| !x.is_empty()) {
+        return Ok(shell.into());
+    }
+    if cfg!(windows) {
+        for candidate in ["pwsh", "powershell"] {
+            if let Ok(shell) = which::which(candidate) {
+                return Ok(shell);
+            }
+        }
+        if let Some(comspec) = env::var_os("COMSPEC") {
+            return Ok(comspec.into());
+        }
+    }
+    bail!("unable to determine the shell to spawn; set SHELL");
+}
+
+/// Sets up the shell so that the prompt shows the project.
+///
+/// The user's own configuration is loaded first and the virtualenv is put
+/// back in front of `PATH` afterwards, as login scripts tend to reorder it.
+fn configure_shell(
+    shell: &mut Command,
+    kind: ShellKind,
+    venv_bin: &Path,
+    prompt: &str,
+) -> Result<Option<TempDir>, Error> {
+    let bin = venv_bin.to_string_lossy();
+    match kind {
+        ShellKind::Bash => {
+            let dir = tempfile::tempdir()?;
+            let rc = dir.path().join("bashrc");
+            fs::write(
+                &rc,
+                format!(
+                    "[ -f ~/.bashrc ] && . ~/.bashrc\n\
+                     export PATH={}:\"$PATH\"\n\
+                     PS1={}\"$PS1\"\n\
+                     deactivate() {{ exit; }}\n",
+                    quote_posix(&bin),
+                    quote_posix(&format!("({}) ", prompt)),
+                ),
+            )?;
+            shell.arg("--rcfile").arg(&rc);
+            Ok(Some(dir))
+        }
+        ShellKind::Zsh => {
+            // zsh reads its rc files from ZDOTDIR, so a folder with rc files
+            // that load the original ones is put in place.
+            let dir = tempfile::tempdir()?;
+            let original = env::var_os("ZDOTDIR")
+                .or_else(|| env::var_os("HOME"))
+                .unwrap_or_else(|| OsString::from("~"));
+            let original = quote_posix(&original.to_string_lossy());
+            fs::write(
+                dir.path().join(".zshenv"),
+                format!("[ -f {0}/.zshenv ] && . {0}/.zshenv\n", original),
+            )?;
+            fs::write(
+                dir.path().join(".zshrc"),
+                format!(
+                    "ZDOTDIR={0}\n\
+                     [ -f {0}/.zshrc ] && . {0}/.zshrc\n\
+                     export PATH={1}:\"$PATH\"\n\
+                     PROMPT={2}\"$PROMPT\"\n\
+                     deactivate() {{ exit; }}\n",
+                    original,
+                    quote_posix(&bin),
+                    quote_posix(&format!("({}) ", prompt)),
+                ),
+            )?;
+            shell.env("ZDOTDIR", dir.path());
+            Ok(Some(dir))
+        }
+        ShellKind::Fish => {
+            shell.arg("-C").arg(format!(
+                "set -gx PATH {} $PATH; \
+                 functions -c fish_prompt _rye_fish_prompt; \
+                 function fish_prompt; printf '%s' {}; _rye_fish_prompt; end; \
+                 function deactivate; exit; end",
+                quote_fish(&bin),
+                quote_fish(&format!("({}) ", prompt)),
+            ));
+            Ok(None)
+        }
+        ShellKind::PowerShell => {
+            shell
+                .arg("-NoLogo")
+                .arg("-NoExit")
+                .arg("-Command")
+                .arg(format!(
+                    "$function:_rye_prompt = $function:prompt; \
+                 function global:prompt {{ {} + (& $function:_rye_prompt) }}; \
+                 function global:deactivate {{ exit }}",
+                    quote_powershell(&format!("({}) ", prompt)),
+                ));
+            Ok(None)
+        }
+        ShellKind::Other => {
+            if cfg!(unix) {
+                shell.arg("-l");
+            }
+            Ok(None)
+        }
+    }
+}
+
+fn quote_posix(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+fn quote_fish(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn quote_powershell(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
@@ -4,13 +4,16 @@ use std::ffi::{OsStr, OsString};
 use std::str::FromStr;
 
 use anyhow::{anyhow, bail, Context, Error};
+use pep508_rs::Requirement;
 use same_file::is_same_file;
 use std::process::Command;
 use which::which_in_global;
 
-use crate::bootstrap::{ensure_self_venv, get_pip_runner};
+use crate::bootstrap::{ensure_self_venv, fetch, get_pip_runner};
 use crate::config::Config;
 use crate::consts::VENV_BIN;
+use crate::installer::{ensure_ephemeral_tool, find_project_python_tool};
+use crate::logging;
 use crate::platform::{get_python_version_request_from_pyenv_pin, get_toolchain_python_bin};
 use crate::pyproject::{latest_available_python_version, PyProject};
 use crate::sources::{PythonVersion, PythonVersionRequest};
@@ -85,6 +88,51 @@ fn find_shadowed_target(target: &str, args: &[OsString]) -> Result<Option<Vec<Os
     Ok(None)
 }
 
+/// Finds the target of a shim of a tool installed with `--match-project-python`.
+///
+/// Within a project the tool is run from a cached virtualenv with the Python
+/// version of the project, otherwise from where it was installed.
+fn get_tool_shim_target(
+    target: &str,
+    args: &[OsString],
+    pyproject: Option<&PyProject>,
+) -> Result<Option<Vec<OsString>>, Error> {
+    let (tool_venv, info) = match find_project_python_tool(target) {
+        Some(rv) => rv,
+        None => return Ok(None),
+    };
+    let venv = match pyproject {
+        Some(pyproject) => {
+            let requirement = info
+                .requirement
+                .parse::<Requirement>()
+                .with_context(|| format!("invalid recorded requirement '{}'", info.requirement))?;
+            let extra_requirements = info
+                .extra_requirements
+                .iter()
+                .map(|x| x.parse::<Requirement>())
+                .collect::<Result<Vec<_>, _>>()?;
+            let py_ver: PythonVersionRequest = pyproject.venv_python_version()?.into();
+            ensure_ephemeral_tool(
+                &requirement,
+                &extra_requirements,
+                Some(&py_ver),
+                false,
+                CommandOutput::Quiet,
+            )
+            .with_context(|| format!("failed to install {} for the project", requirement.name))?
+        }
+        None => tool_venv,
+    };
+    let bin = venv.join(VENV_BIN).join(target);
+    if !bin.is_file() {
+        return Ok(None);
+    }
+    let mut args = args.to_vec();
+    args[0] = bin.into();
+    Ok(Some(args))
+}
+
 /// Figures out where a shim should point to.
 fn get_shim_target(target: &str, args: &[OsString]) -> Result<Option<Vec<OsString>>, Error> {
     // if we can find a project, we always look for a local virtualenv first for shims.
@@ -115,6 +163,10 @@ fn get_shim_target(target: &str, args: &[OsString]) -> Result<Option<Vec<OsStrin
             return Ok(Some(get_pip_shim(&pyproject, args, CommandOutput::Normal)?));
         }
 
+        if let Some(args) = get_tool_shim_target(target, &args, Some(&pyproject))? {
+            return Ok(Some(args));
+        }
+
     // Global shims (either implicit or requested)
     } else if target == "python" || target == "python3" {
         let config = Config::current();
@@ -144,13 +196,18 @@ fn get_shim_target(target: &str, args: &[OsString]) -> Result<Option<Vec<OsStrin
             Err(_) => latest_available_python_version(&version_request)
                 .ok_or_else(|| anyhow!("Unable to determine target Python version"))?,
         };
-        let py = get_toolchain_python_bin(&py_ver)?;
+        let mut py = get_toolchain_python_bin(&py_ver)?;
         if !py.is_file() {
-            bail!(
-                "Requested Python version ({}) is not installed. Install with `rye fetch {}`",
-                py_ver,
-                py_ver
-            );
+            if config.offline() {
+                bail!(
+                    "Requested Python version ({}) is not installed. Install with `rye fetch {}`",
+                    py_ver,
+                    py_ver
+                );
+            }
+            fetch(&py_ver.clone().into(), CommandOutput::Normal)
+                .context("failed fetching toolchain for shim")?;
+            py = get_toolchain_python_bin(&py_ver)?;
         }
 
         let mut args = args.to_vec();
@@ -159,6 +216,8 @@ fn get_shim_target(target: &str, args: &[OsString]) -> Result<Option<Vec<OsStrin
             args.remove(1);
         }
         return Ok(Some(args));
+    } else if let Some(args) = get_tool_shim_target(target, args, None)? {
+        return Ok(Some(args));
     }
 
     // if we make it this far, we did not find a shim in the project, look for
@@ -176,7 +235,16 @@ fn spawn_shim(args: Vec<OsString>) -> Result<Infallible, Error> {
 /// executable is invoked as a shim executable.
 pub fn execute_shim(args: &[OsString]) -> Result<(), Error> {
     if let Some(shim_name) = detect_shim(args) {
-        if let Some(args) = get_shim_target(&shim_name, args)? {
+        logging::debug("shim", format_args!("dispatching shim {}", shim_name));
+        let target = {
+            let _phase = logging::phase("shim", "resolving shim target");
+            get_shim_target(&shim_name, args)?
+        };
+        if let Some(args) = target {
+            logging::debug(
+                "shim",
+                format_args!("executing {}", args[0].to_string_lossy()),
+            );
             match spawn_shim(args)? {}
         } else {
             bail!("target shim binary not found");
@@ -1,22 +1,34 @@
+use std::fs;
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
 
 use anyhow::{bail, Error};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use console::style;
+use serde::Serialize;
 
 use crate::bootstrap::ensure_self_venv;
 use crate::consts::VENV_BIN;
 use crate::pyproject::{get_current_venv_python_version, PyProject};
 use crate::utils::{get_venv_python_bin, CommandOutput};
 
+#[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+enum Format {
+    Json,
+}
+
 /// Prints the current state of the project.
 #[derive(Parser, Debug)]
 pub struct Args {
     /// Print the installed dependencies from the venv
     #[arg(long)]
     installed_deps: bool,
+    /// Request parseable output format rather than lines.
+    #[arg(long, conflicts_with = "installed_deps")]
+    format: Option<Format>,
     /// Use this pyproject.toml file
     #[arg(long, value_name = "PYPROJECT_TOML")]
     pyproject: Option<PathBuf>,
@@ -28,6 +40,9 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     if cmd.installed_deps {
         return print_installed_deps(&project);
     }
+    if let Some(Format::Json) = cmd.format {
+        return print_json(&project);
+    }
 
     println!(
         "project: {}",
@@ -73,6 +88,88 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     Ok(())
 }
 
+/// Output structure for show --format=json
+#[derive(Serialize, Debug)]
+struct ProjectInfo {
+    name: Option<String>,
+    path: PathBuf,
+    venv: PathBuf,
+    /// The interpreter of the virtualenv, which only exists once synced.
+    python: Option<PathBuf>,
+    python_version: Option<String>,
+    target_python: Option<String>,
+    workspace: Option<WorkspaceInfo>,
+    lockfiles: Vec<PathBuf>,
+}
+
+#[derive(Serialize, Debug)]
+struct WorkspaceInfo {
+    path: PathBuf,
+    members: Vec<MemberInfo>,
+}
+
+#[derive(Serialize, Debug)]
+struct MemberInfo {
+    name: Option<String>,
+    path: PathBuf,
+}
+
+fn print_json(project: &PyProject) -> Result<(), Error> {
+    let python = get_venv_python_bin(&project.venv_path());
+    let workspace = match project.workspace() {
+        Some(workspace) => {
+            let mut members = workspace
+                .iter_projects()
+                .map(|child| {
+                    child.map(|child| MemberInfo {
+                        name: child.name().map(|x| x.to_string()),
+                        path: child.root_path().into_owned(),
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            members.sort_by(|a, b| a.path.cmp(&b.path));
+            Some(WorkspaceInfo {
+                path: project.workspace_path().into_owned(),
+                members,
+            })
+        }
+        None => None,
+    };
+
+    // the production, dev and group lockfiles all live next to the
+    // pyproject.toml of the workspace.
+    let mut lockfiles = Vec::new();
+    if let Ok(entries) = fs::read_dir(project.workspace_path()) {
+        for entry in entries {
+            let path = entry?.path();
+            let is_lockfile = path
+                .file_name()
+                .and_then(|x| x.to_str())
+                .map_or(false, |x| {
+                    x.starts_with("requirements") && x.ends_with(".lock")
+                });
+            if is_lockfile {
+                lockfiles.push(path);
+            }
+        }
+    }
+    lockfiles.sort();
+
+    let info = ProjectInfo {
+        name: project.name().map(|x| x.to_string()),
+        path: project.root_path().into_owned(),
+        venv: project.venv_path().into_owned(),
+        python: if python.is_file() { Some(python) } else { None },
+        python_version: project.venv_python_version().ok().map(|x| x.to_string()),
+        target_python: project.target_python_version().map(|x| x.to_string()),
+        workspace,
+        lockfiles,
+    };
+    serde_json::to_writer_pretty(std::io::stdout().lock(), &info)?;
+    println!();
+    Ok(())
+}
+
 fn print_installed_deps(project: &PyProject) -> Result<(), Error> {
     let python = get_venv_python_bin(&project.venv_path());
     if !python.is_file() {
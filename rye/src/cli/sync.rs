@@ -1,27 +1,93 @@
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Error;
-use clap::Parser;
+use clap::{ArgAction, Parser};
+use console::style;
 
-use crate::lock::LockOptions;
+use crate::config::Config;
+use crate::hooks::Hooks;
+use crate::lock::{parse_exclude_newer, LockOptions, LockPlatform};
+use crate::pyproject::PyProject;
 use crate::sync::{sync, SyncMode, SyncOptions};
+use crate::transcript;
 use crate::utils::CommandOutput;
 
+/// How often the watched files are checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long the watched files need to stay unchanged before syncing.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
 /// Updates the virtualenv based on the pyproject.toml
 #[derive(Parser, Debug)]
 pub struct Args {
     /// Force the environment to be re-created
     #[arg(short, long)]
     force: bool,
+    /// Fail instead of re-creating an incompatible environment.
+    #[arg(long, conflicts_with = "force")]
+    no_recreate: bool,
+    /// Recreate the environment without asking if its Python interpreter is gone.
+    #[arg(long, conflicts_with = "no_recreate")]
+    force_recreate: bool,
     /// Do not include dev dependencies.
     #[arg(long)]
     no_dev: bool,
     /// Do not update the lockfile.
-    #[arg(long)]
+    #[arg(long, visible_alias = "frozen")]
     no_lock: bool,
-    /// Enables verbose diagnostics.
-    #[arg(short, long)]
-    verbose: bool,
+    /// Do not update the lockfile but fail if it is out of date.
+    #[arg(
+        long,
+        conflicts_with = "no_lock",
+        conflicts_with = "update",
+        conflicts_with = "update_all"
+    )]
+    locked: bool,
+    /// Install a dependency group from its own lockfile instead of the dev dependencies.
+    #[arg(long, value_name = "GROUP", conflicts_with = "no_dev")]
+    group: Option<String>,
+    /// Only install a dependency group from its own lockfile.
+    #[arg(
+        long,
+        value_name = "GROUP",
+        conflicts_with = "group",
+        conflicts_with = "no_dev"
+    )]
+    only_group: Option<String>,
+    /// Refuse to install packages that are not hash pinned in the lockfile.
+    #[arg(long)]
+    require_hashes: bool,
+    /// Rewrite the scripts of the virtualenv so that it can be moved.
+    ///
+    /// This is enabled by `tool.rye.relocatable` as well.
+    #[arg(long)]
+    relocatable: bool,
+    /// Only report whether the lockfiles and the virtualenv are up to date.
+    ///
+    /// Nothing is changed.  Exits with an error if a sync would change something.
+    #[arg(
+        long,
+        conflicts_with = "force",
+        conflicts_with = "group",
+        conflicts_with = "only_group"
+    )]
+    check: bool,
+    /// Keep packages that were installed into the virtualenv by hand.
+    #[arg(long)]
+    keep_extras: bool,
+    /// Number of packages to install in parallel.
+    ///
+    /// Defaults to the number of CPUs (up to 8).  Pass 1 to install one
+    /// package after another.
+    #[arg(short, long, value_name = "N")]
+    jobs: Option<usize>,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
     /// Turns off all output.
     #[arg(short, long, conflicts_with = "verbose")]
     quiet: bool,
@@ -40,14 +106,85 @@ pub struct Args {
     /// Enables all features.
     #[arg(long)]
     all_features: bool,
+    /// Record hashes of all packages in the lockfile.
+    #[arg(long)]
+    generate_hashes: bool,
+    /// Resolve the lockfiles for every supported platform at once.
+    #[arg(long, conflicts_with = "platform")]
+    universal: bool,
+    /// Resolve the lockfiles for this platform (eg: linux-x86_64, macos-arm64).
+    #[arg(long, value_name = "PLATFORM")]
+    platform: Vec<String>,
+    /// Resolve the lockfiles for this Python version (eg: 3.11).
+    #[arg(long, value_name = "VERSION")]
+    python_version: Option<String>,
+    /// Always build this package from source (`:all:` for all packages).
+    #[arg(long, value_name = "PACKAGE")]
+    no_binary: Vec<String>,
+    /// Never build this package from source (`:all:` for all packages).
+    #[arg(long, value_name = "PACKAGE")]
+    only_binary: Vec<String>,
+    /// Ignore packages uploaded after this date or timestamp.
+    #[arg(long, value_name = "DATE")]
+    exclude_newer: Option<String>,
+    /// Only install these workspace members and the members they depend on.
+    ///
+    /// The lockfiles still cover the whole workspace and the installed
+    /// versions match them.
+    #[arg(short, long, value_name = "MEMBER", conflicts_with = "check")]
+    package: Vec<String>,
+    /// Sync a named environment from `tool.rye.envs` instead.
+    #[arg(long, value_name = "NAME", conflicts_with = "check")]
+    env: Option<String>,
     /// Use this pyproject.toml file
     #[arg(long, value_name = "PYPROJECT_TOML")]
     pyproject: Option<PathBuf>,
+    /// Record all invoked subprocesses into a JSON transcript at this path.
+    #[arg(long, value_name = "PATH")]
+    transcript: Option<PathBuf>,
+    /// Install from the wheel cache only, without network access.
+    ///
+    /// The lockfiles are used as they are.  Fails with the list of missing
+    /// packages unless all of them are in the wheel cache.
+    #[arg(
+        long,
+        conflicts_with = "locked",
+        conflicts_with = "update",
+        conflicts_with = "update_all"
+    )]
+    offline: bool,
+    /// Keep running and sync again whenever a pyproject.toml changes.
+    #[arg(
+        long,
+        conflicts_with = "check",
+        conflicts_with = "force",
+        conflicts_with = "transcript"
+    )]
+    watch: bool,
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
-    sync(SyncOptions {
+    let transcript_path = cmd.transcript;
+    if transcript_path.is_some() {
+        transcript::start();
+    }
+    let (no_lock, locked) = match (cmd.no_lock || cmd.offline, cmd.locked) {
+        (false, false) if cmd.update.is_empty() && !cmd.update_all => {
+            match Config::current().lockfile_mode().as_deref() {
+                Some("frozen") => (true, false),
+                Some("locked") => (false, true),
+                _ => (false, false),
+            }
+        }
+        flags => flags,
+    };
+    let exclude_newer = cmd
+        .exclude_newer
+        .as_deref()
+        .map(parse_exclude_newer)
+        .transpose()?;
+    let make_options = || SyncOptions {
         output,
         dev: !cmd.no_dev,
         mode: if cmd.force {
@@ -56,15 +193,142 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
             SyncMode::Regular
         },
         force: cmd.force,
-        no_lock: cmd.no_lock,
+        no_recreate: cmd.no_recreate,
+        force_recreate: cmd.force_recreate,
+        no_lock,
+        locked,
         lock_options: LockOptions {
-            update: cmd.update,
+            update: cmd.update.clone(),
+            constraints: Vec::new(),
             update_all: cmd.update_all,
             pre: cmd.pre,
-            features: cmd.features,
+            features: cmd.features.clone(),
             all_features: cmd.all_features,
+            generate_hashes: cmd.generate_hashes,
+            platforms: if cmd.universal {
+                LockPlatform::all_names()
+            } else {
+                cmd.platform.clone()
+            },
+            python_version: cmd.python_version.clone(),
+            target: None,
+            record_files: None,
+            exclude_newer: exclude_newer.clone(),
+            verbose_resolution: false,
+            no_binary: cmd.no_binary.clone(),
+            only_binary: cmd.only_binary.clone(),
+            build_settings: Default::default(),
         },
-        pyproject: cmd.pyproject,
-    })?;
-    Ok(())
+        require_hashes: cmd.require_hashes,
+        keep_extras: cmd.keep_extras,
+        relocatable: cmd.relocatable,
+        jobs: cmd.jobs,
+        check: cmd.check,
+        only_group: cmd.only_group.is_some(),
+        group: cmd.only_group.clone().or(cmd.group.clone()),
+        lock_groups: Vec::new(),
+        all_groups: false,
+        pyproject: cmd.pyproject.clone(),
+        env: cmd.env.clone(),
+        env_config: None,
+        packages: cmd.package.clone(),
+        offline: cmd.offline,
+    };
+    let sync_with_hooks = || {
+        // nothing is changed in check mode, so the hooks do not run either
+        if cmd.check {
+            return sync(make_options());
+        }
+        // the hooks are loaded for every sync as they might have changed
+        // since the last one in watch mode.
+        let hooks = Hooks::load(&PyProject::load_or_discover(cmd.pyproject.as_deref())?)?;
+        hooks.run("pre-sync", output)?;
+        sync(make_options())?;
+        hooks.run("post-sync", output)
+    };
+    let result = sync_with_hooks();
+    if let Some(path) = transcript_path {
+        transcript::finish(&path, &result)?;
+    }
+    if !cmd.watch {
+        return result;
+    } else if let Err(err) = result {
+        eprintln!("{} {:#}", style("error:").red(), err);
+    }
+    watch(cmd.pyproject.as_deref(), output, sync_with_hooks)
+}
+
+/// Syncs again whenever one of the watched files changes.
+///
+/// The files are polled, and a sync only starts once they stopped changing
+/// as editors and version control often write them in several steps.
+fn watch<F>(pyproject: Option<&Path>, output: CommandOutput, mut sync_again: F) -> Result<(), Error>
+where
+    F: FnMut() -> Result<(), Error>,
+{
+    let mut files = watched_files(pyproject)?;
+    let mut state = modification_times(&files);
+    if output != CommandOutput::Quiet {
+        eprintln!("Watching for changes (press Ctrl+C to stop)");
+    }
+    loop {
+        thread::sleep(WATCH_INTERVAL);
+        let mut current = modification_times(&files);
+        if current == state {
+            continue;
+        }
+        loop {
+            thread::sleep(WATCH_DEBOUNCE);
+            let settled = modification_times(&files);
+            if settled == current {
+                break;
+            }
+            current = settled;
+        }
+
+        if output != CommandOutput::Quiet {
+            for (path, _) in files
+                .iter()
+                .zip(state.iter().zip(current.iter()))
+                .filter(|(_, (old, new))| old != new)
+            {
+                eprintln!("{} changed", style(path.display()).cyan());
+            }
+        }
+        if let Err(err) = sync_again() {
+            eprintln!("{} {:#}", style("error:").red(), err);
+        }
+        // workspace members might have been added or removed.  If the project
+        // cannot be loaded (for instance while it's being edited) the known
+        // files are watched.
+        if let Ok(new_files) = watched_files(pyproject) {
+            files = new_files;
+        }
+        state = modification_times(&files);
+    }
+}
+
+/// Returns the pyproject.toml files of the workspace and the Python pins.
+fn watched_files(pyproject: Option<&Path>) -> Result<Vec<PathBuf>, Error> {
+    let project = PyProject::load_or_discover(pyproject)?;
+    let mut rv = vec![
+        project.toml_path().into_owned(),
+        project.root_path().join(".python-version"),
+    ];
+    if let Some(workspace) = project.workspace() {
+        rv.push(workspace.path().join(".python-version"));
+        for member in workspace.iter_projects() {
+            rv.push(member?.toml_path().into_owned());
+        }
+    }
+    rv.sort();
+    rv.dedup();
+    Ok(rv)
+}
+
+fn modification_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|x| fs::metadata(x).and_then(|x| x.modified()).ok())
+        .collect()
 }
@@ -0,0 +1,472 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::{env, fs};
+
+use anyhow::{bail, Context, Error};
+use clap::{ArgAction, Parser};
+use console::style;
+
+use crate::pyproject::{EnvConfig, PyProject};
+use crate::secrets::load_secrets;
+use crate::sources::PythonVersionRequest;
+use crate::sync::{sync, SyncMode, SyncOptions};
+use crate::utils::{get_venv_python_bin, CommandOutput, QuietExit};
+
+/// Runs the tests of the project with unittest and writes a JUnit XML
+/// report if one was requested.
+///
+/// Invoked as `python -c SCRIPT REPORT [ARGS...]` where an empty report path
+/// disables the report.  The remaining arguments are passed to unittest.
+const UNITTEST_SCRIPT: &str = r#"
+import sys
+import time
+import unittest
+import traceback
+import xml.etree.ElementTree as ET
+
+report, args = sys.argv[1], sys.argv[2:]
+cases = []
+
+
+class JUnitResult(unittest.TextTestResult):
+    def startTest(self, test):
+        self._started = time.time()
+        super().startTest(test)
+
+    def _record(self, test, kind=None, err=None, message=None):
+        duration = time.time() - getattr(self, "_started", time.time())
+        if err is not None:
+            message = str(err[1])
+            err = "".join(traceback.format_exception(*err))
+        cases.append((test, duration, kind, message, err))
+
+    def addSuccess(self, test):
+        super().addSuccess(test)
+        self._record(test)
+
+    def addFailure(self, test, err):
+        super().addFailure(test, err)
+        self._record(test, "failure", err)
+
+    def addError(self, test, err):
+        super().addError(test, err)
+        self._record(test, "error", err)
+
+    def addSkip(self, test, reason):
+        super().addSkip(test, reason)
+        self._record(test, "skipped", message=reason)
+
+
+def write_report():
+    def count(kind):
+        return str(sum(1 for case in cases if case[2] == kind))
+
+    suite = ET.Element("testsuite", name="unittest", tests=str(len(cases)),
+                       failures=count("failure"), errors=count("error"),
+                       skipped=count("skipped"))
+    for test, duration, kind, message, details in cases:
+        classname, _, name = test.id().rpartition(".")
+        case = ET.SubElement(suite, "testcase", classname=classname, name=name,
+                             time="%.3f" % duration)
+        if kind is not None:
+            node = ET.SubElement(case, kind, message=message or "")
+            node.text = details
+    root = ET.Element("testsuites")
+    root.append(suite)
+    ET.ElementTree(root).write(report, encoding="utf-8", xml_declaration=True)
+
+
+runner = unittest.TextTestRunner(resultclass=JUnitResult)
+program = unittest.main(module=None, argv=["python -m unittest"] + args,
+                        testRunner=runner, exit=False)
+if report:
+    write_report()
+sys.exit(0 if program.result.wasSuccessful() else 1)
+"#;
+
+/// Prints GitHub Actions annotations for the failures in a JUnit XML report.
+///
+/// The location of a failure is the last frame of the traceback that is
+/// within the project, falling back to the file and line of the test case.
+const ANNOTATE_SCRIPT: &str = r#"
+import os
+import re
+import sys
+import xml.etree.ElementTree as ET
+
+FRAME_RE = re.compile(r'^\s*File "([^"]+)", line (\d+)|^([^\s:]+\.py):(\d+):', re.M)
+root = os.getcwd()
+
+
+def escape(value, prop=False):
+    value = value.replace("%", "%25").replace("\r", "%0D").replace("\n", "%0A")
+    if prop:
+        value = value.replace(":", "%3A").replace(",", "%2C")
+    return value
+
+
+def locate(case, details):
+    location = None
+    for match in FRAME_RE.finditer(details or ""):
+        path = match.group(1) or match.group(3)
+        line = match.group(2) or match.group(4)
+        path = os.path.relpath(os.path.join(root, path), root)
+        if not path.startswith("..") and "site-packages" not in path:
+            location = (path, line)
+    if location is None and case.get("file"):
+        location = (case.get("file"), case.get("line") or "1")
+    return location
+
+
+for case in ET.parse(sys.argv[1]).iter("testcase"):
+    for problem in list(case.findall("failure")) + list(case.findall("error")):
+        title = "%s.%s" % (case.get("classname"), case.get("name"))
+        props = "title=" + escape(title, True)
+        location = locate(case, problem.text)
+        if location is not None:
+            props = "file=%s,line=%s,%s" % (escape(location[0], True), location[1], props)
+        message = problem.get("message") or problem.text or "test failed"
+        print("::error %s::%s" % (props, escape(message)))
+"#;
+
+/// Runs the tests of the project.
+///
+/// Uses pytest if it's installed into the virtualenv and unittest otherwise.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Write a JUnit XML report to this path.
+    ///
+    /// When testing with multiple Python versions the version is added to
+    /// the file name.
+    #[arg(long, value_name = "PATH")]
+    junit: Option<PathBuf>,
+    /// Run the tests with every Python version in `tool.rye.matrix`.
+    ///
+    /// Each version is tested in a throwaway virtualenv.
+    #[arg(long)]
+    all_pythons: bool,
+    /// Measure the code coverage of the tests.
+    ///
+    /// This requires coverage to be installed into the virtualenv.
+    #[arg(long)]
+    coverage: bool,
+    /// Fail if the total coverage is below this percentage.
+    ///
+    /// Defaults to `tool.rye.coverage-fail-under`.
+    #[arg(long, value_name = "PERCENT", requires = "coverage")]
+    fail_under: Option<f64>,
+    /// Print failures as GitHub Actions annotations.
+    ///
+    /// This is enabled automatically when running on GitHub Actions.
+    #[arg(long)]
+    github_annotations: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Extra arguments to the test runner.
+    #[arg(last = true)]
+    extra_args: Vec<OsString>,
+}
+
+/// The test runners that are supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Runner {
+    Pytest,
+    Unittest,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    if cmd.all_pythons {
+        return run_matrix(&cmd, output);
+    }
+
+    let pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    sync(SyncOptions::python_only().pyproject(cmd.pyproject.clone()))
+        .context("failed to sync ahead of test")?;
+
+    let secrets = load_secrets(&pyproject)?;
+    let status = run_tests(&cmd, &pyproject, &secrets, cmd.junit.as_deref(), output)?;
+    if !status.success() {
+        return Err(QuietExit(status.code().unwrap_or(1)).into());
+    }
+    Ok(())
+}
+
+/// Runs the tests once for every Python version of the matrix.
+///
+/// A version that fails to sync does not stop the others from being tested.
+fn run_matrix(cmd: &Args, output: CommandOutput) -> Result<(), Error> {
+    let pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let pythons = pyproject.matrix_pythons()?;
+    if pythons.is_empty() {
+        bail!("no Python versions to test with. Add them to tool.rye.matrix.python");
+    }
+
+    let secrets = load_secrets(&pyproject)?;
+    let mut results = Vec::new();
+    for python in pythons {
+        if output != CommandOutput::Quiet {
+            eprintln!(
+                "{} {}",
+                style("Testing with Python").bold(),
+                style(&python).cyan()
+            );
+        }
+        let result = run_matrix_entry(cmd, &pyproject, &secrets, &python, output);
+        if let Err(ref err) = result {
+            eprintln!("{} {:#}", style("error:").red(), err);
+        }
+        results.push((python, result));
+    }
+
+    let mut failed = false;
+    if output != CommandOutput::Quiet {
+        eprintln!();
+        eprintln!("{}", style("Summary:").bold());
+    }
+    for (python, result) in results {
+        let passed = matches!(result, Ok(status) if status.success());
+        let outcome = match result {
+            _ if passed => style("passed").green(),
+            Ok(_) => style("failed").red(),
+            Err(_) => style("error").red(),
+        };
+        failed |= !passed;
+        if output != CommandOutput::Quiet {
+            eprintln!("  {:<10} {}", python.to_string(), outcome);
+        }
+    }
+    if failed {
+        return Err(QuietExit(1).into());
+    }
+    Ok(())
+}
+
+/// Syncs a throwaway virtualenv for a Python version and runs the tests in it.
+fn run_matrix_entry(
+    cmd: &Args,
+    pyproject: &PyProject,
+    secrets: &[(String, String)],
+    python: &PythonVersionRequest,
+    output: CommandOutput,
+) -> Result<ExitStatus, Error> {
+    // the lockfiles of the project are the starting point for resolving the
+    // dependencies so that the pins are kept where possible.
+    let scratch = tempfile::tempdir()?;
+    for name in ["requirements.lock", "requirements-dev.lock"] {
+        let lockfile = pyproject.workspace_path().join(name);
+        if lockfile.is_file() {
+            fs::copy(&lockfile, scratch.path().join(name))?;
+        }
+    }
+    let env = EnvConfig {
+        name: format!("py{}", python),
+        python: Some(python.clone()),
+        groups: Vec::new(),
+        dev: true,
+        location: Some(scratch.path().to_path_buf()),
+    };
+    sync(SyncOptions {
+        output,
+        dev: true,
+        mode: SyncMode::Regular,
+        pyproject: cmd.pyproject.clone(),
+        env_config: Some(env.clone()),
+        ..SyncOptions::default()
+    })
+    .with_context(|| format!("failed to sync with Python {}", python))?;
+
+    let pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?.with_env_config(env);
+    let junit = cmd.junit.as_deref().map(|x| junit_path_for(x, python));
+    run_tests(cmd, &pyproject, secrets, junit.as_deref(), output)
+}
+
+/// Adds the Python version to the file name of a JUnit report.
+fn junit_path_for(path: &Path, python: &PythonVersionRequest) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}-py{}.{}", stem, python, ext.to_string_lossy()),
+        None => format!("{}-py{}", stem, python),
+    };
+    path.with_file_name(name)
+}
+
+/// Runs the tests in the virtualenv of the project.
+///
+/// Like with `rye run`, the secrets do not override the environment but the
+/// variables of the project do.
+fn run_tests(
+    cmd: &Args,
+    pyproject: &PyProject,
+    secrets: &[(String, String)],
+    junit: Option<&Path>,
+    output: CommandOutput,
+) -> Result<ExitStatus, Error> {
+    let py = get_venv_python_bin(&pyproject.venv_path());
+    let root = pyproject.root_path();
+    let runner = if can_import(&py, "pytest")? {
+        Runner::Pytest
+    } else {
+        Runner::Unittest
+    };
+    if cmd.coverage && !can_import(&py, "coverage")? {
+        bail!("coverage is not installed. Add it with `rye add --dev coverage`");
+    }
+    let annotate =
+        cmd.github_annotations || env::var("GITHUB_ACTIONS").ok().as_deref() == Some("true");
+
+    // annotations are generated from the report, so one is always written
+    // when they are requested.
+    let tmp_dir = tempfile::tempdir()?;
+    let report = match junit {
+        Some(path) => Some(env::current_dir()?.join(path)),
+        None if annotate => Some(tmp_dir.path().join("junit.xml")),
+        None => None,
+    };
+
+    let mut test_cmd = Command::new(&py);
+    if cmd.coverage {
+        // the data is written into files of their own per process and
+        // combined afterwards, so that subprocesses can contribute.
+        if !run_coverage(&py, &root, &["erase"])?.success() {
+            bail!("failed to erase the previous coverage data");
+        }
+        test_cmd
+            .arg("-m")
+            .arg("coverage")
+            .arg("run")
+            .arg("--parallel-mode");
+    }
+    match runner {
+        Runner::Pytest => {
+            test_cmd.arg("-m").arg("pytest");
+            if let Some(ref report) = report {
+                // the xunit1 flavor records file and line of every test case
+                test_cmd
+                    .arg("--junitxml")
+                    .arg(report)
+                    .arg("-o")
+                    .arg("junit_family=xunit1");
+            }
+        }
+        Runner::Unittest => {
+            // coverage can only run scripts from a file
+            if cmd.coverage {
+                let script = tmp_dir.path().join("rye_unittest.py");
+                fs::write(&script, UNITTEST_SCRIPT)?;
+                test_cmd.arg(script);
+            } else {
+                test_cmd.arg("-c").arg(UNITTEST_SCRIPT);
+            }
+            test_cmd.arg(report.as_deref().unwrap_or(Path::new("")));
+            if cmd.extra_args.is_empty() {
+                test_cmd.arg("discover");
+            }
+        }
+    }
+    match output {
+        CommandOutput::Verbose => {
+            test_cmd.arg("-v");
+        }
+        CommandOutput::Quiet => {
+            test_cmd.arg("-q");
+        }
+        CommandOutput::Normal => {}
+    }
+    test_cmd
+        .args(&cmd.extra_args)
+        .envs(
+            secrets
+                .iter()
+                .filter(|(key, _)| env::var_os(key).is_none())
+                .map(|(key, value)| (key, value)),
+        )
+        .envs(pyproject.env_vars()?)
+        .env("VIRTUAL_ENV", &*pyproject.venv_path())
+        .current_dir(&root);
+    if output != CommandOutput::Quiet {
+        eprintln!(
+            "Running tests with {}",
+            style(match runner {
+                Runner::Pytest => "pytest",
+                Runner::Unittest => "unittest",
+            })
+            .cyan()
+        );
+    }
+    let mut status = test_cmd.status().context("failed to run the tests")?;
+
+    if let Some(ref report) = report {
+        if annotate && report.is_file() {
+            let annotate_status = Command::new(&py)
+                .arg("-c")
+                .arg(ANNOTATE_SCRIPT)
+                .arg(report)
+                .current_dir(&root)
+                .status()
+                .context("failed to annotate test failures")?;
+            if !annotate_status.success() {
+                bail!("failed to annotate test failures");
+            }
+        }
+        if junit.is_some() && output != CommandOutput::Quiet {
+            eprintln!("Wrote JUnit report to {}", style(report.display()).cyan());
+        }
+    }
+
+    if cmd.coverage {
+        let fail_under = cmd.fail_under.or_else(|| pyproject.coverage_fail_under());
+        let mut report_args = vec!["report".to_string()];
+        if let Some(fail_under) = fail_under {
+            report_args.push(format!("--fail-under={}", fail_under));
+        }
+        let mut report_status = run_coverage(&py, &root, &["combine", "-q"])?;
+        if report_status.success() {
+            report_status = run_coverage(&py, &root, &report_args)?;
+        }
+        // coverage exits with 2 if the total is below the threshold
+        if let (Some(2), Some(fail_under)) = (report_status.code(), fail_under) {
+            eprintln!(
+                "{} the total coverage is below {}%",
+                style("error:").red(),
+                fail_under
+            );
+        }
+        if status.success() {
+            status = report_status;
+        }
+    }
+
+    Ok(status)
+}
+
+/// Can the module be imported in the virtualenv?
+fn can_import(py: &Path, module: &str) -> Result<bool, Error> {
+    let status = Command::new(py)
+        .arg("-c")
+        .arg(format!("import {}", module))
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("unable to run the Python of the virtualenv")?;
+    Ok(status.success())
+}
+
+/// Runs a coverage command in the project.
+fn run_coverage<S: AsRef<OsStr>>(py: &Path, root: &Path, args: &[S]) -> Result<ExitStatus, Error> {
+    Command::new(py)
+        .arg("-m")
+        .arg("coverage")
+        .args(args)
+        .current_dir(root)
+        .status()
+        .context("unable to run coverage")
+}
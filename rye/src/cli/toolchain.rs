@@ -4,18 +4,26 @@ use std::env::consts::{ARCH, OS};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, bail, Context, Error};
 use clap::Parser;
 use clap::ValueEnum;
 use console::style;
+use indicatif::HumanBytes;
 use serde::Deserialize;
 use serde::Serialize;
 
-use crate::platform::{get_canonical_py_path, list_known_toolchains};
+use crate::platform::{
+    find_py_path, get_app_dir, get_canonical_py_path, get_shared_py_path, get_toolchain_python_bin,
+    get_toolchain_usage, list_known_toolchains,
+};
 use crate::sources::{iter_downloadable, PythonVersion};
 use crate::utils::symlink_file;
 
+/// The name of the manifest file that `rye toolchain link` places into the target.
+const LINK_MANIFEST: &str = "rye-toolchain-link.json";
+
 const INSPECT_SCRIPT: &str = r#"
 import json
 import platform
@@ -71,6 +79,23 @@ pub struct ListCommand {
     /// Request parseable output format
     #[arg(long)]
     format: Option<Format>,
+    /// Sort the installed toolchains by size or last use.
+    #[arg(long, value_enum)]
+    sort: Option<SortKey>,
+}
+
+/// Shows the disk usage of the installed toolchains.
+///
+/// Next to the toolchain itself this includes the pip-tools virtualenv Rye
+/// keeps for every Python version, and when the toolchain was last used.
+#[derive(Parser, Debug)]
+pub struct DuCommand {
+    /// Request parseable output format
+    #[arg(long)]
+    format: Option<Format>,
+    /// How to sort the toolchains.
+    #[arg(long, value_enum, default_value = "size")]
+    sort: SortKey,
 }
 
 #[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
@@ -80,20 +105,61 @@ enum Format {
     Json,
 }
 
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq)]
+#[value(rename_all = "kebab-case")]
+enum SortKey {
+    /// By name and version.
+    Name,
+    /// Largest first.
+    Size,
+    /// Most recently used first.
+    LastUsed,
+}
+
+/// Materializes an installed toolchain into a target directory.
+///
+/// This creates a relocatable copy of the toolchain (or a farm of symlinks
+/// pointing to it with `--symlink`) which can for instance be embedded into
+/// an installer.  A manifest of all created files is placed into the target
+/// directory so that `rye toolchain unlink` can remove them again.
+#[derive(Parser, Debug)]
+pub struct LinkCommand {
+    /// Name and version of the toolchain.
+    version: String,
+    /// The directory to place the toolchain into.
+    dest: PathBuf,
+    /// Symlink the files instead of copying them.
+    #[arg(long)]
+    symlink: bool,
+}
+
+/// Removes a toolchain previously placed with `rye toolchain link`.
+#[derive(Parser, Debug)]
+pub struct UnlinkCommand {
+    /// The directory the toolchain was linked into.
+    dest: PathBuf,
+}
+
 #[derive(Parser, Debug)]
 enum SubCommand {
+    Du(DuCommand),
     Fetch(crate::cli::fetch::Args),
+    Link(LinkCommand),
     List(ListCommand),
     Register(RegisterCommand),
     Remove(RemoveCommand),
+    Unlink(UnlinkCommand),
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     match cmd.command {
         SubCommand::Register(args) => register(args),
+        SubCommand::Du(args) => du(args),
         SubCommand::Fetch(args) => crate::cli::fetch::execute(args),
+        SubCommand::Link(args) => link(args),
         SubCommand::List(args) => list(args),
         SubCommand::Remove(args) => remove(args),
+        SubCommand::Unlink(args) => unlink(args),
     }
 }
 
@@ -112,6 +178,11 @@ pub fn remove(cmd: RemoveCommand) -> Result<(), Error> {
     } else if path.is_dir() {
         fs::remove_dir_all(&path)?;
         eprintln!("Removed installed toolchain {}", &ver);
+    } else if get_shared_py_path(&ver)?.is_some() {
+        bail!(
+            "toolchain {} is provided by the shared toolchain store and cannot be removed",
+            ver
+        );
     } else {
         eprintln!("Toolchain is not installed");
     }
@@ -127,6 +198,25 @@ struct ListVersion {
     path: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     downloadable: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_used: Option<u64>,
+}
+
+/// Disk usage of an installed toolchain.
+#[derive(Serialize, Debug)]
+struct ToolchainUsage {
+    name: PythonVersion,
+    path: PathBuf,
+    /// Size of the toolchain itself (zero for registered interpreters).
+    size: u64,
+    /// Size of the pip-tools virtualenv for this Python version.
+    cache_size: u64,
+    /// Unix timestamp of the last use, if recorded.
+    last_used: Option<u64>,
+    /// Provided by the shared toolchain store.
+    shared: bool,
 }
 
 fn list(cmd: ListCommand) -> Result<(), Error> {
@@ -144,10 +234,31 @@ fn list(cmd: ListCommand) -> Result<(), Error> {
     let mut versions = toolchains.into_iter().collect::<Vec<_>>();
     versions.sort_by_cached_key(|a| (a.1.is_none(), a.0.kind.to_string(), Reverse(a.clone())));
 
+    let usage = match cmd.sort {
+        Some(sort) if sort != SortKey::Name => {
+            let mut usage = collect_usage()?;
+            sort_usage(&mut usage, sort);
+            let order = usage
+                .iter()
+                .enumerate()
+                .map(|(idx, x)| (x.name.clone(), idx))
+                .collect::<HashMap<_, _>>();
+            // downloadable toolchains have no usage and stay at the end
+            versions.sort_by_key(|a| order.get(&a.0).copied().unwrap_or(usize::MAX));
+            usage
+                .into_iter()
+                .map(|x| (x.name.clone(), x))
+                .collect::<HashMap<_, _>>()
+        }
+        _ => HashMap::new(),
+    };
+
     if let Some(Format::Json) = cmd.format {
         let json_versions = versions
             .into_iter()
             .map(|(version, path)| ListVersion {
+                size: usage.get(&version).map(|x| x.size),
+                last_used: usage.get(&version).and_then(|x| x.last_used),
                 name: version,
                 downloadable: if path.is_none() { Some(true) } else { None },
                 path: path.map(|p| p.to_string_lossy().into_owned()),
@@ -156,13 +267,28 @@ fn list(cmd: ListCommand) -> Result<(), Error> {
         serde_json::to_writer_pretty(std::io::stdout().lock(), &json_versions)?;
         println!();
     } else {
+        let now = unix_now();
         for (version, path) in versions {
             if let Some(path) = path {
-                println!(
-                    "{} ({})",
-                    style(&version).green(),
-                    style(path.display()).dim()
-                );
+                match (cmd.sort, usage.get(&version)) {
+                    (Some(SortKey::Size), Some(info)) => println!(
+                        "{} ({}) {}",
+                        style(&version).green(),
+                        style(path.display()).dim(),
+                        HumanBytes(info.size)
+                    ),
+                    (Some(SortKey::LastUsed), Some(info)) => println!(
+                        "{} ({}) last used {}",
+                        style(&version).green(),
+                        style(path.display()).dim(),
+                        format_last_used(info.last_used, now)
+                    ),
+                    _ => println!(
+                        "{} ({})",
+                        style(&version).green(),
+                        style(path.display()).dim()
+                    ),
+                }
             } else {
                 println!("{} (downloadable)", style(version).dim());
             }
@@ -171,6 +297,227 @@ fn list(cmd: ListCommand) -> Result<(), Error> {
     Ok(())
 }
 
+fn du(cmd: DuCommand) -> Result<(), Error> {
+    let mut usage = collect_usage()?;
+    sort_usage(&mut usage, cmd.sort);
+
+    if let Some(Format::Json) = cmd.format {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &usage)?;
+        println!();
+        return Ok(());
+    }
+    let now = unix_now();
+    for info in &usage {
+        println!(
+            "{:<24} {:>12} {:>12} cache  last used {}{}",
+            style(&info.name).green(),
+            HumanBytes(info.size).to_string(),
+            HumanBytes(info.cache_size).to_string(),
+            format_last_used(info.last_used, now),
+            if info.shared {
+                style(" (shared)").dim().to_string()
+            } else {
+                String::new()
+            }
+        );
+    }
+    println!(
+        "{:<24} {:>12} {:>12} cache",
+        "total",
+        HumanBytes(usage.iter().map(|x| x.size).sum()).to_string(),
+        HumanBytes(usage.iter().map(|x| x.cache_size).sum()).to_string()
+    );
+    Ok(())
+}
+
+/// Collects the disk usage of all installed toolchains.
+///
+/// The pip-tools virtualenvs are per minor version, so toolchains of the
+/// same minor version report the same cache.
+fn collect_usage() -> Result<Vec<ToolchainUsage>, Error> {
+    let last_used = get_toolchain_usage();
+    let mut rv = Vec::new();
+    for (version, _) in list_known_toolchains()? {
+        let path = find_py_path(&version)?;
+        let cache = get_app_dir().join("pip-tools").join(format!(
+            "{}@{}.{}",
+            version.kind, version.major, version.minor
+        ));
+        rv.push(ToolchainUsage {
+            size: if path.is_dir() { dir_size(&path) } else { 0 },
+            cache_size: dir_size(&cache),
+            last_used: last_used.get(&version.to_string()).copied(),
+            shared: get_shared_py_path(&version)?.is_some(),
+            name: version,
+            path,
+        });
+    }
+    Ok(rv)
+}
+
+fn sort_usage(usage: &mut [ToolchainUsage], sort: SortKey) {
+    match sort {
+        SortKey::Name => {
+            usage.sort_by_cached_key(|x| (x.name.kind.to_string(), Reverse(x.name.clone())))
+        }
+        SortKey::Size => usage.sort_by_key(|x| Reverse(x.size + x.cache_size)),
+        SortKey::LastUsed => usage.sort_by_key(|x| Reverse(x.last_used)),
+    }
+}
+
+fn dir_size(path: &Path) -> u64 {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|x| x.ok())
+        .filter(|x| x.file_type().is_file())
+        .filter_map(|x| x.metadata().ok())
+        .map(|x| x.len())
+        .sum()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
+
+fn format_last_used(last_used: Option<u64>, now: u64) -> String {
+    match last_used.map(|x| now.saturating_sub(x) / (24 * 60 * 60)) {
+        None => "unknown".into(),
+        Some(0) => "today".into(),
+        Some(1) => "yesterday".into(),
+        Some(days) => format!("{} days ago", days),
+    }
+}
+
+/// Manifest written by `rye toolchain link`.
+#[derive(Serialize, Deserialize, Debug)]
+struct LinkManifest {
+    toolchain: PythonVersion,
+    symlink: bool,
+    dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+fn link(cmd: LinkCommand) -> Result<(), Error> {
+    let ver: PythonVersion = cmd.version.parse()?;
+    let canonical_path = find_py_path(&ver)?;
+    if canonical_path.is_file() {
+        bail!(
+            "toolchain {} is a registered interpreter and cannot be linked",
+            ver
+        );
+    } else if !canonical_path.is_dir() {
+        bail!("toolchain {} is not installed", ver);
+    }
+
+    // the installation root is the folder that holds the bin folder (or the
+    // interpreter itself on windows).
+    let py_bin = get_toolchain_python_bin(&ver)?;
+    let mut source = py_bin
+        .parent()
+        .ok_or_else(|| anyhow!("unable to determine toolchain root"))?;
+    if source.file_name() == Some(std::ffi::OsStr::new("bin")) {
+        source = source
+            .parent()
+            .ok_or_else(|| anyhow!("unable to determine toolchain root"))?;
+    }
+
+    let manifest_path = cmd.dest.join(LINK_MANIFEST);
+    if manifest_path.is_file() {
+        bail!(
+            "a toolchain is already linked into {}.  Run `rye toolchain unlink` first.",
+            cmd.dest.display()
+        );
+    }
+    fs::create_dir_all(&cmd.dest)
+        .with_context(|| format!("failed to create target folder {}", cmd.dest.display()))?;
+
+    let mut manifest = LinkManifest {
+        toolchain: ver.clone(),
+        symlink: cmd.symlink,
+        dirs: Vec::new(),
+        files: Vec::new(),
+    };
+    let result = link_files(source, &cmd.dest, cmd.symlink, &mut manifest);
+
+    // always write the manifest, even if linking failed half way through, so
+    // that the partial result can be cleaned up with unlink.
+    fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)
+        .context("failed to write link manifest")?;
+    result?;
+
+    eprintln!(
+        "Linked {} into {} ({} files)",
+        ver,
+        cmd.dest.display(),
+        manifest.files.len()
+    );
+    Ok(())
+}
+
+fn link_files(
+    source: &Path,
+    dest: &Path,
+    symlink: bool,
+    manifest: &mut LinkManifest,
+) -> Result<(), Error> {
+    for entry in walkdir::WalkDir::new(source).min_depth(1) {
+        let entry = entry?;
+        let rel_path = entry.path().strip_prefix(source)?;
+        let target = dest.join(rel_path);
+
+        if entry.file_type().is_dir() {
+            if !target.is_dir() {
+                fs::create_dir(&target)
+                    .with_context(|| format!("failed to create {}", target.display()))?;
+                manifest.dirs.push(rel_path.to_path_buf());
+            }
+            continue;
+        }
+
+        if target.exists() || target.is_symlink() {
+            bail!("refusing to overwrite existing file {}", target.display());
+        }
+        let rv = if symlink {
+            symlink_file(entry.path(), &target)
+        } else if entry.file_type().is_symlink() {
+            symlink_file(fs::read_link(entry.path())?, &target)
+        } else {
+            fs::copy(entry.path(), &target).map(|_| ())
+        };
+        rv.with_context(|| format!("failed to link {}", target.display()))?;
+        manifest.files.push(rel_path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn unlink(cmd: UnlinkCommand) -> Result<(), Error> {
+    let manifest_path = cmd.dest.join(LINK_MANIFEST);
+    let manifest: LinkManifest = serde_json::from_slice(
+        &fs::read(&manifest_path)
+            .with_context(|| format!("no linked toolchain found in {}", cmd.dest.display()))?,
+    )
+    .context("could not parse link manifest")?;
+
+    for file in &manifest.files {
+        fs::remove_file(cmd.dest.join(file)).ok();
+    }
+    // directories are only removed if they are empty so that we do not take
+    // files with us that were placed there by someone else.
+    for dir in manifest.dirs.iter().rev() {
+        fs::remove_dir(cmd.dest.join(dir)).ok();
+    }
+    fs::remove_file(&manifest_path)?;
+
+    eprintln!(
+        "Unlinked {} from {}",
+        manifest.toolchain,
+        cmd.dest.display()
+    );
+    Ok(())
+}
+
 pub fn register_toolchain<F>(
     path: &Path,
     name: Option<&str>,
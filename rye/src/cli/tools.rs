@@ -1,8 +1,19 @@
-use anyhow::Error;
-use clap::Parser;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error};
+use clap::{ArgAction, ArgGroup, Parser};
 use console::style;
+use pep440_rs::Version;
+use pep508_rs::{Requirement, VersionOrUrl};
 
-use crate::installer::list_installed_tools;
+use crate::cli::search::fetch_project_info;
+use crate::installer::{
+    get_installed_version, get_tool_info, install, list_installed_tools, uninstall,
+};
+use crate::pyproject::normalize_package_name;
+use crate::sources::PythonVersionRequest;
+use crate::tools_manifest::{ToolEntry, ToolsManifest};
+use crate::utils::CommandOutput;
 
 /// Helper utility to manage global tools.
 #[derive(Parser, Debug)]
@@ -17,6 +28,94 @@ pub struct ListCommand {
     /// Also how all the scripts installed by the tools.
     #[arg(short, long)]
     include_scripts: bool,
+    /// Only list tools with newer releases on PyPI.
+    #[arg(long)]
+    outdated: bool,
+}
+
+/// Upgrades installed tools to their latest releases.
+///
+/// The requirement a tool was installed with is respected, so a tool that was
+/// installed as `black==23.*` stays within that range.  To change the range,
+/// install the tool again with `--force`.
+#[derive(Parser, Debug)]
+#[command(group = ArgGroup::new("tools").required(true))]
+pub struct UpgradeCommand {
+    /// The tools to upgrade.
+    #[arg(group = "tools")]
+    names: Vec<String>,
+    /// Upgrade all installed tools.
+    #[arg(long, group = "tools")]
+    all: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Reinstalls all installed tools.
+///
+/// Tools are reinstalled with the Python version they were originally
+/// installed with.  Tools that were installed without an explicit Python
+/// version use the current default toolchain.  This is useful after the
+/// default toolchain was changed.
+#[derive(Parser, Debug)]
+pub struct ReinstallAllCommand {
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+/// Adds a tool to the tools manifest (`~/.rye/tools.toml`).
+///
+/// The tool is installed by `rye tools sync`.
+#[derive(Parser, Debug)]
+pub struct AddCommand {
+    /// The requirement of the tool.
+    requirement: String,
+    /// Include scripts from a given dependency.
+    #[arg(long)]
+    include_dep: Vec<String>,
+    /// Additional dependencies to install that are not declared by the main package.
+    #[arg(long)]
+    extra_requirement: Vec<String>,
+    /// The Python version to install the tool with.
+    #[arg(short, long)]
+    python: Option<String>,
+    /// Run the tool with the Python version of the project it's invoked in.
+    #[arg(long)]
+    match_project_python: bool,
+}
+
+/// Removes a tool from the tools manifest (`~/.rye/tools.toml`).
+///
+/// The tool is uninstalled by `rye tools sync`.
+#[derive(Parser, Debug)]
+pub struct RemoveCommand {
+    /// The name of the tool.
+    name: String,
+}
+
+/// Installs and uninstalls tools to match the tools manifest.
+///
+/// Tools that are missing or were installed differently than declared are
+/// (re)installed, tools that are not declared are uninstalled.
+#[derive(Parser, Debug)]
+pub struct SyncCommand {
+    /// Do not uninstall tools which are not in the manifest.
+    #[arg(long)]
+    keep_undeclared: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -25,6 +124,11 @@ enum SubCommand {
     Install(crate::cli::install::Args),
     Uninstall(crate::cli::uninstall::Args),
     List(ListCommand),
+    Upgrade(UpgradeCommand),
+    ReinstallAll(ReinstallAllCommand),
+    Add(AddCommand),
+    Remove(RemoveCommand),
+    Sync(SyncCommand),
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
@@ -32,6 +136,11 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         SubCommand::Install(args) => crate::cli::install::execute(args),
         SubCommand::Uninstall(args) => crate::cli::uninstall::execute(args),
         SubCommand::List(args) => list_tools(args),
+        SubCommand::Upgrade(args) => upgrade(args),
+        SubCommand::ReinstallAll(args) => reinstall_all(args),
+        SubCommand::Add(args) => add(args),
+        SubCommand::Remove(args) => remove(args),
+        SubCommand::Sync(args) => sync(args),
     }
 }
 
@@ -40,7 +149,33 @@ fn list_tools(cmd: ListCommand) -> Result<(), Error> {
     tools.sort();
 
     for (tool, mut scripts) in tools {
-        println!("{}", style(tool).cyan());
+        if cmd.outdated {
+            let installed =
+                match get_installed_version(&tool).and_then(|x| Version::from_str(&x).ok()) {
+                    Some(version) => version,
+                    None => continue,
+                };
+            let (latest, allowed) = match latest_versions(&tool)? {
+                (Some(latest), allowed) if latest > installed => (latest, allowed),
+                _ => continue,
+            };
+            match allowed {
+                Some(allowed) if allowed > installed => println!(
+                    "{} {} -> {}",
+                    style(&tool).cyan(),
+                    installed,
+                    style(allowed).green()
+                ),
+                _ => println!(
+                    "{} {} {}",
+                    style(&tool).cyan(),
+                    installed,
+                    style(format!("(pinned, latest is {})", latest)).dim()
+                ),
+            }
+        } else {
+            println!("{}", style(&tool).cyan());
+        }
         if cmd.include_scripts {
             scripts.sort();
             for script in scripts {
@@ -51,3 +186,246 @@ fn list_tools(cmd: ListCommand) -> Result<(), Error> {
 
     Ok(())
 }
+
+/// Returns the newest release of a tool and the newest one that the
+/// requirement it was installed with allows.
+fn latest_versions(tool: &str) -> Result<(Option<Version>, Option<Version>), Error> {
+    let requirement = get_tool_info(tool)
+        .and_then(|info| info.requirement.parse::<Requirement>().ok())
+        .unwrap_or_else(|| Requirement {
+            name: tool.to_string(),
+            extras: None,
+            version_or_url: None,
+            marker: None,
+        });
+    let versions = match fetch_project_info(&requirement.name)? {
+        Some(info) => info.versions(false),
+        None => return Ok((None, None)),
+    };
+    let allowed = match requirement.version_or_url {
+        None => versions.first().cloned(),
+        Some(VersionOrUrl::VersionSpecifier(ref specs)) => {
+            versions.iter().find(|x| specs.contains(x)).cloned()
+        }
+        // tools installed from a URL are not upgraded from PyPI
+        Some(VersionOrUrl::Url(_)) => None,
+    };
+    Ok((versions.into_iter().next(), allowed))
+}
+
+fn upgrade(cmd: UpgradeCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let installed = list_installed_tools()?;
+    let mut tools = if cmd.all {
+        installed.into_keys().collect::<Vec<_>>()
+    } else {
+        for name in &cmd.names {
+            if !installed.contains_key(&normalize_package_name(name)) {
+                bail!("{} is not installed", name);
+            }
+        }
+        cmd.names
+            .iter()
+            .map(|x| normalize_package_name(x))
+            .collect()
+    };
+    tools.sort();
+
+    let mut failed = Vec::new();
+    for tool in tools {
+        let old = get_installed_version(&tool);
+        // the output of the installation is only of interest when debugging
+        let install_output = match output {
+            CommandOutput::Verbose => output,
+            _ => CommandOutput::Quiet,
+        };
+        if let Err(err) = reinstall_tool(&tool, install_output) {
+            eprintln!("{}: {:?}", style("error").red(), err);
+            failed.push(tool);
+            continue;
+        }
+        let new = get_installed_version(&tool);
+        if output == CommandOutput::Quiet {
+            continue;
+        }
+        match (old, new) {
+            (Some(old), Some(new)) if old != new => eprintln!(
+                "Upgraded {} from {} to {}",
+                style(&tool).cyan(),
+                old,
+                style(new).green()
+            ),
+            (_, new) => eprintln!(
+                "{} is up to date ({})",
+                style(&tool).cyan(),
+                new.unwrap_or_else(|| "unknown version".into())
+            ),
+        }
+    }
+
+    if !failed.is_empty() {
+        bail!("failed to upgrade {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+fn reinstall_all(cmd: ReinstallAllCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let mut tools = list_installed_tools()?.into_keys().collect::<Vec<_>>();
+    tools.sort();
+
+    let mut failed = Vec::new();
+    for tool in tools {
+        if output != CommandOutput::Quiet {
+            eprintln!("Reinstalling {}", style(&tool).cyan());
+        }
+        if let Err(err) = reinstall_tool(&tool, output) {
+            eprintln!("{}: {:?}", style("error").red(), err);
+            failed.push(tool);
+        }
+    }
+
+    if !failed.is_empty() {
+        bail!("failed to reinstall {}", failed.join(", "));
+    }
+
+    Ok(())
+}
+
+fn reinstall_tool(tool: &str, output: CommandOutput) -> Result<(), Error> {
+    // tools installed by older versions of rye did not record how they were
+    // installed.  For those we fall back to the name of the tool.
+    let (requirement, py_ver, include_deps, extra_requirements, match_project_python) =
+        match get_tool_info(tool) {
+            Some(info) => (
+                info.requirement,
+                info.python
+                    .map(|x| x.parse::<PythonVersionRequest>())
+                    .transpose()?,
+                info.include_deps,
+                info.extra_requirements,
+                info.match_project_python,
+            ),
+            None => (tool.to_string(), None, Vec::new(), Vec::new(), false),
+        };
+    let requirement = requirement
+        .parse::<Requirement>()
+        .with_context(|| format!("invalid recorded requirement '{}'", requirement))?;
+    let extra_requirements = extra_requirements
+        .iter()
+        .map(|x| x.parse::<Requirement>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    install(
+        requirement,
+        py_ver.as_ref(),
+        true,
+        &include_deps,
+        &extra_requirements,
+        match_project_python,
+        output,
+    )
+}
+
+fn add(cmd: AddCommand) -> Result<(), Error> {
+    let mut manifest = ToolsManifest::load()?;
+    let parse = |req: &str| {
+        req.parse::<Requirement>()
+            .with_context(|| format!("failed to parse requirement '{}'", req))
+    };
+    let entry = ToolEntry {
+        requirement: parse(&cmd.requirement)?,
+        python: cmd.python.as_deref().map(str::parse).transpose()?,
+        include_deps: cmd
+            .include_dep
+            .iter()
+            .map(|x| normalize_package_name(x))
+            .collect(),
+        extra_requirements: cmd
+            .extra_requirement
+            .iter()
+            .map(|x| parse(x))
+            .collect::<Result<_, _>>()?,
+        match_project_python: cmd.match_project_python,
+    };
+    manifest.set(&entry);
+    manifest.save()?;
+    eprintln!(
+        "Added {} to {}. Run `rye tools sync` to install it.",
+        style(&entry.requirement).cyan(),
+        manifest.path().display()
+    );
+    Ok(())
+}
+
+fn remove(cmd: RemoveCommand) -> Result<(), Error> {
+    let mut manifest = ToolsManifest::load()?;
+    if !manifest.remove(&cmd.name) {
+        bail!("{} is not in {}", cmd.name, manifest.path().display());
+    }
+    manifest.save()?;
+    eprintln!(
+        "Removed {} from {}. Run `rye tools sync` to uninstall it.",
+        style(&cmd.name).cyan(),
+        manifest.path().display()
+    );
+    Ok(())
+}
+
+fn sync(cmd: SyncCommand) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let manifest = ToolsManifest::load()?;
+    // without a manifest all tools would be uninstalled, which is unlikely
+    // to be what was intended.
+    if !manifest.exists() {
+        bail!(
+            "no tools manifest at {}. Add tools with `rye tools add`",
+            manifest.path().display()
+        );
+    }
+    let entries = manifest.entries()?;
+    let installed = list_installed_tools()?;
+
+    let mut failed = Vec::new();
+    for (name, entry) in &entries {
+        let up_to_date = installed.contains_key(name)
+            && get_tool_info(name).map_or(false, |info| entry.is_installed_as(&info));
+        if up_to_date {
+            if output == CommandOutput::Verbose {
+                eprintln!("{} is up to date", style(name).cyan());
+            }
+            continue;
+        }
+        if output != CommandOutput::Quiet {
+            eprintln!("Installing {}", style(&entry.requirement).cyan());
+        }
+        if let Err(err) = install(
+            entry.requirement.clone(),
+            entry.python.as_ref(),
+            true,
+            &entry.include_deps,
+            &entry.extra_requirements,
+            entry.match_project_python,
+            output,
+        ) {
+            eprintln!("{}: {:?}", style("error").red(), err);
+            failed.push(name.clone());
+        }
+    }
+
+    if !cmd.keep_undeclared {
+        let mut undeclared = installed
+            .into_keys()
+            .filter(|tool| !entries.iter().any(|(name, _)| name == tool))
+            .collect::<Vec<_>>();
+        undeclared.sort();
+        for tool in undeclared {
+            uninstall(&tool, output)?;
+        }
+    }
+
+    if !failed.is_empty() {
+        bail!("failed to install {}", failed.join(", "));
+    }
+    Ok(())
+}
@@ -0,0 +1,496 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use std::{env, fs};
+
+use anyhow::{bail, Context, Error};
+use clap::Parser;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use pep440_rs::Version;
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+
+use crate::cli::search::fetch_project_info;
+use crate::lock::locked_versions;
+use crate::pyproject::{normalize_package_name, DependencyKind, PyProject};
+use crate::utils::is_noninteractive;
+
+/// How many lines of output are kept in the log pane.
+const MAX_LOG_LINES: usize = 500;
+
+/// Opens an interactive dashboard of the project.
+///
+/// Shows the status of the project, the locked dependencies (marking the
+/// outdated ones) and the scripts, which can be run from there.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Do not look up the latest versions of the dependencies.
+    #[arg(long)]
+    offline: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+}
+
+/// A locked package as shown in the dependency list.
+struct Package {
+    name: String,
+    version: Option<String>,
+    direct: bool,
+}
+
+/// Which list receives the key presses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Packages,
+    Scripts,
+}
+
+/// Sent to the UI by the background threads.
+enum Message {
+    /// The latest version of a package was looked up.
+    Latest(String, Option<String>),
+    /// A line of output of the running command.
+    Output(String),
+    /// The running command finished.
+    Finished(bool),
+}
+
+struct App {
+    pyproject_path: Option<PathBuf>,
+    status: Vec<(&'static str, String)>,
+    packages: Vec<Package>,
+    scripts: Vec<String>,
+    latest: HashMap<String, Option<String>>,
+    focus: Focus,
+    package_state: ListState,
+    script_state: ListState,
+    log: Vec<String>,
+    running: Option<String>,
+}
+
+impl App {
+    fn new(pyproject_path: Option<PathBuf>) -> Result<App, Error> {
+        let mut app = App {
+            pyproject_path,
+            status: Vec::new(),
+            packages: Vec::new(),
+            scripts: Vec::new(),
+            latest: HashMap::new(),
+            focus: Focus::Packages,
+            package_state: ListState::default(),
+            script_state: ListState::default(),
+            log: Vec::new(),
+            running: None,
+        };
+        app.reload()?;
+        Ok(app)
+    }
+
+    /// Reads the state of the project again (for instance after a sync).
+    fn reload(&mut self) -> Result<(), Error> {
+        let pyproject = PyProject::load_or_discover(self.pyproject_path.as_deref())?;
+        let venv = pyproject.venv_path();
+        let lockfile = pyproject.workspace_path().join("requirements-dev.lock");
+        let lockfile = if lockfile.is_file() {
+            lockfile
+        } else {
+            pyproject.workspace_path().join("requirements.lock")
+        };
+
+        self.status = vec![
+            (
+                "project",
+                pyproject.name().unwrap_or("<unnamed>").to_string(),
+            ),
+            ("path", pyproject.root_path().display().to_string()),
+            (
+                "venv",
+                format!(
+                    "{}{}",
+                    venv.display(),
+                    if venv.is_dir() { "" } else { " (not synced)" }
+                ),
+            ),
+            (
+                "python",
+                pyproject
+                    .venv_python_version()
+                    .map_or_else(|_| "unknown".into(), |x| x.to_string()),
+            ),
+            (
+                "lockfile",
+                if lockfile.is_file() {
+                    lockfile.display().to_string()
+                } else {
+                    "missing".into()
+                },
+            ),
+        ];
+
+        let direct = [DependencyKind::Normal, DependencyKind::Dev]
+            .into_iter()
+            .flat_map(|kind| pyproject.iter_dependencies(kind))
+            .filter_map(|dep| dep.expand(|name: &str| env::var(name).ok()).ok())
+            .map(|req| normalize_package_name(&req.name))
+            .collect::<HashSet<_>>();
+        self.packages = locked_versions(&fs::read_to_string(&lockfile).unwrap_or_default())
+            .into_iter()
+            .map(|(name, version)| Package {
+                direct: direct.contains(&name),
+                name,
+                version,
+            })
+            .collect();
+
+        let mut scripts = pyproject.list_scripts().into_iter().collect::<Vec<_>>();
+        scripts.sort_by_key(|x| x.to_ascii_lowercase());
+        self.scripts = scripts;
+
+        for (state, len) in [
+            (&mut self.package_state, self.packages.len()),
+            (&mut self.script_state, self.scripts.len()),
+        ] {
+            state.select(match state.selected() {
+                _ if len == 0 => None,
+                Some(idx) => Some(idx.min(len - 1)),
+                None => Some(0),
+            });
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, msg: Message) -> Result<(), Error> {
+        match msg {
+            Message::Latest(name, version) => {
+                self.latest.insert(name, version);
+            }
+            Message::Output(line) => {
+                self.log.push(line);
+                if self.log.len() > MAX_LOG_LINES {
+                    self.log.drain(..self.log.len() - MAX_LOG_LINES);
+                }
+            }
+            Message::Finished(success) => {
+                let what = self.running.take().unwrap_or_default();
+                self.log.push(format!(
+                    "{} {}",
+                    what,
+                    if success { "finished" } else { "failed" }
+                ));
+                self.reload()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the selection of the focused list.
+    fn move_selection(&mut self, delta: isize) {
+        let (state, len) = match self.focus {
+            Focus::Packages => (&mut self.package_state, self.packages.len()),
+            Focus::Scripts => (&mut self.script_state, self.scripts.len()),
+        };
+        if len == 0 {
+            return;
+        }
+        let current = state.selected().unwrap_or(0) as isize;
+        state.select(Some((current + delta).clamp(0, len as isize - 1) as usize));
+    }
+
+    /// Runs rye with the given arguments in the background.
+    fn spawn(&mut self, what: String, args: &[&str], tx: &Sender<Message>) -> Result<(), Error> {
+        if self.running.is_some() {
+            self.log
+                .push("another command is still running".to_string());
+            return Ok(());
+        }
+        let mut cmd = Command::new(env::current_exe()?);
+        cmd.args(args)
+            // nothing can be answered while the dashboard is open
+            .env("RYE_NONINTERACTIVE", "1")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(ref pyproject) = self.pyproject_path {
+            cmd.current_dir(pyproject.parent().unwrap_or(pyproject));
+        }
+        let mut child = cmd.spawn().context("failed to spawn rye")?;
+        self.log.push(format!("$ rye {}", args.join(" ")));
+        self.running = Some(what);
+
+        let forward = |stream: Option<Box<dyn Read + Send>>, tx: Sender<Message>| {
+            thread::spawn(move || {
+                if let Some(stream) = stream {
+                    for line in BufReader::new(stream).lines().map_while(Result::ok) {
+                        tx.send(Message::Output(line)).ok();
+                    }
+                }
+            })
+        };
+        let stdout = forward(
+            child
+                .stdout
+                .take()
+                .map(|x| Box::new(x) as Box<dyn Read + Send>),
+            tx.clone(),
+        );
+        let stderr = forward(
+            child
+                .stderr
+                .take()
+                .map(|x| Box::new(x) as Box<dyn Read + Send>),
+            tx.clone(),
+        );
+        let tx = tx.clone();
+        thread::spawn(move || {
+            stdout.join().ok();
+            stderr.join().ok();
+            let success = child.wait().map_or(false, |x| x.success());
+            tx.send(Message::Finished(success)).ok();
+        });
+        Ok(())
+    }
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    if is_noninteractive() || !console::user_attended() {
+        bail!("rye ui needs an interactive terminal");
+    }
+    let pyproject_path = cmd
+        .pyproject
+        .as_deref()
+        .map(|x| x.canonicalize())
+        .transpose()?;
+    let mut app = App::new(pyproject_path)?;
+    let (tx, rx) = mpsc::channel();
+    if !cmd.offline {
+        look_up_latest(
+            app.packages.iter().map(|x| x.name.clone()).collect(),
+            tx.clone(),
+        );
+    }
+
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let rv = Terminal::new(CrosstermBackend::new(io::stdout()))
+        .map_err(Error::from)
+        .and_then(|mut terminal| run_app(&mut terminal, &mut app, &tx, &rx));
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+    rv
+}
+
+/// Looks up the latest versions of the packages in the background.
+fn look_up_latest(names: Vec<String>, tx: Sender<Message>) {
+    thread::spawn(move || {
+        for name in names {
+            let latest = fetch_project_info(&name)
+                .ok()
+                .flatten()
+                .and_then(|info| info.versions(false).into_iter().next())
+                .map(|x| x.to_string());
+            if tx.send(Message::Latest(name, latest)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    tx: &Sender<Message>,
+    rx: &Receiver<Message>,
+) -> Result<(), Error> {
+    loop {
+        while let Ok(msg) = rx.try_recv() {
+            app.handle(msg)?;
+        }
+        terminal.draw(|f| draw(f, app))?;
+
+        if !event::poll(Duration::from_millis(100))? {
+            continue;
+        }
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Tab | KeyCode::BackTab => {
+                app.focus = match app.focus {
+                    Focus::Packages => Focus::Scripts,
+                    Focus::Scripts => Focus::Packages,
+                };
+            }
+            KeyCode::Up | KeyCode::Char('k') => app.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => app.move_selection(1),
+            KeyCode::PageUp => app.move_selection(-10),
+            KeyCode::PageDown => app.move_selection(10),
+            KeyCode::Char('s') => app.spawn("sync".into(), &["sync"], tx)?,
+            KeyCode::Char('l') => app.spawn("lock".into(), &["lock"], tx)?,
+            KeyCode::Enter if app.focus == Focus::Scripts => {
+                if let Some(script) = app
+                    .script_state
+                    .selected()
+                    .and_then(|x| app.scripts.get(x))
+                    .cloned()
+                {
+                    app.spawn(format!("script {}", script), &["run", &script], tx)?;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(app.status.len() as u16 + 2),
+            Constraint::Min(8),
+            Constraint::Length(10),
+            Constraint::Length(1),
+        ])
+        .split(f.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(rows[1]);
+
+    let status = app
+        .status
+        .iter()
+        .map(|(key, value)| {
+            Line::from(vec![
+                Span::styled(format!("{:<9}", key), Style::default().fg(Color::DarkGray)),
+                Span::styled(value.clone(), Style::default().fg(Color::Cyan)),
+            ])
+        })
+        .collect::<Vec<_>>();
+    f.render_widget(
+        Paragraph::new(status).block(Block::default().borders(Borders::ALL).title("Project")),
+        rows[0],
+    );
+
+    let current = app.focus;
+    let focused = |focus: Focus| {
+        let style = if current == focus {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+        Block::default().borders(Borders::ALL).border_style(style)
+    };
+    let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+    let outdated = app
+        .packages
+        .iter()
+        .filter(|x| is_outdated(x, &app.latest))
+        .count();
+    let packages = app
+        .packages
+        .iter()
+        .map(|package| {
+            let mut spans = vec![Span::styled(
+                package.name.clone(),
+                if package.direct {
+                    Style::default().add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default()
+                },
+            )];
+            if let Some(ref version) = package.version {
+                spans.push(Span::raw(format!(" {}", version)));
+            }
+            if is_outdated(package, &app.latest) {
+                if let Some(Some(latest)) = app.latest.get(&package.name) {
+                    spans.push(Span::styled(
+                        format!(" -> {}", latest),
+                        Style::default().fg(Color::Yellow),
+                    ));
+                }
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect::<Vec<_>>();
+    f.render_stateful_widget(
+        List::new(packages)
+            .block(focused(Focus::Packages).title(format!(
+                "Dependencies ({} locked, {} outdated)",
+                app.packages.len(),
+                outdated
+            )))
+            .highlight_style(highlight),
+        columns[0],
+        &mut app.package_state,
+    );
+
+    let scripts = app
+        .scripts
+        .iter()
+        .map(|x| ListItem::new(x.as_str()))
+        .collect::<Vec<_>>();
+    f.render_stateful_widget(
+        List::new(scripts)
+            .block(focused(Focus::Scripts).title("Scripts"))
+            .highlight_style(highlight),
+        columns[1],
+        &mut app.script_state,
+    );
+
+    let height = rows[2].height.saturating_sub(2) as usize;
+    let log = app
+        .log
+        .iter()
+        .skip(app.log.len().saturating_sub(height))
+        .map(|x| Line::from(x.as_str()))
+        .collect::<Vec<_>>();
+    let title = match app.running {
+        Some(ref what) => format!("Output (running {})", what),
+        None => "Output".to_string(),
+    };
+    f.render_widget(
+        Paragraph::new(log)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .wrap(Wrap { trim: false }),
+        rows[2],
+    );
+
+    f.render_widget(
+        Paragraph::new(Line::from(Span::styled(
+            " tab: switch list  enter: run script  s: sync  l: lock  q: quit",
+            Style::default().fg(Color::DarkGray),
+        ))),
+        rows[3],
+    );
+}
+
+/// Is a newer version than the locked one available?
+fn is_outdated(package: &Package, latest: &HashMap<String, Option<String>>) -> bool {
+    let parse = |x: &str| x.parse::<Version>().ok();
+    match (
+        package.version.as_deref().and_then(parse),
+        latest
+            .get(&package.name)
+            .and_then(|x| x.as_deref())
+            .and_then(parse),
+    ) {
+        (Some(locked), Some(latest)) => latest > locked,
+        _ => false,
+    }
+}
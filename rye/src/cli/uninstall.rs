@@ -1,5 +1,5 @@
 use anyhow::Error;
-use clap::Parser;
+use clap::{ArgAction, Parser};
 
 use crate::installer::uninstall;
 use crate::utils::CommandOutput;
@@ -9,9 +9,9 @@ use crate::utils::CommandOutput;
 pub struct Args {
     /// The package to uninstall
     name: String,
-    /// Enables verbose diagnostics.
-    #[arg(short, long)]
-    verbose: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
     /// Turns off all output.
     #[arg(short, long, conflicts_with = "verbose")]
     quiet: bool,
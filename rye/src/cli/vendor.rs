@@ -0,0 +1,162 @@
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Error};
+use clap::{ArgAction, Parser};
+use console::style;
+use walkdir::WalkDir;
+
+use crate::bootstrap::ensure_self_venv;
+use crate::cli::bundle::install_into_folder;
+use crate::lock::{iter_lockfile_entries, strip_line_continuation};
+use crate::pyproject::{ExpandedSources, PyProject};
+use crate::sync::{sync, SyncOptions};
+use crate::utils::CommandOutput;
+
+/// The manifest of a vendor folder.
+const MANIFEST: &str = "vendor.txt";
+
+/// Copies the locked dependencies into the source tree.
+///
+/// The dependencies are installed into a plain folder (`vendor` by default)
+/// together with a `vendor.txt` manifest of the vendored versions.  The
+/// folder has to be put on `sys.path` by the project itself.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The folder to vendor into, relative to the project.
+    #[arg(long, default_value = "vendor")]
+    dir: PathBuf,
+    /// Vendor the dev dependencies as well.
+    #[arg(long)]
+    dev: bool,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let self_venv = ensure_self_venv(output)?;
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let root = project.workspace_path();
+    let lockfile = root.join(if cmd.dev {
+        "requirements-dev.lock"
+    } else {
+        "requirements.lock"
+    });
+    if !lockfile.is_file() {
+        bail!(
+            "lockfile {} does not exist. Run `rye lock` first",
+            lockfile.display()
+        );
+    }
+    let dir = project.root_path().join(&cmd.dir);
+    if dir.is_dir() && !dir.join(MANIFEST).is_file() && fs::read_dir(&dir)?.next().is_some() {
+        bail!(
+            "{} is not empty and was not created by `rye vendor`",
+            dir.display()
+        );
+    }
+
+    // the editables are the project and the members of its workspace, which
+    // are not vendored.
+    let contents = fs::read_to_string(&lockfile)?;
+    let mut manifest = format!(
+        "# vendored by `rye vendor` from {}\n",
+        lockfile.file_name().unwrap().to_string_lossy()
+    );
+    let mut requirements = String::new();
+    for entry in iter_lockfile_entries(&contents) {
+        let line = strip_line_continuation(entry[0]);
+        if line.is_empty() || line.starts_with('#') || line.starts_with("-e ") {
+            continue;
+        }
+        if !line.starts_with('-') {
+            manifest.push_str(line);
+            manifest.push('\n');
+        }
+        for line in entry {
+            requirements.push_str(line);
+            requirements.push('\n');
+        }
+    }
+
+    // packages are installed for the interpreter of the virtualenv
+    sync(SyncOptions::python_only().pyproject(cmd.pyproject.clone()))
+        .context("failed to sync ahead of vendoring")?;
+    let sources = ExpandedSources::from_sources(&project.sources()?)?;
+
+    // the dependencies are installed next to the vendor folder first, so that
+    // a failed install leaves the previous one alone.
+    let parent = dir.parent().unwrap_or(&root);
+    fs::create_dir_all(parent)?;
+    let staging = tempfile::tempdir_in(parent)?;
+    let requirements_file = staging.path().join("requirements.txt");
+    fs::write(&requirements_file, requirements)?;
+    let target = staging.path().join("vendor");
+    if output != CommandOutput::Quiet {
+        eprintln!("vendoring locked packages into {}", dir.display());
+    }
+    install_into_folder(
+        &self_venv,
+        &project.venv_path(),
+        &root,
+        &sources,
+        &target,
+        &[OsStr::new("-r"), requirements_file.as_os_str()],
+        output,
+    )?;
+    fs::create_dir_all(&target)?;
+    fs::write(target.join(MANIFEST), manifest)?;
+    if dir.is_dir() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::rename(&target, &dir)?;
+
+    // compiled packages only work on the platform and Python version they
+    // were vendored for.
+    let compiled = WalkDir::new(&dir)
+        .into_iter()
+        .filter_map(|x| x.ok())
+        .filter(|x| {
+            x.path()
+                .extension()
+                .map_or(false, |ext| ext == "so" || ext == "pyd")
+        })
+        .filter_map(|x| {
+            x.path()
+                .strip_prefix(&dir)
+                .ok()?
+                .components()
+                .next()
+                .map(|x| x.as_os_str().to_string_lossy().into_owned())
+        })
+        .collect::<BTreeSet<_>>();
+
+    if output != CommandOutput::Quiet {
+        if !compiled.is_empty() {
+            eprintln!(
+                "{} these vendored packages contain compiled code and only work on \
+                 this platform: {}",
+                style("note:").yellow(),
+                compiled.into_iter().collect::<Vec<_>>().join(", ")
+            );
+        }
+        eprintln!("Put the vendor folder on sys.path before importing the dependencies:");
+        eprintln!();
+        eprintln!("    import os, sys");
+        eprintln!(
+            "    sys.path.insert(0, os.path.join(os.path.dirname(__file__), {:?}))",
+            cmd.dir.to_string_lossy()
+        );
+    }
+    Ok(())
+}
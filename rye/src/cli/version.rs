@@ -1,10 +1,11 @@
 use std::str::FromStr;
 
-use crate::pyproject::PyProject;
-use anyhow::{anyhow, Error};
+use crate::git_version::GitVersion;
+use crate::pyproject::{PyProject, VersionSource};
+use crate::warnings::{warn, Warning};
+use anyhow::{anyhow, bail, Error};
 use clap::{Parser, ValueEnum};
-use console::style;
-use pep440_rs::Version;
+use pep440_rs::{PreRelease, Version};
 
 /// Get or set project version
 #[derive(Parser, Debug)]
@@ -14,17 +15,88 @@ pub struct Args {
     /// The version bump to apply
     #[arg(short, long)]
     bump: Option<Bump>,
+    /// Show the version a bump would result in without applying it
+    #[arg(long, conflicts_with_all = ["version", "bump"])]
+    next: Option<Bump>,
+    /// Make the bumped version a pre-release of this kind
+    #[arg(long)]
+    pre: Option<PreKind>,
 }
 
-#[derive(Debug, Clone, ValueEnum)]
+#[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum Bump {
     Major,
     Minor,
     Patch,
+    /// The next pre-release (`1.2.0rc1` to `1.2.0rc2`).
+    Prerelease,
+    /// The next dev release (`1.2.0.dev1` to `1.2.0.dev2`).
+    Dev,
+    /// The next post release (`1.2.0` to `1.2.0.post1`).
+    Post,
+    /// The final release of a pre-release (`1.2.0rc1` to `1.2.0`).
+    Release,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum PreKind {
+    Alpha,
+    Beta,
+    Rc,
+}
+
+impl PreKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            PreKind::Alpha => "alpha",
+            PreKind::Beta => "beta",
+            PreKind::Rc => "rc",
+        }
+    }
+}
+
+impl From<&PreRelease> for PreKind {
+    fn from(value: &PreRelease) -> PreKind {
+        match value {
+            PreRelease::Alpha => PreKind::Alpha,
+            PreRelease::Beta => PreKind::Beta,
+            PreRelease::Rc => PreKind::Rc,
+        }
+    }
+}
+
+impl From<PreKind> for PreRelease {
+    fn from(value: PreKind) -> PreRelease {
+        match value {
+            PreKind::Alpha => PreRelease::Alpha,
+            PreKind::Beta => PreRelease::Beta,
+            PreKind::Rc => PreRelease::Rc,
+        }
+    }
 }
 
 pub fn execute(cmd: Args) -> Result<(), Error> {
     let mut pyproject_toml = PyProject::discover()?;
+    let from_git = pyproject_toml.version_source()? == VersionSource::Git;
+    if from_git && (cmd.version.is_some() || cmd.bump.is_some()) {
+        bail!(
+            "the version of this project is derived from git tags. Tag a commit with the \
+             new version instead (for instance `git tag v1.0.0`)"
+        );
+    }
+    if let Some(bump) = cmd.next {
+        // for git versions the bump applies to the latest tag
+        let mut version = if from_git {
+            GitVersion::describe(&pyproject_toml.root_path())?
+                .tag
+                .unwrap_or_else(|| Version::from_str("0.0.0").unwrap())
+        } else {
+            pyproject_toml.version()?
+        };
+        bump_version(&mut version, bump, cmd.pre)?;
+        println!("{}", version);
+        return Ok(());
+    }
     match cmd.version {
         Some(version) => {
             let version =
@@ -37,7 +109,12 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
         None => {
             let mut version = pyproject_toml.version()?;
             match cmd.bump {
-                Some(bump) => bump_version(&mut version, bump, &mut pyproject_toml)?,
+                Some(bump) => {
+                    bump_version(&mut version, bump, cmd.pre)?;
+                    pyproject_toml.set_version(&version);
+                    pyproject_toml.save()?;
+                    eprintln!("version bumped to {}", version);
+                }
                 None => eprintln!("{}", version),
             }
         }
@@ -45,28 +122,118 @@ pub fn execute(cmd: Args) -> Result<(), Error> {
     Ok(())
 }
 
-fn bump_version(version: &mut Version, bump: Bump, pyproject: &mut PyProject) -> Result<(), Error> {
-    if version.is_post() {
-        version.post = None;
+fn bump_version(version: &mut Version, bump: Bump, pre: Option<PreKind>) -> Result<(), Error> {
+    if pre.is_some()
+        && !matches!(
+            bump,
+            Bump::Major | Bump::Minor | Bump::Patch | Bump::Prerelease
+        )
+    {
+        bail!("--pre can only be used with the major, minor, patch and prerelease bumps");
     }
-    if version.is_dev() {
-        version.dev = None;
-        eprintln!(
-            "{} dev version will be bumped to release version",
-            style("warning:").red()
-        );
-    } else {
-        let index = bump as usize;
-        if version.release.get(index).is_none() {
-            version.release.resize(index + 1, 0);
+    let current_pre = version
+        .pre
+        .as_ref()
+        .map(|(kind, num)| (PreKind::from(kind), *num));
+    match bump {
+        Bump::Major | Bump::Minor | Bump::Patch => {
+            version.post = None;
+            if pre.is_none() && (version.is_dev() || current_pre.is_some()) {
+                warn(
+                    Warning::DevVersionBumped,
+                    if current_pre.is_some() {
+                        "pre-release version will be bumped to release version"
+                    } else {
+                        "dev version will be bumped to release version"
+                    },
+                );
+            } else {
+                let index = bump as usize;
+                if version.release.get(index).is_none() {
+                    version.release.resize(index + 1, 0);
+                }
+                version.release[index] += 1;
+                for part in version.release.iter_mut().skip(index + 1) {
+                    *part = 0;
+                }
+            }
+            version.dev = None;
+            version.pre = pre.map(|kind| (kind.into(), 1));
+        }
+        Bump::Prerelease => {
+            version.post = None;
+            version.pre = Some(match (current_pre, pre) {
+                (Some((kind, num)), None) => (kind.into(), num + 1),
+                (Some((kind, num)), Some(new)) if kind == new => (kind.into(), num + 1),
+                (Some((kind, _)), Some(new)) if kind < new => (new.into(), 1),
+                (Some((kind, _)), Some(new)) => bail!(
+                    "cannot go back from a {} to a {} pre-release",
+                    kind.as_str(),
+                    new.as_str()
+                ),
+                // a dev version is a pre-release of the same release already
+                (None, Some(new)) if version.is_dev() => (new.into(), 1),
+                (None, Some(new)) => {
+                    bump_version(version, Bump::Patch, None)?;
+                    (new.into(), 1)
+                }
+                (None, None) => bail!(
+                    "{} is not a pre-release. Pass --pre to pick the kind of pre-release",
+                    version
+                ),
+            });
+            version.dev = None;
+        }
+        Bump::Dev => match version.dev {
+            Some(ref mut num) => *num += 1,
+            None => {
+                match version.pre {
+                    Some((_, ref mut num)) => *num += 1,
+                    None => bump_version(version, Bump::Patch, None)?,
+                }
+                version.post = None;
+                version.dev = Some(1);
+            }
+        },
+        Bump::Post => {
+            version.dev = None;
+            version.post = Some(version.post.map_or(1, |num| num + 1));
+        }
+        Bump::Release => {
+            if current_pre.is_none() && !version.is_dev() {
+                bail!("{} is already a release version", version);
+            }
+            version.pre = None;
+            version.dev = None;
         }
-        version.release[index] += 1;
     }
+    Ok(())
+}
 
-    pyproject.set_version(version);
-    pyproject.save().unwrap();
-
-    eprintln!("version bumped to {}", version);
+#[test]
+fn test_bump_version() {
+    fn bump(version: &str, bump: Bump, pre: Option<PreKind>) -> String {
+        let mut version = Version::from_str(version).unwrap();
+        bump_version(&mut version, bump, pre).unwrap();
+        version.to_string()
+    }
+    assert_eq!(bump("1.2.3", Bump::Minor, None), "1.3.0");
+    assert_eq!(bump("1.2.3", Bump::Major, Some(PreKind::Rc)), "2.0.0rc1");
+    assert_eq!(bump("1.2.0rc1", Bump::Prerelease, None), "1.2.0rc2");
+    assert_eq!(
+        bump("1.2.0b2", Bump::Prerelease, Some(PreKind::Rc)),
+        "1.2.0rc1"
+    );
+    assert_eq!(
+        bump("1.2.0", Bump::Prerelease, Some(PreKind::Alpha)),
+        "1.2.1a1"
+    );
+    assert_eq!(bump("1.2.0rc1", Bump::Release, None), "1.2.0");
+    assert_eq!(bump("1.2.0rc1", Bump::Patch, None), "1.2.0");
+    assert_eq!(bump("1.2.0", Bump::Dev, None), "1.2.1.dev1");
+    assert_eq!(bump("1.2.1.dev1", Bump::Dev, None), "1.2.1.dev2");
+    assert_eq!(bump("1.2.0", Bump::Post, None), "1.2.0.post1");
 
-    Ok(())
+    let mut version = Version::from_str("1.2.0rc1").unwrap();
+    assert!(bump_version(&mut version, Bump::Prerelease, Some(PreKind::Alpha)).is_err());
 }
@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Error};
+use clap::{ArgAction, Parser, ValueEnum};
+use console::style;
+use serde::Serialize;
+
+use crate::lock::{update_members_lockfile, LockMode, LockOptions};
+use crate::pyproject::{ExpandedSources, PyProject};
+use crate::sync::{sync, SyncOptions};
+use crate::utils::CommandOutput;
+
+#[derive(ValueEnum, Copy, Clone, Serialize, Debug, PartialEq)]
+#[value(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+enum Format {
+    Json,
+}
+
+/// Works with the members of a workspace.
+#[derive(Parser, Debug)]
+pub struct Args {
+    #[command(subcommand)]
+    command: SubCommand,
+}
+
+/// Lists the members of the workspace.
+///
+/// Members are listed in the order of their dependencies: every member comes
+/// after the members it depends on.
+#[derive(Parser, Debug)]
+pub struct ListCommand {
+    /// Request parseable output format rather than lines.
+    #[arg(long)]
+    format: Option<Format>,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+}
+
+/// Extracts the locked requirements of members for deployment.
+///
+/// The result contains the members, the members they depend on and their
+/// dependencies, pinned to the versions of the workspace lockfile.
+#[derive(Parser, Debug)]
+pub struct ExportCommand {
+    /// The members to export.
+    #[arg(required = true)]
+    package: Vec<String>,
+    /// Write to this file instead of stdout.
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+    /// Include the dev dependencies.
+    #[arg(long)]
+    dev: bool,
+    /// Leave out the members themselves and only export their dependencies.
+    #[arg(long)]
+    exclude_members: bool,
+    /// Record hashes of the exported packages.
+    #[arg(long)]
+    generate_hashes: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Use this pyproject.toml file
+    #[arg(long, value_name = "PYPROJECT_TOML")]
+    pyproject: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+enum SubCommand {
+    List(ListCommand),
+    Export(ExportCommand),
+}
+
+#[derive(Serialize, Debug)]
+struct Member {
+    name: String,
+    version: Option<String>,
+    path: PathBuf,
+    depends: Vec<String>,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    match cmd.command {
+        SubCommand::List(cmd) => list(cmd),
+        SubCommand::Export(cmd) => export(cmd),
+    }
+}
+
+fn list(cmd: ListCommand) -> Result<(), Error> {
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let workspace = match project.workspace() {
+        Some(workspace) => workspace,
+        None => bail!(
+            "{} is not part of a workspace",
+            project.root_path().display()
+        ),
+    };
+    let projects = workspace.projects_in_order(None)?;
+    let names = projects
+        .iter()
+        .map(|x| x.normalized_name())
+        .collect::<Result<HashSet<_>, _>>()?;
+
+    let mut members = Vec::new();
+    for mut project in projects {
+        let root = project.root_path().into_owned();
+        members.push(Member {
+            name: project.normalized_name()?,
+            version: project.version().ok().map(|x| x.to_string()),
+            path: Path::new(".").join(root.strip_prefix(workspace.path()).unwrap_or(&root)),
+            depends: project.member_dependencies(&names)?,
+        });
+    }
+
+    if let Some(Format::Json) = cmd.format {
+        serde_json::to_writer_pretty(std::io::stdout().lock(), &members)?;
+        println!();
+        return Ok(());
+    }
+    for member in members {
+        print!(
+            "{} {}",
+            style(&member.name).cyan(),
+            style(member.path.display()).dim()
+        );
+        if !member.depends.is_empty() {
+            print!(" (depends on {})", member.depends.join(", "));
+        }
+        println!();
+    }
+    Ok(())
+}
+
+fn export(cmd: ExportCommand) -> Result<(), Error> {
+    // the export goes to stdout, so there is no output without -v
+    let output = CommandOutput::from_quiet_and_verbose(cmd.verbose == 0, cmd.verbose);
+    let project = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    let workspace = match project.workspace() {
+        Some(workspace) => workspace,
+        None => bail!(
+            "{} is not part of a workspace",
+            project.root_path().display()
+        ),
+    };
+    let (lock_mode, lockfile_name) = if cmd.dev {
+        (LockMode::Dev, "requirements-dev.lock")
+    } else {
+        (LockMode::Production, "requirements.lock")
+    };
+    let workspace_lockfile = workspace.path().join(lockfile_name);
+    if !workspace_lockfile.is_file() {
+        bail!(
+            "lockfile {} does not exist. Run `rye lock` first",
+            workspace_lockfile.display()
+        );
+    }
+
+    // the members are resolved with the interpreter of the virtualenv
+    sync(SyncOptions::python_only().pyproject(cmd.pyproject.clone()))
+        .context("failed to sync ahead of export")?;
+
+    let scratch = tempfile::tempdir()?;
+    let lockfile = scratch.path().join(lockfile_name);
+    let mut lock_options = LockOptions {
+        generate_hashes: cmd.generate_hashes || project.generate_hashes(),
+        ..LockOptions::default()
+    };
+    lock_options.add_project_settings(&project)?;
+    update_members_lockfile(
+        &project.venv_python_version()?,
+        workspace,
+        &cmd.package,
+        lock_mode,
+        &lockfile,
+        &workspace_lockfile,
+        output,
+        &ExpandedSources::from_sources(&project.sources()?)?,
+        &lock_options,
+    )?;
+
+    let mut contents = fs::read_to_string(&lockfile)?;
+    if cmd.exclude_members {
+        contents = contents
+            .lines()
+            .filter(|line| !line.starts_with("-e "))
+            .map(|line| format!("{}\n", line))
+            .collect();
+    }
+    match cmd.output {
+        Some(path) => fs::write(&path, contents)
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => print!("{}", contents),
+    }
+    Ok(())
+}
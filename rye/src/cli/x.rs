@@ -0,0 +1,103 @@
+use std::env::{self, join_paths, split_paths};
+use std::ffi::OsString;
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Error};
+use clap::{ArgAction, Parser};
+use pep508_rs::Requirement;
+
+use crate::consts::VENV_BIN;
+use crate::installer::ensure_ephemeral_tool;
+use crate::pyproject::PyProject;
+use crate::sources::PythonVersionRequest;
+use crate::utils::{exec_spawn, CommandOutput};
+
+/// Runs a tool from a package without installing it.
+///
+/// The package is installed into a cached virtualenv of its own, so the next
+/// run of the same tool starts right away.
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// The package to take the command from (defaults to the command).
+    #[arg(long, value_name = "REQUIREMENT")]
+    from: Option<String>,
+    /// Additional packages to install alongside the tool.
+    #[arg(long = "with", value_name = "REQUIREMENT")]
+    with: Vec<String>,
+    /// The Python version to run the tool with.
+    #[arg(short, long)]
+    python: Option<String>,
+    /// Run the tool with the Python version of the current project.
+    #[arg(long, conflicts_with = "python")]
+    match_project_python: bool,
+    /// Reinstall the tool to pick up new releases.
+    #[arg(long)]
+    refresh: bool,
+    /// Enables verbose diagnostics (repeat to log in more detail).
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// Turns off all output.
+    #[arg(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// The command to run and its arguments.
+    ///
+    /// Without `--from` the command is also the requirement, so a version
+    /// can be given (`ruff==0.1.0`).
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<OsString>,
+}
+
+pub fn execute(cmd: Args) -> Result<(), Error> {
+    let output = CommandOutput::from_quiet_and_verbose(cmd.quiet, cmd.verbose);
+    let command = cmd.command[0]
+        .to_str()
+        .ok_or_else(|| anyhow!("command is not valid unicode"))?;
+    let requirement = parse_requirement(cmd.from.as_deref().unwrap_or(command))?;
+    // without --from the command is named after the package
+    let name = match cmd.from {
+        Some(_) => command.to_string(),
+        None => requirement.name.clone(),
+    };
+    let extra_requirements = cmd
+        .with
+        .iter()
+        .map(|x| parse_requirement(x))
+        .collect::<Result<Vec<_>, _>>()?;
+    let py_ver: Option<PythonVersionRequest> = if cmd.match_project_python {
+        let pyproject = PyProject::discover()?;
+        Some(pyproject.venv_python_version()?.into())
+    } else {
+        cmd.python.as_deref().map(str::parse).transpose()?
+    };
+
+    let venv = ensure_ephemeral_tool(
+        &requirement,
+        &extra_requirements,
+        py_ver.as_ref(),
+        cmd.refresh,
+        output,
+    )?;
+    let bin = venv.join(VENV_BIN);
+    let exe = which::which_in(&name, Some(&bin), &bin).map_err(|_| {
+        anyhow!(
+            "{} does not provide a command named '{}'. Use --from to pick the package",
+            requirement.name,
+            name
+        )
+    })?;
+
+    let mut tool_cmd = Command::new(exe);
+    tool_cmd.args(&cmd.command[1..]);
+    let mut paths = vec![bin];
+    if let Some(path) = env::var_os("PATH") {
+        paths.extend(split_paths(&path));
+    }
+    tool_cmd.env("PATH", join_paths(paths)?);
+    tool_cmd.env_remove("PYTHONHOME");
+    match exec_spawn(&mut tool_cmd)? {}
+}
+
+fn parse_requirement(req: &str) -> Result<Requirement, Error> {
+    req.parse::<Requirement>()
+        .with_context(|| format!("failed to parse requirement '{}'", req))
+}
@@ -1,13 +1,15 @@
+use std::cmp::Reverse;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::{Context, Error};
 use pep440_rs::Operator;
 use toml_edit::Document;
 
 use crate::platform::{get_app_dir, get_latest_cpython_version};
-use crate::pyproject::{BuildSystem, SourceRef, SourceRefType};
+use crate::pyproject::{BuildSystem, RepositoryRef, SourceRef, SourceRefType};
 use crate::sources::PythonVersionRequest;
 
 static CONFIG: Mutex<Option<Arc<Config>>> = Mutex::new(None);
@@ -126,8 +128,19 @@ impl Config {
             .map(|x| x.to_string())
     }
 
+    /// Returns the repository that `rye init --private` projects publish to.
+    pub fn default_private_repository(&self) -> Option<String> {
+        self.doc
+            .get("default")
+            .and_then(|x| x.get("private-repository"))
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string())
+    }
+
     /// Should dependencies added by default by pinned with ~= or ==
-    pub fn default_dependency_operator(&self) -> Operator {
+    ///
+    /// `None` means that dependencies are added without a version bound.
+    pub fn default_dependency_operator(&self) -> Option<Operator> {
         self.doc
             .get("default")
             .and_then(|x| {
@@ -136,14 +149,122 @@ impl Config {
                     .or_else(|| x.get("dependency_operator"))
             })
             .and_then(|x| x.as_str())
-            .map_or(Operator::GreaterThanEqual, |x| match x {
-                "==" => Operator::Equal,
-                "~=" => Operator::TildeEqual,
-                ">=" => Operator::GreaterThanEqual,
-                _ => Operator::GreaterThanEqual,
+            .map_or(Some(Operator::GreaterThanEqual), |x| match x {
+                "==" => Some(Operator::Equal),
+                "~=" => Some(Operator::TildeEqual),
+                ">=" => Some(Operator::GreaterThanEqual),
+                "" | "*" | "none" => None,
+                _ => Some(Operator::GreaterThanEqual),
             })
     }
 
+    /// Returns the directory of the machine-wide toolchain store.
+    ///
+    /// Toolchains in there are used in addition to the ones of the user
+    /// but rye never installs into or removes from it.
+    pub fn shared_toolchain_dir(&self) -> Option<PathBuf> {
+        self.doc
+            .get("toolchain")
+            .and_then(|x| x.get("shared-dir"))
+            .and_then(|x| x.as_str())
+            .map(PathBuf::from)
+    }
+
+    /// Returns the URL of the remote cache shared between machines.
+    pub fn remote_cache_url(&self) -> Option<String> {
+        self.doc
+            .get("cache")
+            .and_then(|x| x.get("remote"))
+            .and_then(|x| x.get("url"))
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string())
+    }
+
+    /// Should downloads be uploaded to the remote cache?
+    pub fn remote_cache_upload(&self) -> bool {
+        self.doc
+            .get("cache")
+            .and_then(|x| x.get("remote"))
+            .and_then(|x| x.get("upload"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Returns how installed files are shared with the package store.
+    ///
+    /// This is `clone`, `hardlink` or `copy` (do not share).
+    pub fn link_mode(&self) -> Option<String> {
+        self.doc
+            .get("cache")
+            .and_then(|x| x.get("link-mode"))
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string())
+    }
+
+    /// Are wheels built from sdists cached (`cache.build-cache`)?
+    pub fn build_cache(&self) -> bool {
+        self.doc
+            .get("cache")
+            .and_then(|x| x.get("build-cache"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or(true)
+    }
+
+    /// Should `rye sync` keep wheels of the locked packages (`cache.keep-wheels`)?
+    ///
+    /// These are needed for `rye sync --offline`.
+    pub fn keep_wheels(&self) -> bool {
+        self.doc
+            .get("cache")
+            .and_then(|x| x.get("keep-wheels"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Returns how `rye sync` treats the lockfiles by default.
+    ///
+    /// This is `update`, `locked` (fail if out of date) or `frozen` (install
+    /// the lockfiles as they are).
+    pub fn lockfile_mode(&self) -> Option<String> {
+        self.doc
+            .get("behavior")
+            .and_then(|x| x.get("lockfile-mode"))
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string())
+    }
+
+    /// Returns where virtualenvs are placed.
+    ///
+    /// This is `in-project` (the default, `.venv` next to the pyproject.toml)
+    /// or `central` (a folder in the rye home keyed by the project path).
+    pub fn venv_location(&self) -> Option<String> {
+        self.doc
+            .get("behavior")
+            .and_then(|x| x.get("venv-location"))
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string())
+    }
+
+    /// Returns what happens if a Python version reached its end of life.
+    ///
+    /// This is `warn` (the default) or `deny`.
+    pub fn eol_policy(&self) -> Option<String> {
+        self.doc
+            .get("behavior")
+            .and_then(|x| x.get("eol-policy"))
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string())
+    }
+
+    /// Run build backends of source distributions in a sandbox.
+    pub fn sandbox_builds(&self) -> bool {
+        self.doc
+            .get("behavior")
+            .and_then(|x| x.get("sandbox-builds"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false)
+    }
+
     /// Allow rye shims to resolve globally installed Pythons.
     pub fn global_python(&self) -> bool {
         self.doc
@@ -153,6 +274,20 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Never download toolchains (also enabled by `RYE_OFFLINE=1`).
+    pub fn offline(&self) -> bool {
+        match std::env::var("RYE_OFFLINE").ok().as_deref() {
+            Some("1" | "true") => true,
+            Some(_) => false,
+            None => self
+                .doc
+                .get("behavior")
+                .and_then(|x| x.get("offline"))
+                .and_then(|x| x.as_bool())
+                .unwrap_or(false),
+        }
+    }
+
     /// Pretend that all projects are rye managed.
     pub fn force_rye_managed(&self) -> bool {
         self.doc
@@ -166,15 +301,64 @@ impl Config {
             .unwrap_or(false)
     }
 
+    /// Returns the hosts rye may contact (`network.allow`).
+    ///
+    /// `None` means that all hosts are allowed.
+    pub fn network_allow(&self) -> Option<Vec<String>> {
+        self.doc
+            .get("network")
+            .and_then(|x| x.get("allow"))
+            .and_then(|x| x.as_array())
+            .map(|x| {
+                x.iter()
+                    .filter_map(|x| x.as_str())
+                    .map(|x| x.to_string())
+                    .collect()
+            })
+    }
+
+    /// Returns how often failed downloads are retried (`network.retries`).
+    ///
+    /// `RYE_NETWORK_RETRIES` overrides the config.  `None` means that the
+    /// default is used.
+    pub fn network_retries(&self) -> Option<u32> {
+        std::env::var("RYE_NETWORK_RETRIES")
+            .ok()
+            .and_then(|x| x.trim().parse().ok())
+            .or_else(|| {
+                self.doc
+                    .get("network")
+                    .and_then(|x| x.get("retries"))
+                    .and_then(|x| x.as_integer())
+                    .map(|x| x.clamp(0, u32::MAX as i64) as u32)
+            })
+    }
+
+    /// Returns the delay before the first retry (`network.retry-backoff`).
+    ///
+    /// The delay doubles with every further attempt.
+    pub fn retry_backoff(&self) -> Duration {
+        let secs = self
+            .doc
+            .get("network")
+            .and_then(|x| x.get("retry-backoff"))
+            .and_then(|x| x.as_float().or_else(|| x.as_integer().map(|x| x as f64)))
+            .unwrap_or(1.0);
+        Duration::from_secs_f64(secs.max(0.0))
+    }
+
     /// Returns the HTTP proxy that should be used.
     pub fn http_proxy_url(&self) -> Option<String> {
-        std::env::var("http_proxy").ok().or_else(|| {
-            self.doc
-                .get("proxy")
-                .and_then(|x| x.get("http"))
-                .and_then(|x| x.as_str())
-                .map(|x| x.to_string())
-        })
+        std::env::var("HTTP_PROXY")
+            .ok()
+            .or_else(|| std::env::var("http_proxy").ok())
+            .or_else(|| {
+                self.doc
+                    .get("proxy")
+                    .and_then(|x| x.get("http"))
+                    .and_then(|x| x.as_str())
+                    .map(|x| x.to_string())
+            })
     }
 
     /// Returns the HTTPS proxy that should be used.
@@ -191,13 +375,90 @@ impl Config {
             })
     }
 
+    /// Returns the hosts that are contacted without the proxy.
+    ///
+    /// This is a comma separated list as in the `NO_PROXY` environment variable.
+    pub fn no_proxy(&self) -> Option<String> {
+        std::env::var("NO_PROXY")
+            .ok()
+            .or_else(|| std::env::var("no_proxy").ok())
+            .or_else(|| {
+                self.doc
+                    .get("proxy")
+                    .and_then(|x| x.get("no-proxy"))
+                    .and_then(|x| x.as_str())
+                    .map(|x| x.to_string())
+            })
+    }
+
+    /// Returns the CA bundle that TLS certificates are verified against.
+    ///
+    /// `SSL_CERT_FILE` overrides `network.ca-bundle`.
+    pub fn ca_bundle(&self) -> Option<PathBuf> {
+        std::env::var_os("SSL_CERT_FILE")
+            .map(PathBuf::from)
+            .or_else(|| {
+                self.doc
+                    .get("network")
+                    .and_then(|x| x.get("ca-bundle"))
+                    .and_then(|x| x.as_str())
+                    .map(PathBuf::from)
+            })
+    }
+
+    /// Should the certificate store of the operating system be used?
+    pub fn native_certs(&self) -> bool {
+        self.doc
+            .get("network")
+            .and_then(|x| x.get("native-certs"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Returns the repositories that packages can be published to.
+    ///
+    /// `testpypi` is always available.
+    pub fn repositories(&self) -> Result<Vec<RepositoryRef>, Error> {
+        let mut rv = Vec::new();
+        if let Some(repositories) = self
+            .doc
+            .get("repositories")
+            .and_then(|x| x.as_array_of_tables())
+        {
+            for repository in repositories {
+                rv.push(
+                    RepositoryRef::from_toml_table(repository)
+                        .context("invalid repository in config")?,
+                );
+            }
+        }
+        if !rv.iter().any(|x| x.name == "testpypi") {
+            rv.push(RepositoryRef {
+                name: "testpypi".into(),
+                url: "https://test.pypi.org/legacy/".into(),
+                username: None,
+                skip_existing: false,
+            });
+        }
+        Ok(rv)
+    }
+
+    /// Should publish tokens be stored in the keyring of the system?
+    pub fn use_keyring(&self) -> bool {
+        self.doc
+            .get("publish")
+            .and_then(|x| x.get("keyring"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false)
+    }
+
     /// Returns the list of default sources.
     pub fn sources(&self) -> Result<Vec<SourceRef>, Error> {
         let mut rv = Vec::new();
         let mut need_default = true;
         if let Some(sources) = self.doc.get("sources").and_then(|x| x.as_array_of_tables()) {
             for source in sources {
-                let source_ref = SourceRef::from_toml_table(source)?;
+                let source_ref = SourceRef::from_toml_table(source, self.config_dir())?;
                 if source_ref.name == "default" {
                     need_default = false;
                 }
@@ -213,6 +474,12 @@ impl Config {
             ));
         }
 
+        rv.sort_by_key(|x| Reverse(x.priority));
         Ok(rv)
     }
+
+    /// Returns the folder the config file is in.
+    fn config_dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new("."))
+    }
 }
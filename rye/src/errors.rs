@@ -0,0 +1,165 @@
+use std::env;
+use std::fmt;
+
+use anyhow::Error;
+use serde::Serialize;
+
+use crate::lock::LockFailed;
+use crate::pyproject::DiscoveryUnsuccessful;
+use crate::sync::InstallFailed;
+use crate::transcript::redact;
+use crate::utils::{PromptRequired, PROMPT_REQUIRED_EXIT_CODE};
+
+/// The classes of failures rye reports.
+///
+/// Every class has a stable code and exit code which are documented and
+/// printed with `--error-format json`.  Codes of removed classes are never
+/// reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Failure {
+    /// Any failure that is not classified otherwise.
+    Other,
+    /// A prompt is required, but rye runs in non-interactive mode.
+    PromptRequired,
+    /// No `pyproject.toml` was found.
+    ProjectNotFound,
+    /// The dependencies could not be resolved.
+    LockFailed,
+    /// The packages could not be installed into the virtualenv.
+    InstallFailed,
+    /// Warnings were emitted and `--deny-warnings` is set.
+    DeniedWarnings,
+}
+
+impl Failure {
+    /// Classifies an error by the failures it was caused by.
+    pub fn of(err: &Error) -> Failure {
+        if err.is::<PromptRequired>() {
+            Failure::PromptRequired
+        } else if err.is::<DiscoveryUnsuccessful>() {
+            Failure::ProjectNotFound
+        } else if err.is::<LockFailed>() {
+            Failure::LockFailed
+        } else if err.is::<InstallFailed>() {
+            Failure::InstallFailed
+        } else if err.is::<DeniedWarnings>() {
+            Failure::DeniedWarnings
+        } else {
+            Failure::Other
+        }
+    }
+
+    /// Returns the stable code of the failure.
+    pub fn code(self) -> &'static str {
+        match self {
+            Failure::Other => "RYEE001",
+            Failure::PromptRequired => "RYEE002",
+            Failure::ProjectNotFound => "RYEE003",
+            Failure::LockFailed => "RYEE004",
+            Failure::InstallFailed => "RYEE005",
+            Failure::DeniedWarnings => "RYEE006",
+        }
+    }
+
+    /// Returns the exit code of the process.
+    ///
+    /// Exit code 2 is used by the argument parser for invalid arguments.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Failure::Other => 1,
+            Failure::PromptRequired => PROMPT_REQUIRED_EXIT_CODE,
+            Failure::ProjectNotFound => 4,
+            Failure::LockFailed => 5,
+            Failure::InstallFailed => 6,
+            Failure::DeniedWarnings => 7,
+        }
+    }
+
+    /// Returns a hint on how to remedy the failure.
+    pub fn hint(self) -> Option<&'static str> {
+        match self {
+            Failure::Other => None,
+            Failure::PromptRequired => Some("pass --yes if available or run without --ci"),
+            Failure::ProjectNotFound => {
+                Some("run rye in a project, pass --pyproject or create one with `rye init`")
+            }
+            Failure::LockFailed => {
+                Some("check the dependencies of the project for conflicting requirements")
+            }
+            Failure::InstallFailed => Some("run `rye sync --verbose` for the output of pip"),
+            Failure::DeniedWarnings => {
+                Some("address the warnings or list their codes in tool.rye.suppress-warnings")
+            }
+        }
+    }
+}
+
+/// Raised when warnings were emitted and `--deny-warnings` is set.
+#[derive(Debug)]
+pub struct DeniedWarnings;
+
+impl std::error::Error for DeniedWarnings {}
+
+impl fmt::Display for DeniedWarnings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "warnings were emitted and --deny-warnings is set")
+    }
+}
+
+/// Output structure for --error-format=json
+#[derive(Serialize, Debug)]
+struct ErrorReport {
+    code: &'static str,
+    exit_code: i32,
+    message: String,
+    causes: Vec<String>,
+    hint: Option<&'static str>,
+}
+
+/// Reports failures as JSON objects from now on (also `RYE_OUTPUT=json`).
+///
+/// This is inherited by rye processes that are spawned by this one.
+pub fn set_json_errors() {
+    env::set_var("RYE_OUTPUT", "json");
+}
+
+/// Are failures reported as JSON objects?
+pub fn is_json_errors() -> bool {
+    env::var("RYE_OUTPUT").ok().as_deref() == Some("json")
+}
+
+/// Prints the error to stderr and returns the exit code for it.
+pub fn report_error(err: &Error) -> i32 {
+    let failure = Failure::of(err);
+    if is_json_errors() {
+        let mut chain = err.chain().map(|x| redact(&x.to_string()));
+        let report = ErrorReport {
+            code: failure.code(),
+            exit_code: failure.exit_code(),
+            message: chain.next().unwrap_or_default(),
+            causes: chain.collect(),
+            hint: failure.hint(),
+        };
+        eprintln!("{}", serde_json::to_string(&report).unwrap());
+    } else if failure == Failure::PromptRequired {
+        eprintln!("Error: {}", err);
+    } else {
+        eprintln!("Error: {}", redact(&format!("{:?}", err)));
+    }
+    failure.exit_code()
+}
+
+#[test]
+fn test_failure_of() {
+    use anyhow::Context;
+
+    let err = Err::<(), _>(Error::from(LockFailed { log: "".into() }))
+        .context("could not lock")
+        .unwrap_err();
+    assert_eq!(Failure::of(&err), Failure::LockFailed);
+    assert_eq!(
+        Failure::of(&Error::from(DiscoveryUnsuccessful)),
+        Failure::ProjectNotFound
+    );
+    assert_eq!(Failure::of(&anyhow::anyhow!("oops")), Failure::Other);
+}
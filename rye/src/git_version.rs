@@ -0,0 +1,182 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error};
+use pep440_rs::Version;
+
+/// The state of a git checkout relative to its latest version tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitVersion {
+    /// The version of the latest tag, if there is one.
+    pub tag: Option<Version>,
+    /// The number of commits since the tag (or since the first commit).
+    pub distance: u64,
+    /// The abbreviated hash of the current commit.
+    pub hash: String,
+    /// Are there uncommitted changes?
+    pub dirty: bool,
+}
+
+impl GitVersion {
+    /// Describes the checkout the given folder is in.
+    ///
+    /// Tags are version numbers with an optional `v` prefix (`v1.2.0`).
+    pub fn describe(dir: &Path) -> Result<GitVersion, Error> {
+        let describe = git(
+            dir,
+            &[
+                "describe", "--tags", "--long", "--dirty", "--always", "--match", "v[0-9]*",
+                "--match", "[0-9]*",
+            ],
+        )?;
+        let (describe, dirty) = match describe.strip_suffix("-dirty") {
+            Some(rest) => (rest, true),
+            None => (describe.as_str(), false),
+        };
+
+        let parts = describe.rsplitn(3, '-').collect::<Vec<_>>();
+        if let [hash, distance, tag] = parts[..] {
+            if let (Some(hash), Ok(distance)) = (hash.strip_prefix('g'), distance.parse()) {
+                let tag = tag.strip_prefix('v').unwrap_or(tag);
+                return Ok(GitVersion {
+                    tag: Some(
+                        Version::from_str(tag)
+                            .map_err(|msg| anyhow!("invalid version tag '{}': {}", tag, msg))?,
+                    ),
+                    distance,
+                    hash: hash.to_string(),
+                    dirty,
+                });
+            }
+        }
+
+        // without a tag git only reports the hash
+        let distance = git(dir, &["rev-list", "--count", "HEAD"])?
+            .parse()
+            .context("unexpected output from git rev-list")?;
+        Ok(GitVersion {
+            tag: None,
+            distance,
+            hash: describe.to_string(),
+            dirty,
+        })
+    }
+
+    /// Returns the version of the checkout.
+    ///
+    /// A tagged commit without changes has the version of the tag.  Anything
+    /// else is a dev release of the next patch version, with the commit as
+    /// local version (`1.2.1.dev3+gabc1234`).
+    pub fn version(&self) -> Result<Version, Error> {
+        let tag = match self.tag {
+            Some(ref tag) if self.distance == 0 && !self.dirty => return Ok(tag.clone()),
+            Some(ref tag) => tag,
+            None => {
+                return parse_version(&format!(
+                    "0.1.0.dev{}+g{}{}",
+                    self.distance,
+                    self.hash,
+                    if self.dirty { ".dirty" } else { "" }
+                ))
+            }
+        };
+        let mut release = tag.release.clone();
+        if tag.pre.is_none() && tag.dev.is_none() {
+            *release.last_mut().unwrap() += 1;
+        }
+        parse_version(&format!(
+            "{}.dev{}+g{}{}",
+            release
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join("."),
+            self.distance,
+            self.hash,
+            if self.dirty { ".dirty" } else { "" }
+        ))
+    }
+}
+
+/// Copies the files of a checkout that git does not ignore into a folder.
+///
+/// Projects with a version from git are built from such a copy, so that the
+/// computed version can be written into its `pyproject.toml` without
+/// touching the checkout.
+pub fn copy_checkout(dir: &Path, target: &Path) -> Result<(), Error> {
+    let output = Command::new("git")
+        .args([
+            "ls-files",
+            "-z",
+            "--cached",
+            "--others",
+            "--exclude-standard",
+        ])
+        .current_dir(dir)
+        .output()
+        .context("unable to run git to list the files of the project")?;
+    if !output.status.success() {
+        bail!(
+            "failed to list the files of the project: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    for name in output.stdout.split(|x| *x == 0).filter(|x| !x.is_empty()) {
+        let name = String::from_utf8_lossy(name);
+        let source = dir.join(&*name);
+        // tracked files can be deleted and submodules are folders
+        if !source.is_file() {
+            continue;
+        }
+        let dest = target.join(&*name);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&source, &dest).with_context(|| format!("failed to copy {}", name))?;
+    }
+    Ok(())
+}
+
+fn parse_version(version: &str) -> Result<Version, Error> {
+    Version::from_str(version).map_err(|msg| anyhow!("invalid version: {}", msg))
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .context("unable to run git to determine the version")?;
+    if !output.status.success() {
+        bail!(
+            "failed to determine the version from git: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[test]
+fn test_git_version() {
+    let mut git = GitVersion {
+        tag: Some(Version::from_str("1.2.0").unwrap()),
+        distance: 0,
+        hash: "abc1234".into(),
+        dirty: false,
+    };
+    assert_eq!(git.version().unwrap().to_string(), "1.2.0");
+    git.distance = 3;
+    assert_eq!(git.version().unwrap().to_string(), "1.2.1.dev3+gabc1234");
+    git.dirty = true;
+    assert_eq!(
+        git.version().unwrap().to_string(),
+        "1.2.1.dev3+gabc1234.dirty"
+    );
+    git.tag = None;
+    assert_eq!(
+        git.version().unwrap().to_string(),
+        "0.1.0.dev3+gabc1234.dirty"
+    );
+}
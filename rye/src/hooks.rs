@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::env::{self, join_paths, split_paths};
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::{bail, Context, Error};
+use console::style;
+
+use crate::config::Config;
+use crate::consts::VENV_BIN;
+use crate::pyproject::PyProject;
+use crate::sandbox::sandboxed_command;
+use crate::utils::CommandOutput;
+
+/// The hooks that can be declared in `tool.rye.hooks`.
+pub const HOOKS: &[&str] = &[
+    "pre-sync",
+    "post-sync",
+    "pre-lock",
+    "post-lock",
+    "pre-build",
+    "post-build",
+    "pre-publish",
+    "post-publish",
+];
+
+/// The hooks of a project.
+///
+/// Hook commands run in the root of the project with the virtualenv on the
+/// `PATH`.  A failing hook fails the command it belongs to.  The `post-sync`
+/// hook runs code of the freshly installed packages, so it runs in the build
+/// sandbox with `behavior.sandbox-builds`.
+pub struct Hooks {
+    commands: BTreeMap<String, Vec<String>>,
+    root: PathBuf,
+    workspace_root: PathBuf,
+    venv: PathBuf,
+    env_vars: Vec<(String, String)>,
+}
+
+impl Hooks {
+    /// Loads the hooks of a project.
+    pub fn load(pyproject: &PyProject) -> Result<Hooks, Error> {
+        let commands = pyproject.hooks()?;
+        if let Some(name) = commands.keys().find(|x| !HOOKS.contains(&x.as_str())) {
+            bail!(
+                "unknown hook tool.rye.hooks.{} (known hooks: {})",
+                name,
+                HOOKS.join(", ")
+            );
+        }
+        Ok(Hooks {
+            env_vars: if commands.is_empty() {
+                Vec::new()
+            } else {
+                pyproject.env_vars()?
+            },
+            commands,
+            root: pyproject.root_path().into_owned(),
+            workspace_root: pyproject.workspace_path().into_owned(),
+            venv: pyproject.venv_path().into_owned(),
+        })
+    }
+
+    /// Runs a hook if it's declared.
+    pub fn run(&self, name: &str, output: CommandOutput) -> Result<(), Error> {
+        let args = match self.commands.get(name) {
+            Some(args) => args,
+            None => return Ok(()),
+        };
+        if output != CommandOutput::Quiet {
+            eprintln!("Running {} hook", style(name).cyan());
+        }
+
+        let venv_bin = self.venv.join(VENV_BIN);
+        let mut paths = vec![venv_bin];
+        // a PATH declared in `tool.rye.env` replaces the one of the environment
+        let base_path = match self.env_vars.iter().rev().find(|(key, _)| key == "PATH") {
+            Some((_, value)) => Some(value.into()),
+            None => env::var_os("PATH"),
+        };
+        if let Some(path) = base_path {
+            paths.extend(split_paths(&path));
+        }
+        let path = join_paths(paths)?;
+        let program = which::which_in(&args[0], Some(&path), &self.root)
+            .with_context(|| format!("unable to find '{}' for the {} hook", args[0], name))?;
+
+        let mut cmd = if name == "post-sync" && Config::current().sandbox_builds() {
+            sandboxed_command(&program, &[&self.root, &self.workspace_root, &self.venv])?
+        } else {
+            Command::new(program)
+        };
+        cmd.args(&args[1..])
+            .current_dir(&self.root)
+            .envs(self.env_vars.iter().map(|(k, v)| (k, v)))
+            .env("PATH", &path)
+            .env("VIRTUAL_ENV", &self.venv)
+            .env("PROJECT_ROOT", &self.root)
+            .env("WORKSPACE_ROOT", &self.workspace_root)
+            .env("RYE_HOOK", name)
+            .env_remove("PYTHONHOME");
+        let status = cmd
+            .status()
+            .with_context(|| format!("failed to run the {} hook", name))?;
+        if !status.success() {
+            bail!(
+                "the {} hook failed with exit code {}",
+                name,
+                status.code().unwrap_or(1)
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use std::collections::{BTreeMap, HashMap};
+use std::env::consts::EXE_EXTENSION;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::{env, fs};
@@ -9,16 +10,22 @@ use once_cell::sync::Lazy;
 use pep508_rs::{Requirement, VersionOrUrl};
 use regex::Regex;
 use same_file::is_same_file;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use url::Url;
 
 use crate::bootstrap::{ensure_self_venv, fetch};
 use crate::config::Config;
 use crate::consts::VENV_BIN;
-use crate::platform::get_app_dir;
+use crate::platform::{get_app_dir, record_toolchain_use};
 use crate::pyproject::{normalize_package_name, ExpandedSources};
-use crate::sources::PythonVersionRequest;
+use crate::sources::{PythonVersion, PythonVersionRequest};
 use crate::sync::create_virtualenv;
-use crate::utils::{get_short_executable_name, get_venv_python_bin, symlink_file, CommandOutput};
+use crate::utils::{
+    get_short_executable_name, get_venv_python_bin, set_proxy_variables, symlink_file,
+    CommandOutput,
+};
+use crate::warnings::{warn, Warning};
 
 const FIND_SCRIPT_SCRIPT: &str = r#"
 import os
@@ -63,15 +70,83 @@ while to_resolve:
 
 print(json.dumps(result))
 "#;
+const VERSION_SCRIPT: &str = r#"
+import sys
+
+if sys.version_info >= (3, 8):
+    from importlib.metadata import version
+else:
+    from importlib_metadata import version
+
+print(version(sys.argv[1]))
+"#;
 static SUCCESSFULLY_DOWNLOADED_RE: Lazy<Regex> =
     Lazy::new(|| Regex::new("(?m)^Successfully downloaded (.*?)$").unwrap());
 
+/// Information about an installed tool.
+///
+/// This is written into the virtualenv of the tool so that it can be
+/// reinstalled later with the same settings.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ToolInfo {
+    /// The requirement the tool was installed from.
+    pub requirement: String,
+    /// The Python version constraint the tool was installed with.
+    pub python: Option<String>,
+    /// Dependencies whose scripts were also installed.
+    #[serde(default)]
+    pub include_deps: Vec<String>,
+    /// Additional requirements installed into the tool.
+    #[serde(default)]
+    pub extra_requirements: Vec<String>,
+    /// Run the tool with the Python version of the project it's invoked in.
+    #[serde(default)]
+    pub match_project_python: bool,
+    /// The scripts that are shimmed through rye (file names).
+    #[serde(default)]
+    pub scripts: Vec<String>,
+}
+
+/// Returns the recorded information of an installed tool.
+pub fn get_tool_info(package: &str) -> Option<ToolInfo> {
+    let info_file = get_app_dir()
+        .join("tools")
+        .join(normalize_package_name(package))
+        .join("rye-tool.json");
+    serde_json::from_slice(&fs::read(info_file).ok()?).ok()
+}
+
+/// Returns the installed version of a tool.
+pub fn get_installed_version(tool: &str) -> Option<String> {
+    let package = get_tool_info(tool)
+        .and_then(|info| info.requirement.parse::<Requirement>().ok())
+        .map_or_else(|| tool.to_string(), |req| req.name);
+    let venv = get_app_dir()
+        .join("tools")
+        .join(normalize_package_name(tool));
+    let out = Command::new(get_venv_python_bin(&venv))
+        .arg("-c")
+        .arg(VERSION_SCRIPT)
+        .arg(package)
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// Installs a tool.
+///
+/// If no Python version is requested, the default toolchain is used.
 pub fn install(
     requirement: Requirement,
-    py_ver: &PythonVersionRequest,
+    py_ver: Option<&PythonVersionRequest>,
     force: bool,
     include_deps: &[String],
     extra_requirements: &[Requirement],
+    match_project_python: bool,
     output: CommandOutput,
 ) -> Result<(), Error> {
     let config = Config::current();
@@ -80,6 +155,14 @@ pub fn install(
     let shim_dir = app_dir.join("shims");
     let self_venv = ensure_self_venv(output)?;
     let tool_dir = app_dir.join("tools");
+    let mut tool_info = ToolInfo {
+        requirement: requirement.to_string(),
+        python: py_ver.map(|x| x.to_string()),
+        include_deps: include_deps.to_vec(),
+        extra_requirements: extra_requirements.iter().map(|x| x.to_string()).collect(),
+        match_project_python,
+        scripts: Vec::new(),
+    };
     let include_deps = include_deps
         .iter()
         .map(|x| normalize_package_name(x))
@@ -95,9 +178,13 @@ pub fn install(
     uninstall_helper(&target_venv_path, &shim_dir)?;
 
     // make sure we have a compatible python version
-    let py_ver = fetch(py_ver, output)?;
+    let py_ver = match py_ver {
+        Some(py_ver) => fetch(py_ver, output)?,
+        None => fetch(&config.default_toolchain()?, output)?,
+    };
+    record_toolchain_use(&py_ver);
 
-    create_virtualenv(output, &self_venv, &py_ver, &target_venv_path)?;
+    create_virtualenv(output, &self_venv, &py_ver, &target_venv_path, false)?;
 
     let mut cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
     cmd.arg("--python")
@@ -125,6 +212,7 @@ pub fn install(
     for extra in extra_requirements {
         cmd.arg(extra.to_string());
     }
+    set_proxy_variables(&mut cmd);
 
     let status = cmd.status()?;
     if !status.success() {
@@ -132,6 +220,12 @@ pub fn install(
         bail!("tool installation failed");
     }
 
+    fs::write(
+        target_venv_path.join("rye-tool.json"),
+        serde_json::to_string_pretty(&tool_info)?,
+    )
+    .context("failed writing tool info file")?;
+
     let out = Command::new(py)
         .arg("-c")
         .arg(FIND_SCRIPT_SCRIPT)
@@ -150,7 +244,12 @@ pub fn install(
     let mut installed = Vec::new();
     let mut scripts_found = Vec::new();
     if let Some(files) = all_files.get("") {
-        installed.extend(install_scripts(files, &target_venv_bin_path, &shim_dir)?);
+        installed.extend(install_scripts(
+            files,
+            &target_venv_bin_path,
+            &shim_dir,
+            match_project_python,
+        )?);
     }
 
     for (package, files) in all_files.iter() {
@@ -158,7 +257,12 @@ pub fn install(
             continue;
         }
         if include_deps.contains(&normalize_package_name(package)) {
-            installed.extend(install_scripts(files, &target_venv_bin_path, &shim_dir)?);
+            installed.extend(install_scripts(
+                files,
+                &target_venv_bin_path,
+                &shim_dir,
+                match_project_python,
+            )?);
         } else {
             let scripts = find_scripts(files, &target_venv_bin_path);
             if !scripts.is_empty() {
@@ -167,6 +271,21 @@ pub fn install(
         }
     }
 
+    // the shims of these tools are links to rye, so they need to be
+    // remembered to know which tool they belong to.
+    if match_project_python {
+        tool_info.scripts = installed.clone();
+        fs::write(
+            target_venv_path.join("rye-tool.json"),
+            serde_json::to_string_pretty(&tool_info)?,
+        )
+        .context("failed writing tool info file")?;
+    }
+    let installed = installed
+        .iter()
+        .map(|x| get_short_executable_name(Path::new(x)))
+        .collect::<Vec<_>>();
+
     if !scripts_found.is_empty()
         && output != CommandOutput::Quiet
         && (installed.is_empty() || output == CommandOutput::Verbose)
@@ -188,9 +307,9 @@ pub fn install(
     if output != CommandOutput::Quiet {
         eprintln!();
         if installed.is_empty() {
-            eprintln!(
-                "{}",
-                style("warning: installed package did not expose any scripts").red()
+            warn(
+                Warning::ToolWithoutScripts,
+                "installed package did not expose any scripts",
             );
         } else {
             eprintln!("Installed scripts:");
@@ -211,6 +330,113 @@ pub fn install(
     Ok(())
 }
 
+/// Returns a cached virtualenv with a tool for `rye x`.
+///
+/// The virtualenvs are kept in the cache, keyed by the requirements and the
+/// Python version, so that running the same tool again is fast.  With
+/// `refresh` the virtualenv is recreated to pick up new releases.
+pub fn ensure_ephemeral_tool(
+    requirement: &Requirement,
+    extra_requirements: &[Requirement],
+    py_ver: Option<&PythonVersionRequest>,
+    refresh: bool,
+    output: CommandOutput,
+) -> Result<PathBuf, Error> {
+    let config = Config::current();
+    let py_ver = match py_ver {
+        Some(py_ver) => py_ver.clone(),
+        None => config.default_toolchain()?,
+    };
+    let mut key = format!("{}\n{}", py_ver, requirement);
+    for extra in extra_requirements {
+        key.push('\n');
+        key.push_str(&extra.to_string());
+    }
+    let hash = hex::encode(Sha256::digest(key.as_bytes()));
+    let venv = get_app_dir().join("cache").join("tools").join(format!(
+        "{}-{}",
+        normalize_package_name(&requirement.name),
+        &hash[..16]
+    ));
+
+    let mut requirements = vec![requirement.to_string()];
+    requirements.extend(extra_requirements.iter().map(|x| x.to_string()));
+    ensure_cached_venv(
+        &venv,
+        "rye-tool.json",
+        &py_ver,
+        &requirements,
+        refresh,
+        output,
+        |py_ver| {
+            Ok(serde_json::to_string_pretty(&ToolInfo {
+                requirement: requirement.to_string(),
+                python: Some(py_ver.to_string()),
+                include_deps: Vec::new(),
+                extra_requirements: extra_requirements.iter().map(|x| x.to_string()).collect(),
+                match_project_python: false,
+                scripts: Vec::new(),
+            })?)
+        },
+    )?;
+    Ok(venv)
+}
+
+/// Creates a virtualenv in the cache with the given packages installed.
+///
+/// The info file is written last, so a virtualenv without it was not
+/// installed completely and is recreated.
+pub fn ensure_cached_venv(
+    venv: &Path,
+    info_file_name: &str,
+    py_ver: &PythonVersionRequest,
+    requirements: &[String],
+    refresh: bool,
+    output: CommandOutput,
+    info: impl FnOnce(&PythonVersion) -> Result<String, Error>,
+) -> Result<(), Error> {
+    let info_file = venv.join(info_file_name);
+    if !refresh && info_file.is_file() && get_venv_python_bin(venv).is_file() {
+        return Ok(());
+    }
+    if venv.is_dir() {
+        fs::remove_dir_all(venv).with_context(|| format!("unable to remove {}", venv.display()))?;
+    }
+
+    let sources = ExpandedSources::from_sources(&Config::current().sources()?)?;
+    let self_venv = ensure_self_venv(output)?;
+    let py_ver = fetch(py_ver, output)?;
+    record_toolchain_use(&py_ver);
+    if output != CommandOutput::Quiet && !requirements.is_empty() {
+        eprintln!("Installing {}", style(requirements.join(", ")).cyan());
+    }
+    create_virtualenv(output, &self_venv, &py_ver, venv, false)?;
+
+    if !requirements.is_empty() {
+        let mut cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
+        cmd.arg("--python")
+            .arg(get_venv_python_bin(venv))
+            .arg("install")
+            .env("PYTHONWARNINGS", "ignore");
+        sources.add_as_pip_args(&mut cmd);
+        if output == CommandOutput::Verbose {
+            cmd.arg("--verbose");
+        } else {
+            cmd.arg("-q");
+        }
+        cmd.arg("--").args(requirements);
+        set_proxy_variables(&mut cmd);
+        let status = cmd.status()?;
+        if !status.success() {
+            fs::remove_dir_all(venv).ok();
+            bail!("failed to install {}", requirements.join(", "));
+        }
+    }
+
+    fs::write(&info_file, info(&py_ver)?).context("failed writing info file")?;
+    Ok(())
+}
+
 fn find_scripts(files: &[PathBuf], target_venv_bin_path: &Path) -> Vec<String> {
     let mut rv = Vec::new();
     for file in files {
@@ -221,15 +447,25 @@ fn find_scripts(files: &[PathBuf], target_venv_bin_path: &Path) -> Vec<String> {
     rv
 }
 
+/// Links the scripts of a tool into the shims folder.
+///
+/// Returns the file names of the linked scripts.  With `via_rye` the shims
+/// are links to rye which resolves them when they are invoked.
 fn install_scripts(
     files: &[PathBuf],
     target_venv_bin_path: &Path,
     shim_dir: &Path,
+    via_rye: bool,
 ) -> Result<Vec<String>, Error> {
     let mut rv = Vec::new();
     for file in files {
         if let Ok(rest) = file.strip_prefix(target_venv_bin_path) {
             let shim_target = shim_dir.join(rest);
+            rv.push(rest.to_string_lossy().into_owned());
+            if via_rye {
+                link_rye_shim(shim_dir, &shim_target)?;
+                continue;
+            }
 
             // on windows we want to fall back to hardlinks.  That might be problematic in
             // some cases, but it should work for most cases where setuptools or other
@@ -247,12 +483,68 @@ fn install_scripts(
                 symlink_file(file, shim_target)
                     .with_context(|| format!("unable to symlink tool to {}", file.display()))?;
             }
-            rv.push(get_short_executable_name(file));
         }
     }
     Ok(rv)
 }
 
+/// Links rye itself into the shims folder under the name of a script.
+fn link_rye_shim(shim_dir: &Path, shim: &Path) -> Result<(), Error> {
+    // if rye is itself installed into the shims folder, we want to
+    // use that.  Otherwise we fall back to the current executable
+    let mut this = shim_dir.join("rye").with_extension(EXE_EXTENSION);
+    if !this.is_file() {
+        this = env::current_exe()?;
+    }
+    fs::remove_file(shim).ok();
+    #[cfg(unix)]
+    {
+        let use_softlinks = !cfg!(target_os = "linux");
+        if use_softlinks || fs::hard_link(&this, shim).is_err() {
+            symlink_file(&this, shim)
+                .with_context(|| format!("unable to link rye to {}", shim.display()))?;
+        }
+    }
+    #[cfg(windows)]
+    {
+        if symlink_file(&this, shim).is_err() {
+            fs::hard_link(&this, shim)
+                .with_context(|| format!("unable to link rye to {}", shim.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Is the shim one of the scripts of the tool?
+fn is_tool_shim(shim: &Path, script: &Path, info: Option<&ToolInfo>) -> bool {
+    if let Ok(true) = is_same_file(shim, script) {
+        return true;
+    }
+    let name = script.file_name().map(|x| x.to_string_lossy());
+    shim.is_file()
+        && info.map_or(false, |info| {
+            name.map_or(false, |name| info.scripts.iter().any(|x| *x == name))
+        })
+}
+
+/// Finds the tool installed with `--match-project-python` that provides a
+/// shimmed script.
+pub fn find_project_python_tool(script: &str) -> Option<(PathBuf, ToolInfo)> {
+    for folder in fs::read_dir(get_app_dir().join("tools")).ok()?.flatten() {
+        let info = match fs::read(folder.path().join("rye-tool.json"))
+            .ok()
+            .and_then(|x| serde_json::from_slice::<ToolInfo>(&x).ok())
+        {
+            Some(info) => info,
+            None => continue,
+        };
+        if info.match_project_python && info.scripts.iter().any(|x| x == script) {
+            return Some((folder.path(), info));
+        }
+    }
+    None
+}
+
 pub fn uninstall(package: &str, output: CommandOutput) -> Result<(), Error> {
     let app_dir = get_app_dir();
     let shim_dir = app_dir.join("shims");
@@ -287,6 +579,7 @@ pub fn list_installed_tools() -> Result<HashMap<String, Vec<String>>, Error> {
         }
         let tool_name = folder.file_name().to_string_lossy().to_string();
         let target_venv_bin_path = folder.path().join(VENV_BIN);
+        let info = get_tool_info(&tool_name);
         let mut scripts = Vec::new();
 
         for script in fs::read_dir(target_venv_bin_path)? {
@@ -294,7 +587,7 @@ pub fn list_installed_tools() -> Result<HashMap<String, Vec<String>>, Error> {
             let script_path = script.path();
             if let Some(base_name) = script_path.file_name() {
                 let shim_path = shim_dir.join(base_name);
-                if let Ok(true) = is_same_file(&shim_path, &script_path) {
+                if is_tool_shim(&shim_path, &script_path, info.as_ref()) {
                     scripts.push(get_short_executable_name(&script_path));
                 }
             }
@@ -312,11 +605,14 @@ fn uninstall_helper(target_venv_path: &Path, shim_dir: &Path) -> Result<(), Erro
         return Ok(());
     }
 
+    let info = fs::read(target_venv_path.join("rye-tool.json"))
+        .ok()
+        .and_then(|x| serde_json::from_slice::<ToolInfo>(&x).ok());
     for script in fs::read_dir(target_venv_bin_path)? {
         let script = script?;
         if let Some(base_name) = script.path().file_name() {
             let shim_path = shim_dir.join(base_name);
-            if let Ok(true) = is_same_file(&shim_path, &script.path()) {
+            if is_tool_shim(&shim_path, &script.path(), info.as_ref()) {
                 fs::remove_file(&shim_path).ok();
             }
         }
@@ -338,12 +634,13 @@ pub fn resolve_local_requirement(
         return Ok(None);
     }
 
-    let output = Command::new(self_venv.join("bin/pip"))
-        .arg("download")
+    let mut cmd = Command::new(self_venv.join("bin/pip"));
+    cmd.arg("download")
         .arg("--no-deps")
         .arg("--")
-        .arg(maybe_path)
-        .output()?;
+        .arg(maybe_path);
+    set_proxy_variables(&mut cmd);
+    let output = cmd.output()?;
     let output = String::from_utf8_lossy(&output.stdout);
     if let Some(c) = SUCCESSFULLY_DOWNLOADED_RE.captures(&output) {
         let version_or_url = Some(VersionOrUrl::Url(
@@ -1,7 +1,7 @@
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::io::{BufWriter, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::sync::Arc;
 use std::{fmt, fs};
@@ -9,19 +9,32 @@ use std::{fmt, fs};
 use anyhow::{anyhow, bail, Context, Error};
 use minijinja::render;
 use once_cell::sync::Lazy;
-use pep508_rs::Requirement;
+use pep440_rs::Operator;
+use pep508_rs::{MarkerEnvironment, Requirement, StringVersion, VersionOrUrl};
 use regex::Regex;
 use serde::Serialize;
 use tempfile::NamedTempFile;
 use url::Url;
 
+use crate::config::Config;
+use crate::logging::{self, Level};
+use crate::network::check_requirements;
 use crate::piptools::get_pip_compile;
+use crate::pylock::{pylock_filename, pylock_to_requirements};
 use crate::pyproject::{
-    normalize_package_name, DependencyKind, ExpandedSources, PyProject, Workspace,
+    normalize_package_name, BuildSettings, DependencyKind, EnvConfig, ExpandedSources, LockFormat,
+    PyProject, Workspace,
 };
-use crate::sources::PythonVersion;
+use crate::sources::{PythonVersion, PythonVersionRequest};
+use crate::transcript::{run_output, run_status};
 use crate::utils::{get_venv_python_bin, set_proxy_variables, CommandOutput};
+use crate::warnings::{warn_with_output, Warning};
+use crate::wheel_cache::get_build_cache_dir;
 
+static EXCLUDE_NEWER_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(\d{4}-\d{2}-\d{2})(?:[T ](\d{2}:\d{2}(?::\d{2})?)(Z|[+-]\d{2}:\d{2})?)?$")
+        .unwrap()
+});
 static FILE_EDITABLE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^-e (file://.*?)\s*$").unwrap());
 static REQUIREMENTS_HEADER: &str = r#"# generated by rye
 # use `rye lock` or `rye sync` to update this lockfile
@@ -30,9 +43,526 @@ static REQUIREMENTS_HEADER: &str = r#"# generated by rye
 #   pre: {{ lock_options.pre }}
 #   features: {{ lock_options.features }}
 #   all-features: {{ lock_options.all_features }}
+#   generate-hashes: {{ lock_options.generate_hashes }}
+{%- if lock_options.platforms %}
+#   platforms: {{ lock_options.platforms }}
+{%- endif %}
+{%- if lock_options.python_version %}
+#   python-version: {{ lock_options.python_version }}
+{%- endif %}
+{%- if lock_options.exclude_newer %}
+#   exclude-newer: {{ lock_options.exclude_newer }}
+{%- endif %}
+{%- if lock_options.no_binary %}
+#   no-binary: {{ lock_options.no_binary }}
+{%- endif %}
+{%- if lock_options.only_binary %}
+#   only-binary: {{ lock_options.only_binary }}
+{%- endif %}
+{%- if excluded %}
+#
+# excluded dependencies:
+{%- for dep in excluded %}
+#   {{ dep }}
+{%- endfor %}
+{%- endif %}
+
+"#;
+
+/// Installed as `sitecustomize` into pip-compile to adjust how pip resolves.
+///
+/// With `RYE_EXCLUDE_NEWER` files that were uploaded after the cutoff are
+/// hidden.  This relies on the upload times that indexes report via the JSON
+/// simple API (PEP 700), the projects for which the index did not report
+/// upload times are written to the file named by `RYE_MISSING_UPLOAD_TIMES`.
+/// With `RYE_OVERRIDES` the dependencies that packages declare on overridden
+/// packages are replaced by the overrides and with `RYE_EXCLUSIONS`
+/// dependencies on excluded packages are dropped entirely.
+/// `RYE_TARGET_PLATFORM` resolves for another platform or Python version
+/// than the current one by replacing the markers and the wheel tags pip uses.
+/// `RYE_RECORD_FILES` names a file to which the files pip-compile hashed are
+/// written, so that the hashes can be attributed to files in `pylock.toml`.
+/// `RYE_RESOLUTION_NARRATION` replaces the resolver's reporter with one that
+/// writes every decision of the resolver to stderr.  `RYE_SOURCE_RESTRICTIONS`
+/// drops the files of sources that are shadowed by a source with a higher
+/// priority or that a package is not pinned to.  With `RYE_BUILD_CACHE`
+/// wheels built from sdists are stored in (and reused from) the build cache,
+/// keyed by the hash of the sdist and the tag of the interpreter.
+/// `RYE_BUILD_SETTINGS` turns off build isolation for some packages and adds
+/// extra requirements to the build environments of others.  When locking,
+/// every package is isolated as pip-compile does not run in the virtualenv.
+/// `RYE_KEEP_INSTALLED` keeps pip-sync from uninstalling some packages.
+///
+/// The hook only activates in processes that can import pip, as build
+/// backends that run without isolation inherit it.  The patches rely on
+/// internals of pip, so the hook only works with the version of pip it was
+/// written against ([`PIP_VERSION`](crate::bootstrap::PIP_VERSION)).  If pip cannot be patched the process
+/// fails rather than resolving without the settings.
+static PIP_COMPILE_SITECUSTOMIZE: &str = r#"
+import os
+import re
+import json
+from datetime import datetime
+
+# the version of pip that the patches were written against
+_PIP_VERSION = "23.1.2"
+
+
+def _parse(value):
+    value = re.sub(r"\.\d+", "", value.strip()).replace("Z", "+00:00")
+    return datetime.fromisoformat(value)
+
+
+def _install_exclude_newer():
+    import atexit
+    from pip._internal.models.link import Link
+
+    cutoff = _parse(os.environ["RYE_EXCLUDE_NEWER"])
+    from_json = Link.from_json.__func__
+    from_element = Link.from_element.__func__
+    missing = set()
+
+    def _project(page_url):
+        return page_url.rstrip("/").rpartition("/")[2]
+
+    def patched_from_json(cls, file, page_url):
+        upload_time = file.get("upload-time")
+        if not upload_time:
+            missing.add(_project(page_url))
+        elif _parse(upload_time) > cutoff:
+            return None
+        return from_json(cls, file, page_url)
+
+    def patched_from_element(cls, anchor_attribs, page_url, base_url):
+        missing.add(_project(page_url))
+        return from_element(cls, anchor_attribs, page_url, base_url)
+
+    def _dump():
+        path = os.environ.get("RYE_MISSING_UPLOAD_TIMES")
+        if path and missing:
+            with open(path, "w") as f:
+                json.dump(sorted(missing), f)
+
+    Link.from_json = classmethod(patched_from_json)
+    Link.from_element = classmethod(patched_from_element)
+    atexit.register(_dump)
+
+
+def _install_overrides():
+    from pip._vendor.packaging.requirements import Requirement
+    from pip._vendor.packaging.utils import canonicalize_name
+    from pip._internal.resolution.resolvelib.factory import Factory
+
+    overrides = {}
+    for spec in json.loads(os.environ["RYE_OVERRIDES"]):
+        overrides[canonicalize_name(Requirement(spec).name)] = spec
+    make_requirements_from_spec = Factory.make_requirements_from_spec
+
+    def patched_make_requirements_from_spec(self, specifier, comes_from, *args, **kwargs):
+        if comes_from is not None:
+            try:
+                req = Requirement(specifier)
+            except Exception:
+                req = None
+            override = req and overrides.get(canonicalize_name(req.name))
+            if override is not None:
+                new_req = Requirement(override)
+                new_req.extras |= req.extras
+                if new_req.marker is None:
+                    new_req.marker = req.marker
+                specifier = str(new_req)
+        return make_requirements_from_spec(self, specifier, comes_from, *args, **kwargs)
+
+    Factory.make_requirements_from_spec = patched_make_requirements_from_spec
+
+
+def _install_exclusions():
+    from pip._vendor.packaging.requirements import Requirement
+    from pip._vendor.packaging.utils import canonicalize_name
+    from pip._internal.resolution.resolvelib.factory import Factory
+
+    exclusions = set(canonicalize_name(x) for x in json.loads(os.environ["RYE_EXCLUSIONS"]))
+    make_requirements_from_spec = Factory.make_requirements_from_spec
+
+    def patched_make_requirements_from_spec(self, specifier, comes_from, *args, **kwargs):
+        if comes_from is not None:
+            try:
+                name = canonicalize_name(Requirement(specifier).name)
+            except Exception:
+                name = None
+            if name in exclusions:
+                return iter(())
+        return make_requirements_from_spec(self, specifier, comes_from, *args, **kwargs)
+
+    Factory.make_requirements_from_spec = patched_make_requirements_from_spec
+
+
+def _install_file_recording():
+    import atexit
+    from urllib.parse import urlsplit, urlunsplit
+    from piptools.repositories.pypi import PyPIRepository
+
+    path = os.environ["RYE_RECORD_FILES"]
+    files = {}
+    get_project = PyPIRepository._get_project
+    get_file_hash = PyPIRepository._get_file_hash
+
+    def _record(name, url, digest, upload_time=None):
+        parts = urlsplit(url)
+        netloc = parts.netloc.rpartition("@")[2]
+        url = urlunsplit(parts._replace(netloc=netloc, fragment=""))
+        files[digest] = {"name": name, "url": url, "upload-time": upload_time}
+
+    def patched_get_project(self, ireq):
+        rv = get_project(self, ireq)
+        for release_files in (rv or {}).get("releases", {}).values():
+            for file in release_files:
+                digest = file.get("digests", {}).get("sha256")
+                if digest:
+                    _record(
+                        file["filename"],
+                        file["url"],
+                        "sha256:" + digest,
+                        file.get("upload_time_iso_8601"),
+                    )
+        return rv
+
+    def patched_get_file_hash(self, link):
+        rv = get_file_hash(self, link)
+        _record(link.filename, link.url, rv)
+        return rv
+
+    def _dump():
+        # a lockfile resolved for several platforms is recorded in several runs
+        if os.path.isfile(path):
+            with open(path) as f:
+                files.update((k, v) for k, v in json.load(f).items() if k not in files)
+        with open(path, "w") as f:
+            json.dump(files, f)
+
+    PyPIRepository._get_project = patched_get_project
+    PyPIRepository._get_file_hash = patched_get_file_hash
+    atexit.register(_dump)
+
+
+def _install_resolution_narration():
+    import sys
+    from pip._internal.resolution.resolvelib import resolver
+    from pip._internal.resolution.resolvelib.reporter import PipReporter
+
+    def _describe(requirement, parent):
+        rv = requirement.format_for_error()
+        if parent is not None:
+            rv += " (required by %s %s)" % (parent.name, parent.version)
+        return rv
+
+    def _narrate(kind, message):
+        sys.stderr.write("  %-10s %s\n" % (kind, message))
+        sys.stderr.flush()
+
+    class NarratingReporter(PipReporter):
+        def adding_requirement(self, requirement, parent):
+            super().adding_requirement(requirement, parent)
+            _narrate("require", _describe(requirement, parent))
+
+        def pinning(self, candidate):
+            super().pinning(candidate)
+            _narrate("pin", "%s %s" % (candidate.name, candidate.version))
+
+        def rejecting_candidate(self, criterion, candidate):
+            super().rejecting_candidate(criterion, candidate)
+            _narrate(
+                "reject",
+                "%s %s, conflicts with %s"
+                % (
+                    candidate.name,
+                    candidate.version,
+                    ", ".join(_describe(*x) for x in criterion.information),
+                ),
+            )
+
+        def resolving_conflicts(self, causes):
+            super().resolving_conflicts(causes)
+            _narrate("backtrack", "to resolve %s" % ", ".join(_describe(*x) for x in causes))
+
+        def backtracking(self, candidate):
+            super().backtracking(candidate)
+            _narrate("backtrack", "undoing %s %s" % (candidate.name, candidate.version))
+
+    # the resolver looks up its reporter by name, which fails loudly if it
+    # no longer does
+    if resolver.PipReporter is not PipReporter:
+        raise RuntimeError("the resolver of pip does not use PipReporter")
+    resolver.PipReporter = NarratingReporter
+
+
+def _install_source_restrictions():
+    from fnmatch import fnmatchcase
+    from urllib.parse import urlsplit, urlunsplit
+    from pip._vendor.packaging.utils import canonicalize_name
+    from pip._internal.index.package_finder import PackageFinder
+
+    sources = json.loads(os.environ["RYE_SOURCE_RESTRICTIONS"])
+    find_all_candidates = PackageFinder.find_all_candidates
+
+    def _strip_auth(url):
+        parts = urlsplit(url)
+        return urlunsplit(parts._replace(netloc=parts.netloc.rpartition("@")[2]))
+
+    def _source_of(candidate):
+        comes_from = candidate.link.comes_from
+        url = _strip_auth(getattr(comes_from, "url", comes_from) or candidate.link.url)
+        for source in sources:
+            if url.startswith(source["url"]):
+                return source
+
+    def patched_find_all_candidates(self, project_name):
+        candidates = find_all_candidates(self, project_name)
+        name = canonicalize_name(project_name)
+        pinned = [
+            source
+            for source in sources
+            if any(fnmatchcase(name, pattern) for pattern in source["packages"])
+        ]
+        allowed = pinned or [source for source in sources if not source["packages"]]
+        # files of unknown origin are only dropped for pinned packages
+        rv = []
+        for candidate in candidates:
+            source = _source_of(candidate)
+            if source is None and not pinned:
+                rv.append((candidate, None))
+            elif source is not None and any(source is x for x in allowed):
+                rv.append((candidate, source["priority"]))
+        top = max((priority for _, priority in rv if priority is not None), default=None)
+        return [candidate for candidate, priority in rv if priority in (None, top)]
+
+    PackageFinder.find_all_candidates = patched_find_all_candidates
+
+
+def _install_build_cache():
+    import hashlib
+    import shutil
+    from pip._vendor.packaging.tags import sys_tags
+    from pip._internal import wheel_builder
+
+    cache_dir = os.environ["RYE_BUILD_CACHE"]
+    tag = str(next(iter(sys_tags())))
+    build_one = wheel_builder._build_one
+
+    def _sdist_hash(req):
+        path = req.local_file_path
+        if req.editable or not path or not os.path.isfile(path):
+            return None
+        h = hashlib.sha256()
+        with open(path, "rb") as f:
+            for chunk in iter(lambda: f.read(1 << 20), b""):
+                h.update(chunk)
+        return h.hexdigest()
+
+    def patched_build_one(req, output_dir, *args, **kwargs):
+        digest = _sdist_hash(req)
+        if digest is None:
+            return build_one(req, output_dir, *args, **kwargs)
+        entry = os.path.join(cache_dir, digest[:2], digest, tag)
+        if os.path.isdir(entry):
+            for name in os.listdir(entry):
+                if name.endswith(".whl"):
+                    os.makedirs(output_dir, exist_ok=True)
+                    rv = os.path.join(output_dir, name)
+                    shutil.copyfile(os.path.join(entry, name), rv)
+                    return rv
+        rv = build_one(req, output_dir, *args, **kwargs)
+        if rv:
+            os.makedirs(entry, exist_ok=True)
+            tmp = os.path.join(entry, ".tmp-%d" % os.getpid())
+            shutil.copyfile(rv, tmp)
+            os.replace(tmp, os.path.join(entry, os.path.basename(rv)))
+        return rv
+
+    wheel_builder._build_one = patched_build_one
+
+
+def _install_build_settings():
+    from pip._vendor.packaging.utils import canonicalize_name
+    from pip._internal.distributions.sdist import SourceDistribution
+
+    settings = json.loads(os.environ["RYE_BUILD_SETTINGS"])
+    no_isolation = set(settings["no_isolation"])
+    extra = settings["extra"]
+    prepare_distribution_metadata = SourceDistribution.prepare_distribution_metadata
+    prepare_build_backend = SourceDistribution._prepare_build_backend
+
+    def _name(dist):
+        return canonicalize_name(dist.req.name or "")
+
+    def patched_prepare_distribution_metadata(self, finder, build_isolation, *args, **kwargs):
+        if not settings["locking"] and _name(self) in no_isolation:
+            build_isolation = False
+        return prepare_distribution_metadata(self, finder, build_isolation, *args, **kwargs)
+
+    def patched_prepare_build_backend(self, finder):
+        prepare_build_backend(self, finder)
+        requirements = extra.get(_name(self))
+        if requirements:
+            self.req.build_env.install_requirements(
+                finder, requirements, "normal", kind="extra build dependencies"
+            )
+
+    SourceDistribution.prepare_distribution_metadata = patched_prepare_distribution_metadata
+    SourceDistribution._prepare_build_backend = patched_prepare_build_backend
+
 
+def _install_keep_installed():
+    from pip._vendor.packaging.utils import canonicalize_name
+    from piptools import sync
+
+    keep = set(json.loads(os.environ["RYE_KEEP_INSTALLED"]))
+    diff = sync.diff
+
+    def patched_diff(*args, **kwargs):
+        to_install, to_uninstall = diff(*args, **kwargs)
+        return to_install, {x for x in to_uninstall if canonicalize_name(x) not in keep}
+
+    sync.diff = patched_diff
+
+
+def _install_target_platform():
+    from pip._vendor.packaging import markers
+    from pip._internal.models.target_python import TargetPython
+
+    target = json.loads(os.environ["RYE_TARGET_PLATFORM"])
+    default_environment = markers.default_environment
+    init = TargetPython.__init__
+
+    def patched_default_environment():
+        rv = default_environment()
+        rv.update(target["markers"])
+        return rv
+
+    def patched_init(self, platforms=None, py_version_info=None, *args, **kwargs):
+        if platforms is None:
+            platforms = target.get("platforms")
+        if py_version_info is None and target.get("python_version"):
+            py_version_info = tuple(target["python_version"])
+        init(self, platforms, py_version_info, *args, **kwargs)
+
+    markers.default_environment = patched_default_environment
+    TargetPython.__init__ = patched_init
+
+
+def _install():
+    import sys
+    import importlib.util
+
+    # build backends that run without isolation inherit the hook
+    if importlib.util.find_spec("pip") is None:
+        return
+    # any failure in here fails pip rather than resolving without the settings
+    try:
+        import pip
+
+        if pip.__version__ != _PIP_VERSION:
+            raise RuntimeError(
+                "the hooks require pip %s, but pip %s is installed"
+                % (_PIP_VERSION, pip.__version__)
+            )
+        # pip-tools is not importable in the pip processes that pip-sync starts
+        if os.environ.get("RYE_RECORD_FILES") and importlib.util.find_spec("piptools"):
+            _install_file_recording()
+        if os.environ.get("RYE_KEEP_INSTALLED") and importlib.util.find_spec("piptools"):
+            _install_keep_installed()
+        if os.environ.get("RYE_EXCLUDE_NEWER"):
+            _install_exclude_newer()
+        if os.environ.get("RYE_OVERRIDES"):
+            _install_overrides()
+        if os.environ.get("RYE_EXCLUSIONS"):
+            _install_exclusions()
+        if os.environ.get("RYE_RESOLUTION_NARRATION"):
+            _install_resolution_narration()
+        if os.environ.get("RYE_SOURCE_RESTRICTIONS"):
+            _install_source_restrictions()
+        if os.environ.get("RYE_BUILD_CACHE"):
+            _install_build_cache()
+        if os.environ.get("RYE_BUILD_SETTINGS"):
+            _install_build_settings()
+        if os.environ.get("RYE_TARGET_PLATFORM"):
+            _install_target_platform()
+    except Exception as e:
+        sys.stderr.write("error: rye could not patch pip: %s: %s\n" % (type(e).__name__, e))
+        sys.stderr.flush()
+        os._exit(1)
+
+
+_install()
 "#;
 
+/// Keeps pip-sync from uninstalling the given packages.
+///
+/// `site_dir` has to be on the `PYTHONPATH` of the command.
+pub fn keep_installed(cmd: &mut Command, names: &[String], site_dir: &Path) -> Result<(), Error> {
+    if !names.is_empty() {
+        fs::write(site_dir.join("sitecustomize.py"), PIP_COMPILE_SITECUSTOMIZE)?;
+        cmd.env("RYE_KEEP_INSTALLED", serde_json::to_string(names)?);
+    }
+    Ok(())
+}
+
+/// Passes the priorities and pinned packages of the sources to pip.
+///
+/// This only has an effect if the sitecustomize hook is on the `PYTHONPATH`
+/// of the command, see [`install_pip_hooks`].
+fn restrict_sources(cmd: &mut Command, sources: &ExpandedSources) -> Result<(), Error> {
+    if let Some(restrictions) = sources.restrictions() {
+        cmd.env(
+            "RYE_SOURCE_RESTRICTIONS",
+            serde_json::to_string(restrictions)?,
+        );
+    }
+    Ok(())
+}
+
+/// Serializes the build settings for the sitecustomize hook.
+fn build_settings_env(settings: &BuildSettings, locking: bool) -> Result<String, Error> {
+    let mut value = serde_json::to_value(settings)?;
+    value["locking"] = locking.into();
+    Ok(serde_json::to_string(&value)?)
+}
+
+/// Does pip need the sitecustomize hook when installing packages?
+pub fn needs_pip_hooks(sources: &ExpandedSources, build_settings: &BuildSettings) -> bool {
+    sources.restrictions().is_some()
+        || Config::current().build_cache()
+        || !build_settings.is_empty()
+}
+
+/// Makes pip honor the priorities and pinned packages of the sources, use
+/// the build cache and apply the build settings.
+///
+/// The hook is installed into `site_dir` which has to be on the
+/// `PYTHONPATH` of the command.
+pub fn install_pip_hooks(
+    cmd: &mut Command,
+    sources: &ExpandedSources,
+    build_settings: &BuildSettings,
+    site_dir: &Path,
+) -> Result<(), Error> {
+    if !needs_pip_hooks(sources, build_settings) {
+        return Ok(());
+    }
+    fs::write(site_dir.join("sitecustomize.py"), PIP_COMPILE_SITECUSTOMIZE)?;
+    restrict_sources(cmd, sources)?;
+    if !build_settings.is_empty() {
+        cmd.env(
+            "RYE_BUILD_SETTINGS",
+            build_settings_env(build_settings, false)?,
+        );
+    }
+    if Config::current().build_cache() {
+        cmd.env("RYE_BUILD_CACHE", get_build_cache_dir());
+    }
+    Ok(())
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LockMode {
     Production,
@@ -52,6 +582,27 @@ impl fmt::Display for LockMode {
     }
 }
 
+/// Raised when pip-compile fails.
+///
+/// Carries the output of pip-compile so that callers can explain why
+/// resolution failed.  The log is empty if the output was not captured.
+#[derive(Debug)]
+pub struct LockFailed {
+    pub log: String,
+}
+
+impl std::error::Error for LockFailed {}
+
+impl fmt::Display for LockFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.log.is_empty() {
+            write!(f, "failed to generate lockfile")
+        } else {
+            write!(f, "failed to generate lockfile\n{}", self.log.trim_end())
+        }
+    }
+}
+
 /// Controls how locking should work.
 #[derive(Debug, Clone, Default, Serialize)]
 pub struct LockOptions {
@@ -59,12 +610,513 @@ pub struct LockOptions {
     pub update_all: bool,
     /// Update specific packages.
     pub update: Vec<String>,
+    /// Constraints on top of the ones of the project.
+    pub constraints: Vec<String>,
     /// Pick pre-release versions.
     pub pre: bool,
     /// A list of features (extras) to enable when locking
     pub features: Vec<String>,
     /// Enable all features in the workspace.
     pub all_features: bool,
+    /// Record hashes of the locked packages.
+    pub generate_hashes: bool,
+    /// Resolve for these platforms instead of the current one.
+    pub platforms: Vec<String>,
+    /// Resolve for this Python version instead of the one of the toolchain.
+    pub python_version: Option<String>,
+    /// The platform of a universal lockfile that is being resolved.
+    #[serde(skip)]
+    pub target: Option<LockPlatform>,
+    /// Records the files of the locked packages in this folder.
+    #[serde(skip)]
+    pub record_files: Option<PathBuf>,
+    /// Ignore files uploaded after this timestamp.
+    pub exclude_newer: Option<String>,
+    /// Report the decisions of the resolver.
+    pub verbose_resolution: bool,
+    /// Packages that are always built from source (`:all:` for all).
+    pub no_binary: Vec<String>,
+    /// Packages that are only installed from wheels (`:all:` for all).
+    pub only_binary: Vec<String>,
+    /// How packages are built from sdists.
+    #[serde(skip)]
+    pub build_settings: BuildSettings,
+}
+
+impl LockOptions {
+    /// Adds the binary and build settings of a project to the ones from the
+    /// command line.
+    pub fn add_project_settings(&mut self, pyproject: &PyProject) -> Result<(), Error> {
+        for (packages, configured) in [
+            (&mut self.no_binary, pyproject.no_binary()),
+            (&mut self.only_binary, pyproject.only_binary()),
+        ] {
+            packages.extend(configured);
+            packages.sort();
+            packages.dedup();
+        }
+        self.build_settings = pyproject.build_settings()?;
+        Ok(())
+    }
+
+    /// Returns the format control options of pip for the lockfile.
+    ///
+    /// pip-compile records these options in the lockfile and sync passes
+    /// them on to pip.
+    fn binary_options(&self) -> Result<String, Error> {
+        let normalize = |x: &String| match x.as_str() {
+            ":all:" | ":none:" => x.clone(),
+            name => normalize_package_name(name),
+        };
+        let no_binary = self.no_binary.iter().map(normalize).collect::<Vec<_>>();
+        let only_binary = self.only_binary.iter().map(normalize).collect::<Vec<_>>();
+        if let Some(name) = no_binary.iter().find(|x| only_binary.contains(x)) {
+            bail!("'{}' cannot be both in no-binary and only-binary", name);
+        }
+        let mut rv = String::new();
+        for name in no_binary {
+            rv.push_str(&format!("--no-binary {}\n", name));
+        }
+        for name in only_binary {
+            rv.push_str(&format!("--only-binary {}\n", name));
+        }
+        Ok(rv)
+    }
+}
+
+impl LockOptions {
+    /// Returns the platforms to resolve for, in their canonical order.
+    ///
+    /// This is empty if the lockfile is only resolved for the current platform.
+    pub fn lock_platforms(&self) -> Result<Vec<LockPlatform>, Error> {
+        let mut rv = self
+            .platforms
+            .iter()
+            .map(|x| LockPlatform::find(x))
+            .collect::<Result<Vec<_>, _>>()?;
+        rv.sort_by_key(|x| LOCK_PLATFORMS.iter().position(|y| y == x));
+        rv.dedup();
+        Ok(rv)
+    }
+
+    /// Replaces the platforms with their canonical names.
+    ///
+    /// The names are recorded in the lockfile header, where sync looks up the
+    /// current platform by its canonical name.
+    pub fn canonicalize_platforms(&mut self) -> Result<(), Error> {
+        self.platforms = self
+            .lock_platforms()?
+            .iter()
+            .map(|x| x.name.to_string())
+            .collect();
+        Ok(())
+    }
+
+    /// Returns the Python version to resolve for if it is not the one of
+    /// the toolchain.
+    fn target_python(&self, py_ver: &PythonVersion) -> Result<Option<PythonVersion>, Error> {
+        let version = match self.python_version {
+            Some(ref version) => version,
+            None => return Ok(None),
+        };
+        let req = version
+            .parse::<PythonVersionRequest>()
+            .ok()
+            .filter(|x| x.kind.is_none() && x.suffix.is_none())
+            .and_then(|x| x.minor.map(|minor| (x.major, minor, x.patch)))
+            .ok_or_else(|| {
+                anyhow!(
+                    "invalid python version '{}', expected a version like 3.11",
+                    version
+                )
+            })?;
+        Ok(Some(PythonVersion {
+            kind: py_ver.kind.clone(),
+            major: req.0,
+            minor: req.1,
+            patch: req.2.unwrap_or(0),
+            suffix: None,
+        }))
+    }
+}
+
+/// A platform that lockfiles can be resolved for.
+///
+/// Platforms are keyed by operating system and architecture, their markers
+/// and wheel tags are the ones pip sees when resolving on such a machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockPlatform {
+    /// The name of the platform for `--platform` and `tool.rye.lock-platforms`.
+    pub name: &'static str,
+    /// The operating system and architecture as Rust names them.
+    os: &'static str,
+    arch: &'static str,
+    sys_platform: &'static str,
+    platform_system: &'static str,
+    os_name: &'static str,
+    platform_machine: &'static str,
+}
+
+static LOCK_PLATFORMS: &[LockPlatform] = &[
+    LockPlatform {
+        name: "linux-x86_64",
+        os: "linux",
+        arch: "x86_64",
+        sys_platform: "linux",
+        platform_system: "Linux",
+        os_name: "posix",
+        platform_machine: "x86_64",
+    },
+    LockPlatform {
+        name: "linux-aarch64",
+        os: "linux",
+        arch: "aarch64",
+        sys_platform: "linux",
+        platform_system: "Linux",
+        os_name: "posix",
+        platform_machine: "aarch64",
+    },
+    LockPlatform {
+        name: "macos-arm64",
+        os: "macos",
+        arch: "aarch64",
+        sys_platform: "darwin",
+        platform_system: "Darwin",
+        os_name: "posix",
+        platform_machine: "arm64",
+    },
+    LockPlatform {
+        name: "macos-x86_64",
+        os: "macos",
+        arch: "x86_64",
+        sys_platform: "darwin",
+        platform_system: "Darwin",
+        os_name: "posix",
+        platform_machine: "x86_64",
+    },
+    LockPlatform {
+        name: "windows-x86_64",
+        os: "windows",
+        arch: "x86_64",
+        sys_platform: "win32",
+        platform_system: "Windows",
+        os_name: "nt",
+        platform_machine: "AMD64",
+    },
+];
+
+impl LockPlatform {
+    /// Returns the names of all platforms, which is what `--universal` locks for.
+    pub fn all_names() -> Vec<String> {
+        LOCK_PLATFORMS.iter().map(|x| x.name.to_string()).collect()
+    }
+
+    /// Looks up a platform by name.
+    ///
+    /// The operating system and the architecture can also be spelled the way
+    /// Python reports them, such as `darwin-arm64` or `win32-amd64`.
+    pub fn find(name: &str) -> Result<LockPlatform, Error> {
+        let name = name.trim().to_ascii_lowercase();
+        let (os, arch) = name.split_once('-').unwrap_or((&name, ""));
+        let os = match os {
+            "darwin" | "macosx" | "osx" => "macos",
+            "win32" | "win" => "windows",
+            os => os,
+        };
+        let arch = match arch {
+            "amd64" | "x64" => "x86_64",
+            "arm64" => "aarch64",
+            arch => arch,
+        };
+        LOCK_PLATFORMS
+            .iter()
+            .find(|x| x.os == os && x.arch == arch)
+            .copied()
+            .ok_or_else(|| {
+                anyhow!(
+                    "unknown platform '{}'. Expected one of {}",
+                    name,
+                    LockPlatform::all_names().join(", ")
+                )
+            })
+    }
+
+    /// Returns the platform rye runs on.
+    ///
+    /// This fails on machines that lockfiles cannot be resolved for.
+    pub fn current() -> Result<LockPlatform, Error> {
+        let (os, arch) = (std::env::consts::OS, std::env::consts::ARCH);
+        LOCK_PLATFORMS
+            .iter()
+            .find(|x| x.os == os && x.arch == arch)
+            .copied()
+            .ok_or_else(|| {
+                anyhow!(
+                    "universal lockfiles are not supported on {}-{}. Supported are {}",
+                    os,
+                    arch,
+                    LockPlatform::all_names().join(", ")
+                )
+            })
+    }
+
+    /// Returns the platform tags of the wheels that can be installed.
+    fn wheel_platforms(&self) -> Vec<String> {
+        match (self.os, self.arch) {
+            ("linux", arch) => (17..=39)
+                .rev()
+                .map(|minor| format!("manylinux_2_{}_{}", minor, arch))
+                .chain(
+                    match arch {
+                        "x86_64" => &["manylinux2014", "manylinux2010", "manylinux1", "linux"][..],
+                        _ => &["manylinux2014", "linux"][..],
+                    }
+                    .iter()
+                    .map(|x| format!("{}_{}", x, arch)),
+                )
+                .collect(),
+            // pip expands these to all older macOS versions and universal2
+            ("macos", "aarch64") => vec!["macosx_14_0_arm64".into()],
+            ("macos", _) => vec!["macosx_14_0_x86_64".into()],
+            _ => vec!["win_amd64".into()],
+        }
+    }
+
+    /// Returns the environment that markers are evaluated against on this
+    /// platform with the given Python version.
+    ///
+    /// Markers on the release and version of the operating system cannot be
+    /// known ahead of time, they see empty values.
+    fn marker_environment(&self, py_ver: &PythonVersion) -> Result<MarkerEnvironment, Error> {
+        let version = |value: String| {
+            value
+                .parse::<StringVersion>()
+                .map_err(|err| anyhow!("invalid python version '{}': {}", value, err))
+        };
+        let full_version = format!("{}.{}.{}", py_ver.major, py_ver.minor, py_ver.patch);
+        Ok(MarkerEnvironment {
+            implementation_name: py_ver.kind.to_string(),
+            implementation_version: version(full_version.clone())?,
+            os_name: self.os_name.into(),
+            platform_machine: self.platform_machine.into(),
+            platform_python_implementation: match &*py_ver.kind {
+                "pypy" => "PyPy".into(),
+                _ => "CPython".into(),
+            },
+            platform_release: String::new(),
+            platform_system: self.platform_system.into(),
+            platform_version: String::new(),
+            python_full_version: version(full_version)?,
+            python_version: version(format!("{}.{}", py_ver.major, py_ver.minor))?,
+            sys_platform: self.sys_platform.into(),
+        })
+    }
+}
+
+/// Serializes the target of a resolution for the sitecustomize hook.
+fn target_hook_env(
+    platform: Option<&LockPlatform>,
+    python: Option<&PythonVersion>,
+) -> Result<String, Error> {
+    let mut markers = serde_json::Map::new();
+    let mut target = serde_json::Map::new();
+    if let Some(platform) = platform {
+        markers.insert("sys_platform".into(), platform.sys_platform.into());
+        markers.insert("platform_system".into(), platform.platform_system.into());
+        markers.insert("os_name".into(), platform.os_name.into());
+        markers.insert("platform_machine".into(), platform.platform_machine.into());
+        target.insert("platforms".into(), platform.wheel_platforms().into());
+    }
+    if let Some(python) = python {
+        markers.insert(
+            "python_version".into(),
+            format!("{}.{}", python.major, python.minor).into(),
+        );
+        markers.insert(
+            "python_full_version".into(),
+            format!("{}.{}.{}", python.major, python.minor, python.patch).into(),
+        );
+        target.insert(
+            "python_version".into(),
+            vec![python.major, python.minor, python.patch].into(),
+        );
+    }
+    target.insert("markers".into(), markers.into());
+    Ok(serde_json::to_string(&target)?)
+}
+
+/// Parses the argument of `--exclude-newer` into an RFC 3339 timestamp.
+///
+/// Dates are interpreted as midnight UTC and timestamps without an offset
+/// are assumed to be in UTC as well.
+pub fn parse_exclude_newer(value: &str) -> Result<String, Error> {
+    let m = EXCLUDE_NEWER_RE.captures(value.trim()).ok_or_else(|| {
+        anyhow!(
+            "invalid exclude-newer value '{}'. Expected a date (2024-01-15) or a \
+             timestamp (2024-01-15T10:00:00Z)",
+            value
+        )
+    })?;
+    let time = m.get(2).map_or("00:00:00", |x| x.as_str());
+    let seconds = if time.len() == 5 { ":00" } else { "" };
+    let offset = match m.get(3).map(|x| x.as_str()) {
+        None | Some("Z") => "+00:00",
+        Some(offset) => offset,
+    };
+    Ok(format!("{}T{}{}{}", &m[1], time, seconds, offset))
+}
+
+/// Returns the filename of the lockfile of a dependency group.
+///
+/// The lockfile is named after the normalized name of the group.  A group
+/// named `dev` cannot have a lockfile of its own, as its name would be the
+/// one of the dev lockfile.
+pub fn group_lockfile_name(group: &str) -> Result<String, Error> {
+    let group = normalize_package_name(group);
+    if group == "dev" {
+        bail!(
+            "the group '{}' cannot have a lockfile of its own, as requirements-dev.lock \
+             is the dev lockfile",
+            group
+        );
+    }
+    Ok(format!("requirements-{}.lock", group))
+}
+
+/// Returns the filename of a lockfile for an environment.
+///
+/// Environments with a Python version of their own are resolved separately,
+/// so the name of the environment is inserted ahead of the extension (for
+/// instance `requirements.py38.lock`).  Other environments share the
+/// lockfiles of the project.
+pub fn env_lockfile_name(filename: &str, env: Option<&EnvConfig>) -> String {
+    match env {
+        Some(env) if env.python.is_some() => match filename.rsplit_once('.') {
+            Some((stem, ext)) => format!("{}.{}.{}", stem, env.name, ext),
+            None => format!("{}.{}", filename, env.name),
+        },
+        _ => filename.to_string(),
+    }
+}
+
+/// Returns the filename of the requirements lockfile for a lock mode.
+pub fn requirements_lockfile_name(lock_mode: LockMode) -> &'static str {
+    match lock_mode {
+        LockMode::Production => "requirements.lock",
+        LockMode::Dev => "requirements-dev.lock",
+    }
+}
+
+/// Returns the path of a lockfile of a project.
+///
+/// The lockfiles of a throwaway environment are kept in its folder, all
+/// others next to the workspace (see [`env_lockfile_name`]).
+pub fn lockfile_path(pyproject: &PyProject, filename: &str) -> PathBuf {
+    let env = pyproject.env();
+    match env.and_then(|x| x.location.as_ref()) {
+        Some(location) => location.join(filename),
+        None => pyproject
+            .workspace_path()
+            .join(env_lockfile_name(filename, env)),
+    }
+}
+
+/// Returns the path of the authoritative lockfile for a lock mode.
+///
+/// This is the `pylock.toml` file if the project is configured with
+/// `lock-format = "pylock"` and the requirements lockfile otherwise.
+pub fn authoritative_lockfile_path(
+    pyproject: &PyProject,
+    lock_mode: LockMode,
+) -> Result<PathBuf, Error> {
+    Ok(match pyproject.lock_format()? {
+        LockFormat::Requirements => lockfile_path(pyproject, requirements_lockfile_name(lock_mode)),
+        LockFormat::Pylock => lockfile_path(pyproject, pylock_filename(lock_mode)),
+    })
+}
+
+/// Reads the pins of the authoritative lockfile as requirements.
+///
+/// Returns `None` if the lockfile does not exist.
+pub fn read_locked_requirements(
+    pyproject: &PyProject,
+    lock_mode: LockMode,
+) -> Result<Option<String>, Error> {
+    let path = authoritative_lockfile_path(pyproject, lock_mode)?;
+    if !path.is_file() {
+        return Ok(None);
+    }
+    match pyproject.lock_format()? {
+        LockFormat::Requirements => fs::read_to_string(&path)
+            .with_context(|| format!("failed to read lockfile {}", path.display()))
+            .map(Some),
+        LockFormat::Pylock => pylock_to_requirements(&path, &pyproject.workspace_path()).map(Some),
+    }
+}
+
+/// Returns the names of all groups that can be given a lockfile of their own.
+///
+/// These are the dependency groups and optional dependencies of all projects
+/// in the workspace, normalized and sorted.
+pub fn available_lock_groups(pyproject: &PyProject) -> Result<Vec<String>, Error> {
+    fn project_groups(project: &PyProject) -> impl Iterator<Item = String> + '_ {
+        project
+            .dependency_groups()
+            .into_iter()
+            .chain(project.extras())
+            .map(normalize_package_name)
+    }
+
+    let mut rv = Vec::new();
+    match pyproject.workspace() {
+        Some(workspace) => {
+            for project in workspace.iter_projects() {
+                rv.extend(project_groups(&project?));
+            }
+        }
+        None => rv.extend(project_groups(pyproject)),
+    }
+    rv.sort();
+    rv.dedup();
+    Ok(rv)
+}
+
+/// Warns about members that refer to other members by path or URL.
+///
+/// Members are always installed from the workspace when they are referenced
+/// by name, and the name (with a version) is what ends up in the published
+/// metadata.  A path on the other hand only works on the machine it points to.
+fn warn_about_member_urls(projects: &[PyProject], output: CommandOutput) -> Result<(), Error> {
+    let members = projects
+        .iter()
+        .map(|x| x.normalized_name())
+        .collect::<Result<HashSet<_>, _>>()?;
+    for project in projects {
+        for dep_kind in [DependencyKind::Normal, DependencyKind::Dev] {
+            for dep in project.iter_dependencies(dep_kind) {
+                let req = match dep.expand(|_| Some("VARIABLE".into())) {
+                    Ok(req) => req,
+                    Err(_) => continue,
+                };
+                if let (Some(VersionOrUrl::Url(_)), true) = (
+                    &req.version_or_url,
+                    members.contains(&normalize_package_name(&req.name)),
+                ) {
+                    warn_with_output(
+                        Warning::MemberUrlDependency,
+                        format_args!(
+                            "{} refers to the workspace member {} by URL. Depend on it by \
+                             name instead, it is installed from the workspace all the same.",
+                            project.normalized_name()?,
+                            req.name
+                        ),
+                        output,
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
 }
 
 /// Creates lockfiles for all projects in the workspace.
@@ -80,15 +1132,109 @@ pub fn update_workspace_lockfile(
     if output != CommandOutput::Quiet {
         eprintln!("Generating {} lockfile: {}", lock_mode, lockfile.display());
     }
+    let projects = workspace.iter_projects().collect::<Result<Vec<_>, _>>()?;
+    if lock_mode == LockMode::Production {
+        warn_about_member_urls(&projects, output)?;
+    }
+
+    // members share the lockfile of the workspace.  Lockfiles of their own
+    // are leftovers that would let their versions drift apart.
+    for project in &projects {
+        if lock_mode != LockMode::Production || project.is_workspace_root() {
+            continue;
+        }
+        for name in ["requirements.lock", "requirements-dev.lock"] {
+            let member_lockfile = project.root_path().join(name);
+            if member_lockfile.is_file() {
+                warn_with_output(
+                    Warning::IgnoredMemberLockfile,
+                    format_args!(
+                        "{} is ignored as the members of a workspace share {}",
+                        member_lockfile.display(),
+                        workspace.path().join(name).display()
+                    ),
+                    output,
+                );
+            }
+        }
+    }
+
+    lock_workspace_projects(
+        py_ver,
+        workspace,
+        projects,
+        workspace.constraints(),
+        lock_mode,
+        lockfile,
+        output,
+        sources,
+        lock_options,
+    )
+}
+
+/// Creates a lockfile for some members of the workspace.
+///
+/// These are the members and the members they depend on.  All packages are
+/// pinned to the versions of the lockfile of the whole workspace, so the
+/// result is a subset of it.
+#[allow(clippy::too_many_arguments)]
+pub fn update_members_lockfile(
+    py_ver: &PythonVersion,
+    workspace: &Arc<Workspace>,
+    members: &[String],
+    lock_mode: LockMode,
+    lockfile: &Path,
+    workspace_lockfile: &Path,
+    output: CommandOutput,
+    sources: &ExpandedSources,
+    lock_options: &LockOptions,
+) -> Result<(), Error> {
+    if output != CommandOutput::Quiet {
+        eprintln!(
+            "Generating {} lockfile for {}",
+            lock_mode,
+            members.join(", ")
+        );
+    }
+    let projects = workspace.projects_in_order(Some(members))?;
+    let mut constraints = workspace.constraints();
+    constraints.extend(lockfile_pins(workspace_lockfile));
+    lock_workspace_projects(
+        py_ver,
+        workspace,
+        projects,
+        constraints,
+        lock_mode,
+        lockfile,
+        output,
+        sources,
+        lock_options,
+    )
+}
 
+#[allow(clippy::too_many_arguments)]
+fn lock_workspace_projects(
+    py_ver: &PythonVersion,
+    workspace: &Arc<Workspace>,
+    workspace_projects: Vec<PyProject>,
+    mut constraints: Vec<String>,
+    lock_mode: LockMode,
+    lockfile: &Path,
+    output: CommandOutput,
+    sources: &ExpandedSources,
+    lock_options: &LockOptions,
+) -> Result<(), Error> {
     let features_by_project = collect_workspace_features(lock_options);
     let mut req_file = NamedTempFile::new()?;
     let mut local_req_file = NamedTempFile::new()?;
+    let overrides = workspace.overrides();
+    let override_map = make_override_map(&overrides)?;
+    constraints.extend(lock_options.constraints.iter().cloned());
+    let _constraints_file = write_constraints(&constraints, req_file.as_file_mut())?;
 
     let mut local_projects = HashMap::new();
     let mut projects = Vec::new();
-    for pyproject_result in workspace.iter_projects() {
-        let pyproject = pyproject_result?;
+    for pyproject in workspace_projects {
         let rel_url = make_relative_url(&pyproject.root_path(), &workspace.path())?;
         let applicable_extras = format_project_extras(features_by_project.as_ref(), &pyproject)?;
         writeln!(local_req_file, "-e {}{}", rel_url, applicable_extras)?;
@@ -96,26 +1242,32 @@ pub fn update_workspace_lockfile(
         projects.push(pyproject);
     }
 
+    let lock_groups = workspace.lock_groups();
     for pyproject in &projects {
         dump_dependencies(
             pyproject,
             &local_projects,
+            &override_map,
             req_file.as_file_mut(),
             DependencyKind::Normal,
         )?;
         if lock_mode == LockMode::Dev {
-            dump_dependencies(
-                pyproject,
-                &local_projects,
-                req_file.as_file_mut(),
-                DependencyKind::Dev,
-            )?;
-            dump_dependencies(
-                pyproject,
-                &local_projects,
-                local_req_file.as_file_mut(),
-                DependencyKind::Dev,
-            )?;
+            for dep_kind in dev_dependency_kinds(pyproject, &lock_groups) {
+                dump_dependencies(
+                    pyproject,
+                    &local_projects,
+                    &override_map,
+                    req_file.as_file_mut(),
+                    dep_kind.clone(),
+                )?;
+                dump_dependencies(
+                    pyproject,
+                    &local_projects,
+                    &override_map,
+                    local_req_file.as_file_mut(),
+                    dep_kind,
+                )?;
+            }
         }
     }
 
@@ -124,28 +1276,51 @@ pub fn update_workspace_lockfile(
         output,
         py_ver,
         &workspace.path(),
+        &workspace.venv_path(),
         req_file.path(),
         lockfile,
         sources,
         lock_options,
         &exclusions,
+        &overrides,
         &[],
     )?;
     generate_lockfile(
         output,
         py_ver,
         &workspace.path(),
+        &workspace.venv_path(),
         local_req_file.path(),
         lockfile,
         sources,
         lock_options,
         &exclusions,
+        &[],
         &["--pip-args=--no-deps"],
     )?;
 
     Ok(())
 }
 
+/// Returns the version pins of a lockfile for use as constraints.
+///
+/// pip only accepts plain version pins as constraints, so URLs and editables
+/// are skipped.
+fn lockfile_pins(lockfile: &Path) -> Vec<String> {
+    match fs::read_to_string(lockfile) {
+        Ok(contents) => iter_lockfile_entries(&contents)
+            .map(|entry| strip_line_continuation(entry[0]))
+            .filter(|line| {
+                line.parse::<Requirement>().map_or(false, |req| {
+                    matches!(req.version_or_url, Some(VersionOrUrl::VersionSpecifier(_)))
+                })
+            })
+            .map(|line| line.to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
 fn format_project_extras<'a>(
     features_by_project: Option<&'a HashMap<String, HashSet<&str>>>,
     project: &PyProject,
@@ -221,6 +1396,7 @@ fn find_exclusions(projects: &[PyProject]) -> Result<HashSet<Requirement>, Error
 fn dump_dependencies(
     pyproject: &PyProject,
     local_projects: &HashMap<String, String>,
+    override_map: &HashMap<String, String>,
     out: &mut fs::File,
     dep_kind: DependencyKind,
 ) -> Result<(), Error> {
@@ -230,6 +1406,10 @@ fn dump_dependencies(
             // as the end result parses
             Some("VARIABLE".into())
         }) {
+            if let Some(over) = override_map.get(&normalize_package_name(&expanded_dep.name)) {
+                writeln!(out, "{}", over)?;
+                continue;
+            }
             if let Some(path) = local_projects.get(&normalize_package_name(&expanded_dep.name)) {
                 // if there are extras and we have a local dependency, we just write it
                 // out again for pip-compile to pick up the extras.
@@ -263,18 +1443,33 @@ pub fn update_single_project_lockfile(
     let features_by_project = collect_workspace_features(lock_options);
     let applicable_extras = format_project_extras(features_by_project.as_ref(), pyproject)?;
     let mut req_file = NamedTempFile::new()?;
+    let overrides = pyproject.overrides();
+    let override_map = make_override_map(&overrides)?;
+    let mut constraints = pyproject.constraints();
+    constraints.extend(lock_options.constraints.iter().cloned());
+    let _constraints_file = write_constraints(&constraints, req_file.as_file_mut())?;
     writeln!(
         req_file,
         "-e {}{}",
         make_relative_url(&pyproject.root_path(), &pyproject.workspace_path())?,
         applicable_extras
     )?;
-    for dep in pyproject.iter_dependencies(DependencyKind::Normal) {
-        writeln!(req_file, "{}", dep)?;
-    }
+    dump_dependencies(
+        pyproject,
+        &HashMap::new(),
+        &override_map,
+        req_file.as_file_mut(),
+        DependencyKind::Normal,
+    )?;
     if lock_mode == LockMode::Dev {
-        for dep in pyproject.iter_dependencies(DependencyKind::Dev) {
-            writeln!(req_file, "{}", dep)?;
+        for dep_kind in dev_dependency_kinds(pyproject, &pyproject.lock_groups()) {
+            dump_dependencies(
+                pyproject,
+                &HashMap::new(),
+                &override_map,
+                req_file.as_file_mut(),
+                dep_kind,
+            )?;
         }
     }
 
@@ -283,29 +1478,201 @@ pub fn update_single_project_lockfile(
         output,
         py_ver,
         &pyproject.workspace_path(),
+        &pyproject.venv_path(),
         req_file.path(),
         lockfile,
         sources,
         lock_options,
         &exclusions,
+        &overrides,
         &[],
     )?;
 
     Ok(())
 }
 
+/// Creates the lockfile of a single dependency group.
+///
+/// Both dependency groups and optional dependencies can be locked this way.
+/// The group is resolved with the pins of the production lockfile as
+/// constraints, so that both lockfiles can be installed together.
 #[allow(clippy::too_many_arguments)]
-fn generate_lockfile(
-    output: CommandOutput,
+pub fn update_group_lockfile(
     py_ver: &PythonVersion,
-    workspace_path: &Path,
+    pyproject: &PyProject,
+    group: &str,
+    lockfile: &Path,
+    production_lockfile: &Path,
+    output: CommandOutput,
+    sources: &ExpandedSources,
+    lock_options: &LockOptions,
+) -> Result<(), Error> {
+    if output != CommandOutput::Quiet {
+        eprintln!(
+            "Generating lockfile for group {}: {}",
+            group,
+            lockfile.display()
+        );
+    }
+
+    let workspace_path = pyproject.workspace_path();
+    let mut local_projects = HashMap::new();
+    let workspace_projects = match pyproject.workspace() {
+        Some(workspace) => workspace.iter_projects().collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+    for project in &workspace_projects {
+        local_projects.insert(
+            project.normalized_name()?,
+            make_relative_url(&project.root_path(), &workspace_path)?,
+        );
+    }
+    let projects = if pyproject.workspace().is_some() {
+        &workspace_projects[..]
+    } else {
+        std::slice::from_ref(pyproject)
+    };
+
+    let mut req_file = NamedTempFile::new()?;
+    let overrides = pyproject.overrides();
+    let override_map = make_override_map(&overrides)?;
+    let mut constraints = pyproject.constraints();
+    constraints.extend(lockfile_pins(production_lockfile));
+    constraints.extend(lock_options.constraints.iter().cloned());
+    let _constraints_file = write_constraints(&constraints, req_file.as_file_mut())?;
+
+    let mut found = false;
+    for project in projects {
+        let dep_kind = if project.has_dependency_group(group) {
+            DependencyKind::Group(group.into())
+        } else if project.extras().contains(group) {
+            DependencyKind::Optional(group.into())
+        } else {
+            continue;
+        };
+        dump_dependencies(
+            project,
+            &local_projects,
+            &override_map,
+            req_file.as_file_mut(),
+            dep_kind,
+        )?;
+        found = true;
+    }
+    if !found {
+        bail!("no project defines the dependency group '{}'", group);
+    }
+
+    let exclusions = find_exclusions(projects)?;
+    generate_lockfile(
+        output,
+        py_ver,
+        &workspace_path,
+        &pyproject.venv_path(),
+        req_file.path(),
+        lockfile,
+        sources,
+        lock_options,
+        &exclusions,
+        &overrides,
+        &[],
+    )?;
+
+    Ok(())
+}
+
+/// Returns the kinds of dependencies that go into the dev lockfile.
+///
+/// These are the dev dependencies and all dependency groups that do not
+/// have a lockfile of their own.
+fn dev_dependency_kinds<'a>(
+    pyproject: &'a PyProject,
+    lock_groups: &[String],
+) -> Vec<DependencyKind<'a>> {
+    let lock_groups = lock_groups
+        .iter()
+        .map(|x| normalize_package_name(x))
+        .collect::<HashSet<_>>();
+    Some(DependencyKind::Dev)
+        .into_iter()
+        .chain(
+            pyproject
+                .dependency_groups()
+                .into_iter()
+                .filter(|group| !lock_groups.contains(&normalize_package_name(group)))
+                .map(|group| DependencyKind::Group(group.into())),
+        )
+        .collect()
+}
+
+/// Maps the normalized names of overridden packages to their overrides.
+fn make_override_map(overrides: &[String]) -> Result<HashMap<String, String>, Error> {
+    overrides
+        .iter()
+        .map(|over| {
+            let req = over
+                .parse::<Requirement>()
+                .with_context(|| format!("invalid override '{}'", over))?;
+            Ok((normalize_package_name(&req.name), over.clone()))
+        })
+        .collect()
+}
+
+/// Writes the constraints into a file and references it from the requirements.
+///
+/// The returned file has to be kept alive until pip-compile ran.
+fn write_constraints(
+    constraints: &[String],
+    req_file: &mut fs::File,
+) -> Result<Option<NamedTempFile>, Error> {
+    if constraints.is_empty() {
+        return Ok(None);
+    }
+    // constraint files are resolved relative to the requirements file which
+    // sits in the same temporary directory.
+    let mut constraints_file = NamedTempFile::new()?;
+    for constraint in constraints {
+        writeln!(constraints_file, "{}", constraint)?;
+    }
+    let filename = constraints_file
+        .path()
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid constraints file"))?;
+    writeln!(req_file, "-c {}", filename.to_string_lossy())?;
+    Ok(Some(constraints_file))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn generate_lockfile(
+    output: CommandOutput,
+    py_ver: &PythonVersion,
+    workspace_path: &Path,
+    venv_path: &Path,
     requirements_file_in: &Path,
     lockfile: &Path,
     sources: &ExpandedSources,
     lock_options: &LockOptions,
     exclusions: &HashSet<Requirement>,
+    overrides: &[String],
     extra_args: &[&str],
 ) -> Result<(), Error> {
+    check_requirements(&fs::read_to_string(requirements_file_in)?, "a dependency")?;
+    let platforms = lock_options.lock_platforms()?;
+    if !platforms.is_empty() && lock_options.target.is_none() {
+        return generate_universal_lockfile(
+            output,
+            py_ver,
+            workspace_path,
+            requirements_file_in,
+            lockfile,
+            sources,
+            lock_options,
+            exclusions,
+            extra_args,
+            &platforms,
+        );
+    }
+
     let scratch = tempfile::tempdir()?;
     let requirements_file = scratch.path().join("requirements.txt");
     if lockfile.is_file() {
@@ -314,6 +1681,13 @@ fn generate_lockfile(
         fs::write(&requirements_file, b"")?;
     }
 
+    let _phase = logging::phase("lock", "resolving dependencies");
+    logging::debug("lock", format_args!("resolving {}", lockfile.display()));
+    if logging::enabled(Level::Trace) {
+        for line in fs::read_to_string(requirements_file_in)?.lines() {
+            logging::trace("lock", format_args!("  input {}", line));
+        }
+    }
     let pip_compile = get_pip_compile(py_ver, output)?;
     let mut cmd = Command::new(pip_compile);
     cmd.arg("--resolver=backtracking")
@@ -324,7 +1698,7 @@ fn generate_lockfile(
         .arg("--pip-args")
         .arg(format!(
             "--python=\"{}\"",
-            get_venv_python_bin(&workspace_path.join(".venv")).display()
+            get_venv_python_bin(venv_path).display()
         ))
         .arg("-o")
         .arg(&requirements_file)
@@ -347,13 +1721,112 @@ fn generate_lockfile(
     if lock_options.pre {
         cmd.arg("--pre");
     }
+    if lock_options.generate_hashes {
+        cmd.arg("--generate-hashes");
+    }
+    let binary_options = lock_options.binary_options()?;
+    if !binary_options.is_empty() {
+        let binary_options_file = scratch.path().join("binary-options.in");
+        fs::write(&binary_options_file, binary_options)?;
+        cmd.arg(&binary_options_file);
+    }
+    // packages built without isolation are built against the virtualenv, so
+    // their build dependencies are locked to be installed by sync.
+    let build_dependencies = lock_options.build_settings.unisolated_build_dependencies();
+    if !build_dependencies.is_empty() {
+        let build_dependencies_file = scratch.path().join("build-dependencies.in");
+        fs::write(
+            &build_dependencies_file,
+            build_dependencies.join("\n") + "\n",
+        )?;
+        cmd.arg(&build_dependencies_file);
+    }
+    // exclusions without a version are dropped during resolution, so that
+    // their dependencies are not pulled in either.
+    let excluded_names = exclusions
+        .iter()
+        .filter(|x| x.version_or_url.is_none())
+        .map(|x| normalize_package_name(&x.name))
+        .collect::<Vec<_>>();
+    let target_python = lock_options.target_python(py_ver)?;
+    if lock_options.target.is_some()
+        || target_python.is_some()
+        || lock_options.exclude_newer.is_some()
+        || lock_options.verbose_resolution
+        || !overrides.is_empty()
+        || !excluded_names.is_empty()
+        || lock_options.record_files.is_some()
+        || sources.restrictions().is_some()
+        || !lock_options.build_settings.is_empty()
+    {
+        let site_dir = scratch.path().join("site");
+        fs::create_dir_all(&site_dir)?;
+        fs::write(site_dir.join("sitecustomize.py"), PIP_COMPILE_SITECUSTOMIZE)?;
+        cmd.env("PYTHONPATH", &site_dir);
+    }
+    if lock_options.target.is_some() || target_python.is_some() {
+        cmd.env(
+            "RYE_TARGET_PLATFORM",
+            target_hook_env(lock_options.target.as_ref(), target_python.as_ref())?,
+        );
+    }
+    let missing_upload_times = scratch.path().join("missing-upload-times.json");
+    restrict_sources(&mut cmd, sources)?;
+    if let Some(ref exclude_newer) = lock_options.exclude_newer {
+        cmd.env("RYE_EXCLUDE_NEWER", exclude_newer)
+            .env("RYE_MISSING_UPLOAD_TIMES", &missing_upload_times);
+    }
+    if !overrides.is_empty() {
+        cmd.env("RYE_OVERRIDES", serde_json::to_string(overrides)?);
+    }
+    if !excluded_names.is_empty() {
+        cmd.env("RYE_EXCLUSIONS", serde_json::to_string(&excluded_names)?);
+    }
+    if let Some(ref record_files) = lock_options.record_files {
+        cmd.env(
+            "RYE_RECORD_FILES",
+            recorded_files_path(record_files, lockfile),
+        );
+    }
+    if lock_options.verbose_resolution {
+        cmd.env("RYE_RESOLUTION_NARRATION", "1");
+    }
+    if !lock_options.build_settings.is_empty() {
+        cmd.env(
+            "RYE_BUILD_SETTINGS",
+            build_settings_env(&lock_options.build_settings, true)?,
+        );
+    }
     sources.add_as_pip_args(&mut cmd);
     cmd.args(extra_args);
     set_proxy_variables(&mut cmd);
-    let status = cmd.status().context("unable to run pip-compile")?;
-    if !status.success() {
-        bail!("failed to generate lockfile");
-    };
+    if output == CommandOutput::Quiet {
+        // the output is captured so that it can be reported if resolution
+        // fails instead of being lost.
+        let rv = run_output(&mut cmd).context("unable to run pip-compile")?;
+        if !rv.status.success() {
+            let mut log = String::from_utf8_lossy(&rv.stdout).into_owned();
+            log.push_str(&String::from_utf8_lossy(&rv.stderr));
+            return Err(LockFailed { log }.into());
+        }
+    } else {
+        let status = run_status(&mut cmd).context("unable to run pip-compile")?;
+        if !status.success() {
+            return Err(LockFailed { log: String::new() }.into());
+        };
+    }
+    if let Ok(contents) = fs::read(&missing_upload_times) {
+        let projects: Vec<String> = serde_json::from_slice(&contents)?;
+        warn_with_output(
+            Warning::MissingUploadTimes,
+            format!(
+                "the index does not report upload times for {}, exclude-newer does not apply \
+                 to their files",
+                projects.join(", ")
+            ),
+            output,
+        );
+    }
 
     finalize_lockfile(
         &requirements_file,
@@ -366,6 +1839,70 @@ fn generate_lockfile(
     Ok(())
 }
 
+/// Returns where the files of the packages of a lockfile are recorded.
+pub fn recorded_files_path(record_files: &Path, lockfile: &Path) -> PathBuf {
+    let name = lockfile.file_name().unwrap_or_default().to_string_lossy();
+    record_files.join(format!("{}.json", name))
+}
+
+/// Resolves a lockfile for several platforms and merges the results.
+///
+/// Every platform is resolved on its own, starting out from the pins that
+/// the existing lockfile has for it.
+#[allow(clippy::too_many_arguments)]
+fn generate_universal_lockfile(
+    output: CommandOutput,
+    py_ver: &PythonVersion,
+    workspace_path: &Path,
+    requirements_file_in: &Path,
+    lockfile: &Path,
+    sources: &ExpandedSources,
+    lock_options: &LockOptions,
+    exclusions: &HashSet<Requirement>,
+    extra_args: &[&str],
+    platforms: &[LockPlatform],
+) -> Result<(), Error> {
+    let scratch = tempfile::tempdir()?;
+    let existing = fs::read_to_string(lockfile).unwrap_or_default();
+    let python = lock_options
+        .target_python(py_ver)?
+        .unwrap_or_else(|| py_ver.clone());
+    let filename = lockfile
+        .file_name()
+        .ok_or_else(|| anyhow!("invalid lockfile path {}", lockfile.display()))?;
+
+    let mut resolved = Vec::new();
+    for platform in platforms {
+        if output == CommandOutput::Verbose {
+            eprintln!("Resolving for {}", platform.name);
+        }
+        let platform_lockfile = scratch.path().join(platform.name).join(filename);
+        fs::create_dir_all(scratch.path().join(platform.name))?;
+        fs::write(
+            &platform_lockfile,
+            select_platform_entries(&existing, &platform.marker_environment(&python)?),
+        )?;
+        let mut platform_options = lock_options.clone();
+        platform_options.target = Some(*platform);
+        generate_lockfile(
+            output,
+            py_ver,
+            workspace_path,
+            requirements_file_in,
+            &platform_lockfile,
+            sources,
+            &platform_options,
+            exclusions,
+            extra_args,
+        )?;
+        resolved.push((*platform, fs::read_to_string(&platform_lockfile)?));
+    }
+
+    let merged = scratch.path().join("requirements.txt");
+    fs::write(&merged, merge_platform_lockfiles(&resolved))?;
+    finalize_lockfile(&merged, lockfile, workspace_path, exclusions, lock_options)
+}
+
 fn finalize_lockfile(
     generated: &Path,
     out: &Path,
@@ -374,8 +1911,16 @@ fn finalize_lockfile(
     lock_options: &LockOptions,
 ) -> Result<(), Error> {
     let mut rv = BufWriter::new(fs::File::create(out)?);
-    writeln!(rv, "{}", render!(REQUIREMENTS_HEADER, lock_options))?;
-    for line in fs::read_to_string(generated)?.lines() {
+    let mut excluded = exclusions.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+    excluded.sort();
+    writeln!(
+        rv,
+        "{}",
+        render!(REQUIREMENTS_HEADER, lock_options, excluded)
+    )?;
+    for entry in iter_lockfile_entries(&fs::read_to_string(generated)?) {
+        let line = entry[0];
+
         // we do not want to persist these pieces of information as we always
         // provide it explicitly on the command line.  This is particularly
         // important as we might include auth info here.
@@ -394,22 +1939,353 @@ fn finalize_lockfile(
                 writeln!(rv, "-e {}", rel_url)?;
                 continue;
             }
-        } else if let Ok(ref req) = line.trim().parse::<Requirement>() {
+        } else if let Ok(ref req) = strip_line_continuation(line).parse::<Requirement>() {
             // TODO: this does not evaluate markers
             if exclusions.iter().any(|x| {
                 normalize_package_name(&x.name) == normalize_package_name(&req.name)
                     && (x.version_or_url.is_none() || x.version_or_url == req.version_or_url)
             }) {
                 // skip exclusions
-                writeln!(rv, "# excluded {}", line)?;
+                writeln!(rv, "# excluded {}", strip_line_continuation(line))?;
                 continue;
             }
         }
-        writeln!(rv, "{}", line)?;
+        for line in entry {
+            writeln!(rv, "{}", restore_project_root(line, workspace_root))?;
+        }
     }
     Ok(())
 }
 
+/// Replaces the expanded project root in direct references with `${PROJECT_ROOT}`.
+///
+/// pip-compile writes out local path dependencies with the project root
+/// expanded which would make the lockfile only usable on this machine.
+fn restore_project_root<'a>(line: &'a str, workspace_root: &Path) -> Cow<'a, str> {
+    let expanded = format!("file:///{}", make_project_root_fragment(workspace_root));
+    match line.find(&expanded) {
+        Some(idx)
+            if line[idx + expanded.len()..].is_empty()
+                || line[idx + expanded.len()..].starts_with(['/', ' ', ';', '#']) =>
+        {
+            Cow::Owned(line.replacen(&expanded, "file:///${PROJECT_ROOT}", 1))
+        }
+        _ => Cow::Borrowed(line),
+    }
+}
+
+/// Iterates over the entries of a lockfile.
+///
+/// Each entry is a list of physical lines, as entries with hashes span
+/// multiple lines joined with backslashes.
+pub fn iter_lockfile_entries(contents: &str) -> impl Iterator<Item = Vec<&str>> {
+    let mut lines = contents.lines();
+    std::iter::from_fn(move || {
+        let mut entry = vec![lines.next()?];
+        while entry.last().unwrap().trim_end().ends_with('\\') {
+            match lines.next() {
+                Some(line) => entry.push(line),
+                None => break,
+            }
+        }
+        Some(entry)
+    })
+}
+
+/// Strips a trailing line continuation from a lockfile line.
+pub fn strip_line_continuation(line: &str) -> &str {
+    let line = line.trim();
+    line.strip_suffix('\\').unwrap_or(line).trim_end()
+}
+
+/// Returns the normalized names of all packages pinned in a lockfile.
+pub fn locked_package_names(contents: &str) -> HashSet<String> {
+    iter_lockfile_entries(contents)
+        .filter_map(|entry| {
+            strip_line_continuation(entry[0])
+                .parse::<Requirement>()
+                .ok()
+        })
+        .map(|req| normalize_package_name(&req.name))
+        .collect()
+}
+
+/// Returns the pinned versions of all packages in a lockfile by normalized name.
+///
+/// Packages that are not pinned to a version (eg: URL requirements) map
+/// to `None`.
+pub fn locked_versions(contents: &str) -> BTreeMap<String, Option<String>> {
+    iter_lockfile_entries(contents)
+        .filter_map(|entry| {
+            strip_line_continuation(entry[0])
+                .parse::<Requirement>()
+                .ok()
+        })
+        .map(|req| {
+            let version = match req.version_or_url {
+                Some(VersionOrUrl::VersionSpecifier(ref specs)) => specs
+                    .iter()
+                    .find(|x| *x.operator() == Operator::Equal)
+                    .map(|x| x.version().to_string()),
+                _ => None,
+            };
+            (normalize_package_name(&req.name), version)
+        })
+        .collect()
+}
+
+/// Splits a lockfile into its package entries and the remaining lines.
+///
+/// The entries (including their hashes) are keyed by normalized package
+/// name.  The remaining lines are editables and options, comments and empty
+/// lines are dropped.
+pub fn split_lockfile_entries(contents: &str) -> (BTreeMap<String, String>, Vec<String>) {
+    let mut packages = BTreeMap::new();
+    let mut other = Vec::new();
+    for entry in iter_lockfile_entries(contents) {
+        let line = strip_line_continuation(entry[0]);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.parse::<Requirement>() {
+            Ok(req) => {
+                let entry = entry.iter().map(|x| x.trim()).collect::<Vec<_>>();
+                packages.insert(normalize_package_name(&req.name), entry.join("\n"));
+            }
+            Err(_) => other.push(line.to_string()),
+        }
+    }
+    (packages, other)
+}
+
+/// Adds the entries of another lockfile that are not in the base lockfile.
+///
+/// This is used to install a group lockfile on top of the production
+/// lockfile.  Both lockfiles pin the same versions, so entries for packages
+/// that are already in the base lockfile are skipped.
+pub fn merge_lockfiles(base: &str, other: &str) -> String {
+    let locked = locked_package_names(base);
+    let base_lines = base.lines().map(|x| x.trim_end()).collect::<HashSet<_>>();
+    let mut rv = base.to_string();
+    if !rv.is_empty() && !rv.ends_with('\n') {
+        rv.push('\n');
+    }
+    for entry in iter_lockfile_entries(other) {
+        let line = strip_line_continuation(entry[0]);
+        if line.is_empty() || line.starts_with('#') || base_lines.contains(line) {
+            continue;
+        }
+        if let Ok(req) = line.parse::<Requirement>() {
+            if locked.contains(&normalize_package_name(&req.name)) {
+                continue;
+            }
+        }
+        for line in entry {
+            rv.push_str(line);
+            rv.push('\n');
+        }
+    }
+    rv
+}
+
+/// Merges the lockfiles resolved for several platforms into one.
+///
+/// Entries that all platforms agree on are kept as they are, the others get
+/// a marker for the platforms they were resolved for.  The hashes of an
+/// entry are combined as the platforms install different files.
+fn merge_platform_lockfiles(lockfiles: &[(LockPlatform, String)]) -> String {
+    let all = lockfiles.iter().map(|x| x.0).collect::<Vec<_>>();
+    let mut other = Vec::new();
+    let mut packages = BTreeMap::<_, (Vec<LockPlatform>, BTreeSet<String>)>::new();
+    for (platform, contents) in lockfiles {
+        for entry in iter_lockfile_entries(contents) {
+            let line = strip_line_continuation(entry[0]);
+            // the headers are written again when the merged lockfile is
+            // finalized, only the excluded dependencies are kept.
+            if line.is_empty() || (line.starts_with('#') && !line.starts_with("# excluded ")) {
+                continue;
+            }
+            match line.parse::<Requirement>() {
+                Ok(req) => {
+                    let (platforms, hashes) = packages
+                        .entry((normalize_package_name(&req.name), line.to_string()))
+                        .or_default();
+                    platforms.push(*platform);
+                    hashes.extend(
+                        entry[1..]
+                            .iter()
+                            .map(|x| strip_line_continuation(x).to_string()),
+                    );
+                }
+                Err(_) => {
+                    if !other.iter().any(|x| x == line) {
+                        other.push(line.to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    let mut rv = String::new();
+    for line in other {
+        rv.push_str(&line);
+        rv.push('\n');
+    }
+    for ((_, line), (platforms, hashes)) in packages {
+        if platforms.len() == all.len() {
+            rv.push_str(&line);
+        } else {
+            rv.push_str(&add_platform_marker(&line, &platforms, &all));
+        }
+        for hash in hashes {
+            rv.push_str(" \\\n    ");
+            rv.push_str(&hash);
+        }
+        rv.push('\n');
+    }
+    rv
+}
+
+/// Limits a requirement to some of the platforms a lockfile is resolved for.
+///
+/// Operating systems whose architectures are all included are matched by
+/// `sys_platform` alone, the others by `platform_machine` as well.
+fn add_platform_marker(line: &str, platforms: &[LockPlatform], all: &[LockPlatform]) -> String {
+    let mut clauses = Vec::new();
+    for platform in platforms {
+        let whole_os = all
+            .iter()
+            .filter(|x| x.os == platform.os)
+            .all(|x| platforms.contains(x));
+        let clause = if whole_os {
+            format!("sys_platform == \"{}\"", platform.sys_platform)
+        } else {
+            format!(
+                "sys_platform == \"{}\" and platform_machine == \"{}\"",
+                platform.sys_platform, platform.platform_machine
+            )
+        };
+        if !clauses.contains(&clause) {
+            clauses.push(clause);
+        }
+    }
+    let has_marker = line
+        .parse::<Requirement>()
+        .map_or(false, |req| req.marker.is_some());
+    let (req, existing) = match line.rsplit_once(';') {
+        Some((req, existing)) if has_marker => (req.trim_end(), Some(existing.trim())),
+        _ => (line, None),
+    };
+    let marker = if clauses.len() == 1 && existing.is_none() {
+        clauses.remove(0)
+    } else {
+        let clauses = clauses
+            .iter()
+            .map(|x| {
+                if x.contains(" and ") && clauses.len() > 1 {
+                    format!("({})", x)
+                } else {
+                    x.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" or ");
+        match existing {
+            Some(existing) => format!("({}) and ({})", existing, clauses),
+            None => clauses,
+        }
+    };
+    format!("{} ; {}", req, marker)
+}
+
+/// Drops the entries of a lockfile whose markers do not apply to an
+/// environment.
+fn select_platform_entries(contents: &str, environment: &MarkerEnvironment) -> String {
+    let mut rv = String::new();
+    for entry in iter_lockfile_entries(contents) {
+        if let Ok(req) = strip_line_continuation(entry[0]).parse::<Requirement>() {
+            if !req.evaluate_markers(environment, Vec::new()) {
+                continue;
+            }
+        }
+        for line in entry {
+            rv.push_str(line);
+            rv.push('\n');
+        }
+    }
+    rv
+}
+
+/// Returns the platforms a lockfile was resolved for.
+///
+/// This is `None` for lockfiles that were only resolved for the platform
+/// they were locked on.
+fn lockfile_platforms(contents: &str) -> Option<Vec<String>> {
+    contents
+        .lines()
+        .take_while(|line| line.starts_with('#'))
+        .find_map(|line| line.strip_prefix("#   platforms: "))
+        .and_then(|value| serde_json::from_str(value).ok())
+}
+
+/// Selects the entries of a universal lockfile for the current platform.
+///
+/// Returns `None` if the lockfile is not universal.  The pins for the other
+/// platforms would otherwise show up as locked packages everywhere.
+pub fn select_current_platform(
+    contents: &str,
+    py_ver: &PythonVersion,
+) -> Result<Option<String>, Error> {
+    let platforms = match lockfile_platforms(contents) {
+        Some(platforms) => platforms,
+        None => return Ok(None),
+    };
+    let platform = LockPlatform::current()?;
+    if !platforms
+        .iter()
+        .any(|name| LockPlatform::find(name).ok() == Some(platform))
+    {
+        bail!(
+            "the lockfile was only resolved for {}. Add {} to tool.rye.lock-platforms \
+             or lock with --universal",
+            platforms.join(", "),
+            platform.name
+        );
+    }
+    Ok(Some(select_platform_entries(
+        contents,
+        &platform.marker_environment(py_ver)?,
+    )))
+}
+
+/// Checks if a lockfile contains hashes.
+pub fn lockfile_has_hashes(contents: &str) -> bool {
+    contents
+        .lines()
+        .any(|line| line.trim_start().starts_with("--hash="))
+}
+
+/// Splits a lockfile into the hashed requirements and the editable requirements.
+///
+/// Editable requirements cannot be installed in hash-checking mode, so they
+/// need to be installed separately.
+pub fn split_editable_requirements(contents: &str) -> (String, String) {
+    let mut hashed = String::new();
+    let mut editable = String::new();
+    for entry in iter_lockfile_entries(contents) {
+        let target = if entry[0].starts_with("-e ") {
+            &mut editable
+        } else {
+            &mut hashed
+        };
+        for line in entry {
+            target.push_str(line);
+            target.push('\n');
+        }
+    }
+    (hashed, editable)
+}
+
 pub fn make_project_root_fragment(root: &Path) -> String {
     // XXX: ${PROJECT_ROOT} is supposed to be used in the context of file:///
     // so let's make sure it is url escaped.  This is pretty hacky but
@@ -420,7 +2296,7 @@ pub fn make_project_root_fragment(root: &Path) -> String {
         .replace(' ', "%20")
 }
 
-fn make_relative_url(path: &Path, base: &Path) -> Result<String, Error> {
+pub fn make_relative_url(path: &Path, base: &Path) -> Result<String, Error> {
     // TODO: consider using ${PROJECT_ROOT} here which is what pdm does or make-req prints
     let rv = pathdiff::diff_paths(path, base).ok_or_else(|| {
         anyhow!(
@@ -458,3 +2334,328 @@ fn test_make_relativec_url() {
         "file:."
     );
 }
+
+#[test]
+fn test_parse_exclude_newer() {
+    assert_eq!(
+        parse_exclude_newer("2024-01-15").unwrap(),
+        "2024-01-15T00:00:00+00:00"
+    );
+    assert_eq!(
+        parse_exclude_newer("2024-01-15T10:30Z").unwrap(),
+        "2024-01-15T10:30:00+00:00"
+    );
+    assert_eq!(
+        parse_exclude_newer("2024-01-15T10:30:15-05:00").unwrap(),
+        "2024-01-15T10:30:15-05:00"
+    );
+    assert!(parse_exclude_newer("last month").is_err());
+}
+
+#[test]
+fn test_locked_package_names() {
+    let names = locked_package_names(
+        "# generated by rye\n-e file:.\nFlask==2.3.2 \\\n    --hash=sha256:abc\nzope.interface==6.0\n",
+    );
+    assert_eq!(names.len(), 2);
+    assert!(names.contains("flask"));
+    assert!(names.contains("zope-interface"));
+}
+
+#[test]
+fn test_locked_versions() {
+    let versions = locked_versions(
+        "# generated by rye\n-e file:.\nFlask==2.3.2 \\\n    --hash=sha256:abc\nutils @ file:///utils\n",
+    );
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions["flask"].as_deref(), Some("2.3.2"));
+    assert_eq!(versions["utils"], None);
+}
+
+#[test]
+fn test_split_lockfile_entries() {
+    let (packages, other) = split_lockfile_entries(
+        "# generated by rye\n\n-e file:.\nFlask==2.3.2 \\\n    --hash=sha256:abc\nzope.interface==6.0\n",
+    );
+    assert_eq!(packages.len(), 2);
+    assert_eq!(packages["flask"], "Flask==2.3.2 \\\n--hash=sha256:abc");
+    assert_eq!(packages["zope-interface"], "zope.interface==6.0");
+    assert_eq!(other, vec!["-e file:.".to_string()]);
+}
+
+#[test]
+fn test_restore_project_root() {
+    let root = Path::new("/home/user/my project");
+    assert_eq!(
+        restore_project_root("utils @ file:///home/user/my%20project/../utils", root),
+        "utils @ file:///${PROJECT_ROOT}/../utils"
+    );
+    assert_eq!(
+        restore_project_root("utils @ file:///home/user/my%20project2/utils", root),
+        "utils @ file:///home/user/my%20project2/utils"
+    );
+    assert_eq!(restore_project_root("flask==2.3.2", root), "flask==2.3.2");
+}
+
+#[test]
+fn test_merge_lockfiles() {
+    let base = "-e file:.\nflask==2.3.2\nwerkzeug==2.3.6\n";
+    let other =
+        "# generated by rye\n-e file:.\nmkdocs==1.5.2 \\\n    --hash=sha256:abc\nwerkzeug==2.3.6\n";
+    assert_eq!(
+        merge_lockfiles(base, other),
+        "-e file:.\nflask==2.3.2\nwerkzeug==2.3.6\nmkdocs==1.5.2 \\\n    --hash=sha256:abc\n"
+    );
+}
+
+#[test]
+fn test_group_lockfile_name() {
+    assert_eq!(
+        group_lockfile_name("docs").unwrap(),
+        "requirements-docs.lock"
+    );
+    assert_eq!(
+        group_lockfile_name("Docs_Extra").unwrap(),
+        "requirements-docs-extra.lock"
+    );
+    assert!(group_lockfile_name("dev").is_err());
+    assert!(group_lockfile_name("Dev").is_err());
+}
+
+#[test]
+fn test_split_editable_requirements() {
+    let (hashed, editable) = split_editable_requirements(
+        "-e file:.\nfoo==1.0 \\\n    --hash=sha256:abc \\\n    --hash=sha256:def\nbar==2.0\n",
+    );
+    assert_eq!(
+        hashed,
+        "foo==1.0 \\\n    --hash=sha256:abc \\\n    --hash=sha256:def\nbar==2.0\n"
+    );
+    assert_eq!(editable, "-e file:.\n");
+    assert!(lockfile_has_hashes(&hashed));
+    assert!(!lockfile_has_hashes(&editable));
+}
+
+#[test]
+fn test_find_lock_platform() {
+    assert_eq!(
+        LockPlatform::find("linux-x86_64").unwrap().name,
+        "linux-x86_64"
+    );
+    assert_eq!(
+        LockPlatform::find("darwin-arm64").unwrap().name,
+        "macos-arm64"
+    );
+    assert_eq!(
+        LockPlatform::find("win32-AMD64").unwrap().name,
+        "windows-x86_64"
+    );
+    assert!(LockPlatform::find("linux").is_err());
+    assert!(LockPlatform::find("windows-aarch64").is_err());
+}
+
+#[test]
+fn test_merge_platform_lockfiles() {
+    let platform = |name| LockPlatform::find(name).unwrap();
+    let lockfiles = [
+        (
+            platform("linux-x86_64"),
+            "# generated by rye\n-e file:.\nclick==8.1.7\nuvloop==0.19.0 \\\n    --hash=sha256:aaa\n"
+                .to_string(),
+        ),
+        (
+            platform("linux-aarch64"),
+            "-e file:.\nclick==8.1.7\nuvloop==0.19.0 \\\n    --hash=sha256:bbb\n".to_string(),
+        ),
+        (
+            platform("macos-arm64"),
+            "-e file:.\nclick==8.1.7\nuvloop==0.19.0 \\\n    --hash=sha256:ccc\n".to_string(),
+        ),
+        (
+            platform("macos-x86_64"),
+            "-e file:.\nclick==8.1.7\n# excluded torch==2.1.0\n".to_string(),
+        ),
+        (
+            platform("windows-x86_64"),
+            "-e file:.\nclick==8.1.7\ncolorama==0.4.6\npywin32==306 ; python_version >= \"3.8\"\n"
+                .to_string(),
+        ),
+    ];
+    assert_eq!(
+        merge_platform_lockfiles(&lockfiles),
+        "-e file:.\n\
+         # excluded torch==2.1.0\n\
+         click==8.1.7\n\
+         colorama==0.4.6 ; sys_platform == \"win32\"\n\
+         pywin32==306 ; (python_version >= \"3.8\") and (sys_platform == \"win32\")\n\
+         uvloop==0.19.0 ; sys_platform == \"linux\" or \
+         (sys_platform == \"darwin\" and platform_machine == \"arm64\") \\\n    \
+         --hash=sha256:aaa \\\n    \
+         --hash=sha256:bbb \\\n    \
+         --hash=sha256:ccc\n"
+    );
+}
+
+#[test]
+fn test_select_platform_entries() {
+    let contents = "# generated by rye\n\
+                    #   platforms: [\"linux-x86_64\", \"windows-x86_64\"]\n\
+                    \n\
+                    -e file:.\n\
+                    colorama==0.4.6 ; sys_platform == \"win32\"\n\
+                    uvloop==0.19.0 ; sys_platform == \"linux\" \\\n    \
+                    --hash=sha256:aaa\n";
+    assert_eq!(
+        lockfile_platforms(contents),
+        Some(vec![
+            "linux-x86_64".to_string(),
+            "windows-x86_64".to_string()
+        ])
+    );
+    assert_eq!(lockfile_platforms("-e file:.\nclick==8.1.7\n"), None);
+    let py_ver = PythonVersion {
+        kind: "cpython".into(),
+        major: 3,
+        minor: 12,
+        patch: 1,
+        suffix: None,
+    };
+    let environment = LockPlatform::find("windows-x86_64")
+        .unwrap()
+        .marker_environment(&py_ver)
+        .unwrap();
+    assert_eq!(
+        select_platform_entries(contents, &environment),
+        "# generated by rye\n\
+         #   platforms: [\"linux-x86_64\", \"windows-x86_64\"]\n\
+         \n\
+         -e file:.\n\
+         colorama==0.4.6 ; sys_platform == \"win32\"\n"
+    );
+}
+
+#[test]
+fn test_select_current_platform_with_alias() {
+    let current = match LockPlatform::current() {
+        Ok(current) => current,
+        Err(_) => return,
+    };
+    // spell the current platform the way Python reports it
+    let os = match current.os {
+        "macos" => "darwin",
+        "windows" => "win32",
+        os => os,
+    };
+    let arch = match current.arch {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        arch => arch,
+    };
+    let mut lock_options = LockOptions {
+        platforms: vec![format!("{}-{}", os, arch), "osx-x64".into()],
+        ..LockOptions::default()
+    };
+    lock_options.canonicalize_platforms().unwrap();
+    let mut expected = vec![current.name.to_string(), "macos-x86_64".to_string()];
+    expected.sort_by_key(|x| LOCK_PLATFORMS.iter().position(|y| y.name == x.as_str()));
+    expected.dedup();
+    assert_eq!(lock_options.platforms, expected);
+
+    let excluded = Vec::<String>::new();
+    let contents = format!(
+        "{}-e file:.\nclick==8.1.7\n",
+        render!(REQUIREMENTS_HEADER, lock_options, excluded)
+    );
+    assert_eq!(lockfile_platforms(&contents), Some(expected));
+    let py_ver = PythonVersion {
+        kind: "cpython".into(),
+        major: 3,
+        minor: 12,
+        patch: 1,
+        suffix: None,
+    };
+    let selected = select_current_platform(&contents, &py_ver)
+        .unwrap()
+        .unwrap();
+    assert!(selected.contains("click==8.1.7"));
+}
+
+#[test]
+fn test_env_lockfile_name() {
+    let mut env = EnvConfig {
+        name: "py38".into(),
+        python: None,
+        groups: Vec::new(),
+        dev: true,
+        location: None,
+    };
+    assert_eq!(
+        env_lockfile_name("requirements.lock", Some(&env)),
+        "requirements.lock"
+    );
+    env.python = Some("3.8".parse().unwrap());
+    assert_eq!(
+        env_lockfile_name("requirements-dev.lock", Some(&env)),
+        "requirements-dev.py38.lock"
+    );
+    assert_eq!(
+        env_lockfile_name("pylock.toml", Some(&env)),
+        "pylock.py38.toml"
+    );
+    assert_eq!(
+        env_lockfile_name("requirements.lock", None),
+        "requirements.lock"
+    );
+}
+
+#[test]
+fn test_read_locked_requirements_pylock() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(
+        dir.path().join("pyproject.toml"),
+        "[project]\nname = \"foo\"\nversion = \"1.0\"\n\n[tool.rye]\nlock-format = \"pylock\"\n",
+    )
+    .unwrap();
+    // the requirements lockfile is ignored with the pylock format
+    fs::write(dir.path().join("requirements.lock"), "a==1.0\n").unwrap();
+    fs::write(
+        dir.path().join("pylock.toml"),
+        "lock-version = \"1.0\"\n\n[[packages]]\nname = \"a\"\nversion = \"2.0\"\n",
+    )
+    .unwrap();
+    let pyproject = PyProject::load(&dir.path().join("pyproject.toml")).unwrap();
+    assert_eq!(
+        authoritative_lockfile_path(&pyproject, LockMode::Production).unwrap(),
+        dir.path().join("pylock.toml")
+    );
+    assert_eq!(
+        read_locked_requirements(&pyproject, LockMode::Production)
+            .unwrap()
+            .as_deref(),
+        Some("a==2.0\n")
+    );
+    assert_eq!(
+        read_locked_requirements(&pyproject, LockMode::Dev).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_pip_compile_sitecustomize_pip_version() {
+    assert!(PIP_COMPILE_SITECUSTOMIZE.contains(&format!(
+        "_PIP_VERSION = \"{}\"",
+        crate::bootstrap::PIP_VERSION
+    )));
+}
+
+#[test]
+fn test_build_settings_env() {
+    let settings = BuildSettings {
+        no_isolation: vec!["pyfoo".into()],
+        extra: BTreeMap::from([("pyfoo".into(), vec!["numpy>=1.26".into()])]),
+    };
+    assert_eq!(
+        build_settings_env(&settings, true).unwrap(),
+        r#"{"extra":{"pyfoo":["numpy>=1.26"]},"locking":true,"no_isolation":["pyfoo"]}"#
+    );
+}
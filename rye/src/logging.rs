@@ -0,0 +1,220 @@
+use std::env;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Error};
+use clap::ValueEnum;
+use console::style;
+use once_cell::sync::Lazy;
+
+use crate::transcript::redact;
+use crate::warnings::{warn, Warning};
+
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Off as u8);
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// The levels of the log, from least to most verbose.
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[value(rename_all = "snake_case")]
+pub enum Level {
+    /// Nothing is logged.
+    Off = 0,
+    /// The phases of a command and how long they took.
+    Info = 1,
+    /// Decisions and the commands rye runs.
+    Debug = 2,
+    /// Everything, including the environment of the commands.
+    Trace = 3,
+}
+
+impl Level {
+    fn parse(value: &str) -> Option<Level> {
+        Level::from_str(value.trim(), true).ok()
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Level::Off => "off",
+                Level::Info => "info",
+                Level::Debug => "debug",
+                Level::Trace => "trace",
+            }
+        )
+    }
+}
+
+/// Sets up logging from the command line and the environment.
+///
+/// Falls back to `RYE_LOG` and `RYE_LOG_FILE` and exports both again, so
+/// that rye processes spawned by this one log the same way.  Without a log
+/// file the log is written to stderr.
+pub fn init(level: Option<Level>, file: Option<&Path>) -> Result<(), Error> {
+    Lazy::force(&START);
+    let level = match level {
+        Some(level) => level,
+        None => match env::var("RYE_LOG") {
+            Ok(value) if !value.is_empty() => match Level::parse(&value) {
+                Some(level) => level,
+                None => {
+                    warn(
+                        Warning::InvalidLogLevel,
+                        format_args!("ignoring invalid value for RYE_LOG: '{}'", value),
+                    );
+                    Level::Off
+                }
+            },
+            _ => Level::Off,
+        },
+    };
+    let file = file
+        .map(|x| x.to_path_buf())
+        .or_else(|| env::var_os("RYE_LOG_FILE").map(Into::into))
+        .filter(|x| !x.as_os_str().is_empty());
+    // a log file without a level gets the phases of the commands
+    let level = if level == Level::Off && file.is_some() {
+        Level::Info
+    } else {
+        level
+    };
+
+    if let Some(ref file) = file {
+        // spawned processes can run in other folders
+        let file = env::current_dir()?.join(file);
+        let handle = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&file)
+            .with_context(|| format!("unable to open log file {}", file.display()))?;
+        *LOG_FILE.lock().unwrap() = Some(handle);
+        env::set_var("RYE_LOG_FILE", &file);
+    }
+    LEVEL.store(level as u8, Ordering::Relaxed);
+    env::set_var("RYE_LOG", level.to_string());
+    Ok(())
+}
+
+/// Logs at least at the given level.
+///
+/// This is used for the `-v` flags of the commands, a higher level from
+/// `--log-level` or `RYE_LOG` is retained.
+pub fn raise_level(level: Level) {
+    if level as u8 > LEVEL.load(Ordering::Relaxed) {
+        LEVEL.store(level as u8, Ordering::Relaxed);
+        env::set_var("RYE_LOG", level.to_string());
+    }
+}
+
+/// Is the level logged?
+pub fn enabled(level: Level) -> bool {
+    level != Level::Off && level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Logs a message for a part of rye (`sync`, `lock` etc.).
+///
+/// Messages are redacted as they might contain credentials.
+pub fn log<D: fmt::Display>(level: Level, target: &str, message: D) {
+    if !enabled(level) {
+        return;
+    }
+    let elapsed = START.elapsed().as_secs_f64();
+    let message = redact(&message.to_string());
+    let mut file = LOG_FILE.lock().unwrap();
+    if let Some(ref mut file) = *file {
+        writeln!(
+            file,
+            "[{:>9.3}s {:<5} {}:{}] {}",
+            elapsed,
+            level.to_string().to_ascii_uppercase(),
+            target,
+            std::process::id(),
+            message
+        )
+        .ok();
+    } else {
+        eprintln!(
+            "{} {}",
+            style(format!(
+                "[{:>9.3}s {:<5} {}]",
+                elapsed,
+                level.to_string().to_ascii_uppercase(),
+                target
+            ))
+            .dim(),
+            message
+        );
+    }
+}
+
+/// Logs a message with [`Level::Info`].
+pub fn info<D: fmt::Display>(target: &str, message: D) {
+    log(Level::Info, target, message);
+}
+
+/// Logs a message with [`Level::Debug`].
+pub fn debug<D: fmt::Display>(target: &str, message: D) {
+    log(Level::Debug, target, message);
+}
+
+/// Logs a message with [`Level::Trace`].
+pub fn trace<D: fmt::Display>(target: &str, message: D) {
+    log(Level::Trace, target, message);
+}
+
+/// A phase of a command whose duration is logged once it's dropped.
+pub struct Phase {
+    target: &'static str,
+    name: &'static str,
+    start: Instant,
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        info(
+            self.target,
+            format_args!(
+                "finished {} in {}",
+                self.name,
+                format_duration(self.start.elapsed())
+            ),
+        );
+    }
+}
+
+/// Starts a timed phase of a command (eg: `phase("sync", "locking")`).
+pub fn phase(target: &'static str, name: &'static str) -> Phase {
+    info(target, format_args!("starting {}", name));
+    Phase {
+        target,
+        name,
+        start: Instant::now(),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    if duration.as_secs() > 0 {
+        format!("{:.2}s", duration.as_secs_f64())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
+#[test]
+fn test_level_parse() {
+    assert_eq!(Level::parse("debug"), Some(Level::Debug));
+    assert_eq!(Level::parse(" TRACE "), Some(Level::Trace));
+    assert_eq!(Level::parse("verbose"), None);
+    assert!(Level::Info < Level::Debug);
+    assert_eq!(format_duration(Duration::from_millis(1500)), "1.50s");
+    assert_eq!(format_duration(Duration::from_millis(42)), "42ms");
+}
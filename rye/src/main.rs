@@ -1,20 +1,41 @@
 use std::process;
 use std::sync::atomic::{AtomicBool, Ordering};
 
-use crate::utils::QuietExit;
+use crate::utils::{is_noninteractive, QuietExit};
 
+mod auth;
 mod bootstrap;
 mod cli;
 mod config;
 mod consts;
+mod errors;
+mod git_version;
+mod hooks;
 mod installer;
 mod lock;
+mod logging;
+mod network;
 mod piptools;
 mod platform;
+mod project_import;
+mod project_template;
+mod pylock;
 mod pyproject;
+mod relocate;
+mod remote_cache;
+mod reproducible;
+mod sandbox;
+mod script;
+mod secrets;
 mod sources;
+mod store;
 mod sync;
+mod tools_manifest;
+mod transcript;
+mod trusted_publishing;
 mod utils;
+mod warnings;
+mod wheel_cache;
 
 static SHOW_CONTINUE_PROMPT: AtomicBool = AtomicBool::new(false);
 
@@ -31,13 +52,12 @@ pub fn main() {
             if let Some(QuietExit(code)) = err.downcast_ref() {
                 *code
             } else {
-                eprintln!("Error: {:?}", err);
-                1
+                errors::report_error(&err)
             }
         }
     };
 
-    if SHOW_CONTINUE_PROMPT.load(Ordering::Relaxed) {
+    if SHOW_CONTINUE_PROMPT.load(Ordering::Relaxed) && !is_noninteractive() {
         eprintln!("Press any key to continue");
         console::Term::buffered_stderr().read_key().ok();
     }
@@ -0,0 +1,417 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Error;
+use once_cell::sync::OnceCell;
+use pep508_rs::{Requirement, VersionOrUrl};
+use url::Url;
+
+use crate::bootstrap::update_site_hook;
+use crate::config::Config;
+use crate::lock::strip_line_continuation;
+use crate::utils::CommandOutput;
+use crate::warnings::{warn_with_output, Warning};
+
+/// Installed into the virtualenvs of rye's Python tools to enforce the
+/// network policy within pip, pip-tools and twine.
+///
+/// The hook is loaded by a `.pth` file and only activates when
+/// `RYE_NETWORK_ALLOW` is set.  All requests (including redirects) go
+/// through `Session.send` of requests, so that is where hosts are checked.
+const POLICY_HOOK: &str = r#"
+import os
+import sys
+
+
+def _install():
+    import json
+    from urllib.parse import urlsplit
+
+    allowed = [x.strip().lower() for x in json.loads(os.environ["RYE_NETWORK_ALLOW"])]
+
+    def is_allowed(host):
+        host = (host or "").rstrip(".").lower()
+        if not host:
+            return True
+        for entry in allowed:
+            if entry.startswith("*."):
+                entry = entry[2:]
+            if host == entry or host.endswith("." + entry):
+                return True
+        return False
+
+    def patch(session_cls):
+        send = session_cls.send
+
+        def patched_send(self, request, **kwargs):
+            host = urlsplit(request.url).hostname
+            if not is_allowed(host):
+                message = (
+                    "network policy violation: refusing to contact %s (%s). "
+                    "Allowed hosts are configured in network.allow" % (host, request.url)
+                )
+                sys.stderr.write("error: %s\n" % message)
+                raise ConnectionRefusedError(message)
+            return send(self, request, **kwargs)
+
+        session_cls.send = patched_send
+
+    for module in ("pip._vendor.requests.sessions", "requests.sessions"):
+        try:
+            patch(__import__(module, fromlist=["Session"]).Session)
+        except ImportError:
+            pass
+
+
+if os.environ.get("RYE_NETWORK_ALLOW"):
+    _install()
+"#;
+
+/// Raised when rye is about to contact a host the network policy does not allow.
+#[derive(Debug)]
+pub struct NetworkPolicyViolation {
+    host: String,
+    url: String,
+    purpose: String,
+}
+
+impl std::error::Error for NetworkPolicyViolation {}
+
+impl fmt::Display for NetworkPolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "network policy violation: refusing to contact {} for {} ({}). \
+             Allowed hosts are configured in network.allow",
+            self.host, self.purpose, self.url
+        )
+    }
+}
+
+/// How often failed requests are retried unless configured otherwise.
+const DEFAULT_RETRIES: u32 = 3;
+
+/// The longest delay between two attempts of a request.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Raised when a server responds with an error status.
+#[derive(Debug)]
+pub struct HttpStatusError {
+    pub url: String,
+    pub code: u32,
+}
+
+impl std::error::Error for HttpStatusError {}
+
+impl fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "request to {} failed with status {}",
+            self.url, self.code
+        )?;
+        match self.code {
+            401 | 403 => write!(f, " (access denied, check the credentials)"),
+            404 => write!(f, " (not found)"),
+            429 => write!(f, " (too many requests)"),
+            500..=599 => write!(f, " (server error)"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Is the host allowed by an allow list?
+///
+/// Entries match the host itself and all of its subdomains.
+pub fn is_host_allowed(host: &str, allowed: &[String]) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    allowed.iter().any(|entry| {
+        let entry = entry.trim().trim_start_matches("*.").to_ascii_lowercase();
+        host == entry
+            || host
+                .strip_suffix(entry.as_str())
+                .map_or(false, |x| x.ends_with('.'))
+    })
+}
+
+/// Fails if the network policy does not allow to contact the host of the URL.
+///
+/// URLs without a host (like `file://` URLs) are always allowed.
+pub fn check_url(url: &str, purpose: &str) -> Result<(), Error> {
+    let allowed = match Config::current().network_allow() {
+        Some(allowed) => allowed,
+        None => return Ok(()),
+    };
+    let host = match Url::parse(url)
+        .ok()
+        .and_then(|x| x.host_str().map(String::from))
+    {
+        Some(host) => host,
+        None => return Ok(()),
+    };
+    if is_host_allowed(&host, &allowed) {
+        Ok(())
+    } else {
+        Err(NetworkPolicyViolation {
+            host,
+            url: crate::transcript::redact(url),
+            purpose: purpose.to_string(),
+        }
+        .into())
+    }
+}
+
+/// Checks the direct references and index options in a requirements file.
+pub fn check_requirements(contents: &str, purpose: &str) -> Result<(), Error> {
+    if Config::current().network_allow().is_none() {
+        return Ok(());
+    }
+    for line in contents.lines() {
+        let line = strip_line_continuation(line);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('-') {
+            // options like --index-url or -e carry their URL last
+            if let Some(url) = line.split_whitespace().last() {
+                check_url(url, purpose)?;
+            }
+        } else if let Ok(req) = line.parse::<Requirement>() {
+            if let Some(VersionOrUrl::Url(ref url)) = req.version_or_url {
+                check_url(url.as_str(), purpose)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Passes the network policy to a command that runs one of rye's Python tools.
+pub fn set_network_policy_env(cmd: &mut Command) {
+    if let Some(allowed) = Config::current().network_allow() {
+        cmd.env(
+            "RYE_NETWORK_ALLOW",
+            serde_json::to_string(&allowed).unwrap_or_default(),
+        );
+    }
+}
+
+/// Installs the hook that enforces the network policy into a virtualenv.
+///
+/// Without a network policy the hook is removed from the virtualenv.
+pub fn install_policy_hook(venv: &Path) -> Result<(), Error> {
+    let enabled = Config::current().network_allow().is_some();
+    update_site_hook(
+        venv,
+        "_rye_network_policy",
+        Some(POLICY_HOOK).filter(|_| enabled),
+    )
+}
+
+/// Well known locations of the CA bundle of Linux distributions and BSDs.
+#[cfg(all(unix, not(target_os = "macos")))]
+const SYSTEM_CA_BUNDLES: &[&str] = &[
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/pki/tls/cacert.pem",
+    "/etc/ssl/cert.pem",
+    "/usr/local/share/certs/ca-root-nss.crt",
+];
+
+/// Returns the CA bundle that TLS certificates are verified against.
+///
+/// This is the configured bundle or, with `network.native-certs`, the one
+/// of the operating system.  `None` means that the bundled certificates are
+/// used, except on Windows where curl always uses the certificate store.
+pub fn get_ca_bundle() -> Option<PathBuf> {
+    static NATIVE_BUNDLE: OnceCell<Option<PathBuf>> = OnceCell::new();
+    let config = Config::current();
+    if let Some(bundle) = config.ca_bundle() {
+        return Some(bundle);
+    }
+    if !config.native_certs() {
+        return None;
+    }
+    NATIVE_BUNDLE.get_or_init(find_native_bundle).clone()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn find_native_bundle() -> Option<PathBuf> {
+    SYSTEM_CA_BUNDLES
+        .iter()
+        .map(PathBuf::from)
+        .find(|x| x.is_file())
+}
+
+/// Exports the certificates of the system keychains, as macOS has no bundle.
+#[cfg(target_os = "macos")]
+fn find_native_bundle() -> Option<PathBuf> {
+    let output = Command::new("/usr/bin/security")
+        .arg("find-certificate")
+        .arg("-a")
+        .arg("-p")
+        .arg("/System/Library/Keychains/SystemRootCertificates.keychain")
+        .arg("/Library/Keychains/System.keychain")
+        .output()
+        .ok()
+        .filter(|x| x.status.success())?;
+    let path = crate::platform::get_app_dir()
+        .join("cache")
+        .join("native-certs.pem");
+    std::fs::create_dir_all(path.parent()?).ok()?;
+    std::fs::write(&path, output.stdout).ok()?;
+    Some(path)
+}
+
+#[cfg(windows)]
+fn find_native_bundle() -> Option<PathBuf> {
+    None
+}
+
+/// Applies the proxy and TLS settings to a curl handle.
+pub fn configure_curl(handle: &mut curl::easy::Easy, url: &str) -> Result<(), Error> {
+    let config = Config::current();
+    let proxy = if url.starts_with("http://") {
+        config.http_proxy_url()
+    } else {
+        config.https_proxy_url()
+    };
+    if let Some(proxy) = proxy {
+        handle.proxy(&proxy)?;
+    }
+    if let Some(no_proxy) = config.no_proxy() {
+        handle.noproxy(&no_proxy)?;
+    }
+    if let Some(bundle) = get_ca_bundle() {
+        handle.cainfo(bundle)?;
+    }
+    Ok(())
+}
+
+/// Turns a curl error into an error that explains common network issues.
+///
+/// Certificate errors are usually caused by proxies that intercept TLS.
+pub fn explain_curl_error(err: curl::Error, url: &str) -> Error {
+    let hint = if err.is_couldnt_resolve_host() {
+        Some("the host name could not be resolved (DNS)")
+    } else if err.is_couldnt_connect() {
+        Some("the connection was refused or the host is unreachable")
+    } else if err.is_operation_timedout() {
+        Some("the request timed out")
+    } else if err.is_peer_failed_verification()
+        || err.is_ssl_cacert()
+        || err.is_ssl_cacert_badfile()
+    {
+        Some(
+            "the TLS certificate of the server could not be verified.  If a proxy \
+             intercepts TLS, set network.ca-bundle to its CA certificate or enable \
+             network.native-certs to use the certificate store of the system",
+        )
+    } else if err.is_couldnt_resolve_proxy() {
+        Some("the proxy could not be resolved.  Check HTTPS_PROXY or proxy.https")
+    } else {
+        None
+    };
+    let message = match hint {
+        Some(hint) => format!("request to {} failed: {}", url, hint),
+        None => format!("request to {} failed", url),
+    };
+    Error::new(err).context(message)
+}
+
+/// Is the error worth retrying the request for?
+///
+/// These are connection problems and server errors, but never certificate
+/// errors or client errors like `403`.
+fn is_transient(err: &Error) -> bool {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<curl::Error>() {
+            return err.is_couldnt_resolve_host()
+                || err.is_couldnt_connect()
+                || err.is_operation_timedout()
+                || err.is_send_error()
+                || err.is_recv_error()
+                || err.is_partial_file()
+                || err.is_got_nothing()
+                || err.is_http2_stream_error();
+        }
+        if let Some(err) = cause.downcast_ref::<HttpStatusError>() {
+            return matches!(err.code, 408 | 429 | 500..=599);
+        }
+    }
+    false
+}
+
+/// Runs a request and retries it on transient failures.
+///
+/// The number of retries is configured with `network.retries` and the delay
+/// starts at `network.retry-backoff`, doubling after every attempt.
+pub fn with_retries<T, F>(output: CommandOutput, mut f: F) -> Result<T, Error>
+where
+    F: FnMut() -> Result<T, Error>,
+{
+    let config = Config::current();
+    let retries = config.network_retries().unwrap_or(DEFAULT_RETRIES);
+    let mut delay = config.retry_backoff();
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(err) if attempt < retries && is_transient(&err) => {
+                attempt += 1;
+                warn_with_output(
+                    Warning::RequestRetried,
+                    format_args!(
+                        "{:#}, retrying in {:.1}s ({}/{})",
+                        err,
+                        delay.as_secs_f64(),
+                        attempt,
+                        retries
+                    ),
+                    output,
+                );
+                thread::sleep(delay);
+                delay = (delay * 2).min(MAX_BACKOFF);
+            }
+            rv => return rv,
+        }
+    }
+}
+
+/// Passes the proxy, TLS and retry settings to a command.
+///
+/// This covers pip, requests (twine) and git.
+pub fn set_tls_env(cmd: &mut Command) {
+    let config = Config::current();
+    if let Some(no_proxy) = config.no_proxy() {
+        cmd.env("no_proxy", &no_proxy);
+        cmd.env("NO_PROXY", &no_proxy);
+    }
+    if let Some(retries) = config.network_retries() {
+        cmd.env("PIP_RETRIES", retries.to_string());
+    }
+    if let Some(bundle) = get_ca_bundle() {
+        for var in [
+            "SSL_CERT_FILE",
+            "REQUESTS_CA_BUNDLE",
+            "PIP_CERT",
+            "GIT_SSL_CAINFO",
+        ] {
+            cmd.env(var, &bundle);
+        }
+    }
+}
+
+#[test]
+fn test_is_host_allowed() {
+    let allowed = vec!["pypi.org".to_string(), "*.internal.example".to_string()];
+    assert!(is_host_allowed("pypi.org", &allowed));
+    assert!(is_host_allowed("upload.pypi.org", &allowed));
+    assert!(is_host_allowed("PyPI.org.", &allowed));
+    assert!(is_host_allowed("internal.example", &allowed));
+    assert!(is_host_allowed("pkgs.internal.example", &allowed));
+    assert!(!is_host_allowed("evilpypi.org", &allowed));
+    assert!(!is_host_allowed("files.pythonhosted.org", &allowed));
+}
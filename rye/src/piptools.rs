@@ -3,12 +3,15 @@ use std::process::Command;
 
 use anyhow::{bail, Context, Error};
 
-use crate::bootstrap::ensure_self_venv;
+use crate::bootstrap::{ensure_self_venv, PIP_VERSION};
 use crate::consts::VENV_BIN;
+use crate::network::install_policy_hook;
 use crate::platform::get_app_dir;
+use crate::sandbox::install_sandbox_hook;
 use crate::sources::PythonVersion;
 use crate::sync::create_virtualenv;
-use crate::utils::{get_venv_python_bin, CommandOutput};
+use crate::transcript::run_status;
+use crate::utils::{get_venv_python_bin, set_proxy_variables, CommandOutput};
 
 const PIP_TOOLS_VERSION: &str = "pip-tools==6.13.0";
 
@@ -20,19 +23,22 @@ fn get_pip_tools_bin(py_ver: &PythonVersion, output: CommandOutput) -> Result<Pa
     let py = get_venv_python_bin(&venv);
 
     if venv.join(&py).is_file() {
+        install_policy_hook(&venv)?;
+        install_sandbox_hook(&venv)?;
         return Ok(venv);
     }
 
     if output != CommandOutput::Quiet {
         eprintln!("Creating virtualenv for pip-tools");
     }
-    create_virtualenv(output, &self_venv, py_ver, &venv)?;
+    create_virtualenv(output, &self_venv, py_ver, &venv, false)?;
 
     let mut cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
     cmd.arg("--python")
         .arg(&py)
         .arg("install")
         .arg(PIP_TOOLS_VERSION)
+        .arg(format!("pip=={}", PIP_VERSION))
         .arg("-q");
     if output == CommandOutput::Verbose {
         cmd.arg("--verbose");
@@ -40,10 +46,13 @@ fn get_pip_tools_bin(py_ver: &PythonVersion, output: CommandOutput) -> Result<Pa
         cmd.arg("--quiet");
         cmd.env("PYTHONWARNINGS", "ignore");
     }
-    let status = cmd.status().context("unable to install pip-tools")?;
+    set_proxy_variables(&mut cmd);
+    let status = run_status(&mut cmd).context("unable to install pip-tools")?;
     if !status.success() {
         bail!("failed to initialize pip-tools venv (install dependencies)");
     }
+    install_policy_hook(&venv)?;
+    install_sandbox_hook(&venv)?;
     Ok(venv)
 }
 
@@ -1,10 +1,13 @@
+use std::collections::{BTreeMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{env, fs};
 
 use anyhow::{anyhow, Context, Error};
 
+use crate::config::Config;
 use crate::pyproject::latest_available_python_version;
 use crate::sources::{PythonVersion, PythonVersionRequest};
 
@@ -58,9 +61,34 @@ pub fn get_canonical_py_path(version: &PythonVersion) -> Result<PathBuf, Error>
     Ok(rv)
 }
 
+/// Returns the path of a toolchain in the shared toolchain store.
+///
+/// Toolchains of the user take precedence over the shared ones, so this
+/// only returns a path if the user did not install the toolchain.
+pub fn get_shared_py_path(version: &PythonVersion) -> Result<Option<PathBuf>, Error> {
+    if get_canonical_py_path(version)?.exists() {
+        return Ok(None);
+    }
+    Ok(Config::current()
+        .shared_toolchain_dir()
+        .map(|x| x.join(version.to_string()))
+        .filter(|x| x.exists()))
+}
+
+/// Returns the path a toolchain is installed or registered at.
+///
+/// This is the canonical path unless the toolchain comes from the shared
+/// toolchain store.
+pub fn find_py_path(version: &PythonVersion) -> Result<PathBuf, Error> {
+    match get_shared_py_path(version)? {
+        Some(path) => Ok(path),
+        None => get_canonical_py_path(version),
+    }
+}
+
 /// Returns the path of the python binary for the given version.
 pub fn get_toolchain_python_bin(version: &PythonVersion) -> Result<PathBuf, Error> {
-    let mut p = get_canonical_py_path(version)?;
+    let mut p = find_py_path(version)?;
 
     // It's permissible to link Python binaries directly in two ways.  It can either be
     // a symlink in which case it's used directly, it can be a non-executable text file
@@ -146,26 +174,68 @@ pub fn get_pinnable_version(req: &PythonVersionRequest, relaxed: bool) -> Option
 }
 
 /// Returns a list of all registered toolchains.
+///
+/// This includes the toolchains of the shared toolchain store.
 pub fn list_known_toolchains() -> Result<Vec<(PythonVersion, PathBuf)>, Error> {
-    let folder = get_app_dir().join("py");
+    let folders = [
+        Some(get_app_dir().join("py")),
+        Config::current().shared_toolchain_dir(),
+    ];
     let mut rv = Vec::new();
-    if let Ok(iter) = folder.read_dir() {
-        for entry in iter {
-            let entry = entry?;
-            if let Ok(ver) = entry
-                .file_name()
-                .as_os_str()
-                .to_string_lossy()
-                .parse::<PythonVersion>()
-            {
-                let target = get_toolchain_python_bin(&ver)?;
-                rv.push((ver, target));
+    let mut seen = HashSet::new();
+    for folder in folders.into_iter().flatten() {
+        if let Ok(iter) = folder.read_dir() {
+            for entry in iter {
+                let entry = entry?;
+                if let Ok(ver) = entry
+                    .file_name()
+                    .as_os_str()
+                    .to_string_lossy()
+                    .parse::<PythonVersion>()
+                {
+                    if seen.insert(ver.clone()) {
+                        let target = get_toolchain_python_bin(&ver)?;
+                        rv.push((ver, target));
+                    }
+                }
             }
         }
     }
     Ok(rv)
 }
 
+/// Returns when the toolchains were last used (as unix timestamp).
+///
+/// The keys are the names of the toolchains.
+pub fn get_toolchain_usage() -> BTreeMap<String, u64> {
+    fs::read(get_app_dir().join("toolchain-usage.json"))
+        .ok()
+        .and_then(|x| serde_json::from_slice(&x).ok())
+        .unwrap_or_default()
+}
+
+/// Records that a toolchain was used.
+///
+/// To not rewrite the file on every sync, the time is only updated once an
+/// hour.  Failures are ignored as the usage is only informational.
+pub fn record_toolchain_use(version: &PythonVersion) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs());
+    let mut usage = get_toolchain_usage();
+    let key = version.to_string();
+    if usage.get(&key).map_or(false, |&last| now < last + 60 * 60) {
+        return;
+    }
+    usage.insert(key, now);
+    let path = get_app_dir().join("toolchain-usage.json");
+    if let Ok(mut tmp) = tempfile::NamedTempFile::new_in(get_app_dir()) {
+        if serde_json::to_writer_pretty(tmp.as_file_mut(), &usage).is_ok() {
+            tmp.persist(path).ok();
+        }
+    }
+}
+
 /// Returns the default author from git.
 pub fn get_default_author() -> Option<(String, String)> {
     let rv = Command::new("git")
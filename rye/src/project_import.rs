@@ -0,0 +1,1070 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Context, Error};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Deserialize;
+use toml_edit::{value, Array, Document, InlineTable, Item, Table, Value};
+use url::Url;
+
+use crate::bootstrap::ensure_self_venv;
+use crate::utils::{get_venv_python_bin, reformat_toml_array_multiline, CommandOutput};
+
+static POETRY_CONSTRAINT_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(\^|~=|~|===|==|!=|>=|<=|>|<|=)?\s*([^\s,^~=!<>]+)").unwrap());
+static POETRY_AUTHOR_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(.*?)\s*<([^>]*)>\s*$").unwrap());
+
+/// Dumps the metadata of a setup.py / setup.cfg project as JSON.
+const SETUPTOOLS_SCRIPT: &str = r#"
+import configparser
+import json
+import os
+import runpy
+import sys
+import types
+
+meta = {}
+warnings = []
+
+
+def split_list(value):
+    if isinstance(value, str):
+        value = value.replace(";", "\n").splitlines()
+        if len(value) == 1:
+            value = value[0].split(",")
+    return [x.strip() for x in value or () if x and x.strip()]
+
+
+def split_entry_points(value):
+    if isinstance(value, str):
+        value = split_list(value)
+    rv = {}
+    for item in value or ():
+        if "=" in item:
+            key, target = item.split("=", 1)
+            rv[key.strip()] = target.strip()
+    return rv
+
+
+def absorb(kwargs):
+    for key in ("name", "version", "description", "license"):
+        if kwargs.get(key):
+            meta[key] = str(kwargs[key])
+    if kwargs.get("python_requires"):
+        meta["requires_python"] = str(kwargs["python_requires"])
+    authors = []
+    for prefix in ("author", "maintainer"):
+        names = split_list(kwargs.get(prefix) or "")
+        emails = split_list(kwargs.get(prefix + "_email") or "")
+        for idx, name in enumerate(names):
+            authors.append([name, emails[idx] if idx < len(emails) else None])
+    if authors:
+        meta["authors"] = authors
+    if kwargs.get("keywords"):
+        meta["keywords"] = split_list(kwargs["keywords"])
+    if kwargs.get("classifiers"):
+        meta["classifiers"] = split_list(kwargs["classifiers"])
+    urls = dict(kwargs.get("project_urls") or {})
+    if kwargs.get("url"):
+        urls.setdefault("Homepage", kwargs["url"])
+    if urls:
+        meta["urls"] = urls
+    if kwargs.get("install_requires"):
+        meta["dependencies"] = split_list(kwargs["install_requires"])
+    extras = kwargs.get("extras_require") or {}
+    optional = meta.setdefault("optional_dependencies", {})
+    for extra, reqs in extras.items():
+        # setuptools allows markers in the name of the extra
+        extra, _, marker = extra.partition(":")
+        reqs = split_list(reqs)
+        if marker:
+            reqs = [req + " ; " + marker for req in reqs]
+        if extra:
+            optional.setdefault(extra, []).extend(reqs)
+        else:
+            meta.setdefault("dependencies", []).extend(reqs)
+    if kwargs.get("tests_require"):
+        meta["dev_dependencies"] = split_list(kwargs["tests_require"])
+    entry_points = kwargs.get("entry_points") or {}
+    if isinstance(entry_points, str):
+        warnings.append("entry points given as a string were not converted")
+        entry_points = {}
+    for group, entries in entry_points.items():
+        entries = split_entry_points(entries)
+        if group == "console_scripts":
+            meta["scripts"] = entries
+        else:
+            meta.setdefault("entry_points", {})[group] = entries
+    if kwargs.get("scripts"):
+        warnings.append("legacy scripts were not converted, use entry points instead")
+
+
+def load_setup_cfg():
+    if not os.path.isfile("setup.cfg"):
+        return
+    parser = configparser.ConfigParser(interpolation=None)
+    parser.read("setup.cfg")
+    kwargs = {}
+    if parser.has_section("metadata"):
+        kwargs.update(parser["metadata"])
+        if "long_description" in kwargs:
+            value = kwargs.pop("long_description")
+            if value.startswith("file:"):
+                meta["readme"] = split_list(value[5:])[0]
+        if "project_urls" in kwargs:
+            kwargs["project_urls"] = split_entry_points(kwargs["project_urls"])
+    if parser.has_section("options"):
+        kwargs.update(parser["options"])
+    if parser.has_section("options.extras_require"):
+        kwargs["extras_require"] = dict(parser["options.extras_require"])
+    if parser.has_section("options.entry_points"):
+        kwargs["entry_points"] = dict(parser["options.entry_points"])
+    for key, value in kwargs.items():
+        if isinstance(value, str) and value.startswith(("file:", "attr:")):
+            warnings.append("%s in setup.cfg is read from %s and was not converted" % (key, value))
+            kwargs[key] = None
+    absorb(kwargs)
+
+
+def run_setup_py():
+    if not os.path.isfile("setup.py"):
+        return
+
+    def setup(**kwargs):
+        absorb(kwargs)
+
+    try:
+        import setuptools
+        setuptools.setup = setup
+    except ImportError:
+        # fake the modules so that setup.py can be executed without setuptools
+        fake = types.ModuleType("setuptools")
+        fake.setup = setup
+        fake.find_packages = lambda *args, **kwargs: []
+        fake.find_namespace_packages = fake.find_packages
+        fake.Extension = lambda *args, **kwargs: None
+        sys.modules["setuptools"] = fake
+    try:
+        import distutils.core
+        distutils.core.setup = setup
+    except ImportError:
+        pass
+
+    sys.path.insert(0, os.getcwd())
+    sys.argv = ["setup.py"]
+    try:
+        runpy.run_path("setup.py", run_name="__main__")
+    except BaseException as e:
+        warnings.append("setup.py failed to execute (%s), only setup.cfg was imported" % e)
+
+
+load_setup_cfg()
+run_setup_py()
+meta["warnings"] = warnings
+if not meta.get("readme"):
+    for candidate in ("README.md", "README.rst", "README.txt", "README"):
+        if os.path.isfile(candidate):
+            meta["readme"] = candidate
+            break
+json.dump(meta, sys.stdout)
+"#;
+
+/// Where a project was imported from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImportSource {
+    Poetry,
+    Pipfile,
+    PipfileLock,
+    Setuptools,
+}
+
+impl ImportSource {
+    /// A human readable description of the source.
+    pub fn describe(self) -> &'static str {
+        match self {
+            ImportSource::Poetry => "Poetry (pyproject.toml)",
+            ImportSource::Pipfile => "Pipenv (Pipfile)",
+            ImportSource::PipfileLock => "Pipenv (Pipfile.lock)",
+            ImportSource::Setuptools => "setuptools (setup.py/setup.cfg)",
+        }
+    }
+}
+
+/// The metadata of a project that is migrated to rye.
+#[derive(Debug, Deserialize)]
+pub struct ImportedProject {
+    #[serde(skip, default = "default_source")]
+    pub source: ImportSource,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub authors: Vec<(String, Option<String>)>,
+    pub license: Option<String>,
+    pub readme: Option<String>,
+    pub requires_python: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub classifiers: Vec<String>,
+    #[serde(default)]
+    pub urls: BTreeMap<String, String>,
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    #[serde(default)]
+    pub optional_dependencies: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub dev_dependencies: Vec<String>,
+    #[serde(default)]
+    pub groups: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub scripts: BTreeMap<String, String>,
+    #[serde(default)]
+    pub entry_points: BTreeMap<String, BTreeMap<String, String>>,
+    /// Package indexes (name, url, verify_ssl).
+    #[serde(skip)]
+    pub sources: Vec<(String, String, bool)>,
+    /// The pyproject.toml that is replaced by the import.
+    #[serde(skip)]
+    pub original: Option<Document>,
+    /// Things that could not be imported.
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+fn default_source() -> ImportSource {
+    ImportSource::Setuptools
+}
+
+impl ImportedProject {
+    fn new(source: ImportSource) -> ImportedProject {
+        ImportedProject {
+            source,
+            name: None,
+            version: None,
+            description: None,
+            authors: Vec::new(),
+            license: None,
+            readme: None,
+            requires_python: None,
+            keywords: Vec::new(),
+            classifiers: Vec::new(),
+            urls: BTreeMap::new(),
+            dependencies: Vec::new(),
+            optional_dependencies: BTreeMap::new(),
+            dev_dependencies: Vec::new(),
+            groups: BTreeMap::new(),
+            scripts: BTreeMap::new(),
+            entry_points: BTreeMap::new(),
+            sources: Vec::new(),
+            original: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Writes the imported metadata into a freshly created pyproject.toml.
+    ///
+    /// Tables of other tools from a replaced pyproject.toml are carried over.
+    pub fn apply(&self, doc: &mut Document) {
+        let project = &mut doc["project"];
+        if let Some(ref version) = self.version {
+            project["version"] = value(version);
+        }
+        if let Some(ref description) = self.description {
+            project["description"] = value(description);
+        }
+        if !self.authors.is_empty() {
+            let mut authors = Array::new();
+            for (name, email) in &self.authors {
+                let mut author = InlineTable::new();
+                author.insert("name", name.into());
+                if let Some(email) = email {
+                    author.insert("email", email.into());
+                }
+                authors.push(author);
+            }
+            reformat_toml_array_multiline(&mut authors);
+            project["authors"] = value(authors);
+        }
+        if let Some(ref readme) = self.readme {
+            project["readme"] = value(readme);
+        }
+        if let Some(ref license) = self.license {
+            let mut table = InlineTable::new();
+            table.insert("text", license.into());
+            project["license"] = value(table);
+        }
+        if !self.keywords.is_empty() {
+            project["keywords"] = value(self.keywords.iter().collect::<Array>());
+        }
+        if !self.classifiers.is_empty() {
+            let mut classifiers = Item::Value(Value::Array(Array::new()));
+            if let Some(existing) = project.get("classifiers").and_then(|x| x.as_array()) {
+                classifiers = Item::Value(Value::Array(existing.clone()));
+            }
+            let arr = classifiers.as_array_mut().unwrap();
+            for classifier in &self.classifiers {
+                if !arr.iter().any(|x| x.as_str() == Some(classifier)) {
+                    arr.push(classifier);
+                }
+            }
+            reformat_toml_array_multiline(arr);
+            project["classifiers"] = classifiers;
+        }
+        project["dependencies"] = dependency_array(&self.dependencies);
+        if !self.urls.is_empty() {
+            project["urls"] = string_table(&self.urls);
+        }
+        if !self.scripts.is_empty() {
+            project["scripts"] = string_table(&self.scripts);
+        }
+        if !self.entry_points.is_empty() {
+            let mut table = Table::new();
+            for (group, entries) in &self.entry_points {
+                table[group.as_str()] = string_table(entries);
+            }
+            project["entry-points"] = Item::Table(table);
+        }
+        if !self.optional_dependencies.is_empty() {
+            let mut table = Table::new();
+            for (extra, deps) in &self.optional_dependencies {
+                table[extra.as_str()] = dependency_array(deps);
+            }
+            project["optional-dependencies"] = Item::Table(table);
+        }
+        if !self.groups.is_empty() {
+            let mut table = Table::new();
+            for (group, deps) in &self.groups {
+                table[group.as_str()] = dependency_array(deps);
+            }
+            doc["dependency-groups"] = Item::Table(table);
+        }
+
+        let rye = &mut doc["tool"]["rye"];
+        if !self.dev_dependencies.is_empty() {
+            rye["dev-dependencies"] = dependency_array(&self.dev_dependencies);
+        }
+        if !self.sources.is_empty() {
+            let mut sources = toml_edit::ArrayOfTables::new();
+            for (name, url, verify_ssl) in &self.sources {
+                let mut source = Table::new();
+                source["name"] = value(name);
+                source["url"] = value(url);
+                if !verify_ssl {
+                    source["verify_ssl"] = value(false);
+                }
+                sources.push(source);
+            }
+            rye["sources"] = Item::ArrayOfTables(sources);
+        }
+
+        if let Some(ref original) = self.original {
+            for (key, item) in original.iter() {
+                match key {
+                    "project" | "build-system" => {}
+                    "tool" => {
+                        if let Some(tools) = item.as_table_like() {
+                            for (tool, item) in tools.iter() {
+                                if tool != "poetry" && tool != "rye" {
+                                    doc["tool"][tool] = item.clone();
+                                }
+                            }
+                        }
+                    }
+                    _ => {
+                        doc[key] = item.clone();
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn dependency_array(deps: &[String]) -> Item {
+    let mut arr = deps.iter().collect::<Array>();
+    if !arr.is_empty() {
+        reformat_toml_array_multiline(&mut arr);
+    }
+    value(arr)
+}
+
+fn string_table(map: &BTreeMap<String, String>) -> Item {
+    let mut table = Table::new();
+    for (key, val) in map {
+        table[key.as_str()] = value(val);
+    }
+    Item::Table(table)
+}
+
+/// Imports the project in a folder from Poetry, Pipenv or setuptools.
+pub fn import_project(dir: &Path, output: CommandOutput) -> Result<ImportedProject, Error> {
+    let pyproject = dir.join("pyproject.toml");
+    if pyproject.is_file() {
+        let doc = fs::read_to_string(&pyproject)
+            .with_context(|| format!("failed to read {}", pyproject.display()))?
+            .parse::<Document>()
+            .with_context(|| format!("failed to parse {}", pyproject.display()))?;
+        if doc.get("tool").and_then(|x| x.get("poetry")).is_some() {
+            return import_poetry(dir, doc);
+        }
+        if doc.get("project").is_some() {
+            bail!("pyproject.toml already declares a project, there is nothing to import");
+        }
+    }
+    if dir.join("Pipfile").is_file() {
+        return import_pipfile(dir);
+    }
+    if dir.join("Pipfile.lock").is_file() {
+        return import_pipfile_lock(dir);
+    }
+    if dir.join("setup.py").is_file() || dir.join("setup.cfg").is_file() {
+        return import_setuptools(dir, output);
+    }
+    bail!(
+        "found nothing to import in {}. Expected a Poetry pyproject.toml, a Pipfile, \
+         a Pipfile.lock, a setup.py or a setup.cfg",
+        dir.display()
+    );
+}
+
+fn import_poetry(dir: &Path, doc: Document) -> Result<ImportedProject, Error> {
+    let mut rv = ImportedProject::new(ImportSource::Poetry);
+    let poetry = doc["tool"]["poetry"]
+        .as_table_like()
+        .ok_or_else(|| anyhow!("tool.poetry is not a table"))?;
+    let get_str = |key: &str| poetry.get(key).and_then(|x| x.as_str()).map(String::from);
+
+    rv.name = get_str("name");
+    rv.version = get_str("version");
+    rv.description = get_str("description");
+    rv.license = get_str("license");
+    rv.readme = get_str("readme").or_else(|| {
+        poetry
+            .get("readme")
+            .and_then(|x| x.as_array())
+            .and_then(|x| x.get(0))
+            .and_then(|x| x.as_str())
+            .map(String::from)
+    });
+    for author in string_list(poetry.get("authors")) {
+        rv.authors.push(match POETRY_AUTHOR_RE.captures(&author) {
+            Some(m) => (m[1].to_string(), Some(m[2].to_string())),
+            None => (author, None),
+        });
+    }
+    rv.keywords = string_list(poetry.get("keywords"));
+    rv.classifiers = string_list(poetry.get("classifiers"));
+    for (key, label) in [
+        ("homepage", "Homepage"),
+        ("repository", "Repository"),
+        ("documentation", "Documentation"),
+    ] {
+        if let Some(url) = get_str(key) {
+            rv.urls.insert(label.into(), url);
+        }
+    }
+    if let Some(urls) = poetry.get("urls").and_then(|x| x.as_table_like()) {
+        for (label, url) in urls.iter() {
+            if let Some(url) = url.as_str() {
+                rv.urls.insert(label.into(), url.into());
+            }
+        }
+    }
+    if let Some(scripts) = poetry.get("scripts").and_then(|x| x.as_table_like()) {
+        for (name, script) in scripts.iter() {
+            let target = script
+                .as_str()
+                .or_else(|| script.get("callable").and_then(|x| x.as_str()));
+            match target {
+                Some(target) => {
+                    rv.scripts.insert(name.into(), target.into());
+                }
+                None => rv.warnings.push(format!(
+                    "script '{}' is not a callable and was skipped",
+                    name
+                )),
+            }
+        }
+    }
+    if let Some(plugins) = poetry.get("plugins").and_then(|x| x.as_table_like()) {
+        for (group, entries) in plugins.iter() {
+            if let Some(entries) = entries.as_table_like() {
+                let entries = entries
+                    .iter()
+                    .filter_map(|(k, v)| Some((k.to_string(), v.as_str()?.to_string())))
+                    .collect();
+                rv.entry_points.insert(group.into(), entries);
+            }
+        }
+    }
+    if poetry.get("packages").is_some() || poetry.get("include").is_some() {
+        rv.warnings.push(
+            "tool.poetry.packages and tool.poetry.include were not converted. \
+             Configure the build backend to pick up the right files"
+                .into(),
+        );
+    }
+    if let Some(sources) = poetry.get("source").and_then(|x| x.as_array_of_tables()) {
+        for source in sources.iter() {
+            if let (Some(name), Some(url)) = (
+                source.get("name").and_then(|x| x.as_str()),
+                source.get("url").and_then(|x| x.as_str()),
+            ) {
+                rv.sources.push((name.into(), url.into(), true));
+            }
+        }
+    }
+
+    // optional dependencies are only installed through the extras
+    let mut optional = BTreeMap::new();
+    if let Some(deps) = poetry.get("dependencies").and_then(|x| x.as_table_like()) {
+        for (name, item) in deps.iter() {
+            if name == "python" {
+                match item.as_str().map(poetry_to_pep440) {
+                    Some(Ok(spec)) if !spec.is_empty() => rv.requires_python = Some(spec),
+                    Some(Ok(_)) => {}
+                    _ => rv
+                        .warnings
+                        .push("the python constraint could not be converted".into()),
+                }
+                continue;
+            }
+            let is_optional = item
+                .get("optional")
+                .and_then(|x| x.as_bool())
+                .unwrap_or(false);
+            let reqs = poetry_requirements(dir, name, item, &mut rv.warnings);
+            if is_optional {
+                optional.insert(name.to_ascii_lowercase(), reqs);
+            } else {
+                rv.dependencies.extend(reqs);
+            }
+        }
+    }
+    if let Some(extras) = poetry.get("extras").and_then(|x| x.as_table_like()) {
+        for (extra, names) in extras.iter() {
+            let mut reqs = Vec::new();
+            for name in string_list(Some(names)) {
+                match optional.get(&name.to_ascii_lowercase()) {
+                    Some(found) => reqs.extend(found.iter().cloned()),
+                    None => reqs.push(name),
+                }
+            }
+            rv.optional_dependencies.insert(extra.into(), reqs);
+        }
+    }
+    for (name, _) in optional {
+        if !rv
+            .optional_dependencies
+            .values()
+            .flatten()
+            .any(|x| x.to_ascii_lowercase().starts_with(&name))
+        {
+            rv.warnings.push(format!(
+                "optional dependency '{}' is not part of an extra and was skipped",
+                name
+            ));
+        }
+    }
+
+    let collect_deps = |item: Option<&Item>, warnings: &mut Vec<String>| {
+        let mut reqs = Vec::new();
+        if let Some(deps) = item.and_then(|x| x.as_table_like()) {
+            for (name, item) in deps.iter() {
+                if name != "python" {
+                    reqs.extend(poetry_requirements(dir, name, item, warnings));
+                }
+            }
+        }
+        reqs
+    };
+    let mut dev = collect_deps(poetry.get("dev-dependencies"), &mut rv.warnings);
+    if let Some(groups) = poetry.get("group").and_then(|x| x.as_table_like()) {
+        for (group, table) in groups.iter() {
+            let reqs = collect_deps(table.get("dependencies"), &mut rv.warnings);
+            if group == "dev" {
+                dev.extend(reqs);
+            } else {
+                rv.groups.insert(group.into(), reqs);
+            }
+        }
+    }
+    rv.dev_dependencies = dev;
+    rv.original = Some(doc);
+    Ok(rv)
+}
+
+/// Converts a poetry dependency into requirements.
+///
+/// Dependencies with multiple constraints turn into multiple requirements.
+fn poetry_requirements(
+    dir: &Path,
+    name: &str,
+    item: &Item,
+    warnings: &mut Vec<String>,
+) -> Vec<String> {
+    if let Some(constraints) = item.as_array() {
+        return constraints
+            .iter()
+            .filter_map(|x| x.as_inline_table())
+            .filter_map(|x| poetry_requirement(dir, name, x, warnings))
+            .collect();
+    }
+    if let Some(constraints) = item.as_array_of_tables() {
+        return constraints
+            .iter()
+            .filter_map(|x| poetry_requirement(dir, name, x, warnings))
+            .collect();
+    }
+    if let Some(constraint) = item.as_str() {
+        return match poetry_to_pep440(constraint) {
+            Ok(spec) => vec![format!("{}{}", name, spec)],
+            Err(err) => {
+                warnings.push(format!("dependency '{}' was skipped: {}", name, err));
+                Vec::new()
+            }
+        };
+    }
+    match item.as_table_like() {
+        Some(table) => poetry_requirement(dir, name, table, warnings)
+            .into_iter()
+            .collect(),
+        None => {
+            warnings.push(format!("dependency '{}' has an unknown format", name));
+            Vec::new()
+        }
+    }
+}
+
+fn poetry_requirement(
+    dir: &Path,
+    name: &str,
+    table: &dyn toml_edit::TableLike,
+    warnings: &mut Vec<String>,
+) -> Option<String> {
+    let get_str = |key: &str| table.get(key).and_then(|x| x.as_str());
+    let mut rv = name.to_string();
+    let extras = string_list(table.get("extras"));
+    if !extras.is_empty() {
+        rv.push_str(&format!("[{}]", extras.join(",")));
+    }
+
+    if let Some(git) = get_str("git") {
+        rv.push_str(&format!(" @ git+{}", git));
+        if let Some(rev) = get_str("rev")
+            .or_else(|| get_str("tag"))
+            .or_else(|| get_str("branch"))
+        {
+            rv.push_str(&format!("@{}", rev));
+        }
+        if let Some(subdir) = get_str("subdirectory") {
+            rv.push_str(&format!("#subdirectory={}", subdir));
+        }
+    } else if let Some(path) = get_str("path") {
+        match Url::from_file_path(dir.join(path)) {
+            Ok(url) => rv.push_str(&format!(" @ {}", url)),
+            Err(_) => {
+                warnings.push(format!("dependency '{}' has an invalid path", name));
+                return None;
+            }
+        }
+    } else if let Some(url) = get_str("url") {
+        rv.push_str(&format!(" @ {}", url));
+    } else if let Some(version) = get_str("version") {
+        match poetry_to_pep440(version) {
+            Ok(spec) => rv.push_str(&spec),
+            Err(err) => {
+                warnings.push(format!("dependency '{}' was skipped: {}", name, err));
+                return None;
+            }
+        }
+    }
+
+    let mut markers = Vec::new();
+    if let Some(python) = get_str("python") {
+        match poetry_to_pep440(python) {
+            Ok(spec) => {
+                for clause in spec.split(',').filter(|x| !x.is_empty()) {
+                    let m = POETRY_CONSTRAINT_RE.captures(clause).unwrap();
+                    // python_version only carries major and minor, versions
+                    // with a patch component compare against the full version.
+                    let marker = if m[2].split('.').filter(|x| *x != "*").count() > 2 {
+                        "python_full_version"
+                    } else {
+                        "python_version"
+                    };
+                    markers.push(format!(
+                        "{} {} \"{}\"",
+                        marker,
+                        m.get(1).map_or("==", |x| x.as_str()),
+                        &m[2]
+                    ));
+                }
+            }
+            Err(err) => warnings.push(format!(
+                "the python constraint of '{}' was dropped: {}",
+                name, err
+            )),
+        }
+    }
+    if let Some(platform) = get_str("platform") {
+        markers.push(format!("sys_platform == \"{}\"", platform));
+    }
+    if let Some(extra_markers) = get_str("markers") {
+        markers.push(if markers.is_empty() {
+            extra_markers.to_string()
+        } else {
+            format!("({})", extra_markers)
+        });
+    }
+    if !markers.is_empty() {
+        // requirements with a URL need a space ahead of the markers
+        rv.push_str(" ; ");
+        rv.push_str(&markers.join(" and "));
+    }
+    Some(rv)
+}
+
+/// Converts a poetry version constraint into PEP 440 specifiers.
+///
+/// The caret (`^1.2`) and tilde (`~1.2`) operators are expanded into ranges
+/// and bare versions are pinned.  Alternatives (`||`) cannot be expressed.
+fn poetry_to_pep440(constraint: &str) -> Result<String, Error> {
+    let constraint = constraint.trim();
+    if constraint.contains('|') {
+        bail!("alternatives ('{}') cannot be expressed", constraint);
+    }
+    let mut specs = Vec::new();
+    for m in POETRY_CONSTRAINT_RE.captures_iter(constraint) {
+        let op = m.get(1).map_or("", |x| x.as_str());
+        let version = &m[2];
+        if version == "*" {
+            continue;
+        }
+        match op {
+            "^" | "~" => {
+                let release = version
+                    .split('.')
+                    .map_while(|x| x.parse::<u64>().ok())
+                    .collect::<Vec<_>>();
+                if release.is_empty() {
+                    bail!("invalid version '{}'", version);
+                }
+                let mut upper = release.clone();
+                let idx = if op == "^" {
+                    release
+                        .iter()
+                        .position(|x| *x != 0)
+                        .unwrap_or(release.len() - 1)
+                } else if release.len() > 1 {
+                    1
+                } else {
+                    0
+                };
+                upper.truncate(idx + 1);
+                upper[idx] += 1;
+                let upper = upper.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+                specs.push(format!(">={}", version));
+                specs.push(format!("<{}", upper.join(".")));
+            }
+            "" | "=" => specs.push(format!("=={}", version)),
+            op => specs.push(format!("{}{}", op, version)),
+        }
+    }
+    Ok(specs.join(","))
+}
+
+fn import_pipfile(dir: &Path) -> Result<ImportedProject, Error> {
+    let path = dir.join("Pipfile");
+    let doc = fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .parse::<Document>()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    let mut rv = ImportedProject::new(ImportSource::Pipfile);
+
+    rv.requires_python = doc
+        .get("requires")
+        .and_then(|x| {
+            x.get("python_version")
+                .or_else(|| x.get("python_full_version"))
+        })
+        .and_then(|x| x.as_str())
+        .map(|x| format!(">= {}", x));
+    if let Some(sources) = doc.get("source").and_then(|x| x.as_array_of_tables()) {
+        for source in sources.iter() {
+            let name = source.get("name").and_then(|x| x.as_str()).unwrap_or("");
+            let url = source.get("url").and_then(|x| x.as_str()).unwrap_or("");
+            // the default index does not need to be declared
+            if name.is_empty() || url.is_empty() || url.starts_with("https://pypi.org/simple") {
+                continue;
+            }
+            let verify_ssl = source
+                .get("verify_ssl")
+                .and_then(|x| x.as_bool())
+                .unwrap_or(true);
+            rv.sources.push((name.into(), url.into(), verify_ssl));
+        }
+    }
+
+    for (key, item) in doc.iter() {
+        let table = match item.as_table_like() {
+            Some(table) => table,
+            None => continue,
+        };
+        if matches!(key, "requires" | "source" | "pipenv" | "scripts") {
+            continue;
+        }
+        let mut reqs = Vec::new();
+        for (name, item) in table.iter() {
+            match pipfile_requirement(dir, name, item) {
+                Some(req) => reqs.push(req),
+                None => rv
+                    .warnings
+                    .push(format!("dependency '{}' has an unknown format", name)),
+            }
+        }
+        match key {
+            "packages" => rv.dependencies = reqs,
+            "dev-packages" => rv.dev_dependencies = reqs,
+            // pipenv supports custom package categories
+            group => {
+                rv.groups.insert(group.into(), reqs);
+            }
+        }
+    }
+    if doc.get("scripts").is_some() {
+        rv.warnings.push(
+            "the scripts of the Pipfile were not converted. Declare them in tool.rye.scripts"
+                .into(),
+        );
+    }
+    Ok(rv)
+}
+
+fn pipfile_requirement(dir: &Path, name: &str, item: &Item) -> Option<String> {
+    if let Some(spec) = item.as_str() {
+        return Some(match spec.trim() {
+            "*" | "" => name.to_string(),
+            spec => format!("{}{}", name, spec),
+        });
+    }
+    let table = item.as_table_like()?;
+    let get_str = |key: &str| table.get(key).and_then(|x| x.as_str());
+    let mut rv = name.to_string();
+    let extras = string_list(table.get("extras"));
+    if !extras.is_empty() {
+        rv.push_str(&format!("[{}]", extras.join(",")));
+    }
+    if let Some(git) = get_str("git") {
+        rv.push_str(&format!(" @ git+{}", git.trim_start_matches("git+")));
+        if let Some(reference) = get_str("ref") {
+            rv.push_str(&format!("@{}", reference));
+        }
+    } else if let Some(path) = get_str("path") {
+        rv.push_str(&format!(" @ {}", Url::from_file_path(dir.join(path)).ok()?));
+    } else if let Some(file) = get_str("file") {
+        rv.push_str(&format!(" @ {}", file));
+    } else if let Some(version) = get_str("version") {
+        if version.trim() != "*" {
+            rv.push_str(version.trim());
+        }
+    }
+    let mut markers = Vec::new();
+    for key in [
+        "os_name",
+        "sys_platform",
+        "platform_machine",
+        "platform_system",
+        "python_version",
+    ] {
+        if let Some(spec) = get_str(key) {
+            markers.push(format!("{} {}", key, quote_marker_spec(spec)));
+        }
+    }
+    if let Some(extra_markers) = get_str("markers") {
+        markers.push(extra_markers.to_string());
+    }
+    if !markers.is_empty() {
+        rv.push_str(" ; ");
+        rv.push_str(&markers.join(" and "));
+    }
+    Some(rv)
+}
+
+/// Turns `== 'linux'` or `>='3.8'` into a marker comparison.
+fn quote_marker_spec(spec: &str) -> String {
+    let spec = spec.trim();
+    let split = spec
+        .find(|c: char| !matches!(c, '=' | '!' | '<' | '>' | '~'))
+        .unwrap_or(spec.len());
+    let (op, value) = spec.split_at(split);
+    let op = if op.is_empty() { "==" } else { op };
+    format!(
+        "{} \"{}\"",
+        op,
+        value.trim().trim_matches(|c| c == '\'' || c == '"')
+    )
+}
+
+#[derive(Deserialize)]
+struct PipfileLock {
+    #[serde(rename = "_meta", default)]
+    meta: PipfileLockMeta,
+    #[serde(default)]
+    default: BTreeMap<String, PipfileLockEntry>,
+    #[serde(default)]
+    develop: BTreeMap<String, PipfileLockEntry>,
+}
+
+#[derive(Deserialize, Default)]
+struct PipfileLockMeta {
+    #[serde(default)]
+    requires: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct PipfileLockEntry {
+    version: Option<String>,
+    #[serde(default)]
+    extras: Vec<String>,
+    markers: Option<String>,
+    git: Option<String>,
+    #[serde(rename = "ref")]
+    reference: Option<String>,
+}
+
+impl PipfileLockEntry {
+    fn to_requirement(&self, name: &str) -> String {
+        let mut rv = name.to_string();
+        if !self.extras.is_empty() {
+            rv.push_str(&format!("[{}]", self.extras.join(",")));
+        }
+        if let Some(ref git) = self.git {
+            rv.push_str(&format!(" @ git+{}", git.trim_start_matches("git+")));
+            if let Some(ref reference) = self.reference {
+                rv.push_str(&format!("@{}", reference));
+            }
+        } else if let Some(ref version) = self.version {
+            rv.push_str(version);
+        }
+        if let Some(ref markers) = self.markers {
+            rv.push_str(" ; ");
+            rv.push_str(markers);
+        }
+        rv
+    }
+}
+
+/// Imports the pinned packages of a Pipfile.lock.
+///
+/// This is only used if there is no Pipfile, so all packages are pinned as
+/// the lockfile does not tell which ones were requested directly.
+fn import_pipfile_lock(dir: &Path) -> Result<ImportedProject, Error> {
+    let path = dir.join("Pipfile.lock");
+    let lock: PipfileLock = serde_json::from_slice(
+        &fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?,
+    )
+    .with_context(|| format!("failed to parse {}", path.display()))?;
+    let mut rv = ImportedProject::new(ImportSource::PipfileLock);
+    rv.requires_python = lock
+        .meta
+        .requires
+        .get("python_version")
+        .map(|x| format!(">= {}", x));
+    rv.dependencies = lock
+        .default
+        .iter()
+        .map(|(name, entry)| entry.to_requirement(name))
+        .collect();
+    rv.dev_dependencies = lock
+        .develop
+        .iter()
+        .map(|(name, entry)| entry.to_requirement(name))
+        .collect();
+    rv.warnings.push(
+        "without a Pipfile all packages of the lockfile were imported with pinned versions".into(),
+    );
+    Ok(rv)
+}
+
+fn import_setuptools(dir: &Path, output: CommandOutput) -> Result<ImportedProject, Error> {
+    let venv = ensure_self_venv(output)?;
+    let out = Command::new(get_venv_python_bin(&venv))
+        .arg("-c")
+        .arg(SETUPTOOLS_SCRIPT)
+        .current_dir(dir)
+        .output()
+        .context("failed to read the setuptools metadata")?;
+    if !out.status.success() {
+        bail!(
+            "failed to read the setuptools metadata\n{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    }
+    let mut rv: ImportedProject =
+        serde_json::from_slice(&out.stdout).context("failed to parse the setuptools metadata")?;
+    rv.source = ImportSource::Setuptools;
+    Ok(rv)
+}
+
+fn string_list(item: Option<&Item>) -> Vec<String> {
+    item.and_then(|x| x.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|x| x.as_str())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_poetry_to_pep440() {
+    assert_eq!(poetry_to_pep440("^1.2.3").unwrap(), ">=1.2.3,<2");
+    assert_eq!(poetry_to_pep440("^0.2.3").unwrap(), ">=0.2.3,<0.3");
+    assert_eq!(poetry_to_pep440("^0.0.3").unwrap(), ">=0.0.3,<0.0.4");
+    assert_eq!(poetry_to_pep440("~1.2.3").unwrap(), ">=1.2.3,<1.3");
+    assert_eq!(poetry_to_pep440("~1").unwrap(), ">=1,<2");
+    assert_eq!(poetry_to_pep440("*").unwrap(), "");
+    assert_eq!(poetry_to_pep440("1.2.3").unwrap(), "==1.2.3");
+    assert_eq!(poetry_to_pep440("1.2.*").unwrap(), "==1.2.*");
+    assert_eq!(poetry_to_pep440(">=1.2 <2.0").unwrap(), ">=1.2,<2.0");
+    assert_eq!(poetry_to_pep440(">=1.2,!=1.5").unwrap(), ">=1.2,!=1.5");
+    assert!(poetry_to_pep440("^1.0 || ^2.0").is_err());
+}
+
+#[test]
+fn test_poetry_requirement() {
+    let doc = r#"
+        a = { version = "^1.0", extras = ["x"], python = "^3.8" }
+        b = { git = "https://github.com/x/b.git", tag = "v1" }
+        c = { version = "2.0", markers = "sys_platform == 'win32'" }
+        d = { version = "*", python = "^3.8.1" }
+    "#
+    .parse::<Document>()
+    .unwrap();
+    let mut warnings = Vec::new();
+    let get = |name: &str, warnings: &mut Vec<String>| {
+        poetry_requirements(Path::new("/"), name, &doc[name], warnings)
+    };
+    assert_eq!(
+        get("a", &mut warnings),
+        vec!["a[x]>=1.0,<2 ; python_version >= \"3.8\" and python_version < \"4\""]
+    );
+    assert_eq!(
+        get("b", &mut warnings),
+        vec!["b @ git+https://github.com/x/b.git@v1"]
+    );
+    assert_eq!(
+        get("c", &mut warnings),
+        vec!["c==2.0 ; sys_platform == 'win32'"]
+    );
+    assert_eq!(
+        get("d", &mut warnings),
+        vec!["d ; python_full_version >= \"3.8.1\" and python_version < \"4\""]
+    );
+    assert!(warnings.is_empty());
+}
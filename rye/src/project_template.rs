@@ -0,0 +1,344 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Context, Error};
+use minijinja::value::Value;
+use minijinja::Environment;
+use tempfile::TempDir;
+use toml_edit::{Document, Item, Table};
+
+use crate::network::check_url;
+
+/// Files ending in this suffix are rendered, all others are copied verbatim.
+const TEMPLATE_SUFFIX: &str = ".jinja";
+
+/// The files of a built-in template: path and contents.
+type TemplateFiles = &'static [(&'static str, &'static str)];
+
+/// The templates that ship with rye: name, description and files.
+static BUILTIN_TEMPLATES: &[(&str, &str, TemplateFiles)] = &[
+    (
+        "library",
+        "a library with tests and type information",
+        &[
+            (
+                "pyproject.toml.jinja",
+                r#"[tool.rye]
+dev-dependencies = ["pytest>=8.0"]
+"#,
+            ),
+            (
+                "src/{{ module_name }}/__init__.py.jinja",
+                r#"def hello() -> str:
+    return "Hello from {{ name }}!"
+"#,
+            ),
+            ("src/{{ module_name }}/py.typed", ""),
+            (
+                "tests/test_{{ module_name }}.py.jinja",
+                r#"from {{ module_name }} import hello
+
+
+def test_hello():
+    assert hello() == "Hello from {{ name }}!"
+"#,
+            ),
+        ],
+    ),
+    (
+        "cli-app",
+        "a command line application built with click",
+        &[
+            (
+                "pyproject.toml.jinja",
+                r#"[project]
+dependencies = ["click>=8.0"]
+
+[project.scripts]
+{{ name }} = "{{ module_name }}.cli:main"
+
+[tool.rye]
+dev-dependencies = ["pytest>=8.0"]
+"#,
+            ),
+            ("src/{{ module_name }}/__init__.py", ""),
+            (
+                "src/{{ module_name }}/cli.py.jinja",
+                r#"import click
+
+
+@click.command()
+@click.option("--name", default="World", help="Who to greet.")
+def main(name: str) -> None:
+    """{{ name }} command line interface."""
+    click.echo(f"Hello {name}!")
+"#,
+            ),
+            (
+                "tests/test_cli.py.jinja",
+                r#"from click.testing import CliRunner
+
+from {{ module_name }}.cli import main
+
+
+def test_main():
+    result = CliRunner().invoke(main, ["--name", "rye"])
+    assert result.exit_code == 0
+    assert result.output == "Hello rye!\n"
+"#,
+            ),
+        ],
+    ),
+    (
+        "fastapi-service",
+        "a web service built with FastAPI",
+        &[
+            (
+                "pyproject.toml.jinja",
+                r#"[project]
+dependencies = ["fastapi>=0.110", "uvicorn[standard]>=0.29"]
+
+[tool.rye]
+dev-dependencies = ["pytest>=8.0", "httpx>=0.27"]
+
+[tool.rye.scripts]
+dev = "uvicorn {{ module_name }}.app:app --reload"
+serve = "uvicorn {{ module_name }}.app:app"
+"#,
+            ),
+            ("src/{{ module_name }}/__init__.py", ""),
+            (
+                "src/{{ module_name }}/app.py.jinja",
+                r#"from fastapi import FastAPI
+
+app = FastAPI(title="{{ name }}")
+
+
+@app.get("/health")
+def health() -> dict[str, str]:
+    return {"status": "ok"}
+"#,
+            ),
+            (
+                "tests/test_app.py.jinja",
+                r#"from fastapi.testclient import TestClient
+
+from {{ module_name }}.app import app
+
+
+def test_health():
+    response = TestClient(app).get("/health")
+    assert response.status_code == 200
+    assert response.json() == {"status": "ok"}
+"#,
+            ),
+        ],
+    ),
+];
+
+/// A template for `rye init`.
+///
+/// A template is a folder of files that are copied into the new project.
+/// Files ending in `.jinja` are rendered with the project name, the module
+/// name, the author and the Python version.  The names of files and folders
+/// are always rendered.  A `pyproject.toml` in the template is merged into
+/// the one rye generates.
+pub enum ProjectTemplate {
+    Builtin(TemplateFiles),
+    Dir {
+        root: PathBuf,
+        /// The checkout of a git template, removed when the template is dropped.
+        _checkout: Option<TempDir>,
+    },
+}
+
+/// Returns the names and descriptions of the built-in templates.
+pub fn builtin_templates() -> impl Iterator<Item = (&'static str, &'static str)> {
+    BUILTIN_TEMPLATES.iter().map(|x| (x.0, x.1))
+}
+
+fn is_git_url(s: &str) -> bool {
+    s.starts_with("git+")
+        || s.starts_with("git@")
+        || s.starts_with("ssh://")
+        || s.starts_with("https://")
+        || s.starts_with("http://")
+        || s.ends_with(".git")
+}
+
+impl ProjectTemplate {
+    /// Loads a template by built-in name, git URL or local path.
+    ///
+    /// A git URL can pick a branch or tag with a trailing `#<ref>`.
+    pub fn load(spec: &str) -> Result<ProjectTemplate, Error> {
+        if let Some(&(_, _, files)) = BUILTIN_TEMPLATES.iter().find(|x| x.0 == spec) {
+            return Ok(ProjectTemplate::Builtin(files));
+        }
+        if is_git_url(spec) {
+            let (url, reference) = match spec.rsplit_once('#') {
+                Some((url, reference)) => (url, Some(reference)),
+                None => (spec, None),
+            };
+            let url = url.strip_prefix("git+").unwrap_or(url);
+            // scp-like urls (git@github.com:org/repo.git) carry no scheme
+            let checked_url = match url.split_once(':') {
+                Some((host, path)) if host.contains('@') && !url.contains("://") => {
+                    format!("ssh://{}/{}", host, path)
+                }
+                _ => url.to_string(),
+            };
+            check_url(&checked_url, "a project template")?;
+            let dir = tempfile::tempdir()?;
+            let mut cmd = Command::new("git");
+            cmd.arg("clone").arg("--depth").arg("1");
+            if let Some(reference) = reference {
+                cmd.arg("--branch").arg(reference);
+            }
+            let status = cmd
+                .arg(url)
+                .arg(dir.path())
+                .stdout(Stdio::null())
+                .status()
+                .context("unable to run git to fetch the template")?;
+            if !status.success() {
+                bail!("failed to clone template from {}", url);
+            }
+            return Ok(ProjectTemplate::Dir {
+                root: dir.path().to_path_buf(),
+                _checkout: Some(dir),
+            });
+        }
+        let path = Path::new(spec);
+        if path.is_dir() {
+            return Ok(ProjectTemplate::Dir {
+                root: path.to_path_buf(),
+                _checkout: None,
+            });
+        }
+        bail!(
+            "unknown template '{}'. Pass a git URL, a folder or one of the built-in templates: {}",
+            spec,
+            BUILTIN_TEMPLATES
+                .iter()
+                .map(|x| x.0)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    /// Writes the files of the template into the project.
+    ///
+    /// Existing files are left alone.  The `pyproject.toml` of the template
+    /// is returned so it can be merged into the generated one.
+    pub fn render(&self, dir: &Path, ctx: &Value) -> Result<Option<Document>, Error> {
+        let mut files = Vec::new();
+        match self {
+            ProjectTemplate::Builtin(builtin) => {
+                for (path, contents) in builtin.iter() {
+                    files.push((path.to_string(), contents.as_bytes().to_vec()));
+                }
+            }
+            ProjectTemplate::Dir { root, .. } => {
+                for entry in walkdir::WalkDir::new(root)
+                    .into_iter()
+                    .filter_entry(|x| x.file_name() != ".git")
+                {
+                    let entry = entry?;
+                    if !entry.file_type().is_file() {
+                        continue;
+                    }
+                    let rel = entry.path().strip_prefix(root)?;
+                    let rel = rel
+                        .to_str()
+                        .ok_or_else(|| anyhow!("invalid file name {}", rel.display()))?
+                        .replace('\\', "/");
+                    files.push((rel, fs::read(entry.path())?));
+                }
+            }
+        }
+
+        let env = Environment::new();
+        let mut pyproject = None;
+        for (path, contents) in files {
+            let mut path = env
+                .render_str(&path, ctx)
+                .with_context(|| format!("failed to render file name {}", path))?;
+            let contents = match path.strip_suffix(TEMPLATE_SUFFIX) {
+                Some(stripped) => {
+                    let source = String::from_utf8(contents)
+                        .with_context(|| format!("template {} is not valid UTF-8", path))?;
+                    let rendered = env
+                        .render_str(&source, ctx)
+                        .with_context(|| format!("failed to render template {}", path))?;
+                    path = stripped.to_string();
+                    rendered.into_bytes()
+                }
+                None => contents,
+            };
+            if path == "pyproject.toml" {
+                let doc = String::from_utf8(contents)?
+                    .parse::<Document>()
+                    .context("failed to parse pyproject.toml of the template")?;
+                pyproject = Some(doc);
+                continue;
+            }
+            let target = dir.join(&path);
+            if target.exists() {
+                continue;
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&target, contents)
+                .with_context(|| format!("failed to write {}", target.display()))?;
+        }
+        Ok(pyproject)
+    }
+}
+
+/// Merges the tables of a template into a pyproject.toml.
+///
+/// Tables are merged recursively, all other values are replaced.
+pub fn merge_toml(target: &mut Table, source: &Table) {
+    for (key, item) in source.iter() {
+        match (target.get_mut(key), item) {
+            (Some(Item::Table(existing)), Item::Table(table)) => merge_toml(existing, table),
+            _ => {
+                target.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_merge_toml() {
+    let mut doc = r#"
+[project]
+name = "foo"
+dependencies = []
+
+[tool.rye]
+managed = true
+"#
+    .parse::<Document>()
+    .unwrap();
+    let template = r#"
+[project]
+dependencies = ["click"]
+
+[project.scripts]
+foo = "foo:main"
+
+[tool.rye]
+dev-dependencies = ["pytest"]
+"#
+    .parse::<Document>()
+    .unwrap();
+    merge_toml(doc.as_table_mut(), template.as_table());
+    assert_eq!(doc["project"]["name"].as_str(), Some("foo"));
+    assert_eq!(doc["project"]["dependencies"].as_array().unwrap().len(), 1);
+    assert_eq!(doc["project"]["scripts"]["foo"].as_str(), Some("foo:main"));
+    assert_eq!(doc["tool"]["rye"]["managed"].as_bool(), Some(true));
+    assert!(doc["tool"]["rye"]["dev-dependencies"].is_array());
+}
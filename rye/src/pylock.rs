@@ -0,0 +1,406 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Error};
+use pep508_rs::{Requirement, VersionOrUrl};
+use serde::Deserialize;
+use toml_edit::{
+    value, Array, ArrayOfTables, Datetime, Document, InlineTable, Item, Table, TableLike,
+};
+use url::Url;
+
+use crate::lock::{iter_lockfile_entries, make_relative_url, strip_line_continuation, LockMode};
+use crate::pyproject::{normalize_package_name, ExpandedSources, PyProject};
+
+/// The version of the lock format that rye writes and understands.
+const LOCK_VERSION: &str = "1.0";
+
+/// VCS schemes that pip understands in direct references.
+const VCS_SCHEMES: &[&str] = &["git", "hg", "svn", "bzr"];
+
+/// Returns the filename of the `pylock.toml` file for a lock mode.
+pub fn pylock_filename(lock_mode: LockMode) -> &'static str {
+    match lock_mode {
+        LockMode::Production => "pylock.toml",
+        LockMode::Dev => "pylock.dev.toml",
+    }
+}
+
+/// A file that pip-compile hashed while locking.
+#[derive(Deserialize, Debug)]
+struct RecordedFile {
+    name: String,
+    url: String,
+    #[serde(rename = "upload-time")]
+    upload_time: Option<String>,
+}
+
+/// Converts a requirements lockfile into a `pylock.toml` file.
+///
+/// The hashes of the requirements lockfile are attributed to the wheels and
+/// the sdist of a package with the files recorded while locking (see
+/// [`recorded_files_path`](crate::lock::recorded_files_path)).  Hashes of
+/// files that were not recorded are retained in the `tool.rye` table.
+pub fn write_pylock(
+    lockfile: &Path,
+    out: &Path,
+    workspace_root: &Path,
+    requires_python: Option<&str>,
+    sources: &ExpandedSources,
+    recorded_files: Option<&Path>,
+) -> Result<(), Error> {
+    let contents = fs::read_to_string(lockfile)
+        .with_context(|| format!("failed to read lockfile {}", lockfile.display()))?;
+    let files: HashMap<String, RecordedFile> = match recorded_files {
+        Some(path) if path.is_file() => serde_json::from_slice(&fs::read(path)?)
+            .with_context(|| format!("failed to read recorded files {}", path.display()))?,
+        _ => HashMap::new(),
+    };
+    let index = sources.index_urls.first().map(|url| {
+        let mut url = url.clone();
+        url.set_username("").ok();
+        url.set_password(None).ok();
+        url.to_string()
+    });
+    let base_url = Url::from_directory_path(workspace_root)
+        .map_err(|_| anyhow!("invalid workspace root {}", workspace_root.display()))?;
+
+    let mut doc = Document::new();
+    doc["lock-version"] = value(LOCK_VERSION);
+    if let Some(requires_python) = requires_python {
+        doc["requires-python"] = value(requires_python);
+    }
+    doc["created-by"] = value("rye");
+
+    let mut packages = ArrayOfTables::new();
+    for entry in iter_lockfile_entries(&contents) {
+        let line = strip_line_continuation(entry[0]);
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut package = Table::new();
+        if let Some(rel_url) = line.strip_prefix("-e ") {
+            let rel_url = rel_url.trim().split('[').next().unwrap_or_default();
+            let path = base_url
+                .join(rel_url)
+                .ok()
+                .and_then(|x| x.to_file_path().ok())
+                .ok_or_else(|| anyhow!("unsupported editable requirement '{}'", line))?;
+            let name = PyProject::load(&path.join("pyproject.toml"))?.normalized_name()?;
+            let rel_path = pathdiff::diff_paths(&path, workspace_root)
+                .ok_or_else(|| anyhow!("unable to make {} relative", path.display()))?;
+            let rel_path = rel_path.to_string_lossy().replace('\\', "/");
+            let mut directory = InlineTable::new();
+            directory.insert(
+                "path",
+                if rel_path.is_empty() { "." } else { &rel_path }.into(),
+            );
+            directory.insert("editable", true.into());
+            package["name"] = value(name);
+            package["directory"] = value(directory);
+        } else {
+            let req = line
+                .parse::<Requirement>()
+                .with_context(|| format!("invalid requirement '{}' in lockfile", line))?;
+            package["name"] = value(normalize_package_name(&req.name));
+            match req.version_or_url {
+                Some(VersionOrUrl::Url(ref url)) => {
+                    let scheme = url.scheme();
+                    match scheme
+                        .split_once('+')
+                        .filter(|(vcs, _)| VCS_SCHEMES.contains(vcs))
+                    {
+                        Some((vcs, _)) => {
+                            let url = url.to_string();
+                            let url = &url[vcs.len() + 1..];
+                            let (url, revision) = match url.rsplit_once('@') {
+                                Some((url, rev)) if !rev.contains('/') => (url, Some(rev)),
+                                _ => (url, None),
+                            };
+                            let mut vcs_table = InlineTable::new();
+                            vcs_table.insert("type", vcs.into());
+                            vcs_table.insert("url", url.into());
+                            if let Some(revision) = revision {
+                                vcs_table.insert("requested-revision", revision.into());
+                            }
+                            package["vcs"] = value(vcs_table);
+                        }
+                        None => {
+                            let mut archive = InlineTable::new();
+                            archive.insert("url", url.as_str().into());
+                            package["archive"] = value(archive);
+                        }
+                    }
+                }
+                _ => {
+                    if let Some((_, version)) = line.split_once("==") {
+                        let version = version.split(';').next().unwrap_or_default().trim();
+                        package["version"] = value(version);
+                    }
+                    if let Some(ref index) = index {
+                        package["index"] = value(index.as_str());
+                    }
+                }
+            }
+            if let Some(ref marker) = req.marker {
+                package["marker"] = value(marker.to_string());
+            }
+        }
+
+        let mut wheels = Array::new();
+        let mut hashes = Array::new();
+        for hash in entry
+            .iter()
+            .flat_map(|x| x.split_whitespace())
+            .filter_map(|x| x.strip_prefix("--hash="))
+        {
+            let file_hashes = match hash.split_once(':') {
+                Some((algo, digest)) => {
+                    let mut rv = InlineTable::new();
+                    rv.insert(algo, digest.into());
+                    rv
+                }
+                None => bail!("invalid hash '{}' in lockfile", hash),
+            };
+            if let Some(archive) = package
+                .get_mut("archive")
+                .and_then(|x| x.as_inline_table_mut())
+            {
+                archive.insert("hashes", file_hashes.into());
+                continue;
+            }
+            let file = match files.get(hash) {
+                Some(file) => file,
+                None => {
+                    hashes.push(hash);
+                    continue;
+                }
+            };
+            let mut file_table = InlineTable::new();
+            file_table.insert("name", file.name.as_str().into());
+            file_table.insert("url", file.url.as_str().into());
+            if let Some(upload_time) = file
+                .upload_time
+                .as_ref()
+                .and_then(|x| x.parse::<Datetime>().ok())
+            {
+                file_table.insert("upload-time", upload_time.into());
+            }
+            file_table.insert("hashes", file_hashes.into());
+            if file.name.ends_with(".whl") {
+                file_table.decor_mut().set_prefix("\n    ");
+                wheels.push_formatted(file_table.into());
+            } else if package.contains_key("sdist") {
+                // pylock.toml only allows a single sdist per package
+                hashes.push(hash);
+            } else {
+                package["sdist"] = value(file_table);
+            }
+        }
+        if !wheels.is_empty() {
+            wheels.set_trailing("\n");
+            wheels.set_trailing_comma(true);
+            package["wheels"] = value(wheels);
+        }
+        if !hashes.is_empty() {
+            let mut rye = Table::new();
+            rye["hashes"] = value(hashes);
+            let mut tool = Table::new();
+            tool.set_implicit(true);
+            tool["rye"] = Item::Table(rye);
+            package["tool"] = Item::Table(tool);
+        }
+        packages.push(package);
+    }
+    doc["packages"] = Item::ArrayOfTables(packages);
+
+    fs::write(out, doc.to_string())
+        .with_context(|| format!("failed to write {}", out.display()))?;
+    Ok(())
+}
+
+/// Converts a `pylock.toml` file into requirements for pip.
+///
+/// The hashes of the wheels, source distributions and archives are passed
+/// on, so that packages can be installed in hash-checking mode.  Hashes that
+/// rye could not attribute to a file are read from the `tool.rye` table.
+pub fn pylock_to_requirements(path: &Path, workspace_root: &Path) -> Result<String, Error> {
+    let doc = fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .parse::<Document>()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    match doc.get("lock-version").and_then(|x| x.as_str()) {
+        Some(version) if version.split('.').next() == LOCK_VERSION.split('.').next() => {}
+        Some(version) => bail!(
+            "{} uses unsupported lock-version {}",
+            path.display(),
+            version
+        ),
+        None => bail!("{} is missing lock-version", path.display()),
+    }
+
+    let mut rv = String::new();
+    for package in doc
+        .get("packages")
+        .and_then(|x| x.as_array_of_tables())
+        .into_iter()
+        .flatten()
+    {
+        let name = package
+            .get("name")
+            .and_then(|x| x.as_str())
+            .ok_or_else(|| anyhow!("package without name in {}", path.display()))?;
+        let marker = package
+            .get("marker")
+            .and_then(|x| x.as_str())
+            .map(|x| format!(" ; {}", x))
+            .unwrap_or_default();
+
+        if let Some(directory) = package.get("directory") {
+            let dir = directory
+                .get("path")
+                .and_then(|x| x.as_str())
+                .ok_or_else(|| anyhow!("directory of {} has no path", name))?;
+            let rel_url = make_relative_url(&workspace_root.join(dir), workspace_root)?;
+            if directory
+                .get("editable")
+                .and_then(|x| x.as_bool())
+                .unwrap_or(false)
+            {
+                writeln!(rv, "-e {}", rel_url)?;
+            } else {
+                writeln!(rv, "{}", dir)?;
+            }
+            continue;
+        }
+
+        if let Some(vcs) = package.get("vcs") {
+            let get = |key: &str| vcs.get(key).and_then(|x| x.as_str());
+            let (kind, url) = get("type")
+                .zip(get("url"))
+                .ok_or_else(|| anyhow!("vcs source of {} is incomplete", name))?;
+            write!(rv, "{} @ {}+{}", name, kind, url)?;
+            if let Some(rev) = get("commit-id").or_else(|| get("requested-revision")) {
+                write!(rv, "@{}", rev)?;
+            }
+        } else if let Some(url) = package
+            .get("archive")
+            .and_then(|x| x.get("url"))
+            .and_then(|x| x.as_str())
+        {
+            write!(rv, "{} @ {}", name, url)?;
+        } else if let Some(version) = package.get("version").and_then(|x| x.as_str()) {
+            write!(rv, "{}=={}", name, version)?;
+        } else {
+            bail!("package {} in {} is not pinned", name, path.display());
+        }
+        rv.push_str(&marker);
+
+        let mut hashes = package
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("hashes"))
+            .and_then(|x| x.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.as_str())
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        for file in iter_files(package) {
+            for (algo, hash) in file
+                .get("hashes")
+                .and_then(|x| x.as_table_like())
+                .into_iter()
+                .flat_map(|x| x.iter())
+            {
+                if let Some(hash) = hash.as_str() {
+                    let hash = format!("{}:{}", algo, hash);
+                    if !hashes.contains(&hash) {
+                        hashes.push(hash);
+                    }
+                }
+            }
+        }
+        for hash in hashes {
+            write!(rv, " \\\n    --hash={}", hash)?;
+        }
+        rv.push('\n');
+    }
+
+    Ok(rv)
+}
+
+/// Returns the wheels, the sdist and the archive of a package.
+///
+/// Both regular and inline tables are supported as other tools write
+/// them in either style.
+fn iter_files(package: &Table) -> Vec<&dyn TableLike> {
+    let mut rv = Vec::<&dyn TableLike>::new();
+    if let Some(wheels) = package.get("wheels") {
+        if let Some(wheels) = wheels.as_array_of_tables() {
+            rv.extend(wheels.iter().map(|x| x as &dyn TableLike));
+        } else if let Some(wheels) = wheels.as_array() {
+            rv.extend(
+                wheels
+                    .iter()
+                    .filter_map(|x| x.as_inline_table())
+                    .map(|x| x as &dyn TableLike),
+            );
+        }
+    }
+    for key in ["sdist", "archive"] {
+        if let Some(file) = package.get(key).and_then(|x| x.as_table_like()) {
+            rv.push(file);
+        }
+    }
+    rv
+}
+
+#[test]
+fn test_write_pylock_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let lockfile = dir.path().join("requirements.lock");
+    fs::write(
+        &lockfile,
+        "a==1.0 \\\n    --hash=sha256:aa \\\n    --hash=sha256:bb \\\n    --hash=sha256:cc\n",
+    )
+    .unwrap();
+    let files = dir.path().join("requirements.lock.json");
+    fs::write(
+        &files,
+        r#"{
+            "sha256:aa": {"name": "a-1.0-py3-none-any.whl", "url": "https://x/a-1.0-py3-none-any.whl", "upload-time": "2023-01-01T00:00:00Z"},
+            "sha256:bb": {"name": "a-1.0.tar.gz", "url": "https://x/a-1.0.tar.gz", "upload-time": null}
+        }"#,
+    )
+    .unwrap();
+    let out = dir.path().join("pylock.toml");
+    let sources = ExpandedSources::from_sources(&[]).unwrap();
+    write_pylock(&lockfile, &out, dir.path(), None, &sources, Some(&files)).unwrap();
+
+    let doc = fs::read_to_string(&out)
+        .unwrap()
+        .parse::<Document>()
+        .unwrap();
+    let package = &doc["packages"][0];
+    assert_eq!(
+        package["wheels"][0]["name"].as_str(),
+        Some("a-1.0-py3-none-any.whl")
+    );
+    assert_eq!(
+        package["wheels"][0]["hashes"]["sha256"].as_str(),
+        Some("aa")
+    );
+    assert_eq!(package["sdist"]["hashes"]["sha256"].as_str(), Some("bb"));
+    assert_eq!(
+        package["tool"]["rye"]["hashes"][0].as_str(),
+        Some("sha256:cc")
+    );
+    assert_eq!(
+        pylock_to_requirements(&out, dir.path()).unwrap(),
+        "a==1.0 \\\n    --hash=sha256:cc \\\n    --hash=sha256:aa \\\n    --hash=sha256:bb\n"
+    );
+}
@@ -1,7 +1,8 @@
 use clap::ValueEnum;
 use core::fmt;
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
 use std::env::consts::{ARCH, OS};
 use std::ffi::OsStr;
@@ -19,18 +20,37 @@ use pep440_rs::{Operator, Version, VersionSpecifiers};
 use pep508_rs::Requirement;
 use regex::Regex;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use toml_edit::{Array, Document, Formatted, Item, Table, Value};
 use url::Url;
 
+use crate::auth::{keyring_get, netrc_lookup};
 use crate::config::Config;
 use crate::consts::VENV_BIN;
-use crate::platform::{get_python_version_request_from_pyenv_pin, list_known_toolchains};
+use crate::git_version::GitVersion;
+use crate::network::check_url;
+use crate::platform::{
+    get_app_dir, get_python_version_request_from_pyenv_pin, list_known_toolchains,
+};
 use crate::sources::{get_download_url, matches_version, PythonVersion, PythonVersionRequest};
 use crate::sync::VenvMarker;
 use crate::utils::{
-    expand_env_vars, format_requirement, get_short_executable_name, is_executable,
-    reformat_toml_array_multiline,
+    expand_env_vars, find_similar, format_requirement, get_short_executable_name, interpolate,
+    is_executable, reformat_toml_array_multiline,
 };
+use crate::warnings::{warn, Warning};
+
+/// The keys of the project table that affect the installed metadata.
+const METADATA_KEYS: &[&str] = &[
+    "name",
+    "version",
+    "dependencies",
+    "optional-dependencies",
+    "scripts",
+    "gui-scripts",
+    "entry-points",
+    "dynamic",
+];
 
 static NORMALIZATION_SPLIT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"[-_.]+").unwrap());
 
@@ -40,6 +60,7 @@ pub enum DependencyKind<'a> {
     Dev,
     Excluded,
     Optional(Cow<'a, str>),
+    Group(Cow<'a, str>),
 }
 
 impl<'a> fmt::Display for DependencyKind<'a> {
@@ -49,10 +70,31 @@ impl<'a> fmt::Display for DependencyKind<'a> {
             DependencyKind::Dev => f.write_str("dev"),
             DependencyKind::Excluded => f.write_str("excluded"),
             DependencyKind::Optional(ref sect) => write!(f, "optional ({})", sect),
+            DependencyKind::Group(ref group) => write!(f, "group ({})", group),
         }
     }
 }
 
+/// The format of the lockfile that is authoritative for syncing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LockFormat {
+    /// The requirements-style `requirements.lock` files.
+    #[default]
+    Requirements,
+    /// The standardized `pylock.toml` files (PEP 751).
+    Pylock,
+}
+
+/// Where the version of a project comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VersionSource {
+    /// The `version` in `pyproject.toml`.
+    #[default]
+    Pyproject,
+    /// The latest version tag of the git repository.
+    Git,
+}
+
 #[derive(Clone, Debug)]
 pub struct DependencyRef {
     raw: String,
@@ -100,6 +142,65 @@ impl FromStr for SourceRefType {
     }
 }
 
+/// Where the credentials of a source come from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SourceAuth {
+    /// The keyring of the system, keyed by the URL and the username.
+    Keyring,
+    /// The `~/.netrc` file, keyed by the host.
+    Netrc,
+}
+
+impl FromStr for SourceAuth {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keyring" => Ok(SourceAuth::Keyring),
+            "netrc" => Ok(SourceAuth::Netrc),
+            _ => Err(anyhow!("unknown authentication '{}'", s)),
+        }
+    }
+}
+
+/// A named repository that packages are published to.
+#[derive(Debug, Clone)]
+pub struct RepositoryRef {
+    pub name: String,
+    pub url: String,
+    pub username: Option<String>,
+    pub skip_existing: bool,
+}
+
+impl RepositoryRef {
+    pub fn from_toml_table(repository: &Table) -> Result<RepositoryRef, Error> {
+        let name = repository
+            .get("name")
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string())
+            .ok_or_else(|| anyhow!("expected name"))?;
+        let url = repository
+            .get("url")
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string())
+            .ok_or_else(|| anyhow!("expected url"))?;
+        let username = repository
+            .get("username")
+            .and_then(|x| x.as_str())
+            .map(|x| x.to_string());
+        let skip_existing = repository
+            .get("skip-existing")
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false);
+        Ok(RepositoryRef {
+            name,
+            url,
+            username,
+            skip_existing,
+        })
+    }
+}
+
 /// Represents a source.
 pub struct SourceRef {
     pub name: String,
@@ -107,7 +208,12 @@ pub struct SourceRef {
     pub verify_ssl: bool,
     pub username: Option<String>,
     pub password: Option<String>,
+    pub auth: Option<SourceAuth>,
     pub ty: SourceRefType,
+    /// Sources with a higher priority shadow the ones with a lower priority.
+    pub priority: i64,
+    /// The packages (name patterns) that may only come from this source.
+    pub packages: Vec<String>,
 }
 
 impl SourceRef {
@@ -118,21 +224,36 @@ impl SourceRef {
             verify_ssl: true,
             username: None,
             password: None,
+            auth: None,
             ty,
+            priority: 0,
+            packages: Vec::new(),
         }
     }
 
-    pub fn from_toml_table(source: &Table) -> Result<SourceRef, Error> {
+    /// Parses a source from a config file or `pyproject.toml`.
+    ///
+    /// A relative `path` is resolved against `base`.
+    pub fn from_toml_table(source: &Table, base: &Path) -> Result<SourceRef, Error> {
         let name = source
             .get("name")
             .and_then(|x| x.as_str())
             .map(|x| x.to_string())
             .ok_or_else(|| anyhow!("expected name"))?;
-        let url = source
-            .get("url")
-            .and_then(|x| x.as_str())
-            .map(|x| x.to_string())
-            .ok_or_else(|| anyhow!("expected url"))?;
+        let (url, default_ty) = match (
+            source.get("url").and_then(|x| x.as_str()),
+            source.get("path").and_then(|x| x.as_str()),
+        ) {
+            (Some(url), None) => (url.to_string(), SourceRefType::Index),
+            (None, Some(path)) => (
+                Url::from_directory_path(base.join(path))
+                    .map_err(|_| anyhow!("invalid path '{}'", path))?
+                    .to_string(),
+                SourceRefType::FindLinks,
+            ),
+            (Some(_), Some(_)) => bail!("url and path cannot be used together"),
+            (None, None) => bail!("expected url or path"),
+        };
         let verify_ssl = source
             .get("verify_ssl")
             .and_then(|x| x.as_bool())
@@ -145,18 +266,41 @@ impl SourceRef {
             .get("password")
             .and_then(|x| x.as_str())
             .map(|x| x.to_string());
+        let auth = source
+            .get("auth")
+            .and_then(|x| x.as_str())
+            .map(|x| x.parse::<SourceAuth>())
+            .transpose()
+            .context("invalid value for auth")?;
         let ty = source
             .get("type")
             .and_then(|x| x.as_str())
-            .map_or(Ok(SourceRefType::Index), |x| x.parse::<SourceRefType>())
+            .map_or(Ok(default_ty), |x| x.parse::<SourceRefType>())
             .context("invalid value for type")?;
+        let priority = source
+            .get("priority")
+            .and_then(|x| x.as_integer())
+            .unwrap_or(0);
+        let packages = source
+            .get("packages")
+            .and_then(|x| x.as_array())
+            .map(|x| {
+                x.iter()
+                    .filter_map(|x| x.as_str())
+                    .map(normalize_package_name)
+                    .collect()
+            })
+            .unwrap_or_default();
         Ok(SourceRef {
             name,
             url,
             verify_ssl,
             username,
             password,
+            auth,
             ty,
+            priority,
+            packages,
         })
     }
 
@@ -172,18 +316,67 @@ impl SourceRef {
         }
         if let Some(ref password) = self.password {
             url.set_password(Some(password)).ok();
+        } else if let Some(auth) = self.auth {
+            self.fill_in_credentials(&mut url, auth)?;
         }
         Ok(url)
     }
+
+    /// Looks up the credentials of the source in the keyring or netrc file.
+    fn fill_in_credentials(&self, url: &mut Url, auth: SourceAuth) -> Result<(), Error> {
+        match auth {
+            SourceAuth::Keyring => {
+                let username = match self.username {
+                    Some(ref username) => username,
+                    None => bail!("source '{}' needs a username for the keyring", self.name),
+                };
+                // the keyring is keyed by the URL, or by the host like pip does
+                let mut service = url.clone();
+                service.set_username("").ok();
+                let password =
+                    match keyring_get(service.as_str(), username)? {
+                        Some(password) => password,
+                        None => keyring_get(url.host_str().unwrap_or(""), username)?.ok_or_else(
+                            || anyhow!("no password for source '{}' in the keyring", self.name),
+                        )?,
+                    };
+                url.set_password(Some(&password)).ok();
+            }
+            SourceAuth::Netrc => {
+                let host = url.host_str().unwrap_or("").to_string();
+                match netrc_lookup(&host)? {
+                    Some((login, password)) => {
+                        if let (None, Some(login)) = (&self.username, login) {
+                            url.set_username(&login).ok();
+                        }
+                        url.set_password(Some(&password)).ok();
+                    }
+                    None => bail!("no entry for {} (source '{}') in netrc", host, self.name),
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 type EnvVars = HashMap<String, String>;
 
+/// The environment a command alias runs in.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptEnv {
+    /// Environment variables to set.
+    pub vars: EnvVars,
+    /// A file with environment variables, relative to the project.
+    pub env_file: Option<PathBuf>,
+    /// The working directory, relative to the project.
+    pub cwd: Option<PathBuf>,
+}
+
 /// A reference to a script
 #[derive(Clone, Debug)]
 pub enum Script {
     /// A command alias
-    Cmd(Vec<String>, EnvVars),
+    Cmd(Vec<String>, ScriptEnv),
     /// A multi-script execution
     Chain(Vec<Vec<String>>),
     /// External script reference
@@ -233,13 +426,29 @@ impl Script {
                             .collect()
                     })
                     .unwrap_or_default();
-                Some(Script::Cmd(cmd, env_vars))
+                let path = |key: &str| {
+                    detailed
+                        .get(key)
+                        .and_then(|x| x.as_str())
+                        .map(PathBuf::from)
+                };
+                Some(Script::Cmd(
+                    cmd,
+                    ScriptEnv {
+                        vars: env_vars,
+                        env_file: path("env-file"),
+                        cwd: path("cwd"),
+                    },
+                ))
+            } else if detailed.contains_key("depends") {
+                // a script that only runs its dependencies
+                Some(Script::Chain(Vec::new()))
             } else {
                 None
             }
         } else {
             toml_value_as_command_args(item.as_value()?)
-                .map(|cmd| Script::Cmd(cmd, EnvVars::default()))
+                .map(|cmd| Script::Cmd(cmd, ScriptEnv::default()))
         }
     }
 }
@@ -249,7 +458,7 @@ impl fmt::Display for Script {
         match self {
             Script::Cmd(args, env) => {
                 let mut need_space = false;
-                for (key, value) in env.iter() {
+                for (key, value) in env.vars.iter() {
                     if need_space {
                         write!(f, " ")?;
                     }
@@ -370,7 +579,10 @@ impl Workspace {
                                     }
                                 }
                                 Err(err) => {
-                                    eprintln!("warning: workspace.members: {}", err);
+                                    warn(
+                                        Warning::InvalidWorkspaceMember,
+                                        format_args!("workspace.members: {}", err),
+                                    );
                                 }
                             }
                         }
@@ -424,9 +636,78 @@ impl Workspace {
         Ok(None)
     }
 
+    /// Returns the projects of the workspace ordered by their dependencies.
+    ///
+    /// Every member comes after the members it depends on (regular and dev
+    /// dependencies).  With `names` only those members and the members they
+    /// depend on are returned.
+    pub fn projects_in_order(
+        self: &Arc<Self>,
+        names: Option<&[String]>,
+    ) -> Result<Vec<PyProject>, Error> {
+        fn visit(
+            name: &str,
+            projects: &mut BTreeMap<String, (PyProject, Vec<String>)>,
+            path: &mut Vec<String>,
+            rv: &mut Vec<PyProject>,
+        ) -> Result<(), Error> {
+            if let Some(pos) = path.iter().position(|x| x == name) {
+                bail!(
+                    "dependency cycle between workspace members: {} -> {}",
+                    path[pos..].join(" -> "),
+                    name
+                );
+            }
+            let deps = match projects.get(name) {
+                Some((_, deps)) => deps.clone(),
+                // already visited
+                None => return Ok(()),
+            };
+            path.push(name.to_string());
+            for dep in deps {
+                visit(&dep, projects, path, rv)?;
+            }
+            path.pop();
+            if let Some((project, _)) = projects.remove(name) {
+                rv.push(project);
+            }
+            Ok(())
+        }
+
+        let mut projects = BTreeMap::new();
+        for project in self.iter_projects() {
+            let project = project?;
+            projects.insert(project.normalized_name()?, (project, Vec::new()));
+        }
+        let members = projects.keys().cloned().collect::<HashSet<_>>();
+        for (project, deps) in projects.values_mut() {
+            *deps = project.member_dependencies(&members)?;
+        }
+
+        let start = match names {
+            Some(names) => {
+                let mut rv = Vec::new();
+                for name in names {
+                    let normalized = normalize_package_name(name);
+                    if !members.contains(&normalized) {
+                        bail!("unknown project '{}'", name);
+                    }
+                    rv.push(normalized);
+                }
+                rv
+            }
+            None => projects.keys().cloned().collect(),
+        };
+        let mut rv = Vec::new();
+        for name in start {
+            visit(&name, &mut projects, &mut Vec::new(), &mut rv)?;
+        }
+        Ok(rv)
+    }
+
     /// Returns the virtualenv path of the workspace.
     pub fn venv_path(&self) -> Cow<'_, Path> {
-        Cow::Owned(self.root.join(".venv"))
+        Cow::Owned(get_venv_path(&self.doc, &self.root))
     }
 
     /// Returns the project's target python version.
@@ -441,18 +722,98 @@ impl Workspace {
     ///
     /// This is the python version that should be used for virtualenvs.
     pub fn venv_python_version(&self) -> Result<PythonVersion, Error> {
-        resolve_intended_venv_python_version(&self.doc, &self.root)
+        resolve_intended_venv_python_version(&self.doc, &self.root, true)
+    }
+
+    /// Like [`Workspace::venv_python_version`] but only picks installed toolchains.
+    pub fn installed_venv_python_version(&self) -> Result<PythonVersion, Error> {
+        resolve_intended_venv_python_version(&self.doc, &self.root, false)
     }
 
     /// Returns a list of index URLs that should be considered.
     pub fn sources(&self) -> Result<Vec<SourceRef>, Error> {
-        get_sources(&self.doc)
+        get_sources(&self.doc, &self.path(), &self.path())
     }
 
     /// Is this workspace rye managed?
     pub fn rye_managed(&self) -> bool {
         is_rye_managed(&self.doc)
     }
+
+    /// Should the lockfiles of this workspace contain hashes?
+    pub fn generate_hashes(&self) -> bool {
+        generate_hashes(&self.doc)
+    }
+
+    /// Returns the platforms the lockfiles are resolved for.
+    pub fn lock_platforms(&self) -> Vec<String> {
+        get_rye_string_list(&self.doc, "lock-platforms")
+    }
+
+    /// Returns the packages that are always built from source.
+    pub fn no_binary(&self) -> Vec<String> {
+        get_package_list(&self.doc, "no-binary")
+    }
+
+    /// Returns the packages that are only installed from wheels.
+    pub fn only_binary(&self) -> Vec<String> {
+        get_package_list(&self.doc, "only-binary")
+    }
+
+    /// Returns how packages are built from sdists.
+    pub fn build_settings(&self) -> Result<BuildSettings, Error> {
+        get_build_settings(&self.doc)
+    }
+
+    /// Returns how the lockfiles are exported to conda.
+    pub fn conda_settings(&self) -> CondaSettings {
+        get_conda_settings(&self.doc)
+    }
+
+    /// Should the virtualenv of this workspace be relocatable?
+    pub fn relocatable(&self) -> bool {
+        is_relocatable(&self.doc)
+    }
+
+    /// Should the virtualenv of this workspace be relocatable as a whole?
+    pub fn fully_relocatable(&self) -> bool {
+        is_fully_relocatable(&self.doc)
+    }
+
+    /// Returns the authoritative lockfile format of this workspace.
+    pub fn lock_format(&self) -> Result<LockFormat, Error> {
+        get_lock_format(&self.doc)
+    }
+
+    /// Should `pylock.toml` files be written alongside the lockfiles?
+    pub fn emit_pylock(&self) -> bool {
+        emit_pylock(&self.doc)
+    }
+
+    /// Returns the constraints that apply when locking.
+    pub fn constraints(&self) -> Vec<String> {
+        get_rye_string_list(&self.doc, "constraint-dependencies")
+    }
+
+    /// Returns the overrides that replace dependencies when locking.
+    pub fn overrides(&self) -> Vec<String> {
+        get_rye_string_list(&self.doc, "override-dependencies")
+    }
+
+    /// Returns the optional dependency groups that get their own lockfile.
+    pub fn lock_groups(&self) -> Vec<String> {
+        get_rye_string_list(&self.doc, "lock-groups")
+    }
+
+    /// Returns the path of the env file with secrets for `rye run`.
+    pub fn secrets_file(&self) -> Option<PathBuf> {
+        get_secrets_file(&self.doc, &self.root)
+    }
+
+    /// Returns the path of the env file loaded by `rye run`.
+    pub fn env_file(&self) -> Option<PathBuf> {
+        get_env_file(&self.doc, &self.root)
+    }
 }
 
 /// Check if recurse should be skipped into directory with this name
@@ -473,12 +834,31 @@ impl fmt::Display for DiscoveryUnsuccessful {
     }
 }
 
+/// A named environment declared in `[tool.rye.envs]`.
+#[derive(Debug, Clone)]
+pub struct EnvConfig {
+    /// The name of the environment.
+    pub name: String,
+    /// The Python version of the environment if it differs from the project.
+    pub python: Option<PythonVersionRequest>,
+    /// The dependency groups (or optional dependencies) to install.
+    pub groups: Vec<String>,
+    /// Whether the dev dependencies are installed.
+    pub dev: bool,
+    /// The folder of a throwaway environment.
+    ///
+    /// The virtualenv and the lockfiles of the environment are kept there
+    /// instead of next to the project.
+    pub location: Option<PathBuf>,
+}
+
 /// Helps working with pyproject.toml files
 #[derive(Debug)]
 pub struct PyProject {
     root: PathBuf,
     basename: OsString,
     workspace: Option<Arc<Workspace>>,
+    env: Option<Arc<EnvConfig>>,
     doc: Document,
 }
 
@@ -540,6 +920,7 @@ impl PyProject {
             root: root.to_owned(),
             basename,
             workspace,
+            env: None,
             doc,
         })
     }
@@ -575,10 +956,106 @@ impl PyProject {
             root: root.to_owned(),
             basename,
             workspace: Some(workspace),
+            env: None,
             doc,
         }))
     }
 
+    /// Selects a named environment from `[tool.rye.envs]`.
+    ///
+    /// The virtualenv and Python version of the project then refer to the
+    /// environment.  Environments are declared in the workspace root.
+    pub fn with_env(mut self, name: &str) -> Result<PyProject, Error> {
+        let doc = match self.workspace {
+            Some(ref workspace) => &workspace.doc,
+            None => &self.doc,
+        };
+        let env = match get_envs_table(doc).and_then(|x| x.get(name)) {
+            Some(env) => env,
+            None => {
+                let names = self.env_names();
+                match find_similar(name, names.iter().map(|x| x.as_str())) {
+                    Some(similar) => {
+                        bail!(
+                            "unknown environment '{}'; did you mean '{}'?",
+                            name,
+                            similar
+                        )
+                    }
+                    None => bail!("unknown environment '{}'", name),
+                }
+            }
+        };
+        let python = match env.get("python").and_then(|x| x.as_str()) {
+            Some(python) => {
+                Some(python.parse::<PythonVersionRequest>().with_context(|| {
+                    format!("invalid python version for environment '{}'", name)
+                })?)
+            }
+            None => None,
+        };
+        let groups = env
+            .get("groups")
+            .and_then(|x| x.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.as_str())
+            .map(|x| x.to_string())
+            .collect();
+        let dev = env.get("dev").and_then(|x| x.as_bool()).unwrap_or(false);
+        self.env = Some(Arc::new(EnvConfig {
+            name: name.to_string(),
+            python,
+            groups,
+            dev,
+            location: None,
+        }));
+        Ok(self)
+    }
+
+    /// Selects an environment that is not declared in the pyproject.toml.
+    pub fn with_env_config(mut self, env: EnvConfig) -> PyProject {
+        self.env = Some(Arc::new(env));
+        self
+    }
+
+    /// Returns the Python versions in `tool.rye.matrix.python`.
+    pub fn matrix_pythons(&self) -> Result<Vec<PythonVersionRequest>, Error> {
+        let doc = match self.workspace {
+            Some(ref workspace) => &workspace.doc,
+            None => &self.doc,
+        };
+        doc.get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("matrix"))
+            .and_then(|x| x.get("python"))
+            .and_then(|x| x.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.as_str())
+            .map(|x| {
+                x.parse::<PythonVersionRequest>()
+                    .with_context(|| format!("invalid python version '{}' in tool.rye.matrix", x))
+            })
+            .collect()
+    }
+
+    /// Returns the selected environment.
+    pub fn env(&self) -> Option<&EnvConfig> {
+        self.env.as_deref()
+    }
+
+    /// Returns the names of the environments in `[tool.rye.envs]`.
+    pub fn env_names(&self) -> Vec<String> {
+        let doc = match self.workspace {
+            Some(ref workspace) => &workspace.doc,
+            None => &self.doc,
+        };
+        get_envs_table(doc)
+            .map(|x| x.iter().map(|(name, _)| name.to_string()).collect())
+            .unwrap_or_default()
+    }
+
     /// Returns a reference to the workspace.
     ///
     /// If something isn't a workspace, it's not returned.
@@ -607,16 +1084,36 @@ impl PyProject {
             .unwrap_or_else(|| self.root_path())
     }
 
+    /// Expands the variables in a value of the `tool.rye` section.
+    ///
+    /// See [`interpolate_rye_value`].
+    pub fn interpolate(&self, value: &str) -> Result<String, Error> {
+        interpolate_rye_value(value, &self.root_path(), &self.workspace_path())
+    }
+
     /// Returns the path to the toml file.
     pub fn toml_path(&self) -> Cow<'_, Path> {
         Cow::Owned(self.root.join(&self.basename))
     }
 
     /// Returns the location of the virtualenv.
+    ///
+    /// Named environments live next to it with the name of the environment
+    /// appended (for instance `.venv-docs`).
     pub fn venv_path(&self) -> Cow<'_, Path> {
-        match self.workspace() {
+        let venv = match self.workspace() {
             Some(ws) => ws.venv_path(),
-            None => self.root.join(".venv").into(),
+            None => get_venv_path(&self.doc, &self.root).into(),
+        };
+        match self.env {
+            Some(ref env) if env.location.is_some() => {
+                Cow::Owned(env.location.as_ref().unwrap().join(".venv"))
+            }
+            Some(ref env) => {
+                let name = venv.file_name().unwrap_or_default().to_string_lossy();
+                Cow::Owned(venv.with_file_name(format!("{}-{}", name, env.name)))
+            }
+            None => venv,
         }
     }
 
@@ -638,10 +1135,26 @@ impl PyProject {
     ///
     /// This is the python version that should be used for virtualenvs.
     pub fn venv_python_version(&self) -> Result<PythonVersion, Error> {
-        if let Some(workspace) = self.workspace() {
-            workspace.venv_python_version()
+        self.resolve_venv_python_version(true)
+    }
+
+    /// Like [`PyProject::venv_python_version`] but only picks installed
+    /// toolchains.  This is used when syncing offline.
+    pub fn installed_venv_python_version(&self) -> Result<PythonVersion, Error> {
+        self.resolve_venv_python_version(false)
+    }
+
+    fn resolve_venv_python_version(&self, downloads: bool) -> Result<PythonVersion, Error> {
+        if let Some(requested) = self.env.as_ref().and_then(|x| x.python.as_ref()) {
+            resolve_python_version_request(requested, downloads)
+        } else if let Some(workspace) = self.workspace() {
+            if downloads {
+                workspace.venv_python_version()
+            } else {
+                workspace.installed_venv_python_version()
+            }
         } else {
-            resolve_intended_venv_python_version(&self.doc, &self.root)
+            resolve_intended_venv_python_version(&self.doc, &self.root, downloads)
         }
     }
 
@@ -686,7 +1199,13 @@ impl PyProject {
     }
 
     /// Returns the version.
+    ///
+    /// For projects that take their version from git this is derived from
+    /// the latest version tag.
     pub fn version(&mut self) -> Result<Version, Error> {
+        if self.version_source()? == VersionSource::Git {
+            return GitVersion::describe(&self.root_path())?.version();
+        }
         let version = self
             .doc
             .get("project")
@@ -707,6 +1226,45 @@ impl PyProject {
         }
     }
 
+    /// Returns the version declared in the pyproject.toml.
+    ///
+    /// Unlike [`version`](Self::version) this neither derives nor writes a
+    /// version, so `None` is returned for projects with a dynamic version.
+    pub fn static_version(&self) -> Result<Option<Version>, Error> {
+        let project = self.doc.get("project");
+        let dynamic = project
+            .and_then(|x| x.get("dynamic"))
+            .and_then(|x| x.as_array())
+            .map_or(false, |x| x.iter().any(|x| x.as_str() == Some("version")));
+        if dynamic || self.version_source()? == VersionSource::Git {
+            return Ok(None);
+        }
+        match project
+            .and_then(|x| x.get("version"))
+            .and_then(|x| x.as_str())
+        {
+            Some(version) => Version::from_str(version)
+                .map(Some)
+                .map_err(|msg| anyhow!("invalid version: {}", msg)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the requirements of the build system.
+    pub fn build_requires(&self) -> Vec<String> {
+        self.doc
+            .get("build-system")
+            .and_then(|x| x.get("requires"))
+            .and_then(|x| x.as_array())
+            .map(|x| {
+                x.iter()
+                    .filter_map(|x| x.as_str())
+                    .map(|x| x.to_string())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
     /// Returns the build backend.
     pub fn build_backend(&self) -> Option<BuildSystem> {
         let backend = self
@@ -738,6 +1296,32 @@ impl PyProject {
         }
     }
 
+    /// Returns the scripts that need to run before a script.
+    pub fn get_script_depends(&self, key: &str) -> Vec<String> {
+        self.doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("scripts"))
+            .and_then(|x| x.get(key))
+            .and_then(|x| x.as_table_like())
+            .and_then(|x| x.get("depends"))
+            .and_then(|x| x.as_array())
+            .map(toml_array_as_string_array)
+            .unwrap_or_default()
+    }
+
+    /// Returns the console scripts of the project with their entry points.
+    pub fn console_scripts(&self) -> Vec<(String, String)> {
+        self.doc
+            .get("project")
+            .and_then(|x| x.get("scripts"))
+            .and_then(|x| x.as_table_like())
+            .into_iter()
+            .flat_map(|x| x.iter())
+            .filter_map(|(name, target)| Some((name.to_string(), target.as_str()?.to_string())))
+            .collect()
+    }
+
     /// Returns a list of known scripts.
     pub fn list_scripts(&self) -> HashSet<String> {
         let mut rv = match self
@@ -794,6 +1378,16 @@ impl PyProject {
                 }
                 &mut table[section as &str]
             }
+            DependencyKind::Group(ref group) => {
+                let table = &mut self.doc["dependency-groups"];
+                if table.is_none() {
+                    *table = Item::Table(Table::new());
+                }
+                let key = find_dependency_group(table, group)
+                    .unwrap_or(group as &str)
+                    .to_string();
+                &mut table[key.as_str()]
+            }
         };
         if dependencies.is_none() {
             *dependencies = Item::Value(Value::Array(Array::new()));
@@ -807,6 +1401,23 @@ impl PyProject {
         Ok(())
     }
 
+    /// Adds an override that replaces a dependency when locking.
+    ///
+    /// An existing override for the same package is replaced.
+    pub fn add_override(&mut self, req: &Requirement) -> Result<(), Error> {
+        let overrides = &mut self.doc["tool"]["rye"]["override-dependencies"];
+        if overrides.is_none() {
+            *overrides = Item::Value(Value::Array(Array::new()));
+        }
+        set_dependency(
+            overrides
+                .as_array_mut()
+                .ok_or_else(|| anyhow!("override-dependencies in pyproject.toml are malformed"))?,
+            req,
+        );
+        Ok(())
+    }
+
     /// Removes a dependency
     pub fn remove_dependency(
         &mut self,
@@ -820,6 +1431,13 @@ impl PyProject {
             DependencyKind::Optional(ref section) => {
                 &mut self.doc["project"]["optional-dependencies"][section as &str]
             }
+            DependencyKind::Group(ref group) => {
+                let table = &mut self.doc["dependency-groups"];
+                let key = find_dependency_group(table, group)
+                    .unwrap_or(group as &str)
+                    .to_string();
+                &mut table[key.as_str()]
+            }
         };
         if !dependencies.is_none() {
             Ok(remove_dependency(
@@ -834,11 +1452,20 @@ impl PyProject {
     }
 
     /// Iterates over all dependencies.
+    ///
+    /// For dependency groups the included groups are resolved.
     pub fn iter_dependencies(
         &self,
         kind: DependencyKind,
     ) -> impl Iterator<Item = DependencyRef> + '_ {
         let sec = match kind {
+            DependencyKind::Group(ref group) => {
+                let mut rv = Vec::new();
+                if let Some(groups) = self.doc.get("dependency-groups") {
+                    collect_dependency_group(groups, group, &mut Vec::new(), &mut rv);
+                }
+                return rv.into_iter();
+            }
             DependencyKind::Normal => self.doc.get("project").and_then(|x| x.get("dependencies")),
             DependencyKind::Dev => self
                 .doc
@@ -861,13 +1488,79 @@ impl PyProject {
             .flatten()
             .filter_map(|x| x.as_str())
             .map(DependencyRef::new)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    /// Returns the workspace members this project depends on.
+    ///
+    /// These are the normalized names of the regular and dev dependencies
+    /// that are in `members`.
+    pub fn member_dependencies(&self, members: &HashSet<String>) -> Result<Vec<String>, Error> {
+        let name = self.normalized_name()?;
+        let mut rv = self
+            .iter_dependencies(DependencyKind::Normal)
+            .chain(self.iter_dependencies(DependencyKind::Dev))
+            .filter_map(|dep| dep.expand(|_| Some("VARIABLE".into())).ok())
+            .map(|req| normalize_package_name(&req.name))
+            .filter(|dep| *dep != name && members.contains(dep))
+            .collect::<Vec<_>>();
+        rv.sort();
+        rv.dedup();
+        Ok(rv)
+    }
+
+    /// Returns the names of all dependency groups (PEP 735).
+    pub fn dependency_groups(&self) -> Vec<&str> {
+        self.doc
+            .get("dependency-groups")
+            .and_then(|x| x.as_table_like())
+            .into_iter()
+            .flat_map(|x| x.iter().map(|x| x.0))
+            .collect()
+    }
+
+    /// Does this project define the given dependency group?
+    pub fn has_dependency_group(&self, group: &str) -> bool {
+        self.doc
+            .get("dependency-groups")
+            .and_then(|x| find_dependency_group(x, group))
+            .is_some()
+    }
+
+    /// Returns the repositories that packages can be published to.
+    ///
+    /// Repositories of the project take precedence over the ones in the
+    /// config file with the same name.
+    pub fn repositories(&self) -> Result<Vec<RepositoryRef>, Error> {
+        let mut rv = Vec::new();
+        if let Some(repositories) = self
+            .doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("repositories"))
+            .and_then(|x| x.as_array_of_tables())
+        {
+            for repository in repositories {
+                rv.push(
+                    RepositoryRef::from_toml_table(repository)
+                        .context("invalid repository in tool.rye.repositories")?,
+                );
+            }
+        }
+        for repository in Config::current().repositories()? {
+            if !rv.iter().any(|x| x.name == repository.name) {
+                rv.push(repository);
+            }
+        }
+        Ok(rv)
     }
 
     /// Returns a list of sources that should be considered.
     pub fn sources(&self) -> Result<Vec<SourceRef>, Error> {
         match self.workspace {
             Some(ref workspace) => workspace.sources(),
-            None => get_sources(&self.doc),
+            None => get_sources(&self.doc, &self.root, &self.root),
         }
     }
 
@@ -879,6 +1572,255 @@ impl PyProject {
         }
     }
 
+    /// Should the lockfiles contain hashes?
+    pub fn generate_hashes(&self) -> bool {
+        match self.workspace {
+            Some(ref workspace) => workspace.generate_hashes(),
+            None => generate_hashes(&self.doc),
+        }
+    }
+
+    /// Returns the platforms the lockfiles are resolved for.
+    pub fn lock_platforms(&self) -> Vec<String> {
+        match self.workspace {
+            Some(ref workspace) => workspace.lock_platforms(),
+            None => get_rye_string_list(&self.doc, "lock-platforms"),
+        }
+    }
+
+    /// Returns the packages that are always built from source.
+    pub fn no_binary(&self) -> Vec<String> {
+        match self.workspace {
+            Some(ref workspace) => workspace.no_binary(),
+            None => get_package_list(&self.doc, "no-binary"),
+        }
+    }
+
+    /// Returns the packages that are only installed from wheels.
+    pub fn only_binary(&self) -> Vec<String> {
+        match self.workspace {
+            Some(ref workspace) => workspace.only_binary(),
+            None => get_package_list(&self.doc, "only-binary"),
+        }
+    }
+
+    /// Returns how packages are built from sdists.
+    pub fn build_settings(&self) -> Result<BuildSettings, Error> {
+        match self.workspace {
+            Some(ref workspace) => workspace.build_settings(),
+            None => get_build_settings(&self.doc),
+        }
+    }
+
+    /// Returns how the lockfiles are exported to conda.
+    pub fn conda_settings(&self) -> CondaSettings {
+        match self.workspace {
+            Some(ref workspace) => workspace.conda_settings(),
+            None => get_conda_settings(&self.doc),
+        }
+    }
+
+    /// Returns the coverage `rye test --coverage` requires at least.
+    pub fn coverage_fail_under(&self) -> Option<f64> {
+        self.doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("coverage-fail-under"))
+            .and_then(|x| x.as_float().or_else(|| x.as_integer().map(|x| x as f64)))
+    }
+
+    /// Should the scripts of the virtualenv be rewritten so it can be moved?
+    pub fn relocatable(&self) -> bool {
+        match self.workspace {
+            Some(ref workspace) => workspace.relocatable(),
+            None => is_relocatable(&self.doc),
+        }
+    }
+
+    /// Should the virtualenv be created so that it can be moved as a whole?
+    ///
+    /// This copies the interpreter and rewrites the activation scripts.
+    pub fn fully_relocatable(&self) -> bool {
+        match self.workspace {
+            Some(ref workspace) => workspace.fully_relocatable(),
+            None => is_fully_relocatable(&self.doc),
+        }
+    }
+
+    /// Returns where the version of the project comes from.
+    pub fn version_source(&self) -> Result<VersionSource, Error> {
+        match self
+            .doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("version-source"))
+            .and_then(|x| x.as_str())
+        {
+            None | Some("pyproject") => Ok(VersionSource::Pyproject),
+            Some("git") => Ok(VersionSource::Git),
+            Some(other) => bail!(
+                "unknown version-source '{}' (expected 'pyproject' or 'git')",
+                other
+            ),
+        }
+    }
+
+    /// Returns the authoritative lockfile format.
+    pub fn lock_format(&self) -> Result<LockFormat, Error> {
+        match self.workspace {
+            Some(ref workspace) => workspace.lock_format(),
+            None => get_lock_format(&self.doc),
+        }
+    }
+
+    /// Should `pylock.toml` files be written alongside the lockfiles?
+    ///
+    /// This is always the case if `pylock.toml` is the authoritative format.
+    pub fn emit_pylock(&self) -> bool {
+        match self.workspace {
+            Some(ref workspace) => workspace.emit_pylock(),
+            None => emit_pylock(&self.doc),
+        }
+    }
+
+    /// Returns the constraints that apply when locking.
+    pub fn constraints(&self) -> Vec<String> {
+        match self.workspace {
+            Some(ref workspace) => workspace.constraints(),
+            None => get_rye_string_list(&self.doc, "constraint-dependencies"),
+        }
+    }
+
+    /// Returns the overrides that replace dependencies when locking.
+    pub fn overrides(&self) -> Vec<String> {
+        match self.workspace {
+            Some(ref workspace) => workspace.overrides(),
+            None => get_rye_string_list(&self.doc, "override-dependencies"),
+        }
+    }
+
+    /// Returns the codes of the warnings that should not be shown.
+    ///
+    /// The warnings suppressed by the workspace apply to all of its projects.
+    pub fn suppressed_warnings(&self) -> Vec<String> {
+        let mut rv = get_rye_string_list(&self.doc, "suppress-warnings");
+        if let Some(ref workspace) = self.workspace {
+            rv.extend(get_rye_string_list(&workspace.doc, "suppress-warnings"));
+        }
+        rv
+    }
+
+    /// Returns the optional dependency groups that get their own lockfile.
+    pub fn lock_groups(&self) -> Vec<String> {
+        match self.workspace {
+            Some(ref workspace) => workspace.lock_groups(),
+            None => get_rye_string_list(&self.doc, "lock-groups"),
+        }
+    }
+
+    /// Returns the path of the env file with secrets for `rye run`.
+    pub fn secrets_file(&self) -> Option<PathBuf> {
+        match self.workspace {
+            Some(ref workspace) => workspace.secrets_file(),
+            None => get_secrets_file(&self.doc, &self.root),
+        }
+    }
+
+    /// Returns the commands of `tool.rye.hooks` by hook name.
+    ///
+    /// Projects in a workspace without hooks of their own use the ones of
+    /// the workspace.
+    pub fn hooks(&self) -> Result<BTreeMap<String, Vec<String>>, Error> {
+        match get_hooks(&self.doc)? {
+            Some(hooks) => Ok(hooks),
+            None => match self.workspace {
+                Some(ref workspace) => Ok(get_hooks(&workspace.doc)?.unwrap_or_default()),
+                None => Ok(BTreeMap::new()),
+            },
+        }
+    }
+
+    /// Returns the variables of `tool.rye.env` with their values expanded.
+    ///
+    /// Within a workspace the variables of the workspace come first, so
+    /// projects can override them.
+    pub fn env_vars(&self) -> Result<Vec<(String, String)>, Error> {
+        let root = self.root_path();
+        let workspace_root = self.workspace_path();
+        let mut rv = Vec::new();
+        if let Some(ref workspace) = self.workspace {
+            if !self.is_workspace_root() {
+                add_env_vars(&workspace.doc, &root, &workspace_root, &mut rv)?;
+            }
+        }
+        add_env_vars(&self.doc, &root, &workspace_root, &mut rv)?;
+        Ok(rv)
+    }
+
+    /// Returns the path of the env file loaded by `rye run`.
+    pub fn env_file(&self) -> Option<PathBuf> {
+        match self.workspace {
+            Some(ref workspace) => workspace.env_file(),
+            None => get_env_file(&self.doc, &self.root),
+        }
+    }
+
+    /// Returns a fingerprint of the metadata that ends up in the installed package.
+    ///
+    /// If this changes, an editable install of the project needs to be
+    /// refreshed as for instance entry points would otherwise be missing.
+    pub fn metadata_fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        if let Some(project) = self.doc.get("project") {
+            for key in METADATA_KEYS {
+                hasher.update(key.as_bytes());
+                if let Some(value) = project.get(key) {
+                    hasher.update(value.to_string().trim().as_bytes());
+                }
+                hasher.update(b"\0");
+            }
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// Returns the repositories the project may be published to.
+    ///
+    /// `None` means that publishing is not restricted, an empty list that the
+    /// project must not be published at all (`tool.rye.publish = false`).
+    pub fn publish_repositories(&self) -> Option<Vec<String>> {
+        let publish = self
+            .doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("publish"))?;
+        if publish.as_bool() == Some(false) {
+            Some(Vec::new())
+        } else if publish.is_array() {
+            Some(get_rye_string_list(&self.doc, "publish"))
+        } else {
+            None
+        }
+    }
+
+    /// Is the project marked as not to be uploaded to public indexes?
+    pub fn is_private(&self) -> bool {
+        self.doc
+            .get("project")
+            .and_then(|x| x.get("classifiers"))
+            .and_then(|x| x.as_array())
+            .into_iter()
+            .flatten()
+            .any(|x| x.as_str() == Some("Private :: Do Not Upload"))
+    }
+
+    /// Returns the `requires-python` specifier of the project.
+    pub fn requires_python(&self) -> Option<&str> {
+        self.doc
+            .get("project")
+            .and_then(|x| x.get("requires-python"))
+            .and_then(|x| x.as_str())
+    }
+
     /// Save back changes
     pub fn save(&self) -> Result<(), Error> {
         fs::write(self.toml_path(), self.doc.to_string()).with_context(|| {
@@ -948,10 +1890,14 @@ fn remove_dependency(deps: &mut Array, req: &Requirement) -> Option<Requirement>
 }
 
 pub fn get_current_venv_python_version(venv_path: &Path) -> Option<PythonVersion> {
+    read_venv_marker(venv_path).map(|marker| marker.python)
+}
+
+/// Reads the marker rye places in the virtualenvs it manages.
+pub fn read_venv_marker(venv_path: &Path) -> Option<VenvMarker> {
     let marker_file = venv_path.join("rye-venv.json");
     let contents = fs::read(marker_file).ok()?;
-    let marker: VenvMarker = serde_json::from_slice(&contents).ok()?;
-    Some(marker.python)
+    serde_json::from_slice(&contents).ok()
 }
 
 /// Give a given python version request, returns the latest available version.
@@ -959,6 +1905,14 @@ pub fn get_current_venv_python_version(venv_path: &Path) -> Option<PythonVersion
 /// This can return a version that requires downloading.
 pub fn latest_available_python_version(
     requested_version: &PythonVersionRequest,
+) -> Option<PythonVersion> {
+    latest_python_version(requested_version, true)
+}
+
+/// Returns the latest version for a request, optionally considering downloads.
+fn latest_python_version(
+    requested_version: &PythonVersionRequest,
+    downloads: bool,
 ) -> Option<PythonVersion> {
     let mut all = if let Ok(available) = list_known_toolchains() {
         available
@@ -975,9 +1929,12 @@ pub fn latest_available_python_version(
         Vec::new()
     };
 
-    if let Some((latest, _, _)) = get_download_url(requested_version, OS, ARCH) {
-        all.push(latest);
-    };
+    // in offline mode only the installed toolchains can be used
+    if downloads && !Config::current().offline() {
+        if let Some((latest, _, _)) = get_download_url(requested_version, OS, ARCH) {
+            all.push(latest);
+        }
+    }
 
     all.sort();
     all.into_iter().rev().next()
@@ -997,6 +1954,7 @@ fn resolve_target_python_version(
 fn resolve_intended_venv_python_version(
     doc: &Document,
     root: &Path,
+    downloads: bool,
 ) -> Result<PythonVersion, Error> {
     let requested_version = get_python_version_request_from_pyenv_pin(root)
         .or_else(|| resolve_lower_bound_python_version(doc))
@@ -1008,11 +1966,19 @@ fn resolve_intended_venv_python_version(
             )
         })?;
 
+    resolve_python_version_request(&requested_version, downloads)
+}
+
+/// Picks the toolchain for a requested version.
+fn resolve_python_version_request(
+    requested_version: &PythonVersionRequest,
+    downloads: bool,
+) -> Result<PythonVersion, Error> {
     if let Ok(ver) = PythonVersion::try_from(requested_version.clone()) {
         return Ok(ver);
     }
 
-    if let Some(latest) = latest_available_python_version(&requested_version) {
+    if let Some(latest) = latest_python_version(requested_version, downloads) {
         Ok(latest)
     } else {
         Err(anyhow!(
@@ -1084,7 +2050,29 @@ fn is_unsafe_script(path: &Path) -> bool {
     }
 }
 
-fn get_sources(doc: &Document) -> Result<Vec<SourceRef>, Error> {
+/// Expands `${PROJECT_ROOT}`, `${WORKSPACE_ROOT}` and environment variables
+/// in a value of the `tool.rye` section.
+///
+/// Unlike in dependencies an undefined variable is an error, so that a typo
+/// does not silently turn into an empty string.
+fn interpolate_rye_value(
+    value: &str,
+    project_root: &Path,
+    workspace_root: &Path,
+) -> Result<String, Error> {
+    interpolate(value, |name: &str| match name {
+        "PROJECT_ROOT" => Some(project_root.display().to_string()),
+        "WORKSPACE_ROOT" => Some(workspace_root.display().to_string()),
+        _ => env::var(name).ok(),
+    })
+    .map(|x| x.into_owned())
+}
+
+fn get_sources(
+    doc: &Document,
+    project_root: &Path,
+    workspace_root: &Path,
+) -> Result<Vec<SourceRef>, Error> {
     let cfg = Config::current();
     let mut rv = Vec::new();
 
@@ -1095,7 +2083,10 @@ fn get_sources(doc: &Document) -> Result<Vec<SourceRef>, Error> {
         .and_then(|x| x.as_array_of_tables())
     {
         for source in sources {
-            let source_ref = SourceRef::from_toml_table(source)?;
+            let mut source_ref = SourceRef::from_toml_table(source, project_root)?;
+            source_ref.url =
+                interpolate_rye_value(&source_ref.url, project_root, workspace_root)
+                    .with_context(|| format!("invalid url of source '{}'", source_ref.name))?;
             rv.push(source_ref);
         }
     }
@@ -1109,6 +2100,7 @@ fn get_sources(doc: &Document) -> Result<Vec<SourceRef>, Error> {
         }
     }
 
+    rv.sort_by_key(|x| Reverse(x.priority));
     Ok(rv)
 }
 
@@ -1123,12 +2115,385 @@ fn is_rye_managed(doc: &Document) -> bool {
         .unwrap_or(false)
 }
 
+/// Finds the key of a dependency group.  Group names are normalized like
+/// package names.
+fn find_dependency_group<'a>(groups: &'a Item, group: &str) -> Option<&'a str> {
+    let group = normalize_package_name(group);
+    groups
+        .as_table_like()?
+        .iter()
+        .map(|x| x.0)
+        .find(|key| normalize_package_name(key) == group)
+}
+
+/// Collects the requirements of a dependency group including the groups
+/// it includes with `{include-group = "..."}`.
+fn collect_dependency_group(
+    groups: &Item,
+    group: &str,
+    seen: &mut Vec<String>,
+    out: &mut Vec<DependencyRef>,
+) {
+    let key = match find_dependency_group(groups, group) {
+        Some(key) => key,
+        None => return,
+    };
+    // cycles are invalid, just ignore them
+    if seen.iter().any(|x| x == key) {
+        return;
+    }
+    seen.push(key.to_string());
+    for item in groups[key].as_array().into_iter().flatten() {
+        if let Some(req) = item.as_str() {
+            out.push(DependencyRef::new(req));
+        } else if let Some(include) = item
+            .as_inline_table()
+            .and_then(|x| x.get("include-group"))
+            .and_then(|x| x.as_str())
+        {
+            collect_dependency_group(groups, include, seen, out);
+        }
+    }
+}
+
+fn generate_hashes(doc: &Document) -> bool {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("generate-hashes"))
+        .and_then(|x| x.as_bool())
+        .unwrap_or(false)
+}
+
+/// Reads a list of package names from `tool.rye`.
+fn get_package_list(doc: &Document, key: &str) -> Vec<String> {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get(key))
+        .and_then(|x| x.as_array())
+        .map(|x| {
+            x.iter()
+                .filter_map(|x| x.as_str())
+                .map(|x| x.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// How packages are built from sdists.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BuildSettings {
+    /// Packages that are built in the virtualenv instead of an isolated
+    /// build environment.
+    pub no_isolation: Vec<String>,
+    /// Requirements that are added to the build environment of a package.
+    pub extra: BTreeMap<String, Vec<String>>,
+}
+
+impl BuildSettings {
+    /// Are packages built the way pip builds them by default?
+    pub fn is_empty(&self) -> bool {
+        self.no_isolation.is_empty() && self.extra.is_empty()
+    }
+
+    /// Returns the extra build dependencies of the packages that are built
+    /// without isolation.
+    ///
+    /// These have to be installed into the virtualenv, so they are locked.
+    pub fn unisolated_build_dependencies(&self) -> Vec<&str> {
+        let mut rv = self
+            .no_isolation
+            .iter()
+            .filter_map(|name| self.extra.get(name))
+            .flatten()
+            .map(|x| x.as_str())
+            .collect::<Vec<_>>();
+        rv.sort();
+        rv.dedup();
+        rv
+    }
+}
+
+fn get_build_settings(doc: &Document) -> Result<BuildSettings, Error> {
+    let mut no_isolation = get_package_list(doc, "no-build-isolation")
+        .iter()
+        .map(|x| normalize_package_name(x))
+        .collect::<Vec<_>>();
+    no_isolation.sort();
+    no_isolation.dedup();
+    let mut extra = BTreeMap::new();
+    if let Some(table) = doc
+        .get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("extra-build-dependencies"))
+        .and_then(|x| x.as_table_like())
+    {
+        for (name, requirements) in table.iter() {
+            let requirements = requirements
+                .as_array()
+                .ok_or_else(|| {
+                    anyhow!(
+                        "extra build dependencies of '{}' must be a list of requirements",
+                        name
+                    )
+                })?
+                .iter()
+                .filter_map(|x| x.as_str())
+                .map(|x| {
+                    x.parse::<Requirement>().with_context(|| {
+                        format!("invalid extra build dependency '{}' of '{}'", x, name)
+                    })?;
+                    Ok(x.to_string())
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            if !requirements.is_empty() {
+                extra.insert(normalize_package_name(name), requirements);
+            }
+        }
+    }
+    Ok(BuildSettings {
+        no_isolation,
+        extra,
+    })
+}
+
+/// How the lockfiles are exported as conda environment.
+#[derive(Debug, Clone, Default)]
+pub struct CondaSettings {
+    /// The channels of the environment.
+    pub channels: Vec<String>,
+    /// Conda package names by normalized PyPI name.
+    pub names: HashMap<String, String>,
+    /// Packages that are always installed with pip.
+    pub pip: Vec<String>,
+}
+
+fn get_conda_settings(doc: &Document) -> CondaSettings {
+    let conda = doc
+        .get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("conda"));
+    let get_list = |key: &str| {
+        conda
+            .and_then(|x| x.get(key))
+            .and_then(|x| x.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.as_str())
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+    };
+    let mut channels = get_list("channels");
+    if channels.is_empty() {
+        channels.push("conda-forge".into());
+    }
+    let names = conda
+        .and_then(|x| x.get("names"))
+        .and_then(|x| x.as_table_like())
+        .into_iter()
+        .flat_map(|x| x.iter())
+        .filter_map(|(name, conda_name)| {
+            Some((
+                normalize_package_name(name),
+                conda_name.as_str()?.to_string(),
+            ))
+        })
+        .collect();
+    let pip = get_list("pip")
+        .iter()
+        .map(|x| normalize_package_name(x))
+        .collect();
+    CondaSettings {
+        channels,
+        names,
+        pip,
+    }
+}
+
+fn get_relocatable(doc: &Document) -> Option<&Item> {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("relocatable"))
+}
+
+fn is_relocatable(doc: &Document) -> bool {
+    get_relocatable(doc).map_or(false, |x| {
+        x.as_bool().unwrap_or_else(|| x.as_str() == Some("full"))
+    })
+}
+
+fn is_fully_relocatable(doc: &Document) -> bool {
+    get_relocatable(doc).and_then(|x| x.as_str()) == Some("full")
+}
+
+fn get_rye_string_list(doc: &Document, key: &str) -> Vec<String> {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get(key))
+        .and_then(|x| x.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.as_str())
+        .map(|x| x.to_string())
+        .collect()
+}
+
+/// Returns the virtualenv location of a project or workspace.
+///
+/// Central virtualenvs are keyed by a hash of the project path so that
+/// projects with the same folder name do not share them.
+fn get_venv_path(doc: &Document, root: &Path) -> PathBuf {
+    let location = doc
+        .get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("venv-location"))
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_string())
+        .or_else(|| Config::current().venv_location());
+    if location.as_deref() != Some("central") {
+        return root.join(".venv");
+    }
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let hash = hex::encode(Sha256::digest(root.to_string_lossy().as_bytes()));
+    let name = root
+        .file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "project".into());
+    get_app_dir()
+        .join("venvs")
+        .join(format!("{}-{}", name, &hash[..16]))
+}
+
+fn get_envs_table(doc: &Document) -> Option<&dyn toml_edit::TableLike> {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("envs"))
+        .and_then(|x| x.as_table_like())
+}
+
+fn get_secrets_file(doc: &Document, root: &Path) -> Option<PathBuf> {
+    doc.get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("secrets-file"))
+        .and_then(|x| x.as_str())
+        .map(|x| root.join(x))
+}
+
+fn get_hooks(doc: &Document) -> Result<Option<BTreeMap<String, Vec<String>>>, Error> {
+    let table = match doc
+        .get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("hooks"))
+        .and_then(|x| x.as_table_like())
+    {
+        Some(table) => table,
+        None => return Ok(None),
+    };
+    let mut rv = BTreeMap::new();
+    for (name, item) in table.iter() {
+        let cmd = item
+            .as_value()
+            .and_then(toml_value_as_command_args)
+            .filter(|x| !x.is_empty())
+            .ok_or_else(|| anyhow!("invalid command for hook tool.rye.hooks.{}", name))?;
+        rv.insert(name.to_string(), cmd);
+    }
+    Ok(Some(rv))
+}
+
+/// Adds the variables of `tool.rye.env`.
+///
+/// Values can refer to variables declared before them in addition to the
+/// environment, `PROJECT_ROOT` and `WORKSPACE_ROOT`.
+fn add_env_vars(
+    doc: &Document,
+    project_root: &Path,
+    workspace_root: &Path,
+    vars: &mut Vec<(String, String)>,
+) -> Result<(), Error> {
+    let table = match doc
+        .get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("env"))
+        .and_then(|x| x.as_table_like())
+    {
+        Some(table) => table,
+        None => return Ok(()),
+    };
+    for (key, item) in table.iter() {
+        let value = item
+            .as_str()
+            .ok_or_else(|| anyhow!("tool.rye.env.{} must be a string", key))?;
+        let value = interpolate(value, |name: &str| {
+            if let Some((_, value)) = vars.iter().rev().find(|(key, _)| key == name) {
+                return Some(value.clone());
+            }
+            match name {
+                "PROJECT_ROOT" => Some(project_root.display().to_string()),
+                "WORKSPACE_ROOT" => Some(workspace_root.display().to_string()),
+                _ => env::var(name).ok(),
+            }
+        })
+        .with_context(|| format!("invalid value for tool.rye.env.{}", key))?
+        .into_owned();
+        vars.push((key.to_string(), value));
+    }
+    Ok(())
+}
+
+/// The env file defaults to `.env` and can be turned off with `false`.
+fn get_env_file(doc: &Document, root: &Path) -> Option<PathBuf> {
+    match doc
+        .get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("env-file"))
+    {
+        Some(item) => item.as_str().map(|x| root.join(x)),
+        None => Some(root.join(".env")),
+    }
+}
+
+fn get_lock_format(doc: &Document) -> Result<LockFormat, Error> {
+    match doc
+        .get("tool")
+        .and_then(|x| x.get("rye"))
+        .and_then(|x| x.get("lock-format"))
+        .and_then(|x| x.as_str())
+    {
+        None | Some("requirements") => Ok(LockFormat::Requirements),
+        Some("pylock") => Ok(LockFormat::Pylock),
+        Some(other) => bail!(
+            "unknown lock-format '{}' (expected 'requirements' or 'pylock')",
+            other
+        ),
+    }
+}
+
+fn emit_pylock(doc: &Document) -> bool {
+    matches!(get_lock_format(doc), Ok(LockFormat::Pylock))
+        || doc
+            .get("tool")
+            .and_then(|x| x.get("rye"))
+            .and_then(|x| x.get("emit-pylock"))
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false)
+}
+
 /// Represents expanded sources.
 #[derive(Debug, Clone, Serialize)]
 pub struct ExpandedSources {
     pub index_urls: Vec<Url>,
     pub find_links: Vec<Url>,
     pub trusted_hosts: HashSet<String>,
+    pub restrictions: Vec<SourceRestriction>,
+}
+
+/// Limits which packages can be resolved from a source.
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceRestriction {
+    /// The URL of the source without credentials.
+    pub url: String,
+    pub priority: i64,
+    pub packages: Vec<String>,
 }
 
 impl ExpandedSources {
@@ -1137,9 +2502,19 @@ impl ExpandedSources {
         let mut index_urls = Vec::new();
         let mut find_links = Vec::new();
         let mut trusted_hosts = HashSet::new();
+        let mut restrictions = Vec::new();
 
         for source in sources {
             let url = source.expand_url()?;
+            check_url(url.as_str(), &format!("package source '{}'", source.name))?;
+            let mut public_url = url.clone();
+            public_url.set_username("").ok();
+            public_url.set_password(None).ok();
+            restrictions.push(SourceRestriction {
+                url: public_url.to_string(),
+                priority: source.priority,
+                packages: source.packages.clone(),
+            });
             if !source.verify_ssl {
                 if let Some(host) = url.host_str() {
                     trusted_hosts.insert(host.to_string());
@@ -1155,11 +2530,54 @@ impl ExpandedSources {
             index_urls,
             find_links,
             trusted_hosts,
+            restrictions,
+        })
+    }
+
+    /// Returns the restrictions of the sources if there are any.
+    ///
+    /// Without priorities or pinned packages pip is free to pick packages
+    /// from any source.
+    pub fn restrictions(&self) -> Option<&[SourceRestriction]> {
+        let first_priority = self.restrictions.first().map(|x| x.priority);
+        if self
+            .restrictions
+            .iter()
+            .any(|x| Some(x.priority) != first_priority || !x.packages.is_empty())
+        {
+            Some(&self.restrictions)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the sources for installing without network access.
+    ///
+    /// These are the wheels in `dir` and the local `find-links` sources.
+    pub fn offline(&self, dir: &Path) -> Result<ExpandedSources, Error> {
+        let mut find_links = vec![Url::from_directory_path(dir)
+            .map_err(|_| anyhow!("invalid wheel cache path {}", dir.display()))?];
+        find_links.extend(
+            self.find_links
+                .iter()
+                .filter(|x| x.scheme() == "file")
+                .cloned(),
+        );
+        Ok(ExpandedSources {
+            index_urls: Vec::new(),
+            find_links,
+            trusted_hosts: HashSet::new(),
+            restrictions: Vec::new(),
         })
     }
 
     /// Attach common pip args to a command.
+    ///
+    /// Without index URLs pip is told not to use an index at all.
     pub fn add_as_pip_args(&self, cmd: &mut Command) {
+        if self.index_urls.is_empty() {
+            cmd.arg("--no-index");
+        }
         for (idx, url) in self.index_urls.iter().enumerate() {
             if idx == 0 {
                 cmd.arg("--index-url");
@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Error;
+
+use crate::consts::VENV_BIN;
+
+/// Replaces the shebang of scripts in relocatable virtualenvs.
+///
+/// The shell runs the interpreter that is next to the (resolved) script while
+/// Python sees the second line as a string and skips it.  This is the same
+/// trick pip uses for interpreter paths that are too long for a shebang.
+const RELOCATABLE_LAUNCHER: &str = "#!/bin/sh\n\
+    '''exec' \"$(dirname -- \"$(realpath -- \"$0\")\")\"/python \"$0\" \"$@\"\n\
+    ' '''\n";
+
+/// Finds the virtualenv relative to the activation script in bash and zsh.
+const SH_VENV: &str = r#""$(cd -- "$(dirname -- "${BASH_SOURCE[0]:-$0}")/.." && pwd)""#;
+
+/// Finds the virtualenv relative to the activation script in fish.
+const FISH_VENV: &str = "(builtin realpath (dirname (status filename))/..)";
+
+/// Finds the virtualenv relative to the activation script in cmd.exe.
+const BAT_VENV: &str = "\"VIRTUAL_ENV=%~dp0..\"";
+
+/// Is the virtualenv marked as relocatable in its `pyvenv.cfg`?
+pub fn is_relocatable_venv(venv: &Path) -> bool {
+    fs::read_to_string(venv.join("pyvenv.cfg"))
+        .ok()
+        .map_or(false, |cfg| {
+            cfg.lines().any(|line| {
+                matches!(line.split_once('='), Some((key, value))
+                    if key.trim() == "relocatable" && value.trim() == "true")
+            })
+        })
+}
+
+/// Makes a freshly created virtualenv relocatable as a whole.
+///
+/// The activation scripts for bash, zsh, fish and cmd.exe find the
+/// virtualenv relative to their own location and the virtualenv is marked as
+/// relocatable in its `pyvenv.cfg`.  The other activation scripts (and the
+/// `home` of the interpreter) keep their absolute paths.
+pub fn make_venv_relocatable(venv: &Path) -> Result<(), Error> {
+    let bin = venv.join(VENV_BIN);
+    for path in venv_paths(venv) {
+        for (name, from, to) in [
+            ("activate", format!("'{}'", path), SH_VENV),
+            ("activate", format!("\"{}\"", path), SH_VENV),
+            ("activate.fish", format!("'{}'", path), FISH_VENV),
+            ("activate.fish", format!("\"{}\"", path), FISH_VENV),
+            (
+                "activate.bat",
+                format!("\"VIRTUAL_ENV={}\"", path),
+                BAT_VENV,
+            ),
+        ] {
+            let script = bin.join(name);
+            if let Ok(contents) = fs::read_to_string(&script) {
+                if contents.contains(&from) {
+                    fs::write(&script, contents.replace(&from, to))?;
+                }
+            }
+        }
+    }
+
+    let cfg = venv.join("pyvenv.cfg");
+    let mut contents = fs::read_to_string(&cfg)?;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str("relocatable = true\n");
+    fs::write(&cfg, contents)?;
+    Ok(())
+}
+
+/// Returns the ways the path of the virtualenv can be spelled in its files.
+fn venv_paths(venv: &Path) -> Vec<String> {
+    let mut rv = vec![venv.to_string_lossy().into_owned()];
+    if let Ok(canonical) = venv.canonicalize() {
+        let canonical = canonical.to_string_lossy().into_owned();
+        if !rv.contains(&canonical) {
+            rv.push(canonical);
+        }
+    }
+    rv
+}
+
+/// Rewrites the scripts of a virtualenv so that they keep working when the
+/// virtualenv is moved.
+///
+/// On Windows scripts are executables with an embedded interpreter path
+/// which are left alone.
+pub fn make_scripts_relocatable(venv: &Path) -> Result<(), Error> {
+    if cfg!(windows) {
+        return Ok(());
+    }
+    let bin = venv.join(VENV_BIN);
+    let prefixes = venv_paths(venv)
+        .into_iter()
+        .map(|x| format!("{}/{}", x, VENV_BIN))
+        .collect::<Vec<_>>();
+
+    for entry in fs::read_dir(&bin)? {
+        let entry = entry?;
+        // the interpreter is a symlink unless it was copied, in which case
+        // it's skipped below as it's not text.
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let script = match fs::read_to_string(entry.path()) {
+            Ok(script) => script,
+            Err(_) => continue,
+        };
+        if let Some(rewritten) = prefixes
+            .iter()
+            .find_map(|prefix| relocatable_script(&script, prefix))
+        {
+            fs::write(entry.path(), rewritten)?;
+        }
+    }
+    Ok(())
+}
+
+/// Is this the path of the interpreter in the bin folder of the virtualenv?
+fn is_venv_python(path: &str, bin: &str) -> bool {
+    path.strip_prefix(bin)
+        .and_then(|x| x.strip_prefix('/'))
+        .map_or(false, |x| x.starts_with("python") && !x.contains('/'))
+}
+
+/// Returns the script with the relocatable launcher in place of the shebang.
+///
+/// Both the plain shebang and the `/bin/sh` form that pip writes for long
+/// paths are replaced.  Scripts that do not run the interpreter of the
+/// virtualenv are left alone, so `None` is returned for those.
+fn relocatable_script(script: &str, bin: &str) -> Option<String> {
+    let (first, rest) = script.split_once('\n')?;
+    let interpreter = first.strip_prefix("#!")?.trim();
+    if is_venv_python(interpreter, bin) {
+        return Some(format!("{}{}", RELOCATABLE_LAUNCHER, rest));
+    }
+    if interpreter == "/bin/sh" {
+        let (exec, rest) = rest.split_once('\n')?;
+        let (end, rest) = rest.split_once('\n')?;
+        let python = exec
+            .strip_prefix("'''exec' ")?
+            .split_whitespace()
+            .next()?
+            .trim_matches('"');
+        if is_venv_python(python, bin) && end == "' '''" {
+            return Some(format!("{}{}", RELOCATABLE_LAUNCHER, rest));
+        }
+    }
+    None
+}
+
+#[test]
+fn test_relocatable_script() {
+    let body = "import sys\nfrom black import main\nsys.exit(main())\n";
+    let expected = format!("{}{}", RELOCATABLE_LAUNCHER, body);
+    assert_eq!(
+        relocatable_script(&format!("#!/p/.venv/bin/python\n{}", body), "/p/.venv/bin"),
+        Some(expected.clone())
+    );
+    assert_eq!(
+        relocatable_script(
+            &format!(
+                "#!/bin/sh\n'''exec' \"/p/.venv/bin/python3\" \"$0\" \"$@\"\n' '''\n{}",
+                body
+            ),
+            "/p/.venv/bin"
+        ),
+        Some(expected.clone())
+    );
+    // already relocatable or not using the virtualenv
+    assert_eq!(relocatable_script(&expected, "/p/.venv/bin"), None);
+    assert_eq!(
+        relocatable_script("#!/usr/bin/python3\nprint(1)\n", "/p/.venv/bin"),
+        None
+    );
+    assert_eq!(
+        relocatable_script("#!/p/.venv/bin/sub/python\n", "/p/.venv/bin"),
+        None
+    );
+}
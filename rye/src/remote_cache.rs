@@ -0,0 +1,186 @@
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Error};
+use console::style;
+use tempfile::NamedTempFile;
+
+use crate::bootstrap::download_url_ignore_404;
+use crate::config::Config;
+use crate::transcript::run_status;
+use crate::utils::CommandOutput;
+
+/// A bucket that caches downloads across machines (eg: CI runners).
+///
+/// Buckets are accessed with the command line tools of the cloud provider
+/// so that their regular credential discovery applies.  Plain HTTPS
+/// locations are supported for reading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteCache {
+    /// `s3://bucket/prefix`, accessed with `aws s3`.
+    S3(String),
+    /// `gs://bucket/prefix`, accessed with `gsutil`.
+    Gcs(String),
+    /// `https://host/prefix`, read only.
+    Http(String),
+}
+
+impl RemoteCache {
+    /// Returns the remote cache configured in `cache.remote`.
+    pub fn current() -> Result<Option<RemoteCache>, Error> {
+        Config::current()
+            .remote_cache_url()
+            .map(|url| RemoteCache::parse(&url))
+            .transpose()
+    }
+
+    /// Parses the URL of a remote cache.
+    pub fn parse(url: &str) -> Result<RemoteCache, Error> {
+        let url = url.trim_end_matches('/').to_string();
+        if url.starts_with("s3://") {
+            Ok(RemoteCache::S3(url))
+        } else if url.starts_with("gs://") {
+            Ok(RemoteCache::Gcs(url))
+        } else if url.starts_with("https://") {
+            Ok(RemoteCache::Http(url))
+        } else {
+            bail!(
+                "unsupported remote cache '{}'. Expected an s3://, gs:// or https:// URL",
+                url
+            );
+        }
+    }
+
+    /// Can files be stored in the cache?
+    pub fn is_writable(&self) -> bool {
+        !matches!(self, RemoteCache::Http(_)) && Config::current().remote_cache_upload()
+    }
+
+    fn url(&self, key: &str) -> String {
+        match self {
+            RemoteCache::S3(base) | RemoteCache::Gcs(base) | RemoteCache::Http(base) => {
+                format!("{}/{}", base, key)
+            }
+        }
+    }
+
+    /// Fetches a file from the cache.
+    ///
+    /// Any failure is treated as a cache miss.
+    pub fn fetch_file(&self, key: &str, output: CommandOutput) -> Option<Vec<u8>> {
+        let url = self.url(key);
+        if output == CommandOutput::Verbose {
+            eprintln!("looking up {} in remote cache", url);
+        }
+        let rv = match self {
+            RemoteCache::Http(_) => download_url_ignore_404(&url, output).ok().flatten(),
+            _ => {
+                let tmp = NamedTempFile::new().ok()?;
+                let mut cmd = self.copy_command(&url, &tmp.path().to_string_lossy(), output);
+                if output != CommandOutput::Verbose {
+                    // misses are expected, so the errors are not interesting
+                    cmd.stderr(Stdio::null());
+                }
+                match run_status(&mut cmd) {
+                    Ok(status) if status.success() => fs::read(tmp.path()).ok(),
+                    _ => None,
+                }
+            }
+        };
+        if rv.is_some() && output != CommandOutput::Quiet {
+            eprintln!("{} {}", style("Found in remote cache").cyan(), key);
+        }
+        rv
+    }
+
+    /// Stores a file in the cache.
+    pub fn store_file(&self, key: &str, data: &[u8], output: CommandOutput) -> Result<(), Error> {
+        if !self.is_writable() {
+            return Ok(());
+        }
+        let url = self.url(key);
+        if output != CommandOutput::Quiet {
+            eprintln!("{} {}", style("Uploading to remote cache").cyan(), key);
+        }
+        let tmp = NamedTempFile::new()?;
+        fs::write(tmp.path(), data)?;
+        let status =
+            run_status(&mut self.copy_command(&tmp.path().to_string_lossy(), &url, output))?;
+        if !status.success() {
+            bail!("failed to upload {} to remote cache", url);
+        }
+        Ok(())
+    }
+
+    /// Downloads the files under a prefix that are missing in a local folder.
+    pub fn pull_dir(&self, key: &str, dir: &Path, output: CommandOutput) -> Result<(), Error> {
+        if matches!(self, RemoteCache::Http(_)) {
+            return Ok(());
+        }
+        fs::create_dir_all(dir)?;
+        let url = self.url(key);
+        let status = run_status(&mut self.sync_command(&url, &dir.to_string_lossy(), output))?;
+        if !status.success() {
+            bail!("failed to download {} from remote cache", url);
+        }
+        Ok(())
+    }
+
+    /// Uploads the files in a local folder that are missing under a prefix.
+    pub fn push_dir(&self, key: &str, dir: &Path, output: CommandOutput) -> Result<(), Error> {
+        if !self.is_writable() || !dir.is_dir() {
+            return Ok(());
+        }
+        let url = self.url(key);
+        let status = run_status(&mut self.sync_command(&dir.to_string_lossy(), &url, output))?;
+        if !status.success() {
+            bail!("failed to upload {} to remote cache", url);
+        }
+        Ok(())
+    }
+
+    fn copy_command(&self, src: &str, dst: &str, output: CommandOutput) -> Command {
+        let mut cmd = self.base_command(output);
+        match self {
+            RemoteCache::S3(_) => cmd.arg("s3").arg("cp"),
+            _ => cmd.arg("cp"),
+        };
+        if matches!(self, RemoteCache::S3(_)) && output != CommandOutput::Verbose {
+            cmd.arg("--quiet");
+        }
+        cmd.arg(src).arg(dst);
+        cmd
+    }
+
+    fn sync_command(&self, src: &str, dst: &str, output: CommandOutput) -> Command {
+        let mut cmd = self.base_command(output);
+        match self {
+            RemoteCache::S3(_) => {
+                cmd.arg("s3").arg("sync");
+                if output != CommandOutput::Verbose {
+                    cmd.arg("--quiet");
+                }
+            }
+            _ => {
+                cmd.arg("rsync").arg("-r");
+            }
+        };
+        cmd.arg(src).arg(dst);
+        cmd
+    }
+
+    fn base_command(&self, output: CommandOutput) -> Command {
+        match self {
+            RemoteCache::S3(_) => Command::new("aws"),
+            _ => {
+                let mut cmd = Command::new("gsutil");
+                cmd.arg("-m");
+                if output != CommandOutput::Verbose {
+                    cmd.arg("-q");
+                }
+                cmd
+            }
+        }
+    }
+}
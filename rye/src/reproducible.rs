@@ -0,0 +1,188 @@
+use std::env;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{anyhow, Context, Error};
+use flate2::{Compression, GzBuilder};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+/// The earliest timestamp that zip archives can represent (1980-01-01).
+const ZIP_EPOCH: u64 = 315_532_800;
+
+/// Returns the timestamp that is recorded for all files of a build.
+///
+/// This is `SOURCE_DATE_EPOCH` if set, otherwise the time of the last commit
+/// of the git repository of the project and the start of the zip epoch
+/// outside of git.
+pub fn source_date_epoch(root: &Path) -> Result<u64, Error> {
+    if let Ok(value) = env::var("SOURCE_DATE_EPOCH") {
+        return value
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid SOURCE_DATE_EPOCH '{}'", value));
+    }
+    let commit_time = Command::new("git")
+        .arg("log")
+        .arg("-1")
+        .arg("--format=%ct")
+        .current_dir(root)
+        .output()
+        .ok()
+        .filter(|x| x.status.success())
+        .and_then(|x| String::from_utf8_lossy(&x.stdout).trim().parse().ok());
+    Ok(commit_time.unwrap_or(ZIP_EPOCH))
+}
+
+/// Rewrites a built sdist or wheel so that it only depends on its contents.
+///
+/// Entries are sorted, all timestamps are set to `epoch`, owners are
+/// dropped and permissions are normalized to `0644` (`0755` for executables
+/// and folders).  Other files are left alone.
+pub fn normalize_archive(path: &Path, epoch: u64) -> Result<(), Error> {
+    let name = path.file_name().and_then(|x| x.to_str()).unwrap_or("");
+    let rv = if name.ends_with(".whl") {
+        normalize_wheel(path, epoch)
+    } else if name.ends_with(".tar.gz") {
+        normalize_sdist(path, epoch)
+    } else {
+        return Ok(());
+    };
+    rv.with_context(|| format!("failed to normalize {}", path.display()))
+}
+
+fn normalize_mode(mode: u32, is_dir: bool) -> u32 {
+    if is_dir || mode & 0o111 != 0 {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+fn normalize_wheel(path: &Path, epoch: u64) -> Result<(), Error> {
+    let mut archive = ZipArchive::new(fs::File::open(path)?)?;
+    let mut entries = Vec::new();
+    for idx in 0..archive.len() {
+        let mut file = archive.by_index(idx)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        entries.push((
+            file.name().to_string(),
+            file.is_dir(),
+            file.unix_mode().unwrap_or(0o644),
+            contents,
+        ));
+    }
+
+    // the metadata goes last and the RECORD is the very last file (as
+    // recommended by the wheel spec), everything else is sorted by name.
+    entries.sort_by_cached_key(|(name, ..)| {
+        let in_dist_info = name
+            .split('/')
+            .next()
+            .map_or(false, |x| x.ends_with(".dist-info"));
+        (
+            in_dist_info,
+            name.ends_with(".dist-info/RECORD"),
+            name.clone(),
+        )
+    });
+
+    let timestamp = zip_timestamp(epoch)?;
+    let tmp = path.with_extension("whl.tmp");
+    let mut writer = ZipWriter::new(fs::File::create(&tmp)?);
+    for (name, is_dir, mode, contents) in entries {
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(timestamp)
+            .unix_permissions(normalize_mode(mode, is_dir));
+        if is_dir {
+            writer.add_directory(name, options)?;
+        } else {
+            writer.start_file(name, options)?;
+            writer.write_all(&contents)?;
+        }
+    }
+    writer.finish()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn normalize_sdist(path: &Path, epoch: u64) -> Result<(), Error> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(fs::File::open(path)?));
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let kind = entry.header().entry_type();
+        if !(kind.is_file() || kind.is_dir() || kind.is_symlink()) {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mode = entry.header().mode()?;
+        let link = entry.link_name()?.map(|x| x.into_owned());
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        entries.push((name, kind, mode, link, contents));
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let tmp = path.with_extension("gz.tmp");
+    let encoder = GzBuilder::new().write(fs::File::create(&tmp)?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for (name, kind, mode, link, contents) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(kind);
+        header.set_mtime(epoch);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_username("")?;
+        header.set_groupname("")?;
+        header.set_mode(normalize_mode(mode, kind.is_dir()));
+        header.set_size(contents.len() as u64);
+        if let Some(link) = link {
+            header.set_link_name(link)?;
+        }
+        builder.append_data(&mut header, name, &contents[..])?;
+    }
+    builder.into_inner()?.finish()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Converts a unix timestamp into a zip timestamp (in UTC).
+fn zip_timestamp(epoch: u64) -> Result<DateTime, Error> {
+    let epoch = epoch.max(ZIP_EPOCH);
+    let days = (epoch / 86400) as i64;
+    let secs = epoch % 86400;
+
+    // days since 1970-01-01 to a civil date
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    DateTime::from_date_and_time(
+        year as u16,
+        month as u8,
+        day as u8,
+        (secs / 3600) as u8,
+        (secs % 3600 / 60) as u8,
+        (secs % 60) as u8,
+    )
+    .map_err(|_| anyhow!("timestamp {} cannot be stored in a zip file", epoch))
+}
+
+#[test]
+fn test_zip_timestamp() {
+    let ts = zip_timestamp(1_700_000_000).unwrap();
+    assert_eq!((ts.year(), ts.month(), ts.day()), (2023, 11, 14));
+    assert_eq!((ts.hour(), ts.minute(), ts.second()), (22, 13, 20));
+    assert_eq!(zip_timestamp(0).unwrap().year(), 1980);
+}
@@ -0,0 +1,321 @@
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Error};
+use serde::Serialize;
+
+use crate::bootstrap::update_site_hook;
+use crate::config::Config;
+
+/// Installed into the virtualenvs that run pip to sandbox build backends.
+///
+/// The hook is loaded by a `.pth` file and only activates when
+/// `RYE_SANDBOX_BUILDS` is set.  pip runs build backends (and legacy
+/// `setup.py` invocations) as Python subprocesses through `call_subprocess`,
+/// so these commands are wrapped in the sandbox there.  Other subprocesses
+/// (like the installation of build dependencies or git for VCS requirements)
+/// are left alone.  The sandbox hides the home folder, except for the
+/// interpreter and the folder of the build.  As pip is not importable yet
+/// when the `.pth` file runs
+/// (`pip --python` puts it on the path later), pip is patched once it's
+/// imported, and pip fails if that is not possible.
+const SANDBOX_HOOK: &str = r#"
+import os
+import sys
+
+
+def _is_build(cmd):
+    # PEP 517 hooks run through the in-process script of pyproject_hooks
+    # (pep517 in older versions of pip), legacy builds run setup.py
+    # through a setuptools shim that is passed with -c.
+    args = [str(x) for x in cmd or ()]
+    if len(args) > 1 and os.path.basename(args[1]) == "_in_process.py":
+        return True
+    if not args or not os.path.basename(args[0]).lower().startswith("python"):
+        return False
+    if "-c" in args[1:3]:
+        shim = args[args.index("-c") + 1:][:1]
+        return any("setuptools" in x for x in shim)
+    return False
+
+
+def _install():
+    import json
+    import tempfile
+
+    sandbox = json.loads(os.environ["RYE_SANDBOX_BUILDS"])
+
+    def writable_paths(cwd):
+        paths = [tempfile.gettempdir(), os.path.abspath(cwd or os.getcwd())]
+        return [os.path.realpath(x) for x in paths]
+
+    def readable_paths(cmd):
+        # the interpreter and its environment are all the build needs from
+        # the home folder, which is hidden otherwise
+        paths = [sys.prefix, sys.exec_prefix, sys.base_prefix,
+                 os.path.dirname(os.path.dirname(os.path.realpath(cmd[0])))]
+        return sorted(set(os.path.realpath(x) for x in paths))
+
+    def hidden_home():
+        home = os.path.realpath(os.path.expanduser("~"))
+        return home if os.path.dirname(home) != home else None
+
+    def wrap(cmd, cwd):
+        tool, path = sandbox.get("tool"), sandbox.get("path")
+        home = hidden_home()
+        readable = [x for x in readable_paths(cmd)
+                    if home and (x + os.sep).startswith(home + os.sep)]
+        if tool == "bwrap":
+            args = [path, "--die-with-parent", "--unshare-net", "--ro-bind", "/", "/",
+                    "--dev", "/dev", "--proc", "/proc"]
+            if home:
+                args += ["--tmpfs", home]
+            for x in readable:
+                args += ["--ro-bind", x, x]
+            for writable in writable_paths(cwd):
+                args += ["--bind", writable, writable]
+            return args + ["--"] + list(cmd)
+        if tool == "sandbox-exec":
+            rules = "".join('(allow file-write* (subpath "%s"))' % x
+                            for x in writable_paths(cwd) + ["/dev"])
+            if home:
+                rules += '(deny file-read-data (subpath "%s"))' % home
+                rules += "".join('(allow file-read* (subpath "%s"))' % x
+                                 for x in readable + writable_paths(cwd))
+            profile = "(version 1)(allow default)(deny network*)(deny file-write*)" + rules
+            return [path, "-p", profile] + list(cmd)
+        raise RuntimeError(
+            "refusing to run a build backend: sandboxed builds are enabled "
+            "(behavior.sandbox-builds) but no sandbox is available on this system"
+        )
+
+    def patch_pip():
+        # any failure in here fails pip rather than building unconfined
+        from pip._internal.utils import subprocess as pip_subprocess
+
+        call_subprocess = pip_subprocess.call_subprocess
+
+        def sandboxed_call_subprocess(cmd, *args, **kwargs):
+            if _is_build(cmd):
+                cmd = wrap(cmd, kwargs.get("cwd"))
+            return call_subprocess(cmd, *args, **kwargs)
+
+        pip_subprocess.call_subprocess = sandboxed_call_subprocess
+
+    class PipFinder:
+        def find_spec(self, name, path, target=None):
+            if name != "pip._internal":
+                return None
+            sys.meta_path.remove(self)
+            import importlib.util
+
+            spec = importlib.util.find_spec(name)
+            exec_module = spec.loader.exec_module
+
+            def exec_and_patch(module):
+                exec_module(module)
+                patch_pip()
+
+            spec.loader.exec_module = exec_and_patch
+            return spec
+
+    sys.meta_path.insert(0, PipFinder())
+
+
+if os.environ.get("RYE_SANDBOX_BUILDS"):
+    _install()
+"#;
+
+/// The sandbox tool that confines build backends.
+#[derive(Serialize, Debug)]
+struct Sandbox {
+    tool: Option<&'static str>,
+    path: Option<PathBuf>,
+}
+
+/// Finds the sandbox facility of the operating system.
+///
+/// On Linux this is bubblewrap (`bwrap`), on macOS `sandbox-exec`.
+fn find_sandbox() -> Sandbox {
+    let found = if cfg!(target_os = "linux") {
+        which::which("bwrap").ok().map(|path| ("bwrap", path))
+    } else if cfg!(target_os = "macos") {
+        Some(Path::new("/usr/bin/sandbox-exec"))
+            .filter(|x| x.is_file())
+            .map(|path| ("sandbox-exec", path.to_path_buf()))
+    } else {
+        None
+    };
+    Sandbox {
+        tool: found.as_ref().map(|x| x.0),
+        path: found.map(|x| x.1),
+    }
+}
+
+/// Enables the build sandbox for a command that runs pip.
+///
+/// Without a sandbox facility builds fail rather than run unconfined.
+pub fn set_sandbox_env(cmd: &mut Command) {
+    if Config::current().sandbox_builds() {
+        cmd.env(
+            "RYE_SANDBOX_BUILDS",
+            serde_json::to_string(&find_sandbox()).unwrap_or_default(),
+        );
+    }
+}
+
+/// Returns the home folder, which the sandbox hides from the sandboxed
+/// programs as it holds the credentials of the user.
+fn hidden_home() -> Option<PathBuf> {
+    simple_home_dir::home_dir()
+        .and_then(|x| x.canonicalize().ok())
+        .filter(|x| x.parent().is_some())
+}
+
+/// Creates a command that runs a program in the sandbox of the builds.
+///
+/// The program is confined like build backends: it has no network access,
+/// can only write to the temporary folder and the given paths and of the
+/// home folder it only sees the interpreter and the given paths.  Without a
+/// sandbox facility this fails rather than running the program unconfined.
+pub fn sandboxed_command(program: &Path, writable: &[&Path]) -> Result<Command, Error> {
+    let writable = Some(env::temp_dir().as_path())
+        .into_iter()
+        .chain(writable.iter().copied())
+        .filter_map(|x| x.canonicalize().ok())
+        .collect::<Vec<_>>();
+    let home = hidden_home();
+    // the interpreter of a virtualenv links to the toolchain it was created from
+    let readable = Some(program.to_path_buf())
+        .into_iter()
+        .chain(program.canonicalize().ok())
+        .filter_map(|x| x.parent().and_then(|x| x.parent()).map(Path::to_path_buf))
+        .filter_map(|x| x.canonicalize().ok())
+        .filter(|x| home.as_ref().map_or(false, |home| x.starts_with(home)))
+        .collect::<Vec<_>>();
+    let sandbox = find_sandbox();
+    match (sandbox.tool, sandbox.path) {
+        (Some("bwrap"), Some(path)) => {
+            let mut cmd = Command::new(path);
+            cmd.args(["--die-with-parent", "--unshare-net", "--ro-bind", "/", "/"])
+                .args(["--dev", "/dev", "--proc", "/proc"]);
+            if let Some(ref home) = home {
+                cmd.arg("--tmpfs").arg(home);
+            }
+            for path in &readable {
+                cmd.arg("--ro-bind").arg(path).arg(path);
+            }
+            for path in &writable {
+                cmd.arg("--bind").arg(path).arg(path);
+            }
+            cmd.arg("--").arg(program);
+            Ok(cmd)
+        }
+        (Some("sandbox-exec"), Some(path)) => {
+            let mut rules = writable
+                .iter()
+                .map(|x| x.as_path())
+                .chain(Some(Path::new("/dev")))
+                .map(|x| format!("(allow file-write* (subpath \"{}\"))", x.display()))
+                .collect::<String>();
+            if let Some(ref home) = home {
+                rules.push_str(&format!(
+                    "(deny file-read-data (subpath \"{}\"))",
+                    home.display()
+                ));
+                for path in readable.iter().chain(&writable) {
+                    rules.push_str(&format!(
+                        "(allow file-read* (subpath \"{}\"))",
+                        path.display()
+                    ));
+                }
+            }
+            let mut cmd = Command::new(path);
+            cmd.arg("-p")
+                .arg(format!(
+                    "(version 1)(allow default)(deny network*)(deny file-write*){}",
+                    rules
+                ))
+                .arg(program);
+            Ok(cmd)
+        }
+        _ => bail!(
+            "refusing to run {}: sandboxed builds are enabled (behavior.sandbox-builds) \
+             but no sandbox is available on this system",
+            program.display()
+        ),
+    }
+}
+
+/// Installs the hook that sandboxes build backends into a virtualenv.
+///
+/// Without `behavior.sandbox-builds` the hook is removed from the virtualenv.
+pub fn install_sandbox_hook(venv: &Path) -> Result<(), Error> {
+    let enabled = Config::current().sandbox_builds();
+    update_site_hook(venv, "_rye_sandbox", Some(SANDBOX_HOOK).filter(|_| enabled))
+}
+
+#[test]
+fn test_is_build_with_current_pip() {
+    // collects the commands that the installed pip runs for builds
+    const CHECK: &str = r#"
+import sys
+hook = {}
+exec(sys.argv[1], hook)
+is_build = hook["_is_build"]
+try:
+    from pip._vendor.pyproject_hooks import BuildBackendHookCaller
+except ImportError:
+    from pip._vendor.pep517.wrappers import Pep517HookCaller as BuildBackendHookCaller
+from pip._internal.build_env import get_runnable_pip
+
+commands = []
+
+def runner(cmd, cwd=None, extra_environ=None):
+    commands.append(list(cmd))
+    raise KeyboardInterrupt
+
+caller = BuildBackendHookCaller(".", "setuptools.build_meta", runner=runner)
+for hook_call in [
+    lambda: caller.get_requires_for_build_wheel(),
+    lambda: caller.build_wheel("."),
+]:
+    try:
+        hook_call()
+    except KeyboardInterrupt:
+        pass
+assert len(commands) == 2, commands
+for cmd in commands:
+    assert is_build(cmd), cmd
+for cmd in [
+    [sys.executable, str(get_runnable_pip()), "install", "setuptools>=40.8.0"],
+    ["git", "clone", "-q", "https://github.com/pypa/sampleproject"],
+    [sys.executable, "-c", "import sys; print(sys.prefix)"],
+]:
+    assert not is_build(cmd), cmd
+"#;
+    let python = match which::which("python3").or_else(|_| which::which("python")) {
+        Ok(python) => python,
+        Err(_) => return,
+    };
+    let has_pip = Command::new(&python)
+        .args(["-c", "import pip._internal.build_env"])
+        .output()
+        .map_or(false, |x| x.status.success());
+    if !has_pip {
+        return;
+    }
+    let output = Command::new(&python)
+        .arg("-c")
+        .arg(CHECK)
+        .arg(SANDBOX_HOOK)
+        .env_remove("RYE_SANDBOX_BUILDS")
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,215 @@
+use std::env::consts::{ARCH, OS};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Error};
+use pep440_rs::{Version, VersionSpecifiers};
+use pep508_rs::Requirement;
+use sha2::{Digest, Sha256};
+use toml_edit::Document;
+
+use crate::config::Config;
+use crate::installer::ensure_cached_venv;
+use crate::platform::{get_app_dir, list_known_toolchains};
+use crate::sources::{iter_downloadable, PythonVersionRequest};
+use crate::utils::CommandOutput;
+
+/// The template for `rye init --script`.
+pub const SCRIPT_TEMPLATE: &str = r#"# /// script
+# requires-python = "{{ requires_python }}"
+# dependencies = []
+# ///
+
+
+def main() -> None:
+    print("Hello from {{ name }}!")
+
+
+if __name__ == "__main__":
+    main()
+"#;
+
+/// The inline metadata of a single-file script (PEP 723).
+#[derive(Debug, Default)]
+pub struct ScriptMetadata {
+    pub requires_python: Option<VersionSpecifiers>,
+    pub dependencies: Vec<Requirement>,
+}
+
+impl ScriptMetadata {
+    /// Reads the `script` block from the source of a script.
+    ///
+    /// Scripts without a block have no dependencies.
+    pub fn parse(source: &str) -> Result<ScriptMetadata, Error> {
+        let toml = match find_metadata_block(source, "script")? {
+            Some(toml) => toml,
+            None => return Ok(ScriptMetadata::default()),
+        };
+        let doc = toml
+            .parse::<Document>()
+            .context("invalid TOML in the script metadata")?;
+        let requires_python = match doc.get("requires-python") {
+            Some(item) => Some(
+                item.as_str()
+                    .context("requires-python must be a string")?
+                    .parse::<VersionSpecifiers>()
+                    .map_err(|err| anyhow!("invalid requires-python: {}", err))?,
+            ),
+            None => None,
+        };
+        let mut dependencies = Vec::new();
+        if let Some(item) = doc.get("dependencies") {
+            let arr = item
+                .as_array()
+                .context("dependencies must be an array of strings")?;
+            for dep in arr.iter() {
+                let dep = dep
+                    .as_str()
+                    .context("dependencies must be an array of strings")?;
+                dependencies.push(
+                    dep.parse::<Requirement>()
+                        .with_context(|| format!("invalid dependency '{}'", dep))?,
+                );
+            }
+        }
+        Ok(ScriptMetadata {
+            requires_python,
+            dependencies,
+        })
+    }
+
+    /// Picks the Python version for the script.
+    ///
+    /// This is the default toolchain unless the script requires another
+    /// version, in which case the latest matching cpython is used.
+    pub fn python_version(&self) -> Result<PythonVersionRequest, Error> {
+        let default = Config::current().default_toolchain()?;
+        let specs = match self.requires_python {
+            Some(ref specs) => specs,
+            None => return Ok(default),
+        };
+        if specs.contains(&Version::from(default.clone())) {
+            return Ok(default);
+        }
+        let known = list_known_toolchains()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|x| x.0);
+        let downloadable = if Config::current().offline() {
+            Vec::new()
+        } else {
+            iter_downloadable(OS, ARCH).collect()
+        };
+        known
+            .chain(downloadable)
+            .filter(|x| x.kind == "cpython" && specs.contains(&x.clone().into()))
+            .max()
+            .map(PythonVersionRequest::from)
+            .with_context(|| format!("no Python version matches requires-python {}", specs))
+    }
+
+    /// Returns a cached virtualenv with the dependencies of the script.
+    pub fn ensure_venv(
+        &self,
+        py_ver: &PythonVersionRequest,
+        extra_requirements: &[Requirement],
+        refresh: bool,
+        output: CommandOutput,
+    ) -> Result<PathBuf, Error> {
+        let mut requirements = self
+            .dependencies
+            .iter()
+            .chain(extra_requirements)
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        requirements.sort();
+        requirements.dedup();
+        let key = format!("{}\n{}", py_ver, requirements.join("\n"));
+        let hash = hex::encode(Sha256::digest(key.as_bytes()));
+        let venv = get_app_dir()
+            .join("cache")
+            .join("scripts")
+            .join(&hash[..16]);
+        ensure_cached_venv(
+            &venv,
+            "rye-script.json",
+            py_ver,
+            &requirements,
+            refresh,
+            output,
+            |py_ver| {
+                Ok(serde_json::to_string_pretty(&serde_json::json!({
+                    "python": py_ver.to_string(),
+                    "dependencies": requirements,
+                }))?)
+            },
+        )?;
+        Ok(venv)
+    }
+}
+
+/// Finds a metadata block of the given type and returns its contents.
+fn find_metadata_block(source: &str, block_type: &str) -> Result<Option<String>, Error> {
+    let start = format!("# /// {}", block_type);
+    let mut rv = None;
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        if line.trim_end() != start {
+            continue;
+        }
+        if rv.is_some() {
+            bail!("multiple '{}' metadata blocks", block_type);
+        }
+        // the block ends with the last `# ///` of the comment lines that follow
+        let mut content = Vec::new();
+        let mut end = None;
+        for line in lines.by_ref() {
+            let line = line.trim_end();
+            let stripped = match line.strip_prefix("# ") {
+                Some(rest) => rest,
+                None if line == "#" => "",
+                None => break,
+            };
+            if stripped == "///" {
+                end = Some(content.len());
+            }
+            content.push(stripped);
+        }
+        match end {
+            Some(end) => rv = Some(content[..end].join("\n")),
+            None => bail!("unclosed '{}' metadata block", block_type),
+        }
+    }
+    Ok(rv)
+}
+
+#[test]
+fn test_parse_script_metadata() {
+    let meta = ScriptMetadata::parse(
+        r#"#!/usr/bin/env python
+# /// script
+# requires-python = ">=3.11"
+# dependencies = [
+#   "requests<3",
+#   "rich",
+# ]
+# ///
+
+import requests
+"#,
+    )
+    .unwrap();
+    assert_eq!(meta.requires_python.unwrap().to_string(), ">=3.11");
+    assert_eq!(
+        meta.dependencies
+            .iter()
+            .map(|x| x.name.as_str())
+            .collect::<Vec<_>>(),
+        vec!["requests", "rich"]
+    );
+
+    let meta = ScriptMetadata::parse("print('hello')\n").unwrap();
+    assert!(meta.requires_python.is_none());
+    assert!(meta.dependencies.is_empty());
+
+    assert!(ScriptMetadata::parse("# /// script\n# dependencies = []\n").is_err());
+}
@@ -0,0 +1,65 @@
+use std::env;
+use std::fs;
+use std::io::Read;
+
+use age::armor::ArmoredReader;
+use age::secrecy::Secret;
+use age::Decryptor;
+use anyhow::{bail, Context, Error};
+
+use crate::pyproject::PyProject;
+use crate::transcript::register_secret;
+use crate::utils::{ensure_interactive, parse_env_file};
+
+/// Loads the variables of the secrets file (`tool.rye.secrets-file`).
+///
+/// Files ending in `.age` are decrypted with the passphrase from
+/// `RYE_SECRETS_PASSPHRASE` or a prompt.  A missing file is not an error as
+/// the variables might be provided by the environment instead (eg: on CI).
+/// The values are redacted from transcripts.
+pub fn load_secrets(pyproject: &PyProject) -> Result<Vec<(String, String)>, Error> {
+    let path = match pyproject.secrets_file() {
+        Some(path) if path.is_file() => path,
+        _ => return Ok(Vec::new()),
+    };
+    let contents = fs::read(&path)
+        .with_context(|| format!("failed to read secrets file {}", path.display()))?;
+    let contents = if path.extension().map_or(false, |x| x == "age") {
+        decrypt_secrets(&contents)
+            .with_context(|| format!("failed to decrypt secrets file {}", path.display()))?
+    } else {
+        String::from_utf8(contents).context("secrets file is not valid utf-8")?
+    };
+    let secrets = parse_env_file(&contents)
+        .with_context(|| format!("failed to parse secrets file {}", path.display()))?;
+    for (_, value) in &secrets {
+        register_secret(value);
+    }
+    Ok(secrets)
+}
+
+fn decrypt_secrets(contents: &[u8]) -> Result<String, Error> {
+    let phrase = match env::var("RYE_SECRETS_PASSPHRASE") {
+        Ok(phrase) => Secret::new(phrase),
+        Err(_) => {
+            ensure_interactive("reading the secrets passphrase")?;
+            dialoguer::Password::new()
+                .with_prompt("Secrets passphrase")
+                .report(false)
+                .interact()
+                .map(Secret::new)?
+        }
+    };
+    let armored = ArmoredReader::new(contents);
+    match Decryptor::new(armored)? {
+        Decryptor::Passphrase(decryptor) => {
+            let mut decrypted = String::new();
+            decryptor
+                .decrypt(&phrase, None)?
+                .read_to_string(&mut decrypted)
+                .context("failed to parse utf-8")?;
+            Ok(decrypted)
+        }
+        _ => bail!("secrets file is not encrypted with a passphrase"),
+    }
+}
@@ -1,11 +1,14 @@
 use std::borrow::Cow;
 use std::fmt;
 use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{anyhow, Error};
 use pep440_rs::Version;
 use serde::{de, Deserialize, Serialize};
 
+// the downloads and the end of life of the CPython versions are generated
+// by find-downloads.py
 mod downloads {
     use super::PythonVersion;
     include!("downloads.inc");
@@ -13,6 +16,9 @@ mod downloads {
 
 const DEFAULT_KIND: &str = "cpython";
 
+/// How many months before the end of life a version is flagged.
+const END_OF_LIFE_NOTICE_MONTHS: u32 = 6;
+
 /// Internal descriptor for a python version.
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
 pub struct PythonVersion {
@@ -278,6 +284,104 @@ pub fn iter_downloadable<'s>(
         })
 }
 
+/// Support status of a Python version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupportStatus {
+    /// The version receives fixes (or its end of life is not known).
+    Supported,
+    /// The version reaches its end of life soon (year and month).
+    EndingSoon(u16, u8),
+    /// The version reached its end of life (year and month).
+    EndOfLife(u16, u8),
+}
+
+/// Returns the support status of a Python version at a given year and month.
+pub fn get_support_status(req: &PythonVersionRequest, now: (u16, u8)) -> SupportStatus {
+    if req.kind.as_deref().unwrap_or(DEFAULT_KIND) != DEFAULT_KIND {
+        return SupportStatus::Supported;
+    }
+    let minor = match req.minor {
+        Some(minor) => minor,
+        None => return SupportStatus::Supported,
+    };
+    let (year, month) = match downloads::CPYTHON_END_OF_LIFE
+        .iter()
+        .find(|x| x.0 == req.major && x.1 == minor)
+    {
+        Some(&(_, _, year, month)) => (year, month),
+        None if req.major < 3 => return SupportStatus::EndOfLife(2020, 1),
+        None => return SupportStatus::Supported,
+    };
+    let months_left = (year as i64 * 12 + month as i64) - (now.0 as i64 * 12 + now.1 as i64);
+    if months_left < 0 {
+        SupportStatus::EndOfLife(year, month)
+    } else if months_left < END_OF_LIFE_NOTICE_MONTHS as i64 {
+        SupportStatus::EndingSoon(year, month)
+    } else {
+        SupportStatus::Supported
+    }
+}
+
+/// Returns the oldest CPython version newer than the given one that is supported.
+pub fn get_supported_upgrade(
+    req: &PythonVersionRequest,
+    now: (u16, u8),
+) -> Option<PythonVersionRequest> {
+    downloads::CPYTHON_END_OF_LIFE
+        .iter()
+        .map(|&(major, minor, _, _)| PythonVersionRequest {
+            kind: None,
+            major,
+            minor: Some(minor),
+            patch: None,
+            suffix: None,
+        })
+        .filter(|x| (x.major, x.minor) > (req.major, req.minor))
+        .find(|x| get_support_status(x, now) == SupportStatus::Supported)
+}
+
+/// Returns the current year and month (UTC).
+pub fn current_year_month() -> (u16, u8) {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs() / 86400) as i64;
+    // civil from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year as u16, month as u8)
+}
+
+#[test]
+fn test_get_support_status() {
+    let now = (2026, 10);
+    assert_eq!(
+        get_support_status(&"3.8".parse().unwrap(), now),
+        SupportStatus::EndOfLife(2024, 10)
+    );
+    assert_eq!(
+        get_support_status(&"3.10.12".parse().unwrap(), now),
+        SupportStatus::EndingSoon(2026, 10)
+    );
+    assert_eq!(
+        get_support_status(&"3.12".parse().unwrap(), now),
+        SupportStatus::Supported
+    );
+    assert_eq!(
+        get_support_status(&"pypy@3.8".parse().unwrap(), now),
+        SupportStatus::Supported
+    );
+    assert_eq!(
+        get_supported_upgrade(&"3.9".parse().unwrap(), now).map(|x| x.to_string()),
+        Some("3.11".into())
+    );
+}
+
 #[test]
 fn test_get_download_url() {
     let url = get_download_url(&"3.8.14".parse().unwrap(), "macos", "aarch64");
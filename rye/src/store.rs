@@ -0,0 +1,545 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{bail, Context, Error};
+use filetime::FileTime;
+use same_file::is_same_file;
+use sha2::{Digest, Sha256};
+
+use crate::bootstrap::get_site_packages;
+use crate::config::Config;
+use crate::platform::get_app_dir;
+use crate::pyproject::normalize_package_name;
+use crate::utils::CommandOutput;
+use crate::warnings::{warn_with_output, Warning};
+
+/// Records which packages in a virtualenv were linked already.
+const LINKED_MARKER: &str = "rye-linked.json";
+
+/// How files of installed packages are shared with the package store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    /// Files are copy-on-write clones (reflinks) of the stored files.
+    Clone,
+    /// Files are hardlinks to the stored files.
+    Hardlink,
+    /// Every virtualenv keeps its own copy.
+    Copy,
+}
+
+impl LinkMode {
+    /// Returns the link mode configured in `cache.link-mode` for a virtualenv.
+    ///
+    /// Without a configured mode, files are cloned if the filesystem supports
+    /// it, hardlinked if the store is on the same device and copied otherwise.
+    pub fn current(venv: &Path) -> Result<LinkMode, Error> {
+        match Config::current().link_mode().as_deref() {
+            None => Ok(detect_link_mode(venv)),
+            Some("clone") => Ok(LinkMode::Clone),
+            Some("hardlink") => Ok(LinkMode::Hardlink),
+            Some("copy") => Ok(LinkMode::Copy),
+            Some(other) => bail!(
+                "unknown link mode '{}'. Expected clone, hardlink or copy",
+                other
+            ),
+        }
+    }
+}
+
+/// Finds the best link mode between the store and a virtualenv by linking a
+/// probe file.
+fn detect_link_mode(venv: &Path) -> LinkMode {
+    let store = get_store_dir();
+    let probe = match fs::create_dir_all(&store).and_then(|_| {
+        tempfile::Builder::new()
+            .prefix(".probe")
+            .tempfile_in(&store)
+    }) {
+        Ok(probe) => probe,
+        Err(_) => return LinkMode::Copy,
+    };
+    for mode in [LinkMode::Clone, LinkMode::Hardlink] {
+        let target = venv.join(format!(".rye-link-probe-{}", std::process::id()));
+        let linked = link_file(probe.path(), &target, mode).is_ok();
+        fs::remove_file(&target).ok();
+        if linked {
+            return mode;
+        }
+    }
+    LinkMode::Copy
+}
+
+/// Returns the folder of the content addressed package store.
+pub fn get_store_dir() -> PathBuf {
+    get_app_dir().join("cache").join("objects")
+}
+
+/// Replaces the files of installed packages with links into the store.
+///
+/// Files are addressed by the hash the installer recorded in the `RECORD`
+/// of the package, files whose contents no longer match it are left alone.
+/// Files that are not in the store yet are added to it (read-only), so the
+/// next virtualenv that installs the same file links it as well.  Only files
+/// within site-packages are considered, as scripts contain the path of the
+/// virtualenv.  Packages are only processed once per install.
+///
+/// Once all files of a package are in the store, its `RECORD` is kept for
+/// the interpreter (`python`), so [`install_from_store`] can install it into
+/// other virtualenvs without the installer.
+pub fn link_venv_files(
+    venv: &Path,
+    mode: LinkMode,
+    python: &str,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    if mode == LinkMode::Copy {
+        return Ok(());
+    }
+    let site_packages = get_site_packages(venv)?;
+    let store = get_store_dir();
+    let dists = get_dists_dir(python);
+    fs::create_dir_all(&dists)?;
+    let mut processed = read_linked_marker(venv);
+
+    let mut linked = 0;
+    let mut stored = 0;
+    let mut saved = 0;
+    for entry in fs::read_dir(&site_packages)? {
+        let dist_info = entry?.path();
+        if dist_info.extension().map_or(true, |x| x != "dist-info") {
+            continue;
+        }
+        let name = dist_info
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        let stamp = match record_stamp(&dist_info) {
+            Some(stamp) => stamp,
+            None => continue,
+        };
+        if processed.get(&name) == Some(&stamp) {
+            continue;
+        }
+        let record = match fs::read_to_string(dist_info.join("RECORD")) {
+            Ok(record) => record,
+            Err(_) => continue,
+        };
+        // packages with files outside of site-packages (scripts, data) or
+        // files that could not be shared are left to the installer.
+        let mut complete = true;
+        for line in record.lines().filter(|x| !x.trim().is_empty()) {
+            let (path, hash, size) = match parse_record_line(line) {
+                Some(parsed) => parsed,
+                None => {
+                    complete &= is_unhashed_entry(line);
+                    continue;
+                }
+            };
+            if path.starts_with("..") {
+                complete = false;
+                continue;
+            }
+            let file = site_packages.join(path);
+            if !file.is_file() {
+                complete = false;
+                continue;
+            }
+            let object = store.join(&hash[..2]).join(&hash[2..]);
+            if object.is_file() && is_same_file(&file, &object).unwrap_or(true) {
+                continue;
+            }
+            // files modified after the installation are not shared
+            if record_hash(&file).ok().as_deref() != Some(hash) {
+                complete = false;
+                continue;
+            }
+            let result = if object.is_file() {
+                // objects that were made writable might have been modified
+                // through another hardlink.
+                if mode == LinkMode::Hardlink && !is_read_only(&object) {
+                    complete = false;
+                    continue;
+                }
+                // the modification time records the last use for `rye cache prune`
+                filetime::set_file_mtime(&object, FileTime::now()).ok();
+                replace_with_link(&object, &file, mode).map(|_| {
+                    linked += 1;
+                    saved += size;
+                })
+            } else {
+                add_to_store(&file, &object, mode).map(|_| stored += 1)
+            };
+            if let Err(err) = result {
+                // a filesystem without reflink support or a store on another
+                // device fails for every file, so there is no point to go on.
+                warn_with_output(
+                    Warning::PackageStore,
+                    format!(
+                        "files are not shared through the package store: {:#}. \
+                         Set cache.link-mode to hardlink or copy",
+                        err
+                    ),
+                    output,
+                );
+                return Ok(());
+            }
+        }
+        // packages installed from a URL record it, they are not shared by version
+        complete &= !dist_info.join("direct_url.json").exists();
+        if let (true, Some(key)) = (complete, dist_key(&name)) {
+            fs::write(dists.join(key), &record).ok();
+        }
+        processed.insert(name, stamp);
+    }
+    processed.retain(|name, _| site_packages.join(name).is_dir());
+    write_linked_marker(venv, &processed)?;
+
+    if output == CommandOutput::Verbose {
+        eprintln!(
+            "Linked {} files from the package store ({} KiB saved), stored {} new files",
+            linked,
+            saved / 1024,
+            stored
+        );
+    }
+    Ok(())
+}
+
+/// Installs locked packages by linking their files from the store.
+///
+/// This works for packages that were installed for the same interpreter
+/// before and whose files are all in the store.  These packages are skipped
+/// by the installer afterwards, as they are installed in the locked version.
+/// Everything else (packages that are installed already, with scripts or
+/// with files that were pruned from the store) is left to the installer.
+pub fn install_from_store<'a, I>(
+    venv: &Path,
+    requirements: I,
+    mode: LinkMode,
+    python: &str,
+    output: CommandOutput,
+) -> Result<(), Error>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    if mode == LinkMode::Copy {
+        return Ok(());
+    }
+    let site_packages = get_site_packages(venv)?;
+    let dists = get_dists_dir(python);
+    let installed = fs::read_dir(&site_packages)?
+        .filter_map(|x| x.ok())
+        .filter_map(|x| dist_key(&x.file_name().to_string_lossy()))
+        .filter_map(|x| x.rsplit_once('-').map(|x| x.0.to_string()))
+        .collect::<HashSet<_>>();
+    let mut processed = read_linked_marker(venv);
+
+    let mut installed_count = 0;
+    for requirement in requirements {
+        let (name, version) = match parse_pinned_requirement(requirement) {
+            Some(pinned) => pinned,
+            None => continue,
+        };
+        if installed.contains(&name) {
+            continue;
+        }
+        let record = match fs::read_to_string(dists.join(format!("{}-{}", name, version))) {
+            Ok(record) => record,
+            Err(_) => continue,
+        };
+        match link_dist(&site_packages, &record, mode) {
+            Ok(Some(dist_info)) => {
+                if let Some(stamp) = record_stamp(&site_packages.join(&dist_info)) {
+                    processed.insert(dist_info, stamp);
+                }
+                installed_count += 1;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                if output == CommandOutput::Verbose {
+                    eprintln!("not installing {} from the package store: {:#}", name, err);
+                }
+            }
+        }
+    }
+    write_linked_marker(venv, &processed)?;
+
+    if output == CommandOutput::Verbose && installed_count > 0 {
+        eprintln!(
+            "Installed {} packages from the package store",
+            installed_count
+        );
+    }
+    Ok(())
+}
+
+/// Links the files of a stored `RECORD` into site-packages.
+///
+/// The `.dist-info` folder is written last, so that a package is only seen
+/// as installed once all of its files are in place.  Returns the name of the
+/// `.dist-info` folder, or `None` if not all files are in the store.
+fn link_dist(site_packages: &Path, record: &str, mode: LinkMode) -> Result<Option<String>, Error> {
+    let store = get_store_dir();
+    let mut files = Vec::new();
+    let mut record_path = None;
+    for line in record.lines().filter(|x| !x.trim().is_empty()) {
+        match parse_record_line(line) {
+            Some((path, hash, _)) if !path.starts_with("..") => {
+                let object = store.join(&hash[..2]).join(&hash[2..]);
+                if !object.is_file() || (mode == LinkMode::Hardlink && !is_read_only(&object)) {
+                    return Ok(None);
+                }
+                files.push((path, object));
+            }
+            None if is_unhashed_entry(line) => {
+                let path = line.trim_end_matches(',').trim_matches('"');
+                if path.ends_with(".dist-info/RECORD") {
+                    record_path = Some(path);
+                }
+            }
+            _ => return Ok(None),
+        }
+    }
+    let record_path = match record_path {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+    let dist_info = match record_path.split_once('/') {
+        Some((dist_info, _)) => dist_info.to_string(),
+        None => return Ok(None),
+    };
+    files.sort_by_key(|(path, _)| path.starts_with(dist_info.as_str()));
+
+    let mut created = Vec::new();
+    let result = (|| -> Result<(), Error> {
+        for (path, object) in &files {
+            let file = site_packages.join(path);
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            link_file(object, &file, mode)
+                .with_context(|| format!("failed to link {}", object.display()))?;
+            created.push(file);
+            filetime::set_file_mtime(object, FileTime::now()).ok();
+        }
+        let file = site_packages.join(record_path);
+        fs::write(&file, record)?;
+        created.push(file);
+        Ok(())
+    })();
+    if let Err(err) = result {
+        for file in created.iter().rev() {
+            fs::remove_file(file).ok();
+            for dir in file.ancestors().skip(1) {
+                if dir == site_packages || fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+        }
+        return Err(err);
+    }
+    Ok(Some(dist_info))
+}
+
+/// Returns the folder with the `RECORD` files of the packages that can be
+/// installed from the store for an interpreter.
+fn get_dists_dir(python: &str) -> PathBuf {
+    let digest = hex::encode(Sha256::digest(python.as_bytes()));
+    get_store_dir().join("dists").join(&digest[..16])
+}
+
+/// Returns the normalized `name-version` of a `.dist-info` folder.
+fn dist_key(dist_info: &str) -> Option<String> {
+    let (name, version) = dist_info.strip_suffix(".dist-info")?.rsplit_once('-')?;
+    Some(format!("{}-{}", normalize_package_name(name), version))
+}
+
+/// Parses an entry of a lockfile that pins a package (`name==version`).
+fn parse_pinned_requirement(requirement: &str) -> Option<(String, &str)> {
+    let line = requirement.lines().next()?.trim_end_matches('\\').trim();
+    let (name, version) = line.split_once("==")?;
+    let name = name.split('[').next()?.trim();
+    let version = version.trim();
+    if version.is_empty() || version.contains([' ', ';', '*', '@', ',']) {
+        return None;
+    }
+    Some((normalize_package_name(name), version))
+}
+
+/// Is this a line of a `RECORD` without a hash?
+///
+/// These are the `RECORD` itself and the bytecode compiled by the installer,
+/// which is not shared and compiled again by Python when missing.
+fn is_unhashed_entry(line: &str) -> bool {
+    let path = line.trim_end_matches(',').trim_matches('"');
+    path.ends_with(".dist-info/RECORD") || path.ends_with(".pyc")
+}
+
+/// A reinstall rewrites the `RECORD`, so its modification time tells whether
+/// the files of a package were replaced since they were last linked.
+fn record_stamp(dist_info: &Path) -> Option<String> {
+    let modified = fs::metadata(dist_info.join("RECORD"))
+        .and_then(|x| x.modified())
+        .ok()?;
+    Some(
+        modified
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.as_nanos())
+            .to_string(),
+    )
+}
+
+fn read_linked_marker(venv: &Path) -> BTreeMap<String, String> {
+    fs::read(venv.join(LINKED_MARKER))
+        .ok()
+        .and_then(|x| serde_json::from_slice(&x).ok())
+        .unwrap_or_default()
+}
+
+fn write_linked_marker(venv: &Path, processed: &BTreeMap<String, String>) -> Result<(), Error> {
+    fs::write(
+        venv.join(LINKED_MARKER),
+        serde_json::to_string_pretty(processed)?,
+    )
+    .context("failed writing linked packages marker file")
+}
+
+/// Parses a line of a `RECORD` file into path, hash and size.
+///
+/// The hash is the url safe base64 encoded sha256 digest, which is safe to
+/// use as filename.
+fn parse_record_line(line: &str) -> Option<(&str, &str, u64)> {
+    let mut parts = line.rsplitn(3, ',');
+    let size = parts.next()?.trim().parse().ok()?;
+    let hash = parts.next()?.trim().strip_prefix("sha256=")?;
+    let path = parts.next()?;
+    let path = path
+        .strip_prefix('"')
+        .and_then(|x| x.strip_suffix('"'))
+        .unwrap_or(path);
+    if hash.len() < 3 || hash.contains(['/', '\\', '.']) {
+        return None;
+    }
+    Some((path, hash, size))
+}
+
+/// Returns the hash of a file in the format of `RECORD` files.
+fn record_hash(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(base64::encode_config(
+        hasher.finalize(),
+        base64::URL_SAFE_NO_PAD,
+    ))
+}
+
+fn is_read_only(path: &Path) -> bool {
+    fs::metadata(path).map_or(false, |x| x.permissions().readonly())
+}
+
+/// Removes an object from the store.
+///
+/// Objects are read-only, which prevents their removal on Windows.
+pub fn remove_object(object: &Path) -> io::Result<()> {
+    if cfg!(windows) {
+        let mut permissions = fs::metadata(object)?.permissions();
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(object, permissions)?;
+    }
+    fs::remove_file(object)
+}
+
+fn link_file(src: &Path, dst: &Path, mode: LinkMode) -> io::Result<()> {
+    match mode {
+        LinkMode::Clone => reflink_copy::reflink(src, dst),
+        LinkMode::Hardlink => fs::hard_link(src, dst),
+        LinkMode::Copy => fs::copy(src, dst).map(|_| ()),
+    }
+}
+
+/// Atomically replaces a file with a link to the stored object.
+fn replace_with_link(object: &Path, file: &Path, mode: LinkMode) -> Result<(), Error> {
+    let tmp = file.with_file_name(format!(
+        ".{}.rye-tmp",
+        file.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    fs::remove_file(&tmp).ok();
+    link_file(object, &tmp, mode)
+        .with_context(|| format!("failed to link {}", object.display()))?;
+    if let Err(err) = fs::rename(&tmp, file) {
+        fs::remove_file(&tmp).ok();
+        return Err(err).with_context(|| format!("failed to replace {}", file.display()));
+    }
+    Ok(())
+}
+
+/// Adds a file to the store.
+///
+/// The object is written under a temporary name first so that concurrent
+/// syncs never see a partial object.  Objects are read-only, so they cannot
+/// be modified through the hardlinks in virtualenvs by accident.
+fn add_to_store(file: &Path, object: &Path, mode: LinkMode) -> Result<(), Error> {
+    let folder = object.parent().unwrap();
+    fs::create_dir_all(folder)?;
+    let tmp = tempfile::Builder::new()
+        .prefix(".tmp")
+        .tempfile_in(folder)?
+        .into_temp_path();
+    fs::remove_file(&tmp)?;
+    link_file(file, &tmp, mode).with_context(|| format!("failed to store {}", file.display()))?;
+    let mut permissions = fs::metadata(&tmp)?.permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&tmp, permissions)?;
+    tmp.persist(object)
+        .with_context(|| format!("failed to store {}", file.display()))?;
+    Ok(())
+}
+
+#[test]
+fn test_parse_record_line() {
+    assert_eq!(
+        parse_record_line("flask/app.py,sha256=AbCd-_EfGh,2048"),
+        Some(("flask/app.py", "AbCd-_EfGh", 2048))
+    );
+    assert_eq!(
+        parse_record_line("\"odd,name.py\",sha256=AbCd-_EfGh,12"),
+        Some(("odd,name.py", "AbCd-_EfGh", 12))
+    );
+    assert_eq!(parse_record_line("flask-3.0.0.dist-info/RECORD,,"), None);
+    assert_eq!(parse_record_line("x.py,md5=abcdef,12"), None);
+}
+
+#[test]
+fn test_record_hash() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("x.py");
+    fs::write(&file, "print(42)\n").unwrap();
+    assert_eq!(
+        record_hash(&file).unwrap(),
+        "WKRHNf_fprFJd1Fq1ubmQtR3mZzTYVNwKPLWuZ4HrWg"
+    );
+}
+
+#[test]
+fn test_parse_pinned_requirement() {
+    assert_eq!(
+        parse_pinned_requirement("Flask[async]==3.0.0 \\\n    --hash=sha256:abc"),
+        Some(("flask".into(), "3.0.0"))
+    );
+    assert_eq!(parse_pinned_requirement("flask>=3.0"), None);
+    assert_eq!(parse_pinned_requirement("flask==3.*"), None);
+    assert_eq!(
+        parse_pinned_requirement("flask @ https://example.com/flask.whl"),
+        None
+    );
+    assert_eq!(
+        dist_key("Flask_Login-0.6.3.dist-info").as_deref(),
+        Some("flask-login-0.6.3")
+    );
+    assert_eq!(dist_key("flask"), None);
+}
@@ -1,23 +1,59 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsString;
+use std::fmt;
 use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::{env, fs};
+use std::{env, fs, thread};
 
-use anyhow::{bail, Context, Error};
+use anyhow::{anyhow, bail, Context, Error};
 use console::style;
+use pep508_rs::{Requirement, VersionOrUrl};
 use serde::{Deserialize, Serialize};
-use tempfile::tempdir;
+use tempfile::{tempdir, NamedTempFile};
+use url::Url;
 
 use crate::bootstrap::{ensure_self_venv, fetch, get_pip_module};
+use crate::config::Config;
 use crate::consts::VENV_BIN;
 use crate::lock::{
-    make_project_root_fragment, update_single_project_lockfile, update_workspace_lockfile,
-    LockMode, LockOptions,
+    authoritative_lockfile_path, available_lock_groups, group_lockfile_name, install_pip_hooks,
+    keep_installed, locked_package_names, locked_versions, lockfile_has_hashes, lockfile_path,
+    make_project_root_fragment, merge_lockfiles, needs_pip_hooks, read_locked_requirements,
+    recorded_files_path, requirements_lockfile_name, select_current_platform,
+    split_editable_requirements, split_lockfile_entries, update_group_lockfile,
+    update_members_lockfile, update_single_project_lockfile, update_workspace_lockfile, LockMode,
+    LockOptions,
 };
+use crate::logging;
+use crate::network::{check_requirements, install_policy_hook};
 use crate::piptools::get_pip_sync;
-use crate::platform::get_toolchain_python_bin;
-use crate::pyproject::{get_current_venv_python_version, ExpandedSources, PyProject};
-use crate::sources::PythonVersion;
-use crate::utils::{get_venv_python_bin, set_proxy_variables, symlink_dir, CommandOutput};
+use crate::platform::{get_app_dir, get_toolchain_python_bin, record_toolchain_use};
+use crate::pylock::{pylock_filename, pylock_to_requirements, write_pylock};
+use crate::pyproject::{
+    get_current_venv_python_version, normalize_package_name, read_venv_marker, BuildSettings,
+    EnvConfig, ExpandedSources, LockFormat, PyProject,
+};
+use crate::relocate::{is_relocatable_venv, make_scripts_relocatable, make_venv_relocatable};
+use crate::remote_cache::RemoteCache;
+use crate::sandbox::install_sandbox_hook;
+use crate::sources::{
+    current_year_month, get_support_status, get_supported_upgrade, PythonVersion,
+    PythonVersionRequest, SupportStatus,
+};
+use crate::store::{install_from_store, link_venv_files, LinkMode};
+use crate::transcript::run_status;
+use crate::utils::{
+    get_venv_python_bin, is_noninteractive, set_proxy_variables, symlink_dir, CommandOutput,
+    QuietExit,
+};
+use crate::warnings::{warn, warn_with_output, Warning};
+use crate::wheel_cache::{fill_wheel_cache, find_missing, get_wheel_cache_dir};
+
+/// Records the metadata fingerprints of the editable installs.
+const EDITABLES_MARKER: &str = "rye-editables.json";
+
+/// Records the names of the packages installed from the lockfile.
+const SYNCED_MARKER: &str = "rye-synced.json";
 
 /// Controls the sync mode
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
@@ -44,12 +80,44 @@ pub struct SyncOptions {
     pub mode: SyncMode,
     /// Forces venv creation even when unsafe.
     pub force: bool,
+    /// Fail instead of recreating an incompatible venv.
+    pub no_recreate: bool,
+    /// Recreate a venv whose base interpreter is gone without asking.
+    pub force_recreate: bool,
     /// Do not lock.
     pub no_lock: bool,
+    /// Do not lock but fail if the lockfiles are out of date.
+    pub locked: bool,
     /// Controls locking.
     pub lock_options: LockOptions,
+    /// Refuse to install packages without hashes.
+    pub require_hashes: bool,
+    /// Keep packages that were not installed from the lockfile.
+    pub keep_extras: bool,
+    /// Rewrite the scripts so that the virtualenv can be moved.
+    pub relocatable: bool,
+    /// How many packages to install in parallel (defaults to the CPU count).
+    pub jobs: Option<usize>,
+    /// Only report what would change.
+    pub check: bool,
+    /// Also install the dependencies of this group from its lockfile.
+    pub group: Option<String>,
+    /// Only install the dependencies of the group.
+    pub only_group: bool,
+    /// Additional groups to write lockfiles for.
+    pub lock_groups: Vec<String>,
+    /// Write lockfiles for all groups.
+    pub all_groups: bool,
     /// Explicit pyproject location (Only usable by PythonOnly mode)
     pub pyproject: Option<PathBuf>,
+    /// Sync this named environment instead of the default virtualenv.
+    pub env: Option<String>,
+    /// Sync this environment instead of the default virtualenv.
+    pub env_config: Option<EnvConfig>,
+    /// Only install these workspace members (and the members they depend on).
+    pub packages: Vec<String>,
+    /// Install from the wheel cache only.
+    pub offline: bool,
 }
 
 impl SyncOptions {
@@ -65,23 +133,94 @@ impl SyncOptions {
         self.pyproject = pyproject;
         self
     }
+
+    pub fn env(mut self, env: Option<String>) -> Self {
+        self.env = env;
+        self
+    }
+}
+
+/// Settings for all pip invocations of a sync.
+///
+/// They are set on the commands and not on the process, as `rye batch` runs
+/// several syncs and locks in one process.
+#[derive(Default)]
+struct PipEnv {
+    /// Environment variables for pip.
+    vars: Vec<(&'static str, String)>,
+    /// Applied by the sitecustomize hook, see [`install_pip_hooks`].
+    build_settings: BuildSettings,
+}
+
+impl PipEnv {
+    fn apply(&self, cmd: &mut Command) {
+        cmd.envs(self.vars.iter().map(|(key, value)| (key, value)));
+    }
+}
+
+/// Raised when pip fails to install the packages into the virtualenv.
+#[derive(Debug)]
+pub struct InstallFailed;
+
+impl std::error::Error for InstallFailed {}
+
+impl fmt::Display for InstallFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Installation of dependencies failed")
+    }
 }
 
 /// Config written into the virtualenv for sync purposes.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct VenvMarker {
     pub python: PythonVersion,
+    /// The extension module suffix of the interpreter (encodes the ABI).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abi: Option<String>,
 }
 
+/// Prints the extension module suffix of the running interpreter.
+const PYTHON_ABI_SCRIPT: &str =
+    "import sysconfig; print(sysconfig.get_config_var('EXT_SUFFIX') or '')";
+
 /// Synchronizes a project's virtualenv.
 pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
-    let pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
-    let lockfile = pyproject.workspace_path().join("requirements.lock");
-    let dev_lockfile = pyproject.workspace_path().join("requirements-dev.lock");
+    let mut pyproject = PyProject::load_or_discover(cmd.pyproject.as_deref())?;
+    if let Some(ref env) = cmd.env {
+        pyproject = pyproject.with_env(env)?;
+    } else if let Some(ref env) = cmd.env_config {
+        pyproject = pyproject.with_env_config(env.clone());
+    }
+    let env = pyproject.env().cloned();
+    let lockfile = lockfile_path(&pyproject, requirements_lockfile_name(LockMode::Production));
+    let dev_lockfile = lockfile_path(&pyproject, requirements_lockfile_name(LockMode::Dev));
+    let pylock = lockfile_path(&pyproject, pylock_filename(LockMode::Production));
+    let dev_pylock = lockfile_path(&pyproject, pylock_filename(LockMode::Dev));
+    // the groups of an environment are installed on top, just like --group
+    let group_lockfiles = cmd
+        .group
+        .iter()
+        .chain(env.iter().flat_map(|x| x.groups.iter()))
+        .map(|group| Ok(lockfile_path(&pyproject, &group_lockfile_name(group)?)))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let dev = match env {
+        Some(ref env) => env.dev,
+        None => cmd.dev && cmd.group.is_none(),
+    };
     let venv = pyproject.venv_path();
-    let py_ver = pyproject.venv_python_version()?;
+    // offline only the installed toolchains can be used
+    let py_ver = if cmd.offline {
+        pyproject.installed_venv_python_version()?
+    } else {
+        pyproject.venv_python_version()?
+    };
     let output = cmd.output;
 
+    let lock_groups = groups_to_lock(&pyproject, &cmd)?;
+    if cmd.mode != SyncMode::PythonOnly {
+        check_python_end_of_life(&py_ver.clone().into(), output)?;
+    }
+
     if cmd.pyproject.is_some()
         && cmd.mode != SyncMode::PythonOnly
         && !pyproject.toml_path().ends_with("pyproject.toml")
@@ -90,6 +229,22 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
         bail!("cannot sync or generate lockfile: package needs 'pyproject.toml'");
     }
 
+    if cmd.check {
+        return check_sync(&cmd, &pyproject);
+    }
+
+    let _phase = logging::phase("sync", "sync");
+    logging::debug(
+        "sync",
+        format_args!(
+            "syncing {} ({:?}) into {} with Python {}",
+            pyproject.toml_path().display(),
+            cmd.mode,
+            venv.display(),
+            py_ver
+        ),
+    );
+
     // ensure we are bootstrapped
     let self_venv = ensure_self_venv(output).context("could not sync because bootstrap failed")?;
 
@@ -97,6 +252,13 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
     if venv.is_dir() {
         if let Some(marker_python) = get_current_venv_python_version(&venv) {
             if marker_python != py_ver {
+                if cmd.no_recreate {
+                    bail!(
+                        "virtualenv uses Python {} but {} is expected. Run without --no-recreate to recreate it.",
+                        marker_python,
+                        py_ver
+                    );
+                }
                 if cmd.output != CommandOutput::Quiet {
                     eprintln!(
                         "Python version mismatch (found {}, expect {}), recreating.",
@@ -116,14 +278,68 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
         } else {
             bail!("virtualenv is not managed by rye. Run `rye sync -f` to force.");
         }
+        if !recreate {
+            if let Some(problem) = find_broken_interpreter(&venv) {
+                recreate = confirm_recreate_broken_venv(&cmd, &problem)?;
+            }
+        }
     }
 
     // make sure we have a compatible python version
+    if cmd.offline && !get_toolchain_python_bin(&py_ver)?.is_file() {
+        bail!(
+            "Python version {} is not installed and downloads are disabled in offline mode",
+            py_ver
+        );
+    }
     let py_ver =
         fetch(&py_ver.into(), output).context("failed fetching toolchain ahead of sync")?;
+    record_toolchain_use(&py_ver);
+
+    // the same version can refer to a different build of the interpreter (for
+    // instance a free-threaded or debug build registered under the same name).
+    // Compiled extensions in the virtualenv no longer load if the ABI changed.
+    let abi = if cmd.mode != SyncMode::PythonOnly || !venv.is_dir() {
+        Some(get_python_abi(&get_toolchain_python_bin(&py_ver)?)?)
+    } else {
+        None
+    };
+    if let (false, Some(abi)) = (recreate, &abi) {
+        if let Some(marker_abi) = read_venv_marker(&venv).and_then(|x| x.abi) {
+            if marker_abi != *abi {
+                if cmd.no_recreate {
+                    bail!(
+                        "virtualenv was created for an incompatible Python ABI (found {}, expect {}). Run without --no-recreate to recreate it.",
+                        marker_abi,
+                        abi
+                    );
+                }
+                if cmd.output != CommandOutput::Quiet {
+                    eprintln!(
+                        "Python ABI mismatch (found {}, expect {}), recreating.",
+                        marker_abi, abi
+                    );
+                }
+                recreate = true;
+            }
+        }
+    }
+
+    // relocatable virtualenvs need a copy of the interpreter, so virtualenvs
+    // created before relocation was turned on are replaced.
+    if !recreate && venv.is_dir() && pyproject.fully_relocatable() && !is_relocatable_venv(&venv) {
+        if cmd.no_recreate {
+            bail!("virtualenv is not relocatable. Run without --no-recreate to recreate it.");
+        }
+        if output != CommandOutput::Quiet {
+            eprintln!("Recreating virtualenv to make it relocatable");
+        }
+        recreate = true;
+    }
 
     // kill the virtualenv if it's there and we need to get rid of it.
     if recreate {
+        logging::debug("sync", "removing virtualenv to recreate it");
         fs::remove_dir_all(&venv).ok();
     }
 
@@ -134,6 +350,20 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
         {
             eprintln!("Reusing already existing virtualenv");
         }
+        // virtualenvs created before the ABI was recorded get it filled in,
+        // so that a later change of the interpreter build is detected.
+        if let (Some(marker), Some(abi)) = (read_venv_marker(&venv), &abi) {
+            if marker.abi.is_none() {
+                fs::write(
+                    venv.join("rye-venv.json"),
+                    serde_json::to_string_pretty(&VenvMarker {
+                        python: marker.python,
+                        abi: Some(abi.clone()),
+                    })?,
+                )
+                .context("failed writing venv marker file")?;
+            }
+        }
     } else {
         if output != CommandOutput::Quiet {
             eprintln!(
@@ -142,12 +372,23 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
             );
             eprintln!("Python version: {}", style(&py_ver).cyan());
         }
-        create_virtualenv(output, &self_venv, &py_ver, &venv)
-            .context("failed creating virtualenv ahead of sync")?;
+        let _phase = logging::phase("sync", "creating virtualenv");
+        create_virtualenv(
+            output,
+            &self_venv,
+            &py_ver,
+            &venv,
+            pyproject.fully_relocatable(),
+        )
+        .context("failed creating virtualenv ahead of sync")?;
+        if pyproject.fully_relocatable() {
+            make_venv_relocatable(&venv).context("failed to make the virtualenv relocatable")?;
+        }
         fs::write(
             venv.join("rye-venv.json"),
             serde_json::to_string_pretty(&VenvMarker {
                 python: py_ver.clone(),
+                abi: abi.clone(),
             })?,
         )
         .context("failed writing venv marker file")?;
@@ -159,13 +400,106 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
     // can pass to pip-sync to install the local package.
     if recreate || cmd.mode != SyncMode::PythonOnly {
         let sources = ExpandedSources::from_sources(&pyproject.sources()?)?;
-        if cmd.no_lock {
-            let lockfile = if cmd.dev { &dev_lockfile } else { &lockfile };
-            if !lockfile.is_file() {
-                bail!(
-                    "Locking is disabled but lockfile '{}' does not exist",
-                    lockfile.display()
-                );
+        let mut pip_env = PipEnv::default();
+        let sources = if cmd.offline {
+            let sources = sources.offline(&get_wheel_cache_dir())?;
+            // pip sets up the build environments of editables from its
+            // environment rather than from its arguments.
+            pip_env.vars.push(("PIP_NO_INDEX", "1".into()));
+            pip_env.vars.push((
+                "PIP_FIND_LINKS",
+                sources
+                    .find_links
+                    .iter()
+                    .map(|x| x.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ));
+            sources
+        } else {
+            sources
+        };
+        let root = pyproject.workspace_path();
+        let mut lock_options = cmd.lock_options.clone();
+        if pyproject.generate_hashes() {
+            lock_options.generate_hashes = true;
+        }
+        if lock_options.platforms.is_empty() {
+            lock_options.platforms = pyproject.lock_platforms();
+        }
+        lock_options.canonicalize_platforms()?;
+        lock_options.add_project_settings(&pyproject)?;
+        let lock_format = pyproject.lock_format()?;
+        let no_lock = cmd.no_lock || cmd.locked;
+        let recorded_files = if !no_lock && pyproject.emit_pylock() {
+            let dir = tempdir()?;
+            lock_options.record_files = Some(dir.path().to_path_buf());
+            Some(dir)
+        } else {
+            None
+        };
+        let lock_phase = logging::phase("sync", "locking");
+        if lock_format == LockFormat::Pylock && !no_lock {
+            // pip-compile only understands requirements files, so seed it
+            // with the pins from the authoritative pylock.toml files.
+            for (pylock, lockfile) in [(&pylock, &lockfile), (&dev_pylock, &dev_lockfile)] {
+                if !pylock.is_file() {
+                    continue;
+                }
+                let pins = pylock_to_requirements(pylock, &root)?;
+                if lockfile.is_file() {
+                    let current = fs::read_to_string(lockfile)?;
+                    if locked_versions(&current) == locked_versions(&pins) {
+                        continue;
+                    }
+                    warn_with_output(
+                        Warning::LockfileMismatch,
+                        format!(
+                            "{} and {} pin different versions, using the pins of {}",
+                            pylock.display(),
+                            lockfile.display(),
+                            pylock.display()
+                        ),
+                        output,
+                    );
+                }
+                fs::write(lockfile, pins)?;
+            }
+        }
+        if cmd.locked {
+            let mut report = Vec::new();
+            relock_and_compare(
+                &pyproject,
+                &py_ver,
+                &sources,
+                &lock_options,
+                &lock_groups,
+                &mut report,
+            )?;
+            if !report.is_empty() {
+                if output != CommandOutput::Quiet {
+                    print_report(&report);
+                }
+                bail!("lockfiles are out of date. Run `rye lock` to update them");
+            }
+        }
+        if no_lock {
+            let lockfile = match (lock_format, dev) {
+                (LockFormat::Pylock, true) => &dev_pylock,
+                (LockFormat::Pylock, false) => &pylock,
+                (LockFormat::Requirements, true) => &dev_lockfile,
+                (LockFormat::Requirements, false) => &lockfile,
+            };
+            for lockfile in group_lockfiles
+                .iter()
+                .chain(Some(lockfile).filter(|_| !cmd.only_group))
+            {
+                if !lockfile.is_file() {
+                    bail!(
+                        "Locking is disabled but lockfile '{}' does not exist",
+                        lockfile.display()
+                    );
+                }
             }
         } else if let Some(workspace) = pyproject.workspace() {
             // make sure we have an up-to-date lockfile
@@ -176,7 +510,7 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
                 &lockfile,
                 cmd.output,
                 &sources,
-                &cmd.lock_options,
+                &lock_options,
             )
             .context("could not write production lockfile for workspace")?;
             update_workspace_lockfile(
@@ -186,7 +520,7 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
                 &dev_lockfile,
                 cmd.output,
                 &sources,
-                &cmd.lock_options,
+                &lock_options,
             )
             .context("could not write dev lockfile for workspace")?;
         } else {
@@ -198,7 +532,7 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
                 &lockfile,
                 cmd.output,
                 &sources,
-                &cmd.lock_options,
+                &lock_options,
             )
             .context("could not write production lockfile for project")?;
             update_single_project_lockfile(
@@ -208,13 +542,52 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
                 &dev_lockfile,
                 cmd.output,
                 &sources,
-                &cmd.lock_options,
+                &lock_options,
             )
             .context("could not write dev lockfile for project")?;
         }
 
+        if !no_lock {
+            for group in &lock_groups {
+                update_group_lockfile(
+                    &py_ver,
+                    &pyproject,
+                    group,
+                    &lockfile_path(&pyproject, &group_lockfile_name(group)?),
+                    &lockfile,
+                    cmd.output,
+                    &sources,
+                    &lock_options,
+                )
+                .with_context(|| format!("could not write lockfile for group '{}'", group))?;
+            }
+        }
+
+        if !no_lock && output != CommandOutput::Quiet {
+            warn_about_unlocked_updates(&lock_options.update, &dev_lockfile)?;
+        }
+
+        if !no_lock && pyproject.emit_pylock() {
+            for (lockfile, pylock) in [(&lockfile, &pylock), (&dev_lockfile, &dev_pylock)] {
+                write_pylock(
+                    lockfile,
+                    pylock,
+                    &root,
+                    pyproject.requires_python(),
+                    &sources,
+                    recorded_files
+                        .as_ref()
+                        .map(|x| recorded_files_path(x.path(), lockfile))
+                        .as_deref(),
+                )
+                .with_context(|| format!("could not write {}", pylock.display()))?;
+            }
+        }
+        drop(lock_phase);
+
         // run pip install with the lockfile.
         if cmd.mode != SyncMode::LockOnly {
+            let _phase = logging::phase("sync", "installing dependencies");
             if output != CommandOutput::Quiet {
                 eprintln!("Installing dependencies");
             }
@@ -225,52 +598,421 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
             )
             .context("failed linking pip module into for pip-sync")?;
             let mut pip_sync_cmd = Command::new(get_pip_sync(&py_ver, output)?);
-            let root = pyproject.workspace_path();
 
             let py_path = get_venv_python_bin(&venv);
 
-            pip_sync_cmd
-                .env("PROJECT_ROOT", make_project_root_fragment(&root))
-                .env("PYTHONPATH", tempdir.path())
-                .current_dir(&root)
-                .arg("--python-executable")
-                .arg(&py_path)
-                .arg("--pip-args")
-                // note that the double quotes are necessary to properly handle
-                // spaces in paths
-                .arg(format!("--python=\"{}\" --no-deps", py_path.display()));
-
-            sources.add_as_pip_args(&mut pip_sync_cmd);
-
-            for (idx, url) in sources.index_urls.iter().enumerate() {
-                if idx == 0 {
-                    pip_sync_cmd.arg("--index-url");
+            let (lockfile, lockfile_contents) = if let (true, Some(group_lockfile)) =
+                (cmd.only_group, group_lockfiles.first())
+            {
+                let contents = fs::read_to_string(group_lockfile).with_context(|| {
+                    format!("failed to read lockfile {}", group_lockfile.display())
+                })?;
+                (group_lockfile.clone(), contents)
+            } else if lock_format == LockFormat::Pylock {
+                let pylock = if dev && dev_pylock.is_file() {
+                    &dev_pylock
                 } else {
-                    pip_sync_cmd.arg("--extra-index-url");
+                    &pylock
+                };
+                let converted = tempdir.path().join("requirements-pylock.lock");
+                let contents = pylock_to_requirements(pylock, &root)?;
+                fs::write(&converted, &contents)?;
+                (converted, contents)
+            } else {
+                let lockfile = if dev && dev_lockfile.is_file() {
+                    &dev_lockfile
+                } else {
+                    &lockfile
+                };
+                let contents = fs::read_to_string(lockfile)
+                    .with_context(|| format!("failed to read lockfile {}", lockfile.display()))?;
+                (lockfile.clone(), contents)
+            };
+
+            // only a part of the workspace is installed with --package.  The
+            // versions are pinned to the lockfile of the whole workspace.
+            let (lockfile, lockfile_contents) = match pyproject.workspace() {
+                Some(workspace) if !cmd.packages.is_empty() => {
+                    let members_lockfile = tempdir.path().join("requirements-members.lock");
+                    update_members_lockfile(
+                        &py_ver,
+                        workspace,
+                        &cmd.packages,
+                        if dev {
+                            LockMode::Dev
+                        } else {
+                            LockMode::Production
+                        },
+                        &members_lockfile,
+                        &lockfile,
+                        output,
+                        &sources,
+                        &lock_options,
+                    )
+                    .context("could not lock the selected workspace members")?;
+                    let contents = fs::read_to_string(&members_lockfile)?;
+                    (members_lockfile, contents)
                 }
-                pip_sync_cmd.arg(&url.to_string());
-            }
+                _ => {
+                    for package in &cmd.packages {
+                        if normalize_package_name(package) != pyproject.normalized_name()? {
+                            bail!("unknown project '{}'", package);
+                        }
+                    }
+                    (lockfile, lockfile_contents)
+                }
+            };
 
-            if cmd.dev && dev_lockfile.is_file() {
-                pip_sync_cmd.arg(&dev_lockfile);
+            // the group lockfiles are installed on top of the production lockfile
+            let extra_groups = &group_lockfiles[if cmd.only_group { 1 } else { 0 }..];
+            let (lockfile, lockfile_contents) = if extra_groups.is_empty() {
+                (lockfile, lockfile_contents)
             } else {
-                pip_sync_cmd.arg(&lockfile);
+                let mut contents = lockfile_contents;
+                for group_lockfile in extra_groups {
+                    let group_contents = fs::read_to_string(group_lockfile).with_context(|| {
+                        format!("failed to read lockfile {}", group_lockfile.display())
+                    })?;
+                    contents = merge_lockfiles(&contents, &group_contents);
+                }
+                let merged = tempdir.path().join("requirements-group.lock");
+                fs::write(&merged, &contents)?;
+                (merged, contents)
+            };
+
+            // universal lockfiles pin packages for other platforms as well, only
+            // the entries for this platform are installed.
+            let (lockfile, lockfile_contents) =
+                match select_current_platform(&lockfile_contents, &py_ver)
+                    .with_context(|| format!("cannot install {}", lockfile.display()))?
+                {
+                    Some(contents) => {
+                        let selected = tempdir.path().join("requirements-platform.lock");
+                        fs::write(&selected, &contents)?;
+                        (selected, contents)
+                    }
+                    None => (lockfile, lockfile_contents),
+                };
+
+            // editable requirements cannot be installed in hash-checking mode.  In
+            // that case they are split off and installed separately afterwards.
+            check_requirements(&lockfile_contents, "a locked dependency")?;
+            // pip re-executes itself with the interpreter of the virtualenv
+            // (--python), so the hooks are needed in there as well.
+            install_policy_hook(&venv)?;
+            install_sandbox_hook(&venv)?;
+            let hash_checking = lockfile_has_hashes(&lockfile_contents);
+            if cmd.require_hashes && !hash_checking {
+                bail!(
+                    "lockfile {} has no hashes.  Set tool.rye.generate-hashes to create them",
+                    lockfile.display()
+                );
+            }
+
+            // packages that were previously installed from the lockfile but are
+            // no longer locked are orphans.  Everything else that is not in the
+            // lockfile was installed by hand.
+            let previous = read_synced_state(&venv);
+            let (locked, other_lines) = split_lockfile_entries(&lockfile_contents);
+            let build_requires = collect_build_requires(&pyproject)?;
+
+            // pip-sync does not pass the format control options of the
+            // lockfile on to pip, so they are passed to all pip invocations.
+            for (option, var) in [
+                ("--no-binary", "PIP_NO_BINARY"),
+                ("--only-binary", "PIP_ONLY_BINARY"),
+            ] {
+                let packages = other_lines
+                    .iter()
+                    .filter_map(|x| x.strip_prefix(option))
+                    .map(|x| x.trim_start_matches('=').trim())
+                    .collect::<Vec<_>>();
+                if !packages.is_empty() {
+                    pip_env.vars.push((var, packages.join(",")));
+                }
+            }
+            pip_env.build_settings = pyproject.build_settings()?;
+            if cmd.offline {
+                let missing = find_missing(
+                    locked
+                        .values()
+                        .map(|x| x.as_str())
+                        .chain(build_requires.iter().map(|x| x.as_str())),
+                );
+                if !missing.is_empty() {
+                    bail!(
+                        "cannot sync offline, {} package{} missing from the wheel cache:\n  {}\n\
+                         Sync once with network access and cache.keep-wheels enabled to add them.",
+                        missing.len(),
+                        if missing.len() == 1 { " is" } else { "s are" },
+                        missing.join("\n  ")
+                    );
+                }
+            }
+            let orphans = previous
+                .packages
+                .keys()
+                .filter(|name| !locked.contains_key(*name))
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>();
+            if !orphans.is_empty() && output != CommandOutput::Quiet {
+                eprintln!(
+                    "Uninstalling packages that are no longer locked: {}",
+                    orphans.join(", ")
+                );
             }
 
-            if output == CommandOutput::Verbose {
-                pip_sync_cmd.arg("--verbose");
-                if env::var("PIP_VERBOSE").is_err() {
-                    pip_sync_cmd.env("PIP_VERBOSE", "2");
+            // with a remote cache, pip's cache (downloaded and built wheels) is
+            // kept in a dedicated folder that is shared through the bucket.
+            let remote_cache = if cmd.offline {
+                None
+            } else {
+                RemoteCache::current()?
+            };
+            let pip_cache_dir = get_app_dir().join("cache").join("pip");
+            if let Some(ref remote_cache) = remote_cache {
+                if let Err(err) = remote_cache.pull_dir("pip", &pip_cache_dir, output) {
+                    warn_with_output(Warning::RemoteCache, err, output);
                 }
-            } else if output != CommandOutput::Quiet {
-                pip_sync_cmd.env("PYTHONWARNINGS", "ignore");
+            }
+            let pip_cache_dir = remote_cache.as_ref().map(|_| pip_cache_dir);
+            let jobs = cmd.jobs.unwrap_or_else(default_install_jobs);
+
+            // packages that are built without isolation are built against the
+            // virtualenv, so their build dependencies are installed first.
+            let build_dependencies =
+                unisolated_build_dependencies(&pip_env.build_settings, &locked, &previous)?;
+            if !build_dependencies.is_empty() {
+                install_locked_packages(
+                    &self_venv,
+                    &venv,
+                    &root,
+                    &sources,
+                    &pip_env,
+                    &build_dependencies,
+                    cmd.require_hashes,
+                    pip_cache_dir.as_deref(),
+                    jobs,
+                    output,
+                )?;
+            }
+
+            // packages that were installed for the same interpreter before are
+            // linked from the package store, which leaves them to the installer
+            // as already installed.  Hash-checking mode verifies downloads, so
+            // all packages go through the installer there.
+            let link_mode = LinkMode::current(&venv)?;
+            let store_python = format!("{:?}{}", py_ver, abi.as_deref().unwrap_or(""));
+            if !hash_checking {
+                install_from_store(
+                    &venv,
+                    locked.values().map(|x| x.as_str()),
+                    link_mode,
+                    &store_python,
+                    output,
+                )?;
+            }
+
+            // if the virtualenv was synced before, only the difference to the
+            // recorded state is applied.  Editables and options in the lockfile
+            // are not tracked individually, so changes to those need a full
+            // sync, just like a forced sync which recreates the virtualenv.
+            if !previous.packages.is_empty() && previous.other == other_lines {
+                let changed = locked
+                    .iter()
+                    .filter(|(name, entry)| previous.packages.get(*name) != Some(*entry))
+                    .map(|(_, entry)| entry.as_str())
+                    .collect::<Vec<_>>();
+                apply_sync_delta(
+                    &self_venv,
+                    &venv,
+                    &root,
+                    &sources,
+                    &pip_env,
+                    &changed,
+                    &orphans,
+                    cmd.require_hashes,
+                    pip_cache_dir.as_deref(),
+                    jobs,
+                    output,
+                )?;
             } else {
-                pip_sync_cmd.arg("-q");
+                // pip-sync uninstalls everything that is not in the lockfile.
+                // Packages installed by hand are retained with --keep-extras
+                // by pinning them to the installed version.
+                let (lockfile, lockfile_contents) = if cmd.keep_extras {
+                    if hash_checking {
+                        bail!("--keep-extras cannot be used with a lockfile that has hashes");
+                    }
+                    let mut contents = lockfile_contents;
+                    if !contents.is_empty() && !contents.ends_with('\n') {
+                        contents.push('\n');
+                    }
+                    for pkg in list_installed_packages(&self_venv, &venv)? {
+                        let name = normalize_package_name(&pkg.name);
+                        if !locked.contains_key(&name) && !previous.packages.contains_key(&name) {
+                            contents.push_str(&format!("{}=={}\n", pkg.name, pkg.version));
+                        }
+                    }
+                    let extended = tempdir.path().join("requirements-extras.lock");
+                    fs::write(&extended, &contents)?;
+                    (extended, contents)
+                } else {
+                    (lockfile, lockfile_contents)
+                };
+
+                // pip installs one package after another.  As everything is
+                // pinned, the packages are installed up front in parallel which
+                // leaves pip-sync with just the uninstalls and editables.
+                if jobs > 1 {
+                    install_locked_packages(
+                        &self_venv,
+                        &venv,
+                        &root,
+                        &sources,
+                        &pip_env,
+                        &locked.values().map(|x| x.as_str()).collect::<Vec<_>>(),
+                        cmd.require_hashes,
+                        pip_cache_dir.as_deref(),
+                        jobs,
+                        output,
+                    )?;
+                }
+                let mut pip_args = format!("--python=\"{}\" --no-deps", py_path.display());
+                if cmd.require_hashes {
+                    pip_args.push_str(" --require-hashes");
+                }
+
+                pip_sync_cmd
+                    .env("PROJECT_ROOT", make_project_root_fragment(&root))
+                    .env("PYTHONPATH", tempdir.path())
+                    .current_dir(&root)
+                    .arg("--python-executable")
+                    .arg(&py_path)
+                    .arg("--pip-args")
+                    // note that the double quotes are necessary to properly handle
+                    // spaces in paths
+                    .arg(pip_args);
+
+                sources.add_as_pip_args(&mut pip_sync_cmd);
+                pip_env.apply(&mut pip_sync_cmd);
+                install_pip_hooks(
+                    &mut pip_sync_cmd,
+                    &sources,
+                    &pip_env.build_settings,
+                    tempdir.path(),
+                )?;
+
+                for (idx, url) in sources.index_urls.iter().enumerate() {
+                    if idx == 0 {
+                        pip_sync_cmd.arg("--index-url");
+                    } else {
+                        pip_sync_cmd.arg("--extra-index-url");
+                    }
+                    pip_sync_cmd.arg(&url.to_string());
+                }
+
+                // pip-sync would uninstall the editables that are split off, so
+                // they are kept and only the missing ones are installed.
+                let editable_requirements = if hash_checking {
+                    let (hashed, editable) = split_editable_requirements(&lockfile_contents);
+                    let hashed_lockfile = tempdir.path().join("requirements-hashed.lock");
+                    fs::write(&hashed_lockfile, hashed)?;
+                    pip_sync_cmd.arg(&hashed_lockfile);
+                    let editables = editable_projects(&editable, &root)?;
+                    let names = editables.iter().map(|x| x.1.clone()).collect::<Vec<_>>();
+                    keep_installed(&mut pip_sync_cmd, &names, tempdir.path())?;
+                    Some(editables)
+                } else {
+                    pip_sync_cmd.arg(&lockfile);
+                    None
+                };
+
+                if output == CommandOutput::Verbose {
+                    pip_sync_cmd.arg("--verbose");
+                    if env::var("PIP_VERBOSE").is_err() {
+                        pip_sync_cmd.env("PIP_VERBOSE", "2");
+                    }
+                } else if output != CommandOutput::Quiet {
+                    pip_sync_cmd.env("PYTHONWARNINGS", "ignore");
+                } else {
+                    pip_sync_cmd.arg("-q");
+                }
+                set_proxy_variables(&mut pip_sync_cmd);
+                if let Some(ref pip_cache_dir) = pip_cache_dir {
+                    pip_sync_cmd.env("PIP_CACHE_DIR", pip_cache_dir);
+                }
+
+                let status = run_status(&mut pip_sync_cmd).context("unable to run pip-sync")?;
+                if !status.success() {
+                    return Err(InstallFailed.into());
+                }
+
+                if let Some(editables) = editable_requirements {
+                    let installed = list_installed_editables(&self_venv, &venv)?
+                        .into_iter()
+                        .map(|x| normalize_package_name(&x.name))
+                        .collect::<HashSet<_>>();
+                    let missing = editables
+                        .iter()
+                        .filter(|(_, name)| !installed.contains(name))
+                        .map(|(line, _)| format!("{}\n", line))
+                        .collect::<String>();
+                    if !missing.is_empty() {
+                        let editable_file = tempdir.path().join("requirements-editable.lock");
+                        fs::write(&editable_file, missing)?;
+                        install_without_deps(
+                            &self_venv,
+                            &venv,
+                            &root,
+                            &pip_env,
+                            &sources,
+                            &["-r".into(), editable_file.into_os_string()],
+                            output,
+                        )?;
+                    }
+                }
+            }
+            if !cmd.offline && Config::current().keep_wheels() {
+                if let Err(err) = fill_wheel_cache(
+                    &self_venv,
+                    &venv,
+                    &root,
+                    &sources,
+                    &pip_env.vars,
+                    &locked.values().map(|x| x.as_str()).collect::<Vec<_>>(),
+                    &build_requires,
+                    output,
+                ) {
+                    warn_with_output(Warning::WheelCache, err, output);
+                }
+            }
+            write_synced_state(
+                &venv,
+                &SyncedState {
+                    packages: locked,
+                    other: other_lines,
+                },
+            )?;
+            link_venv_files(&venv, link_mode, &store_python, output)?;
+
+            if let (Some(remote_cache), Some(pip_cache_dir)) = (remote_cache, pip_cache_dir) {
+                if let Err(err) = remote_cache.push_dir("pip", &pip_cache_dir, output) {
+                    warn_with_output(Warning::RemoteCache, err, output);
+                }
             }
-            set_proxy_variables(&mut pip_sync_cmd);
-            let status = pip_sync_cmd.status().context("unable to run pip-sync")?;
-            if !status.success() {
-                bail!("Installation of dependencies failed");
+
+            // pip-sync does not reinstall editables if their version did not
+            // change, so refresh them here and remember what metadata they
+            // were installed with.
+            if !cmd.only_group {
+                refresh_stale_editables(&pyproject, &venv, &self_venv, &pip_env, &sources, output)?;
+                write_editable_fingerprints(&venv, &editable_fingerprints(&pyproject)?)?;
+            }
+
+            if cmd.relocatable || pyproject.relocatable() {
+                make_scripts_relocatable(&venv)
+                    .context("failed to make the scripts of the virtualenv relocatable")?;
             }
         }
     }
@@ -282,11 +1024,873 @@ pub fn sync(cmd: SyncOptions) -> Result<(), Error> {
     Ok(())
 }
 
+/// Returns the editable requirements of a lockfile along with the
+/// normalized names of their projects.
+fn editable_projects(editable: &str, root: &Path) -> Result<Vec<(String, String)>, Error> {
+    let base_url = Url::from_directory_path(root)
+        .map_err(|_| anyhow!("invalid workspace root {}", root.display()))?;
+    let mut rv = Vec::new();
+    for line in editable.lines() {
+        let rel_url = match line.strip_prefix("-e ") {
+            Some(rel_url) => rel_url.trim().split('[').next().unwrap_or_default(),
+            None => continue,
+        };
+        let path = base_url
+            .join(rel_url)
+            .ok()
+            .and_then(|x| x.to_file_path().ok())
+            .ok_or_else(|| anyhow!("unsupported editable requirement '{}'", line))?;
+        let name = PyProject::load(&path.join("pyproject.toml"))?.normalized_name()?;
+        rv.push((line.to_string(), name));
+    }
+    Ok(rv)
+}
+
+/// Warns about Python versions that reached or are close to their end of life.
+///
+/// With `behavior.eol-policy = "deny"` versions past their end of life are
+/// refused instead.
+pub fn check_python_end_of_life(
+    version: &PythonVersionRequest,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let deny = match Config::current().eol_policy().as_deref() {
+        None | Some("warn") => false,
+        Some("deny") => true,
+        Some(other) => bail!("unknown eol policy '{}'. Expected warn or deny", other),
+    };
+    let now = current_year_month();
+    let upgrade = match get_supported_upgrade(version, now) {
+        Some(upgrade) => format!(" Upgrade with `rye pin {}`.", upgrade),
+        None => String::new(),
+    };
+    let simple = PythonVersionRequest {
+        patch: None,
+        suffix: None,
+        ..version.clone()
+    };
+    match get_support_status(version, now) {
+        SupportStatus::Supported => {}
+        SupportStatus::EndOfLife(year, month) if deny => {
+            bail!(
+                "Python {} reached its end of life in {}-{:02} and is not allowed by \
+                 behavior.eol-policy.{}",
+                simple,
+                year,
+                month,
+                upgrade
+            );
+        }
+        SupportStatus::EndOfLife(year, month) => warn_with_output(
+            Warning::PythonEndOfLife,
+            format_args!(
+                "Python {} reached its end of life in {}-{:02} and no longer receives \
+                 security fixes.{}",
+                simple, year, month, upgrade
+            ),
+            output,
+        ),
+        SupportStatus::EndingSoon(year, month) => warn_with_output(
+            Warning::PythonNearingEndOfLife,
+            format_args!(
+                "Python {} reaches its end of life in {}-{:02}.{}",
+                simple, year, month, upgrade
+            ),
+            output,
+        ),
+    }
+    Ok(())
+}
+
+/// Reports what a sync would change without changing anything.
+///
+/// The lockfiles are regenerated into a temporary folder (starting from the
+/// current pins) and compared with the ones on disk.  The virtualenv is
+/// compared with the lockfile that would be installed.
+fn check_sync(cmd: &SyncOptions, pyproject: &PyProject) -> Result<(), Error> {
+    let output = cmd.output;
+    let self_venv = ensure_self_venv(output).context("could not check because bootstrap failed")?;
+    let py_ver = pyproject.venv_python_version()?;
+    let venv = pyproject.venv_path();
+    let sources = ExpandedSources::from_sources(&pyproject.sources()?)?;
+    let mut lock_options = cmd.lock_options.clone();
+    if pyproject.generate_hashes() {
+        lock_options.generate_hashes = true;
+    }
+    if lock_options.platforms.is_empty() {
+        lock_options.platforms = pyproject.lock_platforms();
+    }
+    lock_options.canonicalize_platforms()?;
+    lock_options.add_project_settings(pyproject)?;
+
+    let mut report = Vec::new();
+    let locked = if cmd.no_lock {
+        let mut rv = Vec::new();
+        for lock_mode in [LockMode::Production, LockMode::Dev] {
+            match read_locked_requirements(pyproject, lock_mode)? {
+                Some(contents) => rv.push(contents),
+                None => bail!(
+                    "Locking is disabled but lockfile '{}' does not exist",
+                    authoritative_lockfile_path(pyproject, lock_mode)?.display()
+                ),
+            }
+        }
+        rv
+    } else {
+        let groups = groups_to_lock(pyproject, cmd)?;
+        relock_and_compare(
+            pyproject,
+            &py_ver,
+            &sources,
+            &lock_options,
+            &groups,
+            &mut report,
+        )?
+    };
+
+    if !venv.is_dir() {
+        report.push(("virtualenv does not exist".into(), Vec::new()));
+    } else if let Some(marker) = read_venv_marker(&venv) {
+        if marker.python != py_ver {
+            report.push((
+                format!(
+                    "virtualenv uses Python {} but {} is expected",
+                    marker.python, py_ver
+                ),
+                Vec::new(),
+            ));
+        } else {
+            let installed = list_installed_packages(&self_venv, &venv)?
+                .into_iter()
+                .map(|pkg| (normalize_package_name(&pkg.name), Some(pkg.version)))
+                .collect();
+            let contents = &locked[if cmd.dev { 1 } else { 0 }];
+            let wanted = match select_current_platform(contents, &py_ver)? {
+                Some(selected) => locked_versions(&selected),
+                None => locked_versions(contents),
+            };
+            let mut changes = describe_changes(&installed, &wanted);
+            let recorded: BTreeMap<String, String> = fs::read(venv.join(EDITABLES_MARKER))
+                .ok()
+                .and_then(|contents| serde_json::from_slice(&contents).ok())
+                .unwrap_or_default();
+            for (path, fingerprint) in editable_fingerprints(pyproject)? {
+                if recorded.get(&path).map_or(false, |x| *x != fingerprint) {
+                    changes.push(format!("~ {} (editable)", path));
+                }
+            }
+            if !changes.is_empty() {
+                report.push(("virtualenv is out of date".into(), changes));
+            }
+        }
+    } else {
+        report.push(("virtualenv is not managed by rye".into(), Vec::new()));
+    }
+
+    if report.is_empty() {
+        if output != CommandOutput::Quiet {
+            eprintln!("Everything is in sync");
+        }
+        return Ok(());
+    }
+    if output != CommandOutput::Quiet {
+        print_report(&report);
+    }
+    Err(QuietExit(1).into())
+}
+
+/// Locks into a temporary folder and compares the result with the lockfiles.
+///
+/// The temporary lockfiles start out with the current pins, so only the
+/// changes necessary to satisfy the pyproject.toml are reported.  With
+/// `lock-format = "pylock"` the pins are taken from the `pylock.toml` files.
+/// The lockfiles of the given groups are relocked on top of the fresh
+/// production lockfile.  Returns the fresh contents of the production and
+/// dev lockfile.
+fn relock_and_compare(
+    pyproject: &PyProject,
+    py_ver: &PythonVersion,
+    sources: &ExpandedSources,
+    lock_options: &LockOptions,
+    groups: &[String],
+    report: &mut Vec<(String, Vec<String>)>,
+) -> Result<Vec<String>, Error> {
+    let tempdir = tempdir()?;
+    let mut rv = Vec::new();
+    for lock_mode in [LockMode::Production, LockMode::Dev] {
+        let path = authoritative_lockfile_path(pyproject, lock_mode)?;
+        let filename = path.file_name().unwrap_or_default().to_string_lossy();
+        let current = read_locked_requirements(pyproject, lock_mode)?;
+        let fresh_lockfile = tempdir.path().join(requirements_lockfile_name(lock_mode));
+        if let Some(ref current) = current {
+            fs::write(&fresh_lockfile, current)?;
+        }
+        if let Some(workspace) = pyproject.workspace() {
+            update_workspace_lockfile(
+                py_ver,
+                workspace,
+                lock_mode,
+                &fresh_lockfile,
+                CommandOutput::Quiet,
+                sources,
+                lock_options,
+            )
+        } else {
+            update_single_project_lockfile(
+                py_ver,
+                pyproject,
+                lock_mode,
+                &fresh_lockfile,
+                CommandOutput::Quiet,
+                sources,
+                lock_options,
+            )
+        }
+        .with_context(|| format!("could not generate {}", filename))?;
+        let fresh = fs::read_to_string(&fresh_lockfile)?;
+        compare_lockfile(&filename, current.as_deref(), &fresh, report);
+        rv.push(fresh);
+    }
+
+    let production_lockfile = tempdir
+        .path()
+        .join(requirements_lockfile_name(LockMode::Production));
+    for group in groups {
+        let filename = group_lockfile_name(group)?;
+        let current = fs::read_to_string(lockfile_path(pyproject, &filename)).ok();
+        let fresh_lockfile = tempdir.path().join(&filename);
+        if let Some(ref current) = current {
+            fs::write(&fresh_lockfile, current)?;
+        }
+        update_group_lockfile(
+            py_ver,
+            pyproject,
+            group,
+            &fresh_lockfile,
+            &production_lockfile,
+            CommandOutput::Quiet,
+            sources,
+            lock_options,
+        )
+        .with_context(|| format!("could not generate {}", filename))?;
+        let fresh = fs::read_to_string(&fresh_lockfile)?;
+        compare_lockfile(&filename, current.as_deref(), &fresh, report);
+    }
+    Ok(rv)
+}
+
+/// Reports the differences between a lockfile and its relocked contents.
+fn compare_lockfile(
+    filename: &str,
+    current: Option<&str>,
+    fresh: &str,
+    report: &mut Vec<(String, Vec<String>)>,
+) {
+    match current {
+        None => report.push((format!("{} does not exist", filename), Vec::new())),
+        Some(current) => {
+            let changes = describe_changes(&locked_versions(current), &locked_versions(fresh));
+            if !changes.is_empty() {
+                report.push((format!("{} is out of date", filename), changes));
+            }
+        }
+    }
+}
+
+/// Prints the problems found by a check.
+fn print_report(report: &[(String, Vec<String>)]) {
+    for (problem, changes) in report {
+        println!("{}", style(problem).yellow());
+        for change in changes {
+            println!("  {}", change);
+        }
+    }
+}
+
+/// Describes how to get from one set of package versions to another.
+fn describe_changes(
+    old: &BTreeMap<String, Option<String>>,
+    new: &BTreeMap<String, Option<String>>,
+) -> Vec<String> {
+    let fmt_version = |version: &Option<String>| version.as_deref().unwrap_or("*").to_string();
+    let mut rv = Vec::new();
+    for (name, version) in new {
+        match old.get(name) {
+            None => rv.push(format!("+ {} {}", name, fmt_version(version))),
+            Some(old_version) if version.is_some() && old_version != version => rv.push(format!(
+                "~ {} {} -> {}",
+                name,
+                fmt_version(old_version),
+                fmt_version(version)
+            )),
+            Some(_) => {}
+        }
+    }
+    for (name, version) in old {
+        if !new.contains_key(name) {
+            rv.push(format!("- {} {}", name, fmt_version(version)));
+        }
+    }
+    rv
+}
+
+/// Returns the groups that get a lockfile of their own.
+///
+/// These are the groups in `tool.rye.lock-groups` plus the ones requested
+/// on the command line.
+fn groups_to_lock(pyproject: &PyProject, cmd: &SyncOptions) -> Result<Vec<String>, Error> {
+    let mut rv = pyproject.lock_groups();
+    let env_groups = pyproject.env().map_or(&[][..], |x| &x.groups[..]);
+    if cmd.lock_groups.is_empty() && !cmd.all_groups && cmd.group.is_none() && env_groups.is_empty()
+    {
+        return Ok(rv);
+    }
+
+    let available = available_lock_groups(pyproject)?;
+    if cmd.all_groups {
+        // the dev group is part of the dev lockfile
+        rv.extend(available.iter().filter(|x| *x != "dev").cloned());
+    }
+    for group in cmd
+        .lock_groups
+        .iter()
+        .chain(cmd.group.as_ref())
+        .chain(env_groups)
+    {
+        if !available.contains(&normalize_package_name(group)) {
+            bail!(
+                "unknown group '{}'. Expected a dependency group or optional dependency",
+                group
+            );
+        }
+        rv.push(group.clone());
+    }
+
+    let mut seen = HashSet::new();
+    rv.retain(|group| seen.insert(normalize_package_name(group)));
+    Ok(rv)
+}
+
+/// Warns about packages passed to `--update` that are not in the lockfile.
+///
+/// pip-compile silently ignores those, which usually means the package
+/// name was misspelled.
+fn warn_about_unlocked_updates(update: &[String], lockfile: &Path) -> Result<(), Error> {
+    if update.is_empty() || !lockfile.is_file() {
+        return Ok(());
+    }
+    let locked = locked_package_names(&fs::read_to_string(lockfile)?);
+    for pkg in update {
+        let name = match pkg.parse::<Requirement>() {
+            Ok(req) => normalize_package_name(&req.name),
+            Err(_) => normalize_package_name(pkg),
+        };
+        if !locked.contains(&name) {
+            warn(
+                Warning::UpdateNotLocked,
+                format_args!(
+                    "package '{}' is not in the lockfile, nothing to update",
+                    pkg
+                ),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// A package installed in the virtualenv as reported by `pip list`.
+#[derive(Deserialize, Debug)]
+struct InstalledPackage {
+    name: String,
+    version: String,
+}
+
+/// Lists the packages installed in the virtualenv.
+fn list_installed_packages(self_venv: &Path, venv: &Path) -> Result<Vec<InstalledPackage>, Error> {
+    list_installed(self_venv, venv, "--exclude-editable")
+}
+
+/// Lists the editable installs of the virtualenv.
+fn list_installed_editables(self_venv: &Path, venv: &Path) -> Result<Vec<InstalledPackage>, Error> {
+    list_installed(self_venv, venv, "--editable")
+}
+
+fn list_installed(
+    self_venv: &Path,
+    venv: &Path,
+    filter: &str,
+) -> Result<Vec<InstalledPackage>, Error> {
+    let out = Command::new(self_venv.join(VENV_BIN).join("pip"))
+        .arg("--python")
+        .arg(get_venv_python_bin(venv))
+        .arg("list")
+        .arg("--format=json")
+        .arg(filter)
+        .env("PYTHONWARNINGS", "ignore")
+        .output()
+        .context("unable to list installed packages")?;
+    if !out.status.success() {
+        bail!(
+            "failed to list installed packages\n{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    }
+    serde_json::from_slice(&out.stdout).context("could not parse list of installed packages")
+}
+
+/// The packages installed from the lockfile, recorded after every sync.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct SyncedState {
+    /// The lockfile entry of every package by normalized name.
+    packages: BTreeMap<String, String>,
+    /// The remaining lines of the lockfile (editables and options).
+    other: Vec<String>,
+}
+
+fn read_synced_state(venv: &Path) -> SyncedState {
+    fs::read(venv.join(SYNCED_MARKER))
+        .ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn write_synced_state(venv: &Path, state: &SyncedState) -> Result<(), Error> {
+    fs::write(
+        venv.join(SYNCED_MARKER),
+        serde_json::to_string_pretty(state)?,
+    )
+    .context("failed writing synced packages marker file")
+}
+
+/// Installs the changed packages and uninstalls the orphaned ones.
+#[allow(clippy::too_many_arguments)]
+fn apply_sync_delta(
+    self_venv: &Path,
+    venv: &Path,
+    root: &Path,
+    sources: &ExpandedSources,
+    pip_env: &PipEnv,
+    changed: &[&str],
+    orphans: &[&str],
+    require_hashes: bool,
+    pip_cache_dir: Option<&Path>,
+    jobs: usize,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    if changed.is_empty() && orphans.is_empty() {
+        if output == CommandOutput::Verbose {
+            eprintln!("Virtualenv matches the lockfile, nothing to install");
+        }
+        return Ok(());
+    }
+
+    let pip = self_venv.join(VENV_BIN).join("pip");
+    let py_path = get_venv_python_bin(venv);
+    if !orphans.is_empty() {
+        let mut pip_uninstall_cmd = Command::new(&pip);
+        pip_uninstall_cmd
+            .arg("--python")
+            .arg(&py_path)
+            .arg("uninstall")
+            .arg("-y")
+            .args(orphans);
+        if output != CommandOutput::Verbose {
+            pip_uninstall_cmd.arg("--quiet");
+        }
+        let status = run_status(&mut pip_uninstall_cmd).context("unable to uninstall packages")?;
+        if !status.success() {
+            bail!("Uninstallation of orphaned packages failed");
+        }
+    }
+
+    if !changed.is_empty() {
+        install_locked_packages(
+            self_venv,
+            venv,
+            root,
+            sources,
+            pip_env,
+            changed,
+            require_hashes,
+            pip_cache_dir,
+            jobs,
+            output,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the lockfile entries of the build dependencies of the packages
+/// that are built without isolation and not installed yet.
+fn unisolated_build_dependencies<'a>(
+    settings: &BuildSettings,
+    locked: &'a BTreeMap<String, String>,
+    previous: &SyncedState,
+) -> Result<Vec<&'a str>, Error> {
+    let mut rv = Vec::new();
+    for name in &settings.no_isolation {
+        match locked.get(name) {
+            Some(entry) if previous.packages.get(name) != Some(entry) => {}
+            _ => continue,
+        }
+        for requirement in settings.extra.get(name).into_iter().flatten() {
+            let dep = normalize_package_name(&requirement.parse::<Requirement>()?.name);
+            match locked.get(&dep) {
+                Some(entry) => rv.push(entry.as_str()),
+                None => bail!(
+                    "build dependency '{}' of '{}' is not locked. Lock again to pin it.",
+                    dep,
+                    name
+                ),
+            }
+        }
+    }
+    rv.sort();
+    rv.dedup();
+    Ok(rv)
+}
+
+/// The number of parallel installs if not configured otherwise.
+fn default_install_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |x| x.get().min(8))
+}
+
+/// Installs pinned lockfile entries without their dependencies.
+///
+/// The entries are split into batches that are installed by concurrent pip
+/// processes.  This is safe because every package is pinned and installed
+/// with `--no-deps`.  The exception are direct references which might have
+/// to be built against the packages in the virtualenv, so they are installed
+/// afterwards.
+#[allow(clippy::too_many_arguments)]
+fn install_locked_packages(
+    self_venv: &Path,
+    venv: &Path,
+    root: &Path,
+    sources: &ExpandedSources,
+    pip_env: &PipEnv,
+    entries: &[&str],
+    require_hashes: bool,
+    pip_cache_dir: Option<&Path>,
+    jobs: usize,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let (direct, regular): (Vec<&str>, Vec<&str>) = entries
+        .iter()
+        .copied()
+        .partition(|x| is_direct_reference(x));
+    let jobs = jobs.clamp(1, regular.len().max(1));
+    let mut batches = vec![Vec::new(); jobs];
+    for (idx, entry) in regular.into_iter().enumerate() {
+        batches[idx % jobs].push(entry);
+    }
+    if jobs > 1 && output != CommandOutput::Quiet {
+        eprintln!(
+            "Installing {} packages with {} parallel jobs",
+            entries.len() - direct.len(),
+            jobs
+        );
+    }
+
+    // the output of concurrent pip processes would interleave, so it is
+    // only shown in verbose mode.
+    let batch_output = match output {
+        CommandOutput::Verbose => CommandOutput::Verbose,
+        _ if jobs > 1 => CommandOutput::Quiet,
+        _ => output,
+    };
+    thread::scope(|scope| {
+        let handles = batches
+            .iter()
+            .filter(|batch| !batch.is_empty())
+            .map(|batch| {
+                scope.spawn(move || {
+                    install_batch(
+                        self_venv,
+                        venv,
+                        root,
+                        sources,
+                        pip_env,
+                        batch,
+                        require_hashes,
+                        pip_cache_dir,
+                        batch_output,
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().expect("installer thread panicked"))
+    })?;
+
+    if !direct.is_empty() {
+        install_batch(
+            self_venv,
+            venv,
+            root,
+            sources,
+            pip_env,
+            &direct,
+            require_hashes,
+            pip_cache_dir,
+            output,
+        )?;
+    }
+    Ok(())
+}
+
+/// Is the lockfile entry a direct reference (`name @ url`)?
+fn is_direct_reference(entry: &str) -> bool {
+    entry
+        .lines()
+        .next()
+        .and_then(|line| {
+            line.trim_end_matches('\\')
+                .trim()
+                .parse::<Requirement>()
+                .ok()
+        })
+        .map_or(false, |req| {
+            matches!(req.version_or_url, Some(VersionOrUrl::Url(_)))
+        })
+}
+
+#[allow(clippy::too_many_arguments)]
+fn install_batch(
+    self_venv: &Path,
+    venv: &Path,
+    root: &Path,
+    sources: &ExpandedSources,
+    pip_env: &PipEnv,
+    entries: &[&str],
+    require_hashes: bool,
+    pip_cache_dir: Option<&Path>,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let requirements = NamedTempFile::new()?;
+    fs::write(requirements.path(), entries.join("\n") + "\n")?;
+    let mut pip_install_cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
+    pip_install_cmd
+        .env("PROJECT_ROOT", make_project_root_fragment(root))
+        .current_dir(root)
+        .arg("--python")
+        .arg(get_venv_python_bin(venv))
+        .arg("install")
+        .arg("--no-deps")
+        .arg("-r")
+        .arg(requirements.path());
+    if require_hashes {
+        pip_install_cmd.arg("--require-hashes");
+    }
+    sources.add_as_pip_args(&mut pip_install_cmd);
+    pip_env.apply(&mut pip_install_cmd);
+    let site_dir = tempdir()?;
+    if needs_pip_hooks(sources, &pip_env.build_settings) {
+        pip_install_cmd.env("PYTHONPATH", site_dir.path());
+        install_pip_hooks(
+            &mut pip_install_cmd,
+            sources,
+            &pip_env.build_settings,
+            site_dir.path(),
+        )?;
+    }
+    if output == CommandOutput::Verbose {
+        pip_install_cmd.arg("--verbose");
+    } else if output == CommandOutput::Quiet {
+        pip_install_cmd.arg("--quiet");
+    } else {
+        pip_install_cmd.env("PYTHONWARNINGS", "ignore");
+    }
+    if let Some(pip_cache_dir) = pip_cache_dir {
+        pip_install_cmd.env("PIP_CACHE_DIR", pip_cache_dir);
+    }
+    set_proxy_variables(&mut pip_install_cmd);
+    let status = run_status(&mut pip_install_cmd).context("unable to install packages")?;
+    if !status.success() {
+        return Err(InstallFailed.into());
+    }
+    Ok(())
+}
+
+/// Returns the build requirements of the projects that are installed as editables.
+fn collect_build_requires(pyproject: &PyProject) -> Result<Vec<String>, Error> {
+    let mut rv = Vec::new();
+    if let Some(workspace) = pyproject.workspace() {
+        for project in workspace.iter_projects() {
+            rv.extend(project?.build_requires());
+        }
+    } else {
+        rv.extend(pyproject.build_requires());
+    }
+    rv.sort();
+    rv.dedup();
+    Ok(rv)
+}
+
+/// Returns the metadata fingerprints of all projects installed as editables.
+fn editable_fingerprints(pyproject: &PyProject) -> Result<BTreeMap<String, String>, Error> {
+    let mut rv = BTreeMap::new();
+    if let Some(workspace) = pyproject.workspace() {
+        for project in workspace.iter_projects() {
+            let project = project?;
+            rv.insert(
+                project.root_path().to_string_lossy().into_owned(),
+                project.metadata_fingerprint(),
+            );
+        }
+    } else {
+        rv.insert(
+            pyproject.root_path().to_string_lossy().into_owned(),
+            pyproject.metadata_fingerprint(),
+        );
+    }
+    Ok(rv)
+}
+
+fn write_editable_fingerprints(
+    venv: &Path,
+    fingerprints: &BTreeMap<String, String>,
+) -> Result<(), Error> {
+    fs::write(
+        venv.join(EDITABLES_MARKER),
+        serde_json::to_string_pretty(fingerprints)?,
+    )
+    .context("failed writing editables marker file")
+}
+
+/// Refreshes the editable installs of the virtualenv of a project.
+///
+/// This is what `rye run` does ahead of running a command, so that changed
+/// console scripts are available without a full sync.
+pub fn refresh_editables(pyproject: &PyProject, output: CommandOutput) -> Result<(), Error> {
+    let venv = pyproject.venv_path();
+    if !venv.join(EDITABLES_MARKER).is_file() {
+        return Ok(());
+    }
+    let self_venv = ensure_self_venv(output)?;
+    let sources = ExpandedSources::from_sources(&pyproject.sources()?)?;
+    refresh_stale_editables(
+        pyproject,
+        &venv,
+        &self_venv,
+        &PipEnv::default(),
+        &sources,
+        output,
+    )
+}
+
+/// Reinstalls editable projects whose metadata changed since they were installed.
+///
+/// The `.dist-info` of an editable install is only written at install time, so
+/// without this new console scripts or changed dependencies would not show up
+/// until the next full sync.
+fn refresh_stale_editables(
+    pyproject: &PyProject,
+    venv: &Path,
+    self_venv: &Path,
+    pip_env: &PipEnv,
+    sources: &ExpandedSources,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let recorded: BTreeMap<String, String> = match fs::read(venv.join(EDITABLES_MARKER)) {
+        Ok(contents) => serde_json::from_slice(&contents).unwrap_or_default(),
+        Err(_) => return Ok(()),
+    };
+    let current = editable_fingerprints(pyproject)?;
+    let stale = current
+        .iter()
+        .filter(|(path, fingerprint)| recorded.get(*path).map_or(false, |x| x != *fingerprint))
+        .map(|(path, _)| path)
+        .collect::<Vec<_>>();
+    if stale.is_empty() {
+        return Ok(());
+    }
+
+    let mut args = Vec::<OsString>::new();
+    for path in &stale {
+        if output != CommandOutput::Quiet {
+            eprintln!(
+                "Project metadata of {} changed, refreshing editable install",
+                style(path).cyan()
+            );
+        }
+        args.push("-e".into());
+        args.push(path.as_str().into());
+    }
+    install_without_deps(
+        self_venv,
+        venv,
+        &pyproject.workspace_path(),
+        pip_env,
+        sources,
+        &args,
+        output,
+    )?;
+
+    let mut updated = recorded;
+    for path in stale {
+        updated.insert(path.clone(), current[path].clone());
+    }
+    write_editable_fingerprints(venv, &updated)
+}
+
+/// Installs packages into the virtualenv with pip without their dependencies.
+fn install_without_deps(
+    self_venv: &Path,
+    venv: &Path,
+    root: &Path,
+    pip_env: &PipEnv,
+    sources: &ExpandedSources,
+    args: &[OsString],
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let mut pip_install_cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
+    pip_install_cmd
+        .env("PROJECT_ROOT", make_project_root_fragment(root))
+        .current_dir(root)
+        .arg("--python")
+        .arg(get_venv_python_bin(venv))
+        .arg("install")
+        .arg("--no-deps")
+        .args(args);
+    sources.add_as_pip_args(&mut pip_install_cmd);
+    pip_env.apply(&mut pip_install_cmd);
+    let site_dir = tempdir()?;
+    if needs_pip_hooks(sources, &pip_env.build_settings) {
+        pip_install_cmd.env("PYTHONPATH", site_dir.path());
+        install_pip_hooks(
+            &mut pip_install_cmd,
+            sources,
+            &pip_env.build_settings,
+            site_dir.path(),
+        )?;
+    }
+    if output == CommandOutput::Verbose {
+        pip_install_cmd.arg("--verbose");
+    } else {
+        pip_install_cmd.arg("--quiet");
+        pip_install_cmd.env("PYTHONWARNINGS", "ignore");
+    }
+    set_proxy_variables(&mut pip_install_cmd);
+    let status = run_status(&mut pip_install_cmd).context("unable to install editable packages")?;
+    if !status.success() {
+        bail!("Installation of editable packages failed");
+    }
+    Ok(())
+}
+
+/// Creates a virtualenv.
+///
+/// With `copies` the interpreter is copied into the virtualenv instead of
+/// being linked.
 pub fn create_virtualenv(
     output: CommandOutput,
     self_venv: &Path,
     py_ver: &PythonVersion,
     venv: &Path,
+    copies: bool,
 ) -> Result<(), Error> {
     let py_bin = get_toolchain_python_bin(py_ver)?;
     let mut venv_cmd = Command::new(self_venv.join(VENV_BIN).join("virtualenv"));
@@ -299,11 +1903,12 @@ pub fn create_virtualenv(
     venv_cmd.arg("-p");
     venv_cmd.arg(&py_bin);
     venv_cmd.arg("--no-seed");
+    if copies {
+        venv_cmd.arg("--copies");
+    }
     venv_cmd.arg("--");
     venv_cmd.arg(venv);
-    let status = venv_cmd
-        .status()
-        .context("unable to invoke virtualenv command")?;
+    let status = run_status(&mut venv_cmd).context("unable to invoke virtualenv command")?;
     if !status.success() {
         bail!("failed to initialize virtualenv");
     }
@@ -319,6 +1924,84 @@ pub fn create_virtualenv(
     Ok(())
 }
 
+/// Checks if the interpreter of a virtualenv still exists.
+///
+/// Virtualenvs link to the interpreter they were created from, so removing or
+/// upgrading that toolchain leaves them broken.  Returns what is wrong.
+fn find_broken_interpreter(venv: &Path) -> Option<String> {
+    let home = fs::read_to_string(venv.join("pyvenv.cfg"))
+        .ok()?
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| key.trim() == "home")
+        .map(|(_, value)| PathBuf::from(value.trim()));
+    if let Some(home) = home {
+        if !home.is_dir() {
+            return Some(format!(
+                "its base interpreter folder {} no longer exists",
+                home.display()
+            ));
+        }
+    }
+    let py_bin = get_venv_python_bin(venv);
+    // exists() follows symlinks, so this also catches dangling links.
+    if !py_bin.exists() {
+        return Some(format!("{} no longer points to a Python", py_bin.display()));
+    }
+    None
+}
+
+/// Decides whether a virtualenv with a missing interpreter is recreated.
+///
+/// This asks for confirmation unless `--force-recreate` was passed.
+fn confirm_recreate_broken_venv(cmd: &SyncOptions, problem: &str) -> Result<bool, Error> {
+    if cmd.no_recreate {
+        bail!(
+            "virtualenv is broken as {}. Run without --no-recreate to recreate it.",
+            problem
+        );
+    }
+    if !cmd.force_recreate {
+        if is_noninteractive() || !console::user_attended() {
+            bail!(
+                "virtualenv is broken as {}. Run `rye sync --force-recreate` to recreate it.",
+                problem
+            );
+        }
+        warn(
+            Warning::BrokenVirtualenv,
+            format_args!("virtualenv is broken as {}", problem),
+        );
+        if !dialoguer::Confirm::new()
+            .with_prompt("Recreate the virtualenv?")
+            .default(true)
+            .interact()?
+        {
+            return Err(QuietExit(1).into());
+        }
+    } else if cmd.output != CommandOutput::Quiet {
+        eprintln!("Virtualenv is broken as {}, recreating.", problem);
+    }
+    Ok(true)
+}
+
+/// Returns the extension module suffix of an interpreter.
+///
+/// The suffix encodes the ABI flags (debug, free-threaded) and the platform, so
+/// it changes whenever compiled extensions built for one interpreter no longer
+/// load in the other.
+fn get_python_abi(py_bin: &Path) -> Result<String, Error> {
+    let out = Command::new(py_bin)
+        .arg("-c")
+        .arg(PYTHON_ABI_SCRIPT)
+        .output()
+        .context("unable to determine ABI of interpreter")?;
+    if !out.status.success() {
+        bail!("failed to determine ABI of {}", py_bin.display());
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
 #[cfg(unix)]
 fn inject_tcl_config(venv: &Path, py_bin: &Path, py_ver: &PythonVersion) -> Result<(), Error> {
     let lib_path = match py_bin
@@ -0,0 +1,200 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Error};
+use pep508_rs::Requirement;
+use toml_edit::{value, Array, Document, Item, Table};
+
+use crate::installer::ToolInfo;
+use crate::platform::get_app_dir;
+use crate::pyproject::normalize_package_name;
+use crate::sources::PythonVersionRequest;
+
+/// The manifest of the global tools (`~/.rye/tools.toml`).
+///
+/// Every tool is a table below `tools` keyed by the normalized name of the
+/// tool.  Instead of a table a tool can also be given as a requirement string.
+pub struct ToolsManifest {
+    doc: Document,
+    path: PathBuf,
+}
+
+/// A tool declared in the manifest.
+#[derive(Debug, Clone)]
+pub struct ToolEntry {
+    pub requirement: Requirement,
+    pub python: Option<PythonVersionRequest>,
+    pub include_deps: Vec<String>,
+    pub extra_requirements: Vec<Requirement>,
+    pub match_project_python: bool,
+}
+
+impl ToolEntry {
+    /// Is the tool installed the way the entry asks for?
+    pub fn is_installed_as(&self, info: &ToolInfo) -> bool {
+        info.requirement == self.requirement.to_string()
+            && info.python == self.python.as_ref().map(|x| x.to_string())
+            && info.include_deps == self.include_deps
+            && info.extra_requirements
+                == self
+                    .extra_requirements
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+            && info.match_project_python == self.match_project_python
+    }
+}
+
+impl ToolsManifest {
+    /// Loads the manifest.  A missing manifest is treated as empty.
+    pub fn load() -> Result<ToolsManifest, Error> {
+        let path = get_app_dir().join("tools.toml");
+        let doc = if path.is_file() {
+            fs::read_to_string(&path)
+                .with_context(|| format!("failed to read tools from '{}'", path.display()))?
+                .parse::<Document>()
+                .with_context(|| format!("failed to parse tools from '{}'", path.display()))?
+        } else {
+            Document::new()
+        };
+        Ok(ToolsManifest { doc, path })
+    }
+
+    /// Returns the path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Does the manifest exist on disk?
+    pub fn exists(&self) -> bool {
+        self.path.is_file()
+    }
+
+    /// Saves changes back.
+    pub fn save(&self) -> Result<(), Error> {
+        fs::write(&self.path, self.doc.to_string())
+            .with_context(|| format!("failed to write tools to '{}'", self.path.display()))?;
+        Ok(())
+    }
+
+    /// Returns the declared tools by normalized name.
+    pub fn entries(&self) -> Result<Vec<(String, ToolEntry)>, Error> {
+        let mut rv = Vec::new();
+        let tools = match self.doc.get("tools").and_then(|x| x.as_table_like()) {
+            Some(tools) => tools,
+            None => return Ok(rv),
+        };
+        for (name, item) in tools.iter() {
+            let entry = parse_entry(name, item)
+                .with_context(|| format!("invalid tool '{}' in '{}'", name, self.path.display()))?;
+            rv.push((normalize_package_name(name), entry));
+        }
+        Ok(rv)
+    }
+
+    /// Adds or replaces a tool.
+    pub fn set(&mut self, entry: &ToolEntry) {
+        let tools = self.doc.entry("tools").or_insert(Item::Table(Table::new()));
+        let mut table = Table::new();
+        table["requirement"] = value(entry.requirement.to_string());
+        if let Some(ref python) = entry.python {
+            table["python"] = value(python.to_string());
+        }
+        if !entry.include_deps.is_empty() {
+            table["include-deps"] = value(entry.include_deps.iter().collect::<Array>());
+        }
+        if !entry.extra_requirements.is_empty() {
+            table["extra-requirements"] = value(
+                entry
+                    .extra_requirements
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect::<Array>(),
+            );
+        }
+        if entry.match_project_python {
+            table["match-project-python"] = value(true);
+        }
+        tools[normalize_package_name(&entry.requirement.name).as_str()] = Item::Table(table);
+    }
+
+    /// Removes a tool.  Returns `false` if it was not declared.
+    pub fn remove(&mut self, name: &str) -> bool {
+        let name = normalize_package_name(name);
+        let tools = match self
+            .doc
+            .get_mut("tools")
+            .and_then(|x| x.as_table_like_mut())
+        {
+            Some(tools) => tools,
+            None => return false,
+        };
+        let key = tools
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .find(|key| normalize_package_name(key) == name);
+        match key {
+            Some(key) => tools.remove(&key).is_some(),
+            None => false,
+        }
+    }
+}
+
+fn parse_entry(name: &str, item: &Item) -> Result<ToolEntry, Error> {
+    // a plain string is the requirement of the tool
+    if let Some(requirement) = item.as_str() {
+        return Ok(ToolEntry {
+            requirement: parse_requirement(requirement)?,
+            python: None,
+            include_deps: Vec::new(),
+            extra_requirements: Vec::new(),
+            match_project_python: false,
+        });
+    }
+    let table = item
+        .as_table_like()
+        .ok_or_else(|| anyhow!("expected a requirement or a table"))?;
+    let requirement = match table.get("requirement") {
+        Some(requirement) => requirement
+            .as_str()
+            .ok_or_else(|| anyhow!("requirement must be a string"))?,
+        None => name,
+    };
+    Ok(ToolEntry {
+        requirement: parse_requirement(requirement)?,
+        python: table
+            .get("python")
+            .and_then(|x| x.as_str())
+            .map(|x| x.parse::<PythonVersionRequest>())
+            .transpose()
+            .context("invalid python version")?,
+        include_deps: string_array(table.get("include-deps"))
+            .iter()
+            .map(|x| normalize_package_name(x))
+            .collect(),
+        extra_requirements: string_array(table.get("extra-requirements"))
+            .iter()
+            .map(|x| parse_requirement(x))
+            .collect::<Result<_, _>>()?,
+        match_project_python: table
+            .get("match-project-python")
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false),
+    })
+}
+
+fn string_array(item: Option<&Item>) -> Vec<String> {
+    item.and_then(|x| x.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|x| x.as_str())
+                .map(|x| x.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parse_requirement(req: &str) -> Result<Requirement, Error> {
+    req.parse::<Requirement>()
+        .with_context(|| format!("failed to parse requirement '{}'", req))
+}
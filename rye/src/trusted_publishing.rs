@@ -0,0 +1,210 @@
+use std::env;
+
+use age::secrecy::Secret;
+use anyhow::{bail, Context, Error};
+use serde::Deserialize;
+use url::Url;
+
+use crate::network::{
+    check_url, configure_curl, explain_curl_error, with_retries, HttpStatusError,
+};
+use crate::utils::CommandOutput;
+
+#[derive(Deserialize)]
+struct Audience {
+    audience: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubToken {
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct MintedToken {
+    #[serde(default)]
+    success: bool,
+    token: Option<String>,
+    #[serde(default)]
+    errors: Vec<MintError>,
+}
+
+#[derive(Deserialize)]
+struct MintError {
+    code: String,
+    description: String,
+}
+
+/// Where the OIDC token of the CI comes from.
+enum IdTokenSource {
+    /// GitHub Actions hands out tokens for any audience on request.
+    GitHub { request_url: String, token: String },
+    /// GitLab CI provides tokens declared with `id_tokens` in the job.
+    GitLab(String),
+}
+
+impl IdTokenSource {
+    fn detect() -> Option<IdTokenSource> {
+        if let (Ok(request_url), Ok(token)) = (
+            env::var("ACTIONS_ID_TOKEN_REQUEST_URL"),
+            env::var("ACTIONS_ID_TOKEN_REQUEST_TOKEN"),
+        ) {
+            Some(IdTokenSource::GitHub { request_url, token })
+        } else if let Ok(token) = env::var("PYPI_ID_TOKEN") {
+            Some(IdTokenSource::GitLab(token))
+        } else {
+            None
+        }
+    }
+
+    fn fetch(&self, audience: &str, output: CommandOutput) -> Result<String, Error> {
+        match self {
+            IdTokenSource::GitHub { request_url, token } => {
+                let mut url = Url::parse(request_url).context("invalid OIDC request URL")?;
+                url.query_pairs_mut().append_pair("audience", audience);
+                let response = request(
+                    url.as_str(),
+                    Some(&format!("Authorization: bearer {}", token)),
+                    None,
+                    output,
+                )?;
+                let token: GitHubToken = serde_json::from_slice(&response)
+                    .context("invalid OIDC token response from GitHub Actions")?;
+                Ok(token.value)
+            }
+            IdTokenSource::GitLab(token) => Ok(token.clone()),
+        }
+    }
+}
+
+/// The hosts of the indexes that support trusted publishing.
+const TRUSTED_PUBLISHING_HOSTS: &[&str] = &["pypi.org", "test.pypi.org"];
+
+/// Exchanges the OIDC token of the CI for a short-lived API token.
+///
+/// This implements PyPI's trusted publishing.  Outside of a CI that provides
+/// OIDC tokens, or if the index does not support trusted publishing, `None`
+/// is returned.  On GitHub Actions the job needs the
+/// `id-token: write` permission, on GitLab CI the token has to be declared
+/// as `PYPI_ID_TOKEN` in the `id_tokens` of the job.
+///
+/// The OIDC token of the job is only handed to PyPI and TestPyPI, unless
+/// `any_host` opts into trusted publishing with other indexes.
+pub fn mint_token(
+    repository_url: &Url,
+    any_host: bool,
+    output: CommandOutput,
+) -> Result<Option<Secret<String>>, Error> {
+    let source = match IdTokenSource::detect() {
+        Some(source) => source,
+        None => return Ok(None),
+    };
+
+    // the index lives on the domain the uploads go to, except for pypi
+    // itself which takes uploads on a separate domain.
+    let host = match repository_url.host_str() {
+        Some("upload.pypi.org") => "pypi.org",
+        Some(host) => host,
+        None => bail!("invalid repository url {}", repository_url),
+    };
+    if !any_host && !TRUSTED_PUBLISHING_HOSTS.contains(&host) {
+        if output == CommandOutput::Verbose {
+            eprintln!(
+                "Not using trusted publishing for {}, pass --trusted-publishing to enable it",
+                host
+            );
+        }
+        return Ok(None);
+    }
+    let index_url = format!("{}://{}", repository_url.scheme(), host);
+    if output == CommandOutput::Verbose {
+        eprintln!("Exchanging OIDC token with {}", index_url);
+    }
+
+    // indexes without trusted publishing do not answer with an audience, the
+    // stored credentials are used for those.
+    let audience: Audience = match serde_json::from_slice(&request(
+        &format!("{}/_/oidc/audience", index_url),
+        None,
+        None,
+        output,
+    )?) {
+        Ok(audience) => audience,
+        Err(_) => {
+            if output == CommandOutput::Verbose {
+                eprintln!("{} does not support trusted publishing", index_url);
+            }
+            return Ok(None);
+        }
+    };
+    let id_token = source.fetch(&audience.audience, output)?;
+
+    // the OIDC token can only be exchanged once, so minting is not retried
+    let mint_url = format!("{}/_/oidc/mint-token", index_url);
+    check_url(&mint_url, "trusted publishing")?;
+    let body = serde_json::to_vec(&serde_json::json!({ "token": id_token }))?;
+    let minted: MintedToken = serde_json::from_slice(&request_once(&mint_url, None, Some(&body))?)
+        .context("invalid response when minting an API token")?;
+    match minted.token {
+        Some(token) if minted.success => Ok(Some(Secret::new(token))),
+        _ => bail!(
+            "trusted publishing failed:\n{}",
+            minted
+                .errors
+                .iter()
+                .map(|x| format!("  {}: {}", x.code, x.description))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+    }
+}
+
+/// Sends a GET request (or a POST of JSON `body`) and returns the response.
+///
+/// Client error responses are returned as well, as they carry the details.
+/// Server errors are retried.
+fn request(
+    url: &str,
+    header: Option<&str>,
+    body: Option<&[u8]>,
+    output: CommandOutput,
+) -> Result<Vec<u8>, Error> {
+    check_url(url, "trusted publishing")?;
+    with_retries(output, || request_once(url, header, body))
+}
+
+fn request_once(url: &str, header: Option<&str>, body: Option<&[u8]>) -> Result<Vec<u8>, Error> {
+    let mut rv = Vec::new();
+    let mut handle = curl::easy::Easy::new();
+    handle.url(url)?;
+    configure_curl(&mut handle, url)?;
+    let mut headers = curl::easy::List::new();
+    if let Some(header) = header {
+        headers.append(header)?;
+    }
+    if let Some(body) = body {
+        headers.append("Content-Type: application/json")?;
+        handle.post(true)?;
+        handle.post_fields_copy(body)?;
+    }
+    handle.http_headers(headers)?;
+    {
+        let mut transfer = handle.transfer();
+        transfer.write_function(|data| {
+            rv.extend_from_slice(data);
+            Ok(data.len())
+        })?;
+        transfer
+            .perform()
+            .map_err(|err| explain_curl_error(err, url))?;
+    }
+    let code = handle.response_code()?;
+    if !(200..300).contains(&code) && !(400..500).contains(&code) {
+        return Err(HttpStatusError {
+            url: url.to_string(),
+            code,
+        }
+        .into());
+    }
+    Ok(rv)
+}
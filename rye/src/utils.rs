@@ -3,7 +3,7 @@ use std::convert::Infallible;
 use std::io::{Cursor, Read};
 use std::path::{Path, PathBuf};
 use std::process::{Command, ExitStatus, Stdio};
-use std::{fmt, fs};
+use std::{env, fmt, fs};
 
 use anyhow::{anyhow, bail, Error};
 use once_cell::sync::Lazy;
@@ -13,6 +13,8 @@ use sha2::{Digest, Sha256};
 use toml_edit::{Array, RawString};
 
 static ENV_VAR_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\$\{([A-Z0-9_]+)\}").unwrap());
+static INTERPOLATION_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\$(\$?)\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
 
 #[cfg(unix)]
 pub use std::os::unix::fs::{symlink as symlink_file, symlink as symlink_dir};
@@ -21,6 +23,9 @@ pub use std::os::windows::fs::symlink_file;
 
 use crate::config::Config;
 use crate::consts::VENV_BIN;
+use crate::logging::{self, Level};
+use crate::network::{set_network_policy_env, set_tls_env};
+use crate::sandbox::set_sandbox_env;
 
 #[cfg(windows)]
 pub fn symlink_dir<P, Q>(original: P, link: Q) -> Result<(), std::io::Error>
@@ -50,6 +55,54 @@ impl fmt::Display for QuietExit {
     }
 }
 
+/// The exit code used when a prompt is required in non-interactive mode.
+pub const PROMPT_REQUIRED_EXIT_CODE: i32 = 3;
+
+/// Raised instead of prompting when running in non-interactive mode.
+#[derive(Debug)]
+pub struct PromptRequired(pub &'static str);
+
+impl std::error::Error for PromptRequired {}
+
+impl fmt::Display for PromptRequired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} requires a prompt, but rye runs in non-interactive mode (pass --yes if available)",
+            self.0
+        )
+    }
+}
+
+/// Enables the non-interactive mode for this process and its children.
+///
+/// This also makes sure that pip and git fail rather than prompt for
+/// credentials.
+pub fn set_noninteractive() {
+    env::set_var("RYE_NONINTERACTIVE", "1");
+    env::set_var("PIP_NO_INPUT", "1");
+    env::set_var("GIT_TERMINAL_PROMPT", "0");
+}
+
+/// Checks if prompts are forbidden (`--ci` or `RYE_NONINTERACTIVE=1`).
+pub fn is_noninteractive() -> bool {
+    matches!(
+        env::var("RYE_NONINTERACTIVE").ok().as_deref(),
+        Some("1" | "true")
+    )
+}
+
+/// Fails with [`PromptRequired`] in non-interactive mode.
+///
+/// This needs to be invoked ahead of every prompt.
+pub fn ensure_interactive(what: &'static str) -> Result<(), Error> {
+    if is_noninteractive() {
+        Err(PromptRequired(what).into())
+    } else {
+        Ok(())
+    }
+}
+
 /// Controls the fetch output.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub enum CommandOutput {
@@ -64,10 +117,19 @@ pub enum CommandOutput {
 
 impl CommandOutput {
     /// Returns the preferred command output for those flags.
-    pub fn from_quiet_and_verbose(quiet: bool, verbose: bool) -> CommandOutput {
+    ///
+    /// Every `-v` also raises the log level, see [`logging::raise_level`].
+    pub fn from_quiet_and_verbose(quiet: bool, verbose: u8) -> CommandOutput {
+        // -v logs the phases of the command, -vv and -vvv log in more detail
+        logging::raise_level(match verbose {
+            0 => Level::Off,
+            1 => Level::Info,
+            2 => Level::Debug,
+            _ => Level::Trace,
+        });
         if quiet {
             CommandOutput::Quiet
-        } else if verbose {
+        } else if verbose > 0 {
             CommandOutput::Verbose
         } else {
             CommandOutput::Normal
@@ -156,6 +218,62 @@ where
     ENV_VAR_RE.replace_all(string, |m: &Captures| f(&m[1]).unwrap_or_default())
 }
 
+/// Strictly expands variables (`${NAME}`) in a string.
+///
+/// Unlike [`expand_env_vars`] an undefined variable is an error.  `$${NAME}`
+/// is kept as a literal `${NAME}`.
+pub fn interpolate<F>(string: &str, mut f: F) -> Result<Cow<'_, str>, Error>
+where
+    F: for<'a> FnMut(&'a str) -> Option<String>,
+{
+    let mut undefined = None;
+    let rv = INTERPOLATION_RE.replace_all(string, |m: &Captures| {
+        if !m[1].is_empty() {
+            return format!("${{{}}}", &m[2]);
+        }
+        match f(&m[2]) {
+            Some(value) => value,
+            None => {
+                undefined.get_or_insert_with(|| m[2].to_string());
+                String::new()
+            }
+        }
+    });
+    match undefined {
+        Some(name) => bail!("undefined variable ${{{}}}", name),
+        None => Ok(rv),
+    }
+}
+
+/// Parses the contents of an env file (`KEY=value` lines).
+///
+/// Empty lines, comments and an `export` prefix are ignored.  Values can be
+/// wrapped in single or double quotes.
+pub fn parse_env_file(contents: &str) -> Result<Vec<(String, String)>, Error> {
+    let mut rv = Vec::new();
+    for (idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() => (key.trim(), value.trim()),
+            _ => bail!("invalid env file: expected KEY=value on line {}", idx + 1),
+        };
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|quote| {
+                value
+                    .strip_prefix(*quote)
+                    .and_then(|x| x.strip_suffix(*quote))
+            })
+            .unwrap_or(value);
+        rv.push((key.to_string(), value.to_string()));
+    }
+    Ok(rv)
+}
+
 #[derive(Copy, Clone, Debug)]
 enum ArchiveFormat {
     TarGz,
@@ -286,8 +404,11 @@ pub fn exec_spawn(cmd: &mut Command) -> Result<Infallible, Error> {
     }
 }
 
-/// Attaches standard proxy environment variables to a process.
+/// Attaches standard proxy and TLS environment variables and the network policy to a process.
 pub fn set_proxy_variables(cmd: &mut Command) {
+    set_network_policy_env(cmd);
+    set_tls_env(cmd);
+    set_sandbox_env(cmd);
     let config = Config::current();
     if let Some(proxy) = config.https_proxy_url() {
         cmd.env("https_proxy", proxy);
@@ -334,6 +455,22 @@ pub fn success_status() -> ExitStatus {
     }
 }
 
+/// Finds the candidate that is most similar to a mistyped name.
+pub fn find_similar<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    // allow roughly one typo (including swapped letters) for every three
+    // characters
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (strsim::osa_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
 /// Takes a bytes slice and compares it to a given string checksum.
 pub fn check_checksum(content: &[u8], checksum: &str) -> Result<(), Error> {
     let mut hasher = Sha256::new();
@@ -387,6 +524,14 @@ pub fn reformat_toml_array_multiline(deps: &mut Array) {
     deps.set_trailing_comma(true);
 }
 
+#[test]
+fn test_find_similar() {
+    let candidates = ["serve", "test", "lint"];
+    assert_eq!(find_similar("serv", candidates), Some("serve"));
+    assert_eq!(find_similar("tset", candidates), Some("test"));
+    assert_eq!(find_similar("deploy", candidates), None);
+}
+
 #[test]
 fn test_quiet_exit_display() {
     let quiet_exit = QuietExit(0);
@@ -437,11 +582,11 @@ mod test_command_output {
     #[test]
     fn test_command_output_from_quiet_and_verbose() {
         let quiet = true;
-        let verbose = true;
+        let verbose = 1;
 
         assert_eq!(
             CommandOutput::Quiet,
-            CommandOutput::from_quiet_and_verbose(quiet, false)
+            CommandOutput::from_quiet_and_verbose(quiet, 0)
         );
         assert_eq!(
             CommandOutput::Verbose,
@@ -449,7 +594,7 @@ mod test_command_output {
         );
         assert_eq!(
             CommandOutput::Normal,
-            CommandOutput::from_quiet_and_verbose(false, false)
+            CommandOutput::from_quiet_and_verbose(false, 0)
         );
         assert_eq!(
             CommandOutput::Quiet,
@@ -483,6 +628,60 @@ mod test_expand_env_vars {
     }
 }
 
+#[cfg(test)]
+mod test_interpolate {
+    use super::interpolate;
+
+    #[test]
+    fn test_interpolate() {
+        let vars = |name: &str| match name {
+            "PROJECT_ROOT" => Some("/src/app".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            interpolate("${PROJECT_ROOT}/docs", vars).unwrap(),
+            "/src/app/docs"
+        );
+        assert_eq!(
+            interpolate("$${PROJECT_ROOT} $$HOME", vars).unwrap(),
+            "${PROJECT_ROOT} $$HOME"
+        );
+        assert_eq!(
+            interpolate("${PROJECT_ROOT}/${MISSING}", vars)
+                .unwrap_err()
+                .to_string(),
+            "undefined variable ${MISSING}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_parse_env_file {
+    use super::parse_env_file;
+
+    #[test]
+    fn test_parse_env_file() {
+        let vars = parse_env_file(
+            "# secrets\nAPI_KEY=abc\nexport DB_URL = \"postgres://x\"\n\nEMPTY=\nQUOTED='a b'\n",
+        )
+        .unwrap();
+        assert_eq!(
+            vars,
+            vec![
+                ("API_KEY".to_string(), "abc".to_string()),
+                ("DB_URL".to_string(), "postgres://x".to_string()),
+                ("EMPTY".to_string(), "".to_string()),
+                ("QUOTED".to_string(), "a b".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_env_file_invalid() {
+        assert!(parse_env_file("NOT A VAR").is_err());
+    }
+}
+
 #[cfg(test)]
 mod test_is_inside_git_work_tree {
     use std::path::PathBuf;
@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use console::style;
+use once_cell::sync::Lazy;
+
+use crate::utils::CommandOutput;
+
+static SUPPRESSED: Lazy<Mutex<HashSet<String>>> = Lazy::new(Default::default);
+static DENY_WARNINGS: AtomicBool = AtomicBool::new(false);
+static WARNINGS_EMITTED: AtomicBool = AtomicBool::new(false);
+
+/// The warnings rye emits.
+///
+/// Every warning has a stable code which is printed along with it and which
+/// can be listed in `tool.rye.suppress-warnings`.  Codes of removed warnings
+/// are never reused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// The remote cache could not be read or written.
+    RemoteCache,
+    /// The Python version reached its end of life.
+    PythonEndOfLife,
+    /// The Python version is about to reach its end of life.
+    PythonNearingEndOfLife,
+    /// A package passed to `--update` is not in the lockfile.
+    UpdateNotLocked,
+    /// The virtualenv is broken.
+    BrokenVirtualenv,
+    /// The pinned Python version conflicts with `requires-python`.
+    RequiresPythonConflict,
+    /// A dev or pre-release version is bumped to a release version.
+    DevVersionBumped,
+    /// Only pre-releases of a package match the requirement.
+    PreReleaseOnly,
+    /// Added dependencies could not be checked for conflicts.
+    ConflictCheckFailed,
+    /// Added dependencies conflict with the project.
+    DependencyConflict,
+    /// A dependency conflict was left unresolved.
+    UnresolvedConflict,
+    /// A version could not be installed while bisecting.
+    BisectSkipped,
+    /// An installed tool does not provide any scripts.
+    ToolWithoutScripts,
+    /// A pattern of `tool.rye.workspace.members` is invalid.
+    InvalidWorkspaceMember,
+    /// `pylock.toml` and the requirements lockfile pin different versions.
+    LockfileMismatch,
+    /// The index does not report upload times for `exclude-newer`.
+    MissingUploadTimes,
+    /// Files could not be shared through the package store.
+    PackageStore,
+    /// Parts of a project could not be imported by `rye init --import`.
+    ImportIncomplete,
+    /// A workspace member has a lockfile of its own, which is ignored.
+    IgnoredMemberLockfile,
+    /// A workspace member refers to another member by path or URL.
+    MemberUrlDependency,
+    /// A network request failed and is retried.
+    RequestRetried,
+    /// Packages could not be added to the wheel cache.
+    WheelCache,
+    /// A line of a requirements file cannot be added to `pyproject.toml`.
+    RequirementsFileLine,
+    /// `RYE_LOG` is not a valid log level.
+    InvalidLogLevel,
+}
+
+impl Warning {
+    /// Returns the stable code of the warning.
+    pub fn code(self) -> &'static str {
+        match self {
+            Warning::RemoteCache => "RYEW001",
+            Warning::PythonEndOfLife => "RYEW002",
+            Warning::PythonNearingEndOfLife => "RYEW003",
+            Warning::UpdateNotLocked => "RYEW004",
+            Warning::BrokenVirtualenv => "RYEW005",
+            Warning::RequiresPythonConflict => "RYEW006",
+            Warning::DevVersionBumped => "RYEW007",
+            Warning::PreReleaseOnly => "RYEW008",
+            Warning::ConflictCheckFailed => "RYEW009",
+            Warning::DependencyConflict => "RYEW010",
+            Warning::UnresolvedConflict => "RYEW011",
+            Warning::BisectSkipped => "RYEW012",
+            Warning::ToolWithoutScripts => "RYEW013",
+            Warning::InvalidWorkspaceMember => "RYEW014",
+            Warning::LockfileMismatch => "RYEW015",
+            Warning::MissingUploadTimes => "RYEW016",
+            Warning::PackageStore => "RYEW017",
+            Warning::ImportIncomplete => "RYEW018",
+            Warning::IgnoredMemberLockfile => "RYEW019",
+            Warning::MemberUrlDependency => "RYEW020",
+            Warning::RequestRetried => "RYEW021",
+            Warning::WheelCache => "RYEW022",
+            Warning::RequirementsFileLine => "RYEW023",
+            Warning::InvalidLogLevel => "RYEW024",
+        }
+    }
+}
+
+/// Suppresses the warnings with the given codes.
+pub fn suppress_warnings<I: IntoIterator<Item = String>>(codes: I) {
+    let mut suppressed = SUPPRESSED.lock().unwrap();
+    suppressed.extend(codes.into_iter().map(|x| x.trim().to_ascii_uppercase()));
+}
+
+/// Turns emitted warnings into a failure of the command (`--deny-warnings`).
+pub fn deny_warnings() {
+    DENY_WARNINGS.store(true, Ordering::Relaxed);
+}
+
+/// Were warnings emitted although they are denied?
+pub fn denied_warnings_emitted() -> bool {
+    DENY_WARNINGS.load(Ordering::Relaxed) && WARNINGS_EMITTED.load(Ordering::Relaxed)
+}
+
+/// Is the warning suppressed?
+pub fn is_suppressed(warning: Warning) -> bool {
+    SUPPRESSED.lock().unwrap().contains(warning.code())
+}
+
+/// Prints a warning unless it's suppressed.
+pub fn warn<D: fmt::Display>(warning: Warning, message: D) {
+    warn_with_output(warning, message, CommandOutput::Normal);
+}
+
+/// Like [`warn`] but nothing is printed with quiet output.
+///
+/// The warning still fails the command if warnings are denied.
+pub fn warn_with_output<D: fmt::Display>(warning: Warning, message: D, output: CommandOutput) {
+    if is_suppressed(warning) {
+        return;
+    }
+    WARNINGS_EMITTED.store(true, Ordering::Relaxed);
+    if output != CommandOutput::Quiet {
+        eprintln!(
+            "{} {}",
+            style(format!("warning[{}]:", warning.code())).red(),
+            message
+        );
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error};
+use pep440_rs::Version;
+use pep508_rs::{Requirement, VersionOrUrl};
+use tempfile::NamedTempFile;
+
+use crate::consts::VENV_BIN;
+use crate::lock::{make_project_root_fragment, strip_line_continuation};
+use crate::platform::get_app_dir;
+use crate::pyproject::{normalize_package_name, ExpandedSources};
+use crate::transcript::run_status;
+use crate::utils::{get_venv_python_bin, set_proxy_variables, CommandOutput};
+
+/// Returns the folder that keeps the wheels of locked packages.
+///
+/// `rye sync --offline` installs from this folder only.
+pub fn get_wheel_cache_dir() -> PathBuf {
+    get_app_dir().join("cache").join("wheels")
+}
+
+/// Returns the folder with the wheels that were built from sdists.
+///
+/// The wheels are keyed by the hash of the sdist and the interpreter tag.
+pub fn get_build_cache_dir() -> PathBuf {
+    get_app_dir().join("cache").join("builds")
+}
+
+/// The versions of the packages in the wheel cache, keyed by name.
+struct CachedArtifacts(HashMap<String, Vec<Version>>);
+
+impl CachedArtifacts {
+    fn load(dir: &Path) -> CachedArtifacts {
+        let mut rv = HashMap::<String, Vec<Version>>::new();
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            if let Some((name, version)) =
+                entry.file_name().to_str().and_then(parse_artifact_filename)
+            {
+                rv.entry(name).or_default().push(version);
+            }
+        }
+        CachedArtifacts(rv)
+    }
+
+    /// Can the requirement be installed from the cache?
+    ///
+    /// References to local paths are always available.
+    fn provides(&self, req: &Requirement) -> bool {
+        let versions = self
+            .0
+            .get(&normalize_package_name(&req.name))
+            .map_or(&[][..], |x| &x[..]);
+        match req.version_or_url {
+            Some(VersionOrUrl::VersionSpecifier(ref specs)) => {
+                versions.iter().any(|x| specs.contains(x))
+            }
+            Some(VersionOrUrl::Url(ref url)) => url.scheme() == "file",
+            None => !versions.is_empty(),
+        }
+    }
+}
+
+/// Returns the name and version of a wheel or sdist.
+fn parse_artifact_filename(filename: &str) -> Option<(String, Version)> {
+    let (name, version) = if let Some(stem) = filename.strip_suffix(".whl") {
+        let mut parts = stem.split('-');
+        (parts.next()?, parts.next()?)
+    } else {
+        filename
+            .strip_suffix(".tar.gz")
+            .or_else(|| filename.strip_suffix(".zip"))?
+            .rsplit_once('-')?
+    };
+    Some((
+        normalize_package_name(name),
+        Version::from_str(version).ok()?,
+    ))
+}
+
+/// Returns the first line of a lockfile entry as requirement.
+fn parse_entry(entry: &str) -> Option<Requirement> {
+    strip_line_continuation(entry.lines().next()?).parse().ok()
+}
+
+/// Returns the requirements that cannot be installed from the wheel cache.
+///
+/// The requirements are lockfile entries or build requirements.
+pub fn find_missing<'a, I>(requirements: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let cached = CachedArtifacts::load(&get_wheel_cache_dir());
+    requirements
+        .into_iter()
+        .filter_map(|entry| {
+            let req = parse_entry(entry)?;
+            if cached.provides(&req) {
+                None
+            } else {
+                Some(req.to_string())
+            }
+        })
+        .collect()
+}
+
+/// Adds the locked packages that are missing to the wheel cache.
+///
+/// pip builds wheels of the packages, which are usually served from its
+/// HTTP cache.  The build requirements of the projects are added as well,
+/// so that the projects can be installed as editables without network
+/// access.
+#[allow(clippy::too_many_arguments)]
+pub fn fill_wheel_cache(
+    self_venv: &Path,
+    venv: &Path,
+    root: &Path,
+    sources: &ExpandedSources,
+    pip_env: &[(&str, String)],
+    entries: &[&str],
+    build_requires: &[String],
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let dir = get_wheel_cache_dir();
+    fs::create_dir_all(&dir)?;
+    let cached = CachedArtifacts::load(&dir);
+    let missing = entries
+        .iter()
+        .filter(|x| parse_entry(x).map_or(false, |req| !cached.provides(&req)))
+        .copied()
+        .collect::<Vec<_>>();
+    let missing_build_requires = build_requires
+        .iter()
+        .filter(|x| parse_entry(x).map_or(false, |req| !cached.provides(&req)))
+        .map(|x| x.as_str())
+        .collect::<Vec<_>>();
+
+    // locked entries carry hashes and are pinned, build requirements are
+    // neither, so they cannot be mixed in one invocation.
+    if !missing.is_empty() {
+        if output == CommandOutput::Verbose {
+            eprintln!("Adding {} packages to the wheel cache", missing.len());
+        }
+        build_wheels(
+            self_venv, venv, root, sources, pip_env, &dir, &missing, true, output,
+        )?;
+    }
+    if !missing_build_requires.is_empty() {
+        build_wheels(
+            self_venv,
+            venv,
+            root,
+            sources,
+            pip_env,
+            &dir,
+            &missing_build_requires,
+            false,
+            output,
+        )?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+fn build_wheels(
+    self_venv: &Path,
+    venv: &Path,
+    root: &Path,
+    sources: &ExpandedSources,
+    pip_env: &[(&str, String)],
+    dir: &Path,
+    requirements: &[&str],
+    no_deps: bool,
+    output: CommandOutput,
+) -> Result<(), Error> {
+    let requirements_file = NamedTempFile::new()?;
+    fs::write(requirements_file.path(), requirements.join("\n") + "\n")?;
+    let mut cmd = Command::new(self_venv.join(VENV_BIN).join("pip"));
+    cmd.env("PROJECT_ROOT", make_project_root_fragment(root))
+        .current_dir(root)
+        .arg("--python")
+        .arg(get_venv_python_bin(venv))
+        .arg("wheel")
+        .arg("--wheel-dir")
+        .arg(dir)
+        .arg("-r")
+        .arg(requirements_file.path());
+    if no_deps {
+        cmd.arg("--no-deps");
+    }
+    if output == CommandOutput::Verbose {
+        cmd.arg("--verbose");
+    } else {
+        cmd.arg("--quiet");
+        cmd.env("PYTHONWARNINGS", "ignore");
+    }
+    sources.add_as_pip_args(&mut cmd);
+    cmd.envs(pip_env.iter().map(|(key, value)| (key, value)));
+    set_proxy_variables(&mut cmd);
+    let status = run_status(&mut cmd).context("unable to run pip wheel")?;
+    if !status.success() {
+        bail!("failed to add packages to the wheel cache");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_parse_artifact_filename() {
+    let (name, version) = parse_artifact_filename("Flask_Login-0.6.3-py3-none-any.whl").unwrap();
+    assert_eq!(name, "flask-login");
+    assert_eq!(version.to_string(), "0.6.3");
+    let (name, version) = parse_artifact_filename("python-dateutil-2.9.0.tar.gz").unwrap();
+    assert_eq!(name, "python-dateutil");
+    assert_eq!(version.to_string(), "2.9.0");
+    assert!(parse_artifact_filename("README.txt").is_none());
+}